@@ -201,15 +201,15 @@ class PatchWatcher(private val project: Project) : Disposable {
 
     /**
      * Replace content between `<!-- agent:name -->` and `<!-- /agent:name -->` markers.
+     * The opening marker may carry attributes (`<!-- agent:log mode=append -->`).
      */
     private fun applyComponentPatch(doc: String, component: String, content: String): String {
-        val openTag = "<!-- agent:$component -->"
+        val openTag = Regex("<!-- agent:${Regex.escape(component)}(\\s[^>]*)? -->")
         val closeTag = "<!-- /agent:$component -->"
 
-        val openIdx = doc.indexOf(openTag)
-        if (openIdx < 0) return doc
+        val openMatch = openTag.find(doc) ?: return doc
 
-        val contentStart = openIdx + openTag.length
+        val contentStart = openMatch.range.last + 1
         val closeIdx = doc.indexOf(closeTag, contentStart)
         if (closeIdx < 0) return doc
 
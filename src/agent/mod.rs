@@ -23,6 +23,9 @@ pub trait Agent {
     ) -> Result<AgentResponse>;
 }
 
+/// Agent backends that work without an `[agents.NAME]` config.
+pub const BUILTIN: &[&str] = &["claude", "junie"];
+
 /// Resolve an agent backend by name.
 pub fn resolve(name: &str, config: Option<&AgentConfig>) -> Result<Box<dyn Agent>> {
     let (cmd, args) = match config {
@@ -0,0 +1,232 @@
+//! Checklists for `mode = "checklist"` components.
+//!
+//! Items are `- [ ] text` / `- [x] text` lines (`*` bullets and indentation
+//! allowed). An item is identified by a stable ID comment at the end of the
+//! line (`- [ ] Ship it <!-- id:ship -->`), or else by its text (case and
+//! whitespace insensitive).
+//!
+//! Rules, for agent patches and concurrent merges alike:
+//! - a checked box stays checked (an agent can check items, not uncheck them),
+//!   unless the user unchecks it during a merge;
+//! - new items are appended after the last item;
+//! - an item the agent dropped is kept and struck through (`- [ ] ~~text~~`)
+//!   instead of silently disappearing.
+//!
+//! Lines that aren't items are kept as they are.
+
+/// A parsed checklist item line.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Item {
+    indent: String,
+    bullet: char,
+    checked: bool,
+    /// Item text without strikethrough or ID.
+    text: String,
+    /// Struck through: removed by the agent.
+    removed: bool,
+    id: Option<String>,
+}
+
+impl Item {
+    fn parse(line: &str) -> Option<Self> {
+        let rest = line.trim_start();
+        let indent = line[..line.len() - rest.len()].to_string();
+        let bullet = rest.chars().next().filter(|c| matches!(c, '-' | '*' | '+'))?;
+        let rest = rest[1..].strip_prefix(' ')?;
+        let (checked, rest) = if let Some(r) = rest.strip_prefix("[ ]") {
+            (false, r)
+        } else if let Some(r) = rest.strip_prefix("[x]").or_else(|| rest.strip_prefix("[X]")) {
+            (true, r)
+        } else {
+            return None;
+        };
+        let mut text = rest.trim();
+        let mut id = None;
+        if let Some(start) = text.rfind("<!-- id:")
+            && let Some(value) = text[start + 8..].strip_suffix("-->")
+        {
+            id = Some(value.trim().to_string());
+            text = text[..start].trim_end();
+        }
+        let (text, removed) = match text.strip_prefix("~~").and_then(|t| t.strip_suffix("~~")) {
+            Some(inner) => (inner.trim(), true),
+            None => (text, false),
+        };
+        Some(Item { indent, bullet, checked, text: text.to_string(), removed, id })
+    }
+
+    fn key(&self) -> String {
+        match &self.id {
+            Some(id) => format!("id:{}", id),
+            None => self.text.split_whitespace().collect::<Vec<_>>().join(" ").to_lowercase(),
+        }
+    }
+
+    fn render(&self) -> String {
+        let mut line = format!("{}{} [{}] ", self.indent, self.bullet, if self.checked { 'x' } else { ' ' });
+        if self.removed {
+            line.push_str(&format!("~~{}~~", self.text));
+        } else {
+            line.push_str(&self.text);
+        }
+        if let Some(id) = &self.id {
+            line.push_str(&format!(" <!-- id:{} -->", id));
+        }
+        line
+    }
+}
+
+/// Items of a checklist, keyed.
+fn items(text: &str) -> Vec<(String, Item)> {
+    text.lines().filter_map(Item::parse).map(|item| (item.key(), item)).collect()
+}
+
+fn find<'a>(items: &'a [(String, Item)], key: &str) -> Option<&'a Item> {
+    items.iter().find(|(k, _)| k == key).map(|(_, item)| item)
+}
+
+/// Apply an agent's checklist `patch` to the `existing` component content.
+pub fn apply(existing: &str, patch: &str) -> String {
+    merge(None, patch, existing)
+}
+
+/// Merge concurrent edits to a checklist: `ours` is the agent's version,
+/// `theirs` the user's, `base` the version both started from (if known).
+///
+/// The user's layout is kept. On top of the rules in the module docs, an item
+/// the user deleted (in `base` but not `theirs`) stays deleted, an item the
+/// user added (not in `base`) is never struck through, and an item's checked
+/// state only follows the agent if the agent checked it since `base`.
+pub fn merge3(base: Option<&str>, ours: &str, theirs: &str) -> String {
+    merge(Some(base.unwrap_or("")), ours, theirs)
+}
+
+/// `base` is `None` for a plain patch: then every item of `theirs` was seen
+/// by the agent, and items missing from `ours` are struck through.
+fn merge(base: Option<&str>, ours: &str, theirs: &str) -> String {
+    let base_items = base.map(items);
+    let in_base = |key: &str| base_items.as_ref().is_none_or(|b| find(b, key).is_some());
+    let our_items = items(ours);
+
+    let mut out: Vec<String> = Vec::new();
+    let mut seen: Vec<String> = Vec::new();
+    let mut last_item = None;
+    for line in theirs.lines() {
+        let Some(item) = Item::parse(line) else {
+            out.push(line.to_string());
+            continue;
+        };
+        let key = item.key();
+        let mut merged = item.clone();
+        match find(&our_items, &key) {
+            Some(ours) => {
+                let base = base_items.as_ref().and_then(|b| find(b, &key));
+                merged.checked |= ours.checked && base.is_none_or(|b| !b.checked);
+                // Text and removal follow whichever side changed them (the user first)
+                let changed_by_user = |f: fn(&Item) -> String| base.is_some_and(|b| f(b) != f(&item));
+                if !changed_by_user(|i| i.text.clone()) {
+                    merged.text = ours.text.clone();
+                }
+                if !changed_by_user(|i| i.removed.to_string()) {
+                    merged.removed = ours.removed;
+                }
+            }
+            // Dropped by the agent (for a plain patch, the agent saw every item)
+            None if in_base(&key) => merged.removed = true,
+            None => {}
+        }
+        out.push(if merged == item { line.to_string() } else { merged.render() });
+        seen.push(key);
+        last_item = Some(out.len());
+    }
+
+    // New agent items go after the last item; items the user deleted stay deleted
+    let new_items: Vec<String> = ours
+        .lines()
+        .filter_map(|line| Item::parse(line).map(|item| (line, item)))
+        .filter(|(_, item)| {
+            let key = item.key();
+            !seen.contains(&key) && (base.is_none() || !in_base(&key))
+        })
+        .map(|(line, _)| line.trim_end().to_string())
+        .collect();
+    let tail = out.split_off(last_item.unwrap_or(out.len()));
+    out.extend(new_items);
+    out.extend(tail);
+
+    let mut result = out.join("\n");
+    if !result.is_empty() {
+        result.push('\n');
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_items() {
+        let item = Item::parse("  * [x] ~~Ship it~~ <!-- id:ship -->").unwrap();
+        assert_eq!(item.indent, "  ");
+        assert_eq!(item.bullet, '*');
+        assert!(item.checked && item.removed);
+        assert_eq!(item.text, "Ship it");
+        assert_eq!(item.key(), "id:ship");
+        assert_eq!(item.render(), "  * [x] ~~Ship it~~ <!-- id:ship -->");
+
+        assert_eq!(Item::parse("- [ ]  Write   Docs").unwrap().key(), "write docs");
+        assert!(Item::parse("- plain bullet").is_none());
+        assert!(Item::parse("-[ ] no space").is_none());
+    }
+
+    #[test]
+    fn patch_preserves_checked_state() {
+        let existing = "- [x] Write spec\n- [ ] Implement\n";
+        let patch = "- [ ] Write spec\n- [ ] Implement\n- [ ] Test\n";
+        assert_eq!(apply(existing, patch), "- [x] Write spec\n- [ ] Implement\n- [ ] Test\n");
+    }
+
+    #[test]
+    fn patch_marks_dropped_items() {
+        let existing = "## Todo\n- [ ] Write spec\n- [x] Old idea\n\nNotes stay.\n";
+        let patch = "- [x] Write spec\n- [ ] New task\n";
+        assert_eq!(
+            apply(existing, patch),
+            "## Todo\n- [x] Write spec\n- [x] ~~Old idea~~\n- [ ] New task\n\nNotes stay.\n"
+        );
+    }
+
+    #[test]
+    fn patch_matches_by_id_and_restores_removed() {
+        let existing = "- [x] Ship v1 <!-- id:ship -->\n- [ ] ~~Docs~~\n";
+        let patch = "- [ ] Ship v1.0 <!-- id:ship -->\n- [ ] Docs\n";
+        assert_eq!(apply(existing, patch), "- [x] Ship v1.0 <!-- id:ship -->\n- [ ] Docs\n");
+    }
+
+    #[test]
+    fn merge3_concurrent_edits() {
+        let base = "- [ ] A\n- [ ] B\n- [ ] C\n";
+        // Agent rewrites the list: drops C, adds D
+        let ours = "- [ ] A\n- [ ] B\n- [ ] D\n";
+        // User checks A, deletes B, adds E
+        let theirs = "- [x] A\n- [ ] C\n- [ ] E\n";
+        assert_eq!(merge3(Some(base), ours, theirs), "- [x] A\n- [ ] ~~C~~\n- [ ] E\n- [ ] D\n");
+    }
+
+    #[test]
+    fn merge3_keeps_user_unchecking() {
+        let base = "- [x] A\n- [ ] B\n";
+        // Agent leaves A checked and checks B; user unchecks A
+        let ours = "- [x] A\n- [x] B\n";
+        let theirs = "- [ ] A\n- [ ] B\n";
+        assert_eq!(merge3(Some(base), ours, theirs), "- [ ] A\n- [x] B\n");
+    }
+
+    #[test]
+    fn merge3_without_base_unions_items() {
+        let ours = "- [ ] A\n- [x] B\n";
+        let theirs = "- [x] A\n- [ ] C\n";
+        assert_eq!(merge3(None, ours, theirs), "- [x] A\n- [ ] C\n- [x] B\n");
+    }
+}
@@ -25,7 +25,7 @@ fn all_commands() -> Vec<CommandInfo> {
     cmds.push(cmd("/agent-doc", "<FILE>", "Submit document session (diff, respond, write back)"));
     cmds.push(cmd("/agent-doc claim", "<FILE>", "Claim file for current tmux pane"));
     cmds.push(cmd("/agent-doc run", "<FILE>", "Run session workflow with agent backend"));
-    cmds.push(cmd("/agent-doc init", "<FILE>", "Scaffold a new session document"));
+    cmds.push(cmd("/agent-doc init", "<FILE> [--template NAME]", "Scaffold a new session document"));
     cmds.push(cmd("/agent-doc diff", "<FILE>", "Preview the diff that would be sent"));
     cmds.push(cmd("/agent-doc reset", "<FILE>", "Clear session ID and delete snapshot"));
     cmds.push(cmd("/agent-doc clean", "<FILE>", "Squash session git history"));
@@ -37,8 +37,21 @@ fn all_commands() -> Vec<CommandInfo> {
     cmds.push(cmd("/agent-doc layout", "<FILES>", "Arrange tmux panes to mirror editor"));
     cmds.push(cmd("/agent-doc patch", "<FILE> <COMPONENT>", "Replace content in a named component"));
     cmds.push(cmd("/agent-doc watch", "", "Watch files for changes and auto-submit"));
+    cmds.push(cmd("/agent-doc serve", "<FILE>", "Serve the document CRDT over a local y-sync socket"));
     cmds.push(cmd("/agent-doc outline", "<FILE>", "Display markdown outline with token counts"));
     cmds.push(cmd("/agent-doc resync", "", "Validate sessions.json, remove stale entries"));
+    cmds.push(cmd("/agent-doc gc", "[--dry-run] [--older-than AGE]", "Remove orphaned snapshot/CRDT/lock/pending files"));
+    cmds.push(cmd("/agent-doc lint", "<FILE...> [--json]", "Report malformed component markers and unknown patch targets"));
+    cmds.push(cmd("/agent-doc migrate", "<PATH...> [--dry-run]", "Rewrite deprecated frontmatter keys"));
+    cmds.push(cmd("/agent-doc doctor", "[FILE...] [--fix]", "Check sidecar state for drift, corruption and stale entries"));
+    cmds.push(cmd("/agent-doc crdt-log", "<FILE> [--at N]", "List the CRDT update log or print the text at an earlier entry"));
+    cmds.push(cmd("/agent-doc config show", "<FILE>", "Show effective settings and the config layer each comes from"));
+    cmds.push(cmd("/agent-doc config get", "<KEY> [--global|--project]", "Print a config setting"));
+    cmds.push(cmd("/agent-doc config set", "<KEY> <VALUE> [--global|--project]", "Set a config setting, keeping the file's formatting"));
+    cmds.push(cmd("/agent-doc config unset", "<KEY> [--global|--project]", "Remove a config setting"));
+    cmds.push(cmd("/agent-doc config list", "[--global|--project]", "List settings in the config files"));
+    cmds.push(cmd("/agent-doc config validate", "[--global|--project]", "Check config keys, types and agent commands on PATH"));
+    cmds.push(cmd("/agent-doc resolve", "<FILE> [--ours|--theirs|--both|--drop] [--id N]", "Settle recorded merge conflicts one hunk at a time"));
     cmds.push(cmd("/agent-doc compact", "<FILE>", "Archive old exchanges to reduce document size"));
     cmds.push(cmd("/agent-doc convert", "<FILE>", "Convert append-mode document to template mode"));
     cmds.push(cmd("/agent-doc mode", "<FILE>", "Get or set the document mode"));
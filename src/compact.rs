@@ -47,15 +47,9 @@ pub fn run(
         {
             // Compact CRDT state if CRDT write mode
             if resolved.is_crdt()
-                && let Ok(Some(crdt_state)) = snapshot::load_crdt(file)
+                && let Ok(Some((before, after))) = snapshot::compact_crdt(file)
             {
-                let compacted_crdt = crate::crdt::compact(&crdt_state)?;
-                snapshot::save_crdt(file, &compacted_crdt)?;
-                eprintln!(
-                    "[compact] CRDT state compacted: {} → {} bytes",
-                    crdt_state.len(),
-                    compacted_crdt.len()
-                );
+                eprintln!("[compact] CRDT state compacted: {} → {} bytes", before, after);
             }
 
             let target = component_name.unwrap_or("exchange");
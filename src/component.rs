@@ -1,11 +1,15 @@
 use anyhow::{bail, Result};
+use std::collections::BTreeMap;
 
 /// A parsed component in a document.
 ///
 /// Components are bounded regions marked by `<!-- agent:name -->...<!-- /agent:name -->`.
+/// The opening marker may carry attributes: `<!-- agent:log mode=append max_entries=20 timestamp -->`.
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Component {
     pub name: String,
+    /// Inline attributes from the opening marker. A bare key maps to `"true"`.
+    pub attrs: BTreeMap<String, String>,
     /// Byte offset of `<` in opening marker.
     pub open_start: usize,
     /// Byte offset past `>` in opening marker (includes trailing newline if present).
@@ -35,7 +39,7 @@ impl Component {
 }
 
 /// Valid name: `[a-zA-Z0-9][a-zA-Z0-9-]*`
-fn is_valid_name(name: &str) -> bool {
+pub(crate) fn is_valid_name(name: &str) -> bool {
     if name.is_empty() {
         return false;
     }
@@ -49,13 +53,14 @@ fn is_valid_name(name: &str) -> bool {
 
 /// True if the text inside `<!-- ... -->` is an agent component marker.
 ///
-/// Matches `agent:NAME` (open) or `/agent:NAME` (close).
+/// Matches `agent:NAME [attrs]` (open) or `/agent:NAME` (close).
 pub fn is_agent_marker(comment_text: &str) -> bool {
     let trimmed = comment_text.trim();
     if let Some(rest) = trimmed.strip_prefix("/agent:") {
         is_valid_name(rest)
     } else if let Some(rest) = trimmed.strip_prefix("agent:") {
-        is_valid_name(rest)
+        let (name, attrs) = split_name(rest);
+        is_valid_name(name) && parse_attrs(attrs).is_ok()
     } else {
         false
     }
@@ -166,8 +171,8 @@ pub fn parse(doc: &str) -> Result<Vec<Component>> {
     let len = bytes.len();
     let code_ranges = find_code_ranges(doc);
     let mut templates: Vec<Component> = Vec::new();
-    // Stack of (name, open_start, open_end)
-    let mut stack: Vec<(String, usize, usize)> = Vec::new();
+    // Stack of (name, open_start, open_end, attrs)
+    let mut stack: Vec<(String, usize, usize, BTreeMap<String, String>)> = Vec::new();
     let mut pos = 0;
 
     while pos + 4 <= len {
@@ -210,7 +215,20 @@ pub fn parse(doc: &str) -> Result<Vec<Component>> {
                 bail!("invalid component name: '{}'", name);
             }
             match stack.pop() {
-                Some((open_name, open_start, open_end)) => {
+                Some((open_name, open_start, open_end, attrs)) => {
+                    if open_name != name
+                        && let Some((_, outer_start, _, _)) = stack.iter().rev().find(|(n, _, _, _)| n == name)
+                    {
+                        bail!(
+                            "crossing component markers: <!-- /agent:{} --> (line {}) closes '{}' (opened line {}) while '{}' (opened line {}) is still open",
+                            name,
+                            line_of(doc, marker_start),
+                            name,
+                            line_of(doc, *outer_start),
+                            open_name,
+                            line_of(doc, open_start)
+                        );
+                    }
                     if open_name != name {
                         bail!(
                             "mismatched component: opened '{}' but closed '{}'",
@@ -220,6 +238,7 @@ pub fn parse(doc: &str) -> Result<Vec<Component>> {
                     }
                     templates.push(Component {
                         name: name.to_string(),
+                        attrs,
                         open_start,
                         open_end,
                         close_start: marker_start,
@@ -228,18 +247,22 @@ pub fn parse(doc: &str) -> Result<Vec<Component>> {
                 }
                 None => bail!("closing marker <!-- /agent:{} --> without matching open", name),
             }
-        } else if let Some(name) = trimmed.strip_prefix("agent:") {
-            // Opening marker
+        } else if let Some(rest) = trimmed.strip_prefix("agent:") {
+            // Opening marker, optionally followed by attributes
+            let (name, attrs) = split_name(rest);
             if !is_valid_name(name) {
                 bail!("invalid component name: '{}'", name);
             }
-            stack.push((name.to_string(), marker_start, marker_end));
+            let attrs = parse_attrs(attrs).map_err(|e| {
+                anyhow::anyhow!("<!-- agent:{} --> (line {}): {}", name, line_of(doc, marker_start), e)
+            })?;
+            stack.push((name.to_string(), marker_start, marker_end, attrs));
         }
 
         pos = close;
     }
 
-    if let Some((name, _, _)) = stack.last() {
+    if let Some((name, _, _, _)) = stack.last() {
         bail!(
             "unclosed component: <!-- agent:{} --> without matching close",
             name
@@ -250,8 +273,139 @@ pub fn parse(doc: &str) -> Result<Vec<Component>> {
     Ok(templates)
 }
 
+/// Split `NAME attrs...` into the name and the (possibly empty) attribute text.
+pub(crate) fn split_name(rest: &str) -> (&str, &str) {
+    match rest.find(char::is_whitespace) {
+        Some(i) => (&rest[..i], rest[i..].trim_start()),
+        None => (rest, ""),
+    }
+}
+
+/// Parse marker attributes: `key=value`, `key="quoted value"` or a bare `key`
+/// (meaning `"true"`). Keys match `[a-z][a-z0-9_-]*`.
+pub(crate) fn parse_attrs(text: &str) -> Result<BTreeMap<String, String>> {
+    let mut attrs = BTreeMap::new();
+    let mut rest = text.trim();
+    while !rest.is_empty() {
+        let key_end = rest.find(|c: char| c == '=' || c.is_whitespace()).unwrap_or(rest.len());
+        let key = &rest[..key_end];
+        let valid_key = key.starts_with(|c: char| c.is_ascii_lowercase())
+            && key.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_' || c == '-');
+        if !valid_key {
+            bail!("invalid attribute '{}'", key);
+        }
+        rest = &rest[key_end..];
+        let value = if let Some(after_eq) = rest.strip_prefix('=') {
+            if let Some(quoted) = after_eq.strip_prefix('"') {
+                let Some(end) = quoted.find('"') else {
+                    bail!("unterminated quote in attribute '{}'", key);
+                };
+                rest = &quoted[end + 1..];
+                quoted[..end].to_string()
+            } else {
+                let end = after_eq.find(char::is_whitespace).unwrap_or(after_eq.len());
+                rest = &after_eq[end..];
+                after_eq[..end].to_string()
+            }
+        } else {
+            "true".to_string()
+        };
+        if attrs.insert(key.to_string(), value).is_some() {
+            bail!("duplicate attribute '{}'", key);
+        }
+        rest = rest.trim_start();
+    }
+    Ok(attrs)
+}
+
+/// 1-based line number of a byte offset.
+fn line_of(doc: &str, offset: usize) -> usize {
+    doc[..offset].matches('\n').count() + 1
+}
+
+/// A component and the components nested directly inside it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ComponentNode {
+    pub component: Component,
+    /// `/`-separated names from the top-level ancestor, e.g. `plan/plan-step-1`.
+    pub path: String,
+    pub children: Vec<ComponentNode>,
+}
+
+/// Parse components into a tree of top-level components and their children.
+pub fn parse_tree(doc: &str) -> Result<Vec<ComponentNode>> {
+    let components = parse(doc)?;
+    let mut roots = Vec::new();
+    let mut i = 0;
+    while i < components.len() {
+        let (node, next) = build_node(&components, i, "");
+        roots.push(node);
+        i = next;
+    }
+    Ok(roots)
+}
+
+/// Build the node for `components[i]`; returns it and the index after its descendants.
+fn build_node(components: &[Component], i: usize, parent_path: &str) -> (ComponentNode, usize) {
+    let comp = &components[i];
+    let path = if parent_path.is_empty() {
+        comp.name.clone()
+    } else {
+        format!("{}/{}", parent_path, comp.name)
+    };
+    let mut children = Vec::new();
+    let mut j = i + 1;
+    while j < components.len() && components[j].open_start < comp.close_start {
+        let (child, next) = build_node(components, j, &path);
+        children.push(child);
+        j = next;
+    }
+    (ComponentNode { component: comp.clone(), path, children }, j)
+}
+
+/// Index of the component directly enclosing each component, from [`parse`] output.
+pub fn parents(components: &[Component]) -> Vec<Option<usize>> {
+    let mut stack: Vec<usize> = Vec::new();
+    let mut parents = Vec::with_capacity(components.len());
+    for comp in components {
+        while stack.last().is_some_and(|&top| components[top].close_start <= comp.open_start) {
+            stack.pop();
+        }
+        parents.push(stack.last().copied());
+        stack.push(parents.len() - 1);
+    }
+    parents
+}
+
+/// Find the component addressed by `path`.
+///
+/// A bare name matches a top-level component with that name, else the first
+/// one at any depth. A `/`-separated path walks down from a top-level component; each later
+/// segment matches a direct child named either `segment` or
+/// `<parent>-<segment>`, so `plan/step-1` addresses `agent:plan-step-1`
+/// inside `agent:plan`.
+pub fn find_by_path<'a>(components: &'a [Component], path: &str) -> Option<&'a Component> {
+    let parents = parents(components);
+    if !path.contains('/') {
+        let mut matches = (0..components.len()).filter(|&i| components[i].name == path);
+        let first = matches.next()?;
+        let top_level = std::iter::once(first).chain(matches).find(|&i| parents[i].is_none());
+        return Some(&components[top_level.unwrap_or(first)]);
+    }
+    let mut parent: Option<usize> = None;
+    for segment in path.split('/') {
+        let prefixed = parent.map(|p| format!("{}-{}", components[p].name, segment));
+        let found = (0..components.len()).find(|&i| {
+            parents[i] == parent
+                && (components[i].name == segment || prefixed.as_deref() == Some(components[i].name.as_str()))
+        })?;
+        parent = Some(found);
+    }
+    parent.map(|i| &components[i])
+}
+
 /// Find the end of an HTML comment (`-->`), returning byte offset past `>`.
-fn find_comment_end(bytes: &[u8], start: usize) -> Option<usize> {
+pub(crate) fn find_comment_end(bytes: &[u8], start: usize) -> Option<usize> {
     let len = bytes.len();
     let mut i = start;
     while i + 3 <= len {
@@ -339,6 +493,102 @@ beta
         assert!(err.to_string().contains("mismatched"));
     }
 
+    #[test]
+    fn crossing_markers_error() {
+        let doc = "<!-- agent:a -->\n<!-- agent:b -->\n<!-- /agent:a -->\n<!-- /agent:b -->\n";
+        let err = parse(doc).unwrap_err().to_string();
+        assert!(err.contains("crossing component markers"), "{}", err);
+        assert!(err.contains("line 3"), "{}", err);
+    }
+
+    const PLAN_DOC: &str = "\
+<!-- agent:plan -->
+<!-- agent:plan-step-1 -->
+one
+<!-- /agent:plan-step-1 -->
+<!-- agent:notes -->
+<!-- agent:detail -->
+deep
+<!-- /agent:detail -->
+<!-- /agent:notes -->
+<!-- /agent:plan -->
+<!-- agent:notes -->
+top
+<!-- /agent:notes -->
+";
+
+    #[test]
+    fn parse_tree_nests_children() {
+        let tree = parse_tree(PLAN_DOC).unwrap();
+        assert_eq!(tree.len(), 2);
+        assert_eq!(tree[0].path, "plan");
+        let children: Vec<&str> = tree[0].children.iter().map(|c| c.path.as_str()).collect();
+        assert_eq!(children, vec!["plan/plan-step-1", "plan/notes"]);
+        assert_eq!(tree[0].children[1].children[0].path, "plan/notes/detail");
+        assert_eq!(tree[1].path, "notes");
+        assert!(tree[1].children.is_empty());
+    }
+
+    #[test]
+    fn parents_of_nested_components() {
+        let components = parse(PLAN_DOC).unwrap();
+        assert_eq!(parents(&components), vec![None, Some(0), Some(0), Some(2), None]);
+    }
+
+    #[test]
+    fn find_by_path_addresses_nested() {
+        let components = parse(PLAN_DOC).unwrap();
+        let content = |path: &str| find_by_path(&components, path).map(|c| c.content(PLAN_DOC));
+        assert_eq!(content("plan/step-1"), Some("one\n"));
+        assert_eq!(content("plan/plan-step-1"), Some("one\n"));
+        assert_eq!(content("plan/notes/detail"), Some("deep\n"));
+        assert_eq!(content("notes/detail"), None);
+        assert_eq!(content("detail"), Some("deep\n"));
+        assert_eq!(content("plan/missing"), None);
+        // A bare name prefers a top-level match over an earlier nested one.
+        assert_eq!(content("notes"), Some("top\n"));
+        assert_eq!(content("plan-step-1"), Some("one\n"));
+    }
+
+    #[test]
+    fn marker_attributes() {
+        let doc = "<!-- agent:log mode=append max_entries=20 timestamp -->\nentry\n<!-- /agent:log -->\n";
+        let components = parse(doc).unwrap();
+        assert_eq!(components[0].name, "log");
+        assert_eq!(components[0].content(doc), "entry\n");
+        let attrs: Vec<(&str, &str)> = components[0]
+            .attrs
+            .iter()
+            .map(|(k, v)| (k.as_str(), v.as_str()))
+            .collect();
+        assert_eq!(attrs, vec![("max_entries", "20"), ("mode", "append"), ("timestamp", "true")]);
+    }
+
+    #[test]
+    fn marker_attributes_quoted_value() {
+        let doc = "<!-- agent:x title=\"Build status\" -->\n<!-- /agent:x -->\n";
+        let components = parse(doc).unwrap();
+        assert_eq!(components[0].attrs["title"], "Build status");
+    }
+
+    #[test]
+    fn marker_attributes_errors() {
+        let err = parse("<!-- agent:x mode=append mode=replace -->\n<!-- /agent:x -->\n").unwrap_err();
+        assert!(err.to_string().contains("duplicate attribute 'mode'"));
+        let err = parse("<!-- agent:x Bad=1 -->\n<!-- /agent:x -->\n").unwrap_err();
+        assert!(err.to_string().contains("invalid attribute 'Bad'"));
+        let err = parse("<!-- agent:x title=\"open -->\n<!-- /agent:x -->\n").unwrap_err();
+        assert!(err.to_string().contains("unterminated quote"));
+    }
+
+    #[test]
+    fn replace_keeps_marker_attributes() {
+        let doc = "<!-- agent:log mode=append -->\nold\n<!-- /agent:log -->\n";
+        let components = parse(doc).unwrap();
+        let updated = components[0].replace_content(doc, "new\n");
+        assert_eq!(updated, "<!-- agent:log mode=append -->\nnew\n<!-- /agent:log -->\n");
+    }
+
     #[test]
     fn invalid_name() {
         let doc = "<!-- agent:-bad -->\n<!-- /agent:-bad -->\n";
@@ -386,6 +636,7 @@ beta
         assert!(is_agent_marker("/agent:status"));
         assert!(is_agent_marker("agent:my-thing"));
         assert!(is_agent_marker(" /agent:A1 "));
+        assert!(is_agent_marker("agent:log mode=append timestamp"));
     }
 
     #[test]
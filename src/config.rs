@@ -1,12 +1,70 @@
-use anyhow::Result;
+//! Configuration, layered from lowest to highest precedence:
+//!
+//! 1. built-in defaults;
+//! 2. global `~/.config/agent-doc/config.toml` (also the only place agent
+//!    backends are defined, under `[agents.NAME]`);
+//! 3. project `.agent-doc/config.toml`;
+//! 4. the document's frontmatter (`agent`, `model`, `agent_doc_stream.interval`).
+//!
+//! Command-line flags override all of them. `agent-doc config show <file>`
+//! prints each effective value and the layer it came from.
+
+use anyhow::{Context, Result};
 use serde::{Deserialize, Serialize};
-use std::collections::BTreeMap;
-use std::path::PathBuf;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::{frontmatter, snapshot};
+
+pub const DEFAULT_AGENT: &str = "claude";
+pub const DEFAULT_STREAM_INTERVAL_MS: u64 = 200;
+pub const DEFAULT_WATCH_DEBOUNCE_MS: u64 = 500;
+pub const DEFAULT_IPC_TIMEOUT_MS: u64 = 2000;
+
+/// Project config file, relative to the project root.
+pub(crate) const PROJECT_CONFIG: &str = ".agent-doc/config.toml";
+
+/// Settings that the global and project config files share.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct Settings {
+    /// Agent backend for documents that don't set `agent`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub default_agent: Option<String>,
+    /// Model passed to the agent backend.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    /// Stream write-back interval in milliseconds.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stream_interval_ms: Option<u64>,
+    /// Watch daemon debounce in milliseconds.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub watch_debounce_ms: Option<u64>,
+    /// How long `write --ipc` waits for an editor plugin to apply a patch.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ipc_timeout_ms: Option<u64>,
+    #[serde(default)]
+    pub prompts: Prompts,
+}
+
+/// Prompt templates for `run`/`submit` and `stream`. `{{document}}`,
+/// `{{diff}}` and `{{file}}` are filled in; unset templates use the built-in
+/// prompts.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct Prompts {
+    /// First submit of a document (no agent session to resume yet).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub start: Option<String>,
+    /// Later submits, resuming the agent session.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub resume: Option<String>,
+}
 
 #[derive(Debug, Default, Serialize, Deserialize)]
 pub struct Config {
-    #[serde(default)]
-    pub default_agent: Option<String>,
+    #[serde(flatten)]
+    pub settings: Settings,
     #[serde(default)]
     pub agents: BTreeMap<String, AgentConfig>,
 }
@@ -27,13 +85,28 @@ pub fn load() -> Result<Config> {
     let path = config_path();
     if path.exists() {
         let content = std::fs::read_to_string(&path)?;
-        Ok(toml::from_str(&content)?)
+        toml::from_str(&content).with_context(|| format!("failed to parse {}", path.display()))
     } else {
         Ok(Config::default())
     }
 }
 
-fn config_path() -> PathBuf {
+/// Load the project config for the project containing `path`, if any.
+pub fn load_project(path: &Path) -> Result<Option<(PathBuf, Settings)>> {
+    let path = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+    let Some(root) = snapshot::find_project_root(&path) else {
+        return Ok(None);
+    };
+    let config = root.join(PROJECT_CONFIG);
+    if !config.exists() {
+        return Ok(None);
+    }
+    let content = std::fs::read_to_string(&config).with_context(|| format!("failed to read {}", config.display()))?;
+    let settings = toml::from_str(&content).with_context(|| format!("failed to parse {}", config.display()))?;
+    Ok(Some((config, settings)))
+}
+
+pub(crate) fn config_path() -> PathBuf {
     dirs_config_dir()
         .join("agent-doc")
         .join("config.toml")
@@ -47,3 +120,218 @@ fn dirs_config_dir() -> PathBuf {
             PathBuf::from(home).join(".config")
         })
 }
+
+/// Where an effective setting came from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Source {
+    Default,
+    Global(PathBuf),
+    Project(PathBuf),
+    Frontmatter,
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Default => write!(f, "default"),
+            Self::Global(path) => write!(f, "global ({})", path.display()),
+            Self::Project(path) => write!(f, "project ({})", path.display()),
+            Self::Frontmatter => write!(f, "frontmatter"),
+        }
+    }
+}
+
+/// The config layers that apply to a path, lowest precedence first.
+pub struct Layers {
+    layers: Vec<(Source, Settings)>,
+}
+
+impl Layers {
+    /// Global and project layers for the project containing `path`.
+    pub fn for_path(config: &Config, path: &Path) -> Result<Self> {
+        let mut layers = vec![(Source::Global(config_path()), config.settings.clone())];
+        if let Some((file, settings)) = load_project(path)? {
+            layers.push((Source::Project(file), settings));
+        }
+        Ok(Self { layers })
+    }
+
+    /// [`Layers::for_path`] plus the document's frontmatter. Frontmatter that
+    /// doesn't parse is skipped; the command reading the document reports it.
+    pub fn for_document(config: &Config, file: &Path) -> Result<Self> {
+        let mut layers = Self::for_path(config, file)?;
+        let content = std::fs::read_to_string(file).with_context(|| format!("failed to read {}", file.display()))?;
+        if let Ok((fm, _)) = frontmatter::parse(&content) {
+            let settings = Settings {
+                default_agent: fm.agent,
+                model: fm.model,
+                stream_interval_ms: fm.stream_config.and_then(|s| s.interval),
+                ..Settings::default()
+            };
+            layers.layers.push((Source::Frontmatter, settings));
+        }
+        Ok(layers)
+    }
+
+    /// The highest-precedence value `pick` finds, and its layer.
+    fn get<T>(&self, pick: impl Fn(&Settings) -> Option<T>) -> Option<(T, &Source)> {
+        self.layers.iter().rev().find_map(|(source, settings)| pick(settings).map(|v| (v, source)))
+    }
+
+    pub fn agent(&self) -> String {
+        self.get(|s| s.default_agent.clone()).map_or_else(|| DEFAULT_AGENT.to_string(), |(v, _)| v)
+    }
+
+    pub fn model(&self) -> Option<String> {
+        self.get(|s| s.model.clone()).map(|(v, _)| v)
+    }
+
+    pub fn stream_interval_ms(&self) -> u64 {
+        self.get(|s| s.stream_interval_ms).map_or(DEFAULT_STREAM_INTERVAL_MS, |(v, _)| v)
+    }
+
+    pub fn watch_debounce_ms(&self) -> u64 {
+        self.get(|s| s.watch_debounce_ms).map_or(DEFAULT_WATCH_DEBOUNCE_MS, |(v, _)| v)
+    }
+
+    pub fn ipc_timeout(&self) -> Duration {
+        Duration::from_millis(self.get(|s| s.ipc_timeout_ms).map_or(DEFAULT_IPC_TIMEOUT_MS, |(v, _)| v))
+    }
+
+    /// Render the configured prompt template, or `None` to use the built-in prompt.
+    pub fn prompt(&self, resume: bool, file: &Path, diff: &str, document: &str) -> Result<Option<String>> {
+        let key = if resume { "resume" } else { "start" };
+        let Some((template, source)) =
+            self.get(|s| if resume { s.prompts.resume.clone() } else { s.prompts.start.clone() })
+        else {
+            return Ok(None);
+        };
+        let values = HashMap::from([
+            ("file".to_string(), file.display().to_string()),
+            ("diff".to_string(), diff.to_string()),
+            ("document".to_string(), document.to_string()),
+        ]);
+        crate::init::substitute(&template, &values)
+            .map(Some)
+            .with_context(|| format!("invalid prompts.{} template in {} config", key, source))
+    }
+
+    /// Each setting as `(key, value, source)`, for display.
+    fn effective(&self) -> Vec<(&'static str, String, String)> {
+        fn row<T: fmt::Display>(key: &'static str, found: Option<(T, &Source)>, default: T) -> (&'static str, String, String) {
+            match found {
+                Some((value, source)) => (key, value.to_string(), source.to_string()),
+                None => (key, default.to_string(), Source::Default.to_string()),
+            }
+        }
+        let prompt = |template: String| {
+            let first = template.lines().next().unwrap_or("");
+            if template.trim_end().contains('\n') { format!("{}…", first) } else { first.to_string() }
+        };
+        vec![
+            row("default_agent", self.get(|s| s.default_agent.clone()), DEFAULT_AGENT.to_string()),
+            row("model", self.get(|s| s.model.clone()), "(agent default)".to_string()),
+            row("stream_interval_ms", self.get(|s| s.stream_interval_ms), DEFAULT_STREAM_INTERVAL_MS),
+            row("watch_debounce_ms", self.get(|s| s.watch_debounce_ms), DEFAULT_WATCH_DEBOUNCE_MS),
+            row("ipc_timeout_ms", self.get(|s| s.ipc_timeout_ms), DEFAULT_IPC_TIMEOUT_MS),
+            row("prompts.start", self.get(|s| s.prompts.start.clone().map(prompt)), "(built-in)".to_string()),
+            row("prompts.resume", self.get(|s| s.prompts.resume.clone().map(prompt)), "(built-in)".to_string()),
+        ]
+    }
+}
+
+/// IPC timeout for a document. A config that fails to load falls back to the
+/// default rather than failing the write.
+pub fn ipc_timeout(file: &Path) -> Duration {
+    match load().and_then(|config| Layers::for_path(&config, file)) {
+        Ok(layers) => layers.ipc_timeout(),
+        Err(e) => {
+            eprintln!("[config] {:#}; using default IPC timeout", e);
+            Duration::from_millis(DEFAULT_IPC_TIMEOUT_MS)
+        }
+    }
+}
+
+/// `agent-doc config show <file>`: print each effective setting for a
+/// document and the layer it came from.
+pub fn show(config: &Config, file: &Path) -> Result<()> {
+    if !file.exists() {
+        anyhow::bail!("file not found: {}", file.display());
+    }
+    let rows = Layers::for_document(config, file)?.effective();
+    let key_width = rows.iter().map(|(k, _, _)| k.len()).max().unwrap_or(0);
+    let value_width = rows.iter().map(|(_, v, _)| v.chars().count()).max().unwrap_or(0);
+    for (key, value, source) in rows {
+        let pad = value_width - value.chars().count();
+        println!("{:key_width$}  {}{}  {}", key, value, " ".repeat(pad), source);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn project(config_toml: &str, doc: &str) -> (TempDir, PathBuf) {
+        let dir = TempDir::new().unwrap();
+        std::fs::create_dir_all(dir.path().join(".agent-doc")).unwrap();
+        std::fs::write(dir.path().join(PROJECT_CONFIG), config_toml).unwrap();
+        let file = dir.path().join("doc.md");
+        std::fs::write(&file, doc).unwrap();
+        (dir, file)
+    }
+
+    fn global(toml: &str) -> Config {
+        toml::from_str(toml).unwrap()
+    }
+
+    #[test]
+    fn later_layers_win() {
+        let config = global("default_agent = \"codex\"\nmodel = \"g\"\nwatch_debounce_ms = 900\n\n[agents.codex]\ncommand = \"codex\"\n");
+        assert_eq!(config.agents["codex"].command, "codex");
+        let (_dir, file) = project(
+            "model = \"p\"\nstream_interval_ms = 50\nipc_timeout_ms = 5000\n",
+            "---\nmodel: fm\nagent_doc_stream:\n  interval: 25\n---\n",
+        );
+
+        let layers = Layers::for_document(&config, &file).unwrap();
+        assert_eq!(layers.agent(), "codex");
+        assert_eq!(layers.model().as_deref(), Some("fm"));
+        assert_eq!(layers.stream_interval_ms(), 25);
+        assert_eq!(layers.watch_debounce_ms(), 900);
+        assert_eq!(layers.ipc_timeout(), Duration::from_millis(5000));
+
+        let layers = Layers::for_path(&config, &file).unwrap();
+        assert_eq!(layers.model().as_deref(), Some("p"));
+        assert_eq!(layers.stream_interval_ms(), 50);
+
+        let rows = Layers::for_document(&Config::default(), &file).unwrap().effective();
+        let sources: Vec<&str> = rows.iter().map(|(_, _, s)| s.split(' ').next().unwrap()).collect();
+        assert_eq!(sources, vec!["default", "frontmatter", "frontmatter", "default", "project", "default", "default"]);
+    }
+
+    #[test]
+    fn prompt_templates() {
+        let (_dir, file) = project(
+            "[prompts]\nresume = \"\"\"\nDiff for {{ file }}:\n{{diff}}\nDoc: {{document}}\"\"\"\nstart = \"{{nope}}\"\n",
+            "---\n---\n",
+        );
+        let layers = Layers::for_document(&Config::default(), &file).unwrap();
+        let prompt = layers.prompt(true, Path::new("doc.md"), "+x", "body").unwrap();
+        assert_eq!(prompt.as_deref(), Some("Diff for doc.md:\n+x\nDoc: body"));
+        let err = layers.prompt(false, Path::new("doc.md"), "", "").unwrap_err();
+        assert!(format!("{:#}", err).contains("invalid prompts.start template in project"));
+
+        let layers = Layers::for_document(&Config::default(), &file).unwrap();
+        let rows = layers.effective();
+        assert_eq!(rows[6].1, "Diff for {{ file }}:…");
+    }
+
+    #[test]
+    fn invalid_project_config_is_an_error() {
+        let (_dir, file) = project("stream_interval_ms = \"fast\"\n", "# Doc\n");
+        let err = Layers::for_path(&Config::default(), &file).err().unwrap();
+        assert!(format!("{:#}", err).contains("failed to parse"));
+    }
+}
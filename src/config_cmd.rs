@@ -0,0 +1,582 @@
+//! `agent-doc config get|set|unset|list|validate` — Read and edit the global
+//! and project config files.
+//!
+//! Usage: agent-doc config set [--global|--project] <KEY> <VALUE>
+//!
+//! Keys are dotted paths: `model`, `prompts.resume`, `agents.codex.command`.
+//! `set` and `unset` edit the project config (`.agent-doc/config.toml`) when
+//! run inside a project and the global config otherwise; `--global` and
+//! `--project` pick the file explicitly. Edits go through `toml_edit`, so
+//! comments, ordering and the formatting of other keys are kept.
+//!
+//! `validate` checks both files for unknown keys, wrong value types,
+//! `[agents.NAME]` tables without a command, agent commands that aren't on
+//! PATH, an unknown `default_agent` and prompt templates with undefined
+//! variables.
+
+use anyhow::{Context, Result};
+use std::collections::HashMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use toml_edit::{DocumentMut, Item, TableLike, Value};
+
+use crate::{agent, config, init, schema, snapshot, write};
+
+/// Which config file a command reads or edits.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Scope {
+    Global,
+    Project,
+}
+
+impl Scope {
+    /// Scope selected by `--global` / `--project`, if any.
+    pub fn from_flags(global: bool, project: bool) -> Option<Self> {
+        if global {
+            Some(Self::Global)
+        } else if project {
+            Some(Self::Project)
+        } else {
+            None
+        }
+    }
+
+    /// Path of this scope's config file (which may not exist yet).
+    fn path(self) -> Result<PathBuf> {
+        match self {
+            Self::Global => Ok(config::config_path()),
+            Self::Project => {
+                let cwd = std::env::current_dir()?;
+                let root = snapshot::find_project_root(&cwd)
+                    .context("not in an agent-doc project (no .agent-doc directory); use --global")?;
+                Ok(root.join(config::PROJECT_CONFIG))
+            }
+        }
+    }
+}
+
+impl fmt::Display for Scope {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Global => write!(f, "global"),
+            Self::Project => write!(f, "project"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    Str,
+    Int,
+    StrArray,
+    Table,
+}
+
+const SETTINGS: &[(&str, Kind)] = &[
+    ("default_agent", Kind::Str),
+    ("model", Kind::Str),
+    ("stream_interval_ms", Kind::Int),
+    ("watch_debounce_ms", Kind::Int),
+    ("ipc_timeout_ms", Kind::Int),
+    ("prompts", Kind::Table),
+    ("agents", Kind::Table),
+];
+
+const PROMPTS: &[(&str, Kind)] = &[("start", Kind::Str), ("resume", Kind::Str)];
+
+const AGENT_FIELDS: &[(&str, Kind)] = &[
+    ("command", Kind::Str),
+    ("args", Kind::StrArray),
+    ("result_path", Kind::Str),
+    ("session_path", Kind::Str),
+];
+
+/// The kind of value a dotted key holds, or an error naming the unknown part.
+fn spec(key: &str) -> Result<Kind> {
+    let path: Vec<&str> = key.split('.').collect();
+    let (fields, rest) = match path.as_slice() {
+        ["agents", name] if !name.is_empty() => return Ok(Kind::Table),
+        ["agents", _, rest @ ..] => (AGENT_FIELDS, rest),
+        ["prompts", rest @ ..] if !rest.is_empty() => (PROMPTS, rest),
+        _ => (SETTINGS, path.as_slice()),
+    };
+    if let [field] = rest
+        && let Some((_, kind)) = fields.iter().find(|(name, _)| name == field)
+    {
+        return Ok(*kind);
+    }
+    let last = rest.last().copied().unwrap_or(key);
+    let hint = schema::suggest(last, fields.iter().map(|(name, _)| *name))
+        .map_or(String::new(), |s| format!(" (did you mean '{}'?)", s));
+    anyhow::bail!("unknown config key '{}'{}", key, hint)
+}
+
+fn check_scope(key: &str, scope: Scope) -> Result<()> {
+    if scope == Scope::Project && (key == "agents" || key.starts_with("agents.")) {
+        anyhow::bail!("agents are only read from the global config; use --global");
+    }
+    Ok(())
+}
+
+fn parse_value(key: &str, kind: Kind, raw: &str) -> Result<Value> {
+    match kind {
+        Kind::Str => Ok(Value::from(raw)),
+        Kind::Int => {
+            let n: i64 = raw
+                .parse()
+                .ok()
+                .filter(|n| *n >= 0)
+                .with_context(|| format!("'{}' expects a non-negative integer, got '{}'", key, raw))?;
+            Ok(Value::from(n))
+        }
+        Kind::StrArray if raw.trim_start().starts_with('[') => {
+            let value: Value = raw.parse().with_context(|| format!("'{}' is not a valid TOML array", raw))?;
+            if !value.as_array().is_some_and(|a| a.iter().all(|v| v.is_str())) {
+                anyhow::bail!("'{}' expects an array of strings", key);
+            }
+            Ok(value)
+        }
+        Kind::StrArray => Ok(Value::Array(raw.split_whitespace().collect())),
+        Kind::Table => anyhow::bail!("'{}' is a table; set its keys individually", key),
+    }
+}
+
+fn parse(content: &str, path: &Path) -> Result<DocumentMut> {
+    content.parse().with_context(|| format!("failed to parse {}", path.display()))
+}
+
+fn read(path: &Path) -> Result<String> {
+    if !path.exists() {
+        return Ok(String::new());
+    }
+    std::fs::read_to_string(path).with_context(|| format!("failed to read {}", path.display()))
+}
+
+/// The item at a dotted key.
+fn lookup<'a>(doc: &'a DocumentMut, key: &str) -> Option<&'a Item> {
+    key.split('.').try_fold(doc.as_item(), |item, part| item.get(part))
+}
+
+/// Set `key` to `raw` in a config file's content. The key's comments and the
+/// rest of the file are kept.
+fn set_value(content: &str, path: &Path, key: &str, raw: &str) -> Result<String> {
+    let mut value = parse_value(key, spec(key)?, raw)?;
+    let mut doc = parse(content, path)?;
+    let parts: Vec<&str> = key.split('.').collect();
+    let (last, parents) = parts.split_last().context("empty key")?;
+    let mut table: &mut dyn TableLike = doc.as_table_mut();
+    for (i, part) in parents.iter().enumerate() {
+        if table.get(part).is_none() {
+            let mut child = toml_edit::Table::new();
+            child.set_implicit(true);
+            table.insert(part, Item::Table(child));
+        }
+        table = table
+            .get_mut(part)
+            .and_then(Item::as_table_like_mut)
+            .with_context(|| format!("'{}' is not a table", parts[..=i].join(".")))?;
+    }
+    match table.get_mut(last) {
+        Some(Item::Value(old)) => {
+            *value.decor_mut() = old.decor().clone();
+            *old = value;
+        }
+        Some(item) => *item = Item::Value(value),
+        None => {
+            table.insert(last, Item::Value(value));
+        }
+    }
+    Ok(doc.to_string())
+}
+
+/// Remove `key` from a config file's content, along with tables it leaves
+/// empty.
+fn unset_value(content: &str, path: &Path, key: &str) -> Result<String> {
+    spec(key)?;
+    let mut doc = parse(content, path)?;
+    let parts: Vec<&str> = key.split('.').collect();
+    if !remove(doc.as_table_mut(), &parts) {
+        anyhow::bail!("'{}' is not set in {}", key, path.display());
+    }
+    Ok(doc.to_string())
+}
+
+fn remove(table: &mut dyn TableLike, path: &[&str]) -> bool {
+    match path {
+        [] => false,
+        [last] => table.remove(last).is_some(),
+        [first, rest @ ..] => {
+            let Some(child) = table.get_mut(first).and_then(Item::as_table_like_mut) else {
+                return false;
+            };
+            let removed = remove(child, rest);
+            if removed && child.is_empty() {
+                table.remove(first);
+            }
+            removed
+        }
+    }
+}
+
+/// Every value in a config file as `(dotted key, TOML value)`.
+fn entries(prefix: &str, item: &Item, out: &mut Vec<(String, String)>) {
+    if let Some(table) = item.as_table_like() {
+        for (key, child) in table.iter() {
+            let key = if prefix.is_empty() { key.to_string() } else { format!("{}.{}", prefix, key) };
+            entries(&key, child, out);
+        }
+    } else if let Some(value) = item.as_value() {
+        out.push((prefix.to_string(), display(value)));
+    } else {
+        out.push((prefix.to_string(), item.to_string().trim().to_string()));
+    }
+}
+
+/// A value as TOML, on one line and without its comments.
+fn display(value: &Value) -> String {
+    match value.as_str() {
+        Some(s) => format!("{:?}", s),
+        None => {
+            let mut value = value.clone();
+            value.decor_mut().clear();
+            value.to_string()
+        }
+    }
+}
+
+/// A setting's built-in default, for `get` when no file sets it.
+fn default_value(key: &str) -> Option<String> {
+    match key {
+        "default_agent" => Some(config::DEFAULT_AGENT.to_string()),
+        "stream_interval_ms" => Some(config::DEFAULT_STREAM_INTERVAL_MS.to_string()),
+        "watch_debounce_ms" => Some(config::DEFAULT_WATCH_DEBOUNCE_MS.to_string()),
+        "ipc_timeout_ms" => Some(config::DEFAULT_IPC_TIMEOUT_MS.to_string()),
+        _ => None,
+    }
+}
+
+/// Config files to read: the given scope's, or the project's (if in a
+/// project) and then the global one.
+fn scopes(scope: Option<Scope>) -> Vec<Scope> {
+    match scope {
+        Some(scope) => vec![scope],
+        None => [Scope::Project, Scope::Global].into_iter().filter(|s| s.path().is_ok()).collect(),
+    }
+}
+
+/// `config get`: print a value. Without a scope, prints the effective value
+/// (project, then global, then the built-in default).
+pub fn get(key: &str, scope: Option<Scope>) -> Result<()> {
+    spec(key)?;
+    for scope in scopes(scope) {
+        let path = scope.path()?;
+        let doc = parse(&read(&path)?, &path)?;
+        if let Some(item) = lookup(&doc, key) {
+            if let Some(s) = item.as_str() {
+                println!("{}", s);
+            } else if let Some(value) = item.as_value() {
+                println!("{}", display(value));
+            } else {
+                let mut out = Vec::new();
+                entries(key, item, &mut out);
+                for (key, value) in out {
+                    println!("{} = {}", key, value);
+                }
+            }
+            return Ok(());
+        }
+    }
+    match default_value(key) {
+        Some(value) if scope.is_none() => {
+            println!("{}", value);
+            Ok(())
+        }
+        _ => anyhow::bail!("'{}' is not set", key),
+    }
+}
+
+/// `config set`: set a value, in the project config by default when inside
+/// a project and the global config otherwise.
+pub fn set(key: &str, raw: &str, scope: Option<Scope>) -> Result<()> {
+    let scope = scope.unwrap_or_else(default_scope);
+    check_scope(key, scope)?;
+    let path = scope.path()?;
+    let updated = set_value(&read(&path)?, &path, key, raw)?;
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent).with_context(|| format!("failed to create {}", parent.display()))?;
+    }
+    write::atomic_write_pub(&path, &updated)?;
+    eprintln!("[config] set {} in {}", key, path.display());
+    for problem in check(&updated, scope, &global_agents())? {
+        if problem.key == key || key.starts_with(&format!("{}.", problem.key)) {
+            eprintln!("[config] warning: {}: {}", problem.key, problem.message);
+        }
+    }
+    Ok(())
+}
+
+/// `config unset`: remove a value (and tables left empty).
+pub fn unset(key: &str, scope: Option<Scope>) -> Result<()> {
+    let scope = scope.unwrap_or_else(default_scope);
+    check_scope(key, scope)?;
+    let path = scope.path()?;
+    let updated = unset_value(&read(&path)?, &path, key)?;
+    write::atomic_write_pub(&path, &updated)?;
+    eprintln!("[config] unset {} in {}", key, path.display());
+    Ok(())
+}
+
+/// `config list`: print every value in the config files as `key = value`.
+pub fn list(scope: Option<Scope>) -> Result<()> {
+    let scopes = scopes(scope);
+    for scope in &scopes {
+        let path = scope.path()?;
+        if !path.exists() {
+            continue;
+        }
+        let doc = parse(&read(&path)?, &path)?;
+        if scopes.len() > 1 {
+            println!("# {} ({})", scope, path.display());
+        }
+        let mut out = Vec::new();
+        entries("", doc.as_item(), &mut out);
+        for (key, value) in out {
+            println!("{} = {}", key, value);
+        }
+    }
+    Ok(())
+}
+
+/// `config validate`: check the config files; exits non-zero on problems.
+pub fn validate(scope: Option<Scope>) -> Result<()> {
+    let agents = global_agents();
+    let mut checked = 0;
+    let mut found = 0;
+    for scope in scopes(scope) {
+        let path = scope.path()?;
+        if !path.exists() {
+            continue;
+        }
+        checked += 1;
+        let content = read(&path)?;
+        let problems = match check(&content, scope, &agents) {
+            Ok(problems) => problems,
+            Err(e) => {
+                found += 1;
+                println!("{}: {:#}", path.display(), e);
+                continue;
+            }
+        };
+        found += problems.len();
+        for problem in problems {
+            println!("{}: {}: {}", path.display(), problem.key, problem.message);
+        }
+    }
+    if checked == 0 {
+        eprintln!("[config] no config files found");
+        return Ok(());
+    }
+    if found > 0 {
+        anyhow::bail!("{} problem(s) found", found);
+    }
+    eprintln!("[config] {} file(s) ok", checked);
+    Ok(())
+}
+
+fn default_scope() -> Scope {
+    if Scope::Project.path().is_ok() { Scope::Project } else { Scope::Global }
+}
+
+/// Agent names defined in a config file's content.
+fn agent_names(doc: &DocumentMut) -> Vec<String> {
+    doc.get("agents")
+        .and_then(Item::as_table_like)
+        .map_or_else(Vec::new, |t| t.iter().map(|(name, _)| name.to_string()).collect())
+}
+
+/// Agent names defined in the global config; none if it can't be read.
+fn global_agents() -> Vec<String> {
+    read(&config::config_path())
+        .ok()
+        .and_then(|content| content.parse::<DocumentMut>().ok())
+        .map_or_else(Vec::new, |doc| agent_names(&doc))
+}
+
+/// A config value that is invalid.
+#[derive(Debug)]
+struct Problem {
+    key: String,
+    message: String,
+}
+
+/// Check a config file's content. `global_agents` are the agents a project
+/// config's `default_agent` may name. Returns an error if it doesn't parse.
+fn check(content: &str, scope: Scope, global_agents: &[String]) -> Result<Vec<Problem>> {
+    let doc: DocumentMut = content.parse().context("invalid TOML")?;
+    let agents = match scope {
+        Scope::Global => agent_names(&doc),
+        Scope::Project => global_agents.to_vec(),
+    };
+    let mut problems = Vec::new();
+    for (key, item) in doc.iter() {
+        if key == "agents" && scope == Scope::Project {
+            let message = "agents are only read from the global config".to_string();
+            problems.push(Problem { key: key.to_string(), message });
+            continue;
+        }
+        check_item(key, item, &mut problems);
+    }
+
+    if let Some(table) = doc.get("agents").and_then(Item::as_table_like)
+        && scope == Scope::Global
+    {
+        for (name, agent) in table.iter() {
+            let key = format!("agents.{}", name);
+            match agent.get("command").and_then(Item::as_str) {
+                None if agent.is_table_like() && agent.get("command").is_none() => {
+                    problems.push(Problem { key, message: "missing required 'command'".to_string() });
+                }
+                Some("") => problems.push(Problem { key: format!("{}.command", key), message: "command is empty".to_string() }),
+                Some(command) if !on_path(command) => problems.push(Problem {
+                    key: format!("{}.command", key),
+                    message: format!("'{}' not found on PATH", command),
+                }),
+                _ => {}
+            }
+        }
+    }
+
+    if let Some(name) = doc.get("default_agent").and_then(Item::as_str)
+        && !agent::BUILTIN.contains(&name)
+        && !agents.iter().any(|a| a == name)
+    {
+        problems.push(Problem {
+            key: "default_agent".to_string(),
+            message: format!(
+                "unknown agent '{}' (built-in: {}; or define [agents.{}] in the global config)",
+                name,
+                agent::BUILTIN.join(", "),
+                name
+            ),
+        });
+    }
+
+    let values: HashMap<String, String> =
+        ["document", "diff", "file"].iter().map(|k| (k.to_string(), String::new())).collect();
+    for (name, _) in PROMPTS {
+        let key = format!("prompts.{}", name);
+        if let Some(template) = lookup(&doc, &key).and_then(Item::as_str)
+            && let Err(e) = init::substitute(template, &values)
+        {
+            problems.push(Problem { key, message: format!("{} (available: document, diff, file)", e) });
+        }
+    }
+    Ok(problems)
+}
+
+fn check_item(key: &str, item: &Item, problems: &mut Vec<Problem>) {
+    let mut report = |message: &str| problems.push(Problem { key: key.to_string(), message: message.to_string() });
+    match spec(key) {
+        Err(e) => report(&e.to_string()),
+        Ok(Kind::Table) => match item.as_table_like() {
+            Some(table) => {
+                for (child, value) in table.iter() {
+                    check_item(&format!("{}.{}", key, child), value, problems);
+                }
+            }
+            None => report("expected a table"),
+        },
+        Ok(Kind::Str) if !item.is_str() => report("expected a string"),
+        Ok(Kind::Int) if item.as_integer().is_none_or(|n| n < 0) => report("expected a non-negative integer"),
+        Ok(Kind::StrArray) if !item.as_array().is_some_and(|a| a.iter().all(|v| v.is_str())) => {
+            report("expected an array of strings")
+        }
+        Ok(_) => {}
+    }
+}
+
+/// Whether `command` names an executable, either as a path or on PATH.
+fn on_path(command: &str) -> bool {
+    let path = Path::new(command);
+    if path.components().count() > 1 {
+        return is_executable(path);
+    }
+    std::env::var_os("PATH").is_some_and(|paths| std::env::split_paths(&paths).any(|dir| is_executable(&dir.join(path))))
+}
+
+fn is_executable(path: &Path) -> bool {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        path.metadata().is_ok_and(|m| m.is_file() && m.permissions().mode() & 0o111 != 0)
+    }
+    #[cfg(not(unix))]
+    {
+        path.is_file()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG: &str = "# agent-doc settings\ndefault_agent = \"claude\"  # everyday\n\n[agents.codex]\n# the CLI\ncommand = \"codex\"\nargs = [\"--prompt\"]\n";
+
+    #[test]
+    fn set_and_unset_preserve_formatting() {
+        let path = Path::new("config.toml");
+        let updated = set_value(CONFIG, path, "default_agent", "codex").unwrap();
+        assert_eq!(updated, CONFIG.replace("\"claude\"  # everyday", "\"codex\"  # everyday"));
+
+        let updated = set_value(&updated, path, "agents.codex.args", "-p --json").unwrap();
+        assert!(updated.contains("# the CLI\ncommand = \"codex\"\nargs = [\"-p\", \"--json\"]\n"), "{}", updated);
+        let updated = set_value(&updated, path, "ipc_timeout_ms", "5000").unwrap();
+        assert!(updated.starts_with("# agent-doc settings\ndefault_agent = \"codex\"  # everyday\nipc_timeout_ms = 5000\n"));
+        let updated = set_value(&updated, path, "prompts.resume", "Diff:\n{{diff}}").unwrap();
+        assert!(updated.contains("[prompts]\nresume = "), "{}", updated);
+
+        let updated = unset_value(&updated, path, "prompts.resume").unwrap();
+        assert!(!updated.contains("prompts"));
+        let updated = unset_value(&updated, path, "agents.codex").unwrap();
+        assert_eq!(updated, "# agent-doc settings\ndefault_agent = \"codex\"  # everyday\nipc_timeout_ms = 5000\n");
+        assert!(unset_value(&updated, path, "model").unwrap_err().to_string().contains("'model' is not set"));
+    }
+
+    #[test]
+    fn rejects_unknown_keys_and_bad_values() {
+        let path = Path::new("config.toml");
+        let err = set_value("", path, "agents.codex.comand", "x").unwrap_err();
+        assert!(err.to_string().contains("did you mean 'command'?"), "{}", err);
+        let err = set_value("", path, "stream_intervl_ms", "5").unwrap_err();
+        assert!(err.to_string().contains("did you mean 'stream_interval_ms'?"), "{}", err);
+        assert!(set_value("", path, "ipc_timeout_ms", "-1").is_err());
+        assert!(set_value("", path, "agents.codex", "x").is_err());
+        assert!(set_value("", path, "agents.codex.args", "[1, 2]").is_err());
+        assert!(check_scope("agents.codex.command", Scope::Project).is_err());
+
+        let mut out = Vec::new();
+        entries("", parse(CONFIG, path).unwrap().as_item(), &mut out);
+        assert_eq!(out[2], ("agents.codex.args".to_string(), "[\"--prompt\"]".to_string()));
+    }
+
+    #[test]
+    fn check_reports_problems() {
+        let config = "default_agent = \"nope\"\nmodle = \"x\"\nwatch_debounce_ms = \"fast\"\n\
+                      [prompts]\nstart = \"{{document}} {{title}}\"\n\
+                      [agents.gpt]\nargs = [\"-p\"]\n[agents.missing]\ncommand = \"agent-doc-no-such-binary\"\n[agents.sh]\ncommand = \"sh\"\n";
+        let problems = check(config, Scope::Global, &[]).unwrap();
+        let keys: Vec<&str> = problems.iter().map(|p| p.key.as_str()).collect();
+        assert_eq!(
+            keys,
+            vec!["modle", "watch_debounce_ms", "agents.gpt", "agents.missing.command", "default_agent", "prompts.start"]
+        );
+        assert!(problems[0].message.contains("did you mean 'model'?"));
+        assert!(problems[3].message.contains("not found on PATH"));
+        assert!(problems[5].message.contains("undefined variable 'title'"));
+
+        let problems = check("[agents.x]\ncommand = \"sh\"\n", Scope::Project, &[]).unwrap();
+        assert_eq!(problems[0].message, "agents are only read from the global config");
+        assert!(check("default_agent = \"gpt\"\n", Scope::Project, &["gpt".to_string()]).unwrap().is_empty());
+        assert!(check("model = \n", Scope::Global, &[]).is_err());
+    }
+}
@@ -0,0 +1,563 @@
+//! Merge conflict records and the `resolve` command.
+//!
+//! Conflicts left by a `write`/`submit` merge are stored as JSON in
+//! `.agent-doc/conflicts/<doc_key>.json` and summarized in the document's
+//! conflicts component (`<!-- agent:conflicts -->` by default, configurable
+//! with `agent_doc_conflicts` in frontmatter). `agent-doc resolve` settles
+//! them one hunk at a time.
+
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+use crate::merge::{document_line, with_newline, ConflictHunk, ConflictPlacement, DocumentConflict};
+use crate::hooks::{self, Event};
+use crate::{component, frontmatter, snapshot, write};
+
+/// An unresolved conflict in a document.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ConflictRecord {
+    pub id: u32,
+    /// Component containing the conflict; `None` for text outside components.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub component: Option<String>,
+    /// 0-based line in the component (or document) where the conflict starts.
+    pub line: usize,
+    pub placement: ConflictPlacement,
+    #[serde(flatten)]
+    pub hunk: ConflictHunk,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct ConflictStore {
+    next_id: u32,
+    conflicts: Vec<ConflictRecord>,
+}
+
+/// How `resolve` settles a conflict.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Resolution {
+    /// Keep the agent's version.
+    Ours,
+    /// Keep the user's version.
+    Theirs,
+    /// Keep both: agent's version first, then the user's.
+    Both,
+    /// Drop the record without touching the document (already fixed by hand).
+    Drop,
+}
+
+fn load_store(file: &Path) -> Result<ConflictStore> {
+    let path = snapshot::conflicts_path_for(file)?;
+    if !path.exists() {
+        return Ok(ConflictStore::default());
+    }
+    let content = std::fs::read_to_string(&path)
+        .with_context(|| format!("failed to read {}", path.display()))?;
+    serde_json::from_str(&content).with_context(|| format!("failed to parse {}", path.display()))
+}
+
+fn save_store(file: &Path, store: &ConflictStore) -> Result<()> {
+    let path = snapshot::conflicts_path_for(file)?;
+    if store.conflicts.is_empty() {
+        if path.exists() {
+            std::fs::remove_file(&path)
+                .with_context(|| format!("failed to remove {}", path.display()))?;
+        }
+        return Ok(());
+    }
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    write::atomic_write_pub(&path, &serde_json::to_string_pretty(store)?)
+}
+
+/// A merged document whose conflicts have been recorded.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RecordedMerge {
+    /// Merged text, with the conflicts summary updated.
+    pub text: String,
+    /// Conflicts recorded by this merge.
+    pub records: Vec<ConflictRecord>,
+}
+
+impl RecordedMerge {
+    /// A merge that left no conflicts.
+    pub fn clean(text: String) -> Self {
+        RecordedMerge { text, records: Vec::new() }
+    }
+
+    /// Fire `on-conflict` hooks for the conflicts this merge recorded.
+    ///
+    /// Writers call this once the merged document is written and the doc lock
+    /// is released. The merge has already happened, so hook failures are logged.
+    pub fn fire_hooks(&self, file: &Path) {
+        if self.records.is_empty() {
+            return;
+        }
+        if let Err(e) = hooks::fire(file, Event::OnConflict, serde_json::json!({ "conflicts": self.records })) {
+            eprintln!("[conflicts] {:#}", e);
+        }
+    }
+}
+
+/// Record `conflicts` from a merge of `file` and return `merged` with the
+/// conflicts summary updated.
+pub fn record(file: &Path, merged: &str, conflicts: &[DocumentConflict]) -> Result<RecordedMerge> {
+    let mut store = load_store(file)?;
+    for conflict in conflicts {
+        store.next_id += 1;
+        store.conflicts.push(ConflictRecord {
+            id: store.next_id,
+            component: conflict.component.clone(),
+            line: conflict.line,
+            placement: conflict.placement,
+            hunk: conflict.hunk.clone(),
+        });
+    }
+    save_store(file, &store)?;
+    eprintln!(
+        "[conflicts] Recorded {} conflict(s); run `agent-doc resolve {}` to settle them",
+        conflicts.len(),
+        file.display()
+    );
+    Ok(RecordedMerge {
+        text: render_summary(merged, &store.conflicts, file),
+        records: store.conflicts[store.conflicts.len() - conflicts.len()..].to_vec(),
+    })
+}
+
+/// Write the summary of `records` into the document's conflicts component,
+/// adding the component at the end of the document if it's missing.
+/// An empty `records` clears the summary but keeps the component.
+fn render_summary(content: &str, records: &[ConflictRecord], file: &Path) -> String {
+    let Some(name) = frontmatter::conflicts_component(content) else {
+        return content.to_string();
+    };
+    let summary = summary_text(content, records, file);
+    let components = match component::parse(content) {
+        Ok(components) => components,
+        Err(e) => {
+            eprintln!("[conflicts] Skipping summary: component markers don't parse ({})", e);
+            return content.to_string();
+        }
+    };
+    if let Some(comp) = components.iter().find(|c| c.name == name) {
+        // The summary's line count doesn't depend on the numbers in it, so
+        // numbering against a first rendering is exact even when the
+        // conflicts component sits above the conflicts it lists.
+        let first = comp.replace_content(content, &summary);
+        return comp.replace_content(content, &summary_text(&first, records, file));
+    }
+    if records.is_empty() {
+        return content.to_string();
+    }
+    let mut result = content.to_string();
+    if !result.is_empty() && !result.ends_with('\n') {
+        result.push('\n');
+    }
+    result.push_str(&format!("\n<!-- agent:{0} -->\n{1}<!-- /agent:{0} -->\n", name, summary));
+    result
+}
+
+/// Summary listing `records`, with lines numbered as in `content`.
+fn summary_text(content: &str, records: &[ConflictRecord], file: &Path) -> String {
+    if records.is_empty() {
+        return String::new();
+    }
+    let mut out = format!(
+        "**{} unresolved merge conflict(s).** Settle one at a time with `agent-doc resolve {} --ours|--theirs|--both`.\n\n",
+        records.len(),
+        file.display()
+    );
+    for r in records {
+        let line = document_line(content, r.component.as_deref(), r.line);
+        let location = match &r.component {
+            Some(name) => format!("`{}`, line {}", name, line),
+            None => format!("line {}", line),
+        };
+        let kept = match r.placement {
+            ConflictPlacement::Markers => "conflict markers in document",
+            ConflictPlacement::KeptTheirs => "your version kept",
+        };
+        out.push_str(&format!(
+            "#### #{} — {} ({})\n\nAgent:\n\n{}\n\nYours:\n\n{}\n\n",
+            r.id,
+            location,
+            kept,
+            fence(&r.hunk.ours),
+            fence(&r.hunk.theirs),
+        ));
+    }
+    out
+}
+
+/// Wrap text in a tilde fence longer than any tilde run inside it.
+fn fence(text: &str) -> String {
+    let longest = text.split(|c| c != '~').map(str::len).max().unwrap_or(0);
+    let fence = "~".repeat(longest.max(3) + 1);
+    format!("{}text\n{}{}", fence, with_newline(text), fence)
+}
+
+/// Text that settles `record` under `resolution`; `None` for [`Resolution::Drop`].
+fn replacement(record: &ConflictRecord, resolution: Resolution) -> Option<String> {
+    match resolution {
+        Resolution::Ours => Some(record.hunk.ours.clone()),
+        Resolution::Theirs => Some(record.hunk.theirs.clone()),
+        Resolution::Both => Some(format!("{}{}", with_newline(&record.hunk.ours), record.hunk.theirs)),
+        Resolution::Drop => None,
+    }
+}
+
+/// Apply `resolution` to the conflict `record` in `content`.
+/// Returns the new content and the change in line count at the conflict.
+fn apply(content: &str, record: &ConflictRecord, resolution: Resolution) -> Result<(String, isize)> {
+    let current = match record.placement {
+        ConflictPlacement::Markers => record.hunk.to_markers(),
+        ConflictPlacement::KeptTheirs => record.hunk.theirs.clone(),
+    };
+    let Some(replacement) = replacement(record, resolution) else {
+        return Ok((content.to_string(), 0));
+    };
+    replace_hunk(content, record, &current, &replacement)
+}
+
+/// Replace `current`, the text `record` left at its conflict, with `replacement`.
+fn replace_hunk(content: &str, record: &ConflictRecord, current: &str, replacement: &str) -> Result<(String, isize)> {
+    if current == replacement {
+        return Ok((content.to_string(), 0));
+    }
+
+    let (start, end) = match &record.component {
+        Some(name) => {
+            let components = component::parse(content).context("failed to parse components")?;
+            let comp = components
+                .iter()
+                .find(|c| c.name == *name)
+                .ok_or_else(|| anyhow::anyhow!("component '{}' no longer exists", name))?;
+            (comp.open_end, comp.close_start)
+        }
+        None => (0, content.len()),
+    };
+    let region = &content[start..end];
+    let at_line = line_offset(region, record.line).filter(|&off| region[off..].starts_with(current));
+    let offset = match at_line {
+        Some(off) => off,
+        None if !current.is_empty() => region.find(current).ok_or_else(|| {
+            anyhow::anyhow!(
+                "conflict #{} is no longer in the document (edited by hand?); use --drop to discard it",
+                record.id
+            )
+        })?,
+        None => bail!(
+            "conflict #{} can't be located in the document; use --drop to discard it",
+            record.id
+        ),
+    };
+
+    let pos = start + offset;
+    let mut result = String::with_capacity(content.len() + replacement.len());
+    result.push_str(&content[..pos]);
+    result.push_str(replacement);
+    result.push_str(&content[pos + current.len()..]);
+    let delta = replacement.matches('\n').count() as isize - current.matches('\n').count() as isize;
+    Ok((result, delta))
+}
+
+/// Byte offset of the start of 0-based `line`, if the text has that many lines.
+fn line_offset(text: &str, line: usize) -> Option<usize> {
+    if line == 0 {
+        return Some(0);
+    }
+    text.match_indices('\n').nth(line - 1).map(|(i, _)| i + 1)
+}
+
+/// Settle one conflict in `file`, or list the open conflicts if `resolution` is `None`.
+///
+/// Resolves conflict `id`, or the oldest one when no id is given.
+pub fn run(file: &Path, resolution: Option<Resolution>, id: Option<u32>) -> Result<()> {
+    if !file.exists() {
+        bail!("file not found: {}", file.display());
+    }
+    let doc_lock = write::acquire_doc_lock(file)?;
+    let mut store = load_store(file)?;
+    if store.conflicts.is_empty() {
+        eprintln!("[resolve] No unresolved conflicts in {}", file.display());
+        return Ok(());
+    }
+
+    let content = std::fs::read_to_string(file)
+        .with_context(|| format!("failed to read {}", file.display()))?;
+    let Some(resolution) = resolution else {
+        for r in &store.conflicts {
+            let location = r.component.as_deref().unwrap_or("(document)");
+            let kept = match r.placement {
+                ConflictPlacement::Markers => "markers",
+                ConflictPlacement::KeptTheirs => "yours kept",
+            };
+            let line = document_line(&content, r.component.as_deref(), r.line);
+            println!("#{}\t{}\tline {}\t{}", r.id, location, line, kept);
+        }
+        return Ok(());
+    };
+
+    let index = match id {
+        Some(id) => store
+            .conflicts
+            .iter()
+            .position(|r| r.id == id)
+            .ok_or_else(|| anyhow::anyhow!("no conflict #{} in {}", id, file.display()))?,
+        None => 0,
+    };
+    let record = store.conflicts.remove(index);
+
+    let (resolved, delta) = apply(&content, &record, resolution)?;
+    for r in &mut store.conflicts {
+        if r.component == record.component && r.line > record.line {
+            r.line = r.line.saturating_add_signed(delta);
+        }
+    }
+
+    let updated = render_summary(&resolved, &store.conflicts, file);
+    if updated != content {
+        write::atomic_write_pub(file, &updated)?;
+    }
+    // The snapshot holds the agent's side of the last write: settle the same
+    // hunk there instead of copying the document, which would hide any
+    // unsubmitted user edits from the next diff
+    if let Some(snap) = snapshot::load(file)? {
+        let snap_resolved = match replacement(&record, resolution) {
+            Some(replacement) => replace_hunk(&snap, &record, &record.hunk.ours, &replacement)
+                .map_or_else(|_| snap.clone(), |(text, _)| text),
+            None => snap.clone(),
+        };
+        let snap_updated = render_summary(&snap_resolved, &store.conflicts, file);
+        if snap_updated != snap {
+            snapshot::save(file, &snap_updated)?;
+        }
+    }
+    save_store(file, &store)?;
+    drop(doc_lock);
+    eprintln!(
+        "[resolve] Resolved conflict #{} ({:?}); {} remaining",
+        record.id,
+        resolution,
+        store.conflicts.len()
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::merge::{merge_by_component, MergeStrategy};
+    use std::collections::HashMap;
+    use tempfile::TempDir;
+
+    const BASE: &str = "# Doc\n\n<!-- agent:status -->\nIdle.\n<!-- /agent:status -->\n\n<!-- agent:log -->\nentry 1\nentry 2\n<!-- /agent:log -->\n";
+
+    fn setup(content: &str) -> (TempDir, std::path::PathBuf) {
+        let dir = TempDir::new().unwrap();
+        std::fs::create_dir_all(dir.path().join(".agent-doc")).unwrap();
+        let doc = dir.path().join("doc.md");
+        std::fs::write(&doc, content).unwrap();
+        (dir, doc)
+    }
+
+    /// Merge with `strategies`, record the conflicts and write the result.
+    fn merge_and_record(doc: &Path, ours: &str, theirs: &str, strategies: &[(&str, MergeStrategy)]) -> String {
+        record_merge(doc, ours, theirs, strategies).text
+    }
+
+    fn record_merge(doc: &Path, ours: &str, theirs: &str, strategies: &[(&str, MergeStrategy)]) -> RecordedMerge {
+        let strategies: HashMap<String, MergeStrategy> =
+            strategies.iter().map(|(n, s)| (n.to_string(), *s)).collect();
+        let merged = merge_by_component(BASE, ours, theirs, &strategies).unwrap();
+        let recorded = record(doc, &merged.text, &merged.conflicts).unwrap();
+        std::fs::write(doc, &recorded.text).unwrap();
+        recorded
+    }
+
+    #[test]
+    fn on_conflict_hooks_fire_after_record() {
+        let (dir, doc) = setup(BASE);
+        std::fs::write(
+            dir.path().join(".agent-doc/hooks.toml"),
+            "[[on-conflict]]\ncommand = \"cat > payload.json\"\n\n[[on-conflict]]\ncommand = \"exit 1\"\non_failure = \"abort\"\n",
+        )
+        .unwrap();
+        let ours = BASE.replace("Idle.", "Agent status.");
+        let theirs = BASE.replace("Idle.", "User status.");
+        let recorded = record_merge(&doc, &ours, &theirs, &[("status", MergeStrategy::Strict)]);
+        assert!(!dir.path().join("payload.json").exists(), "not fired while recording");
+
+        recorded.fire_hooks(&doc); // the aborting hook is only logged
+        let payload: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(dir.path().join("payload.json")).unwrap()).unwrap();
+        assert_eq!(payload["conflicts"][0]["component"], "status");
+        assert_eq!(payload["conflicts"][0]["id"], recorded.records[0].id);
+
+        std::fs::remove_file(dir.path().join("payload.json")).unwrap();
+        RecordedMerge::clean(recorded.text).fire_hooks(&doc);
+        assert!(!dir.path().join("payload.json").exists(), "clean merges fire nothing");
+    }
+
+    #[test]
+    fn record_writes_store_and_summary() {
+        let (_dir, doc) = setup(BASE);
+        let ours = BASE.replace("Idle.", "Agent status.");
+        let theirs = BASE.replace("Idle.", "User status.");
+        let text = merge_and_record(&doc, &ours, &theirs, &[("status", MergeStrategy::Strict)]);
+
+        let records = load_store(&doc).unwrap().conflicts;
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].id, 1);
+        assert_eq!(records[0].component.as_deref(), Some("status"));
+        assert!(snapshot::conflicts_path_for(&doc).unwrap().exists());
+
+        let summary = component::parse(&text).unwrap();
+        let summary = summary.iter().find(|c| c.name == "conflicts").unwrap().content(&text);
+        assert!(summary.contains("#1 — `status`, line 4"));
+        assert!(summary.contains("Agent status."));
+    }
+
+    #[test]
+    fn summary_updates_existing_component() {
+        let base = "<!-- agent:conflicts -->\nold\n<!-- /agent:conflicts -->\n";
+        let (_dir, doc) = setup(base);
+        let record = ConflictRecord {
+            id: 1,
+            component: None,
+            line: 0,
+            placement: ConflictPlacement::Markers,
+            hunk: ConflictHunk { base_line: 0, base: "a\n".into(), ours: "b\n".into(), theirs: "c\n".into() },
+        };
+        let text = render_summary(base, &[record], &doc);
+        assert_eq!(text.matches("<!-- agent:conflicts -->").count(), 1);
+        assert!(!text.contains("old"));
+        assert!(text.contains("1 unresolved merge conflict(s)"));
+    }
+
+    #[test]
+    fn summary_numbers_lines_below_it() {
+        let content = "<!-- agent:conflicts -->\n<!-- /agent:conflicts -->\n<!-- agent:status -->\nUser status.\n<!-- /agent:status -->\n";
+        let (_dir, doc) = setup(content);
+        let record = ConflictRecord {
+            id: 1,
+            component: Some("status".into()),
+            line: 0,
+            placement: ConflictPlacement::KeptTheirs,
+            hunk: ConflictHunk {
+                base_line: 0,
+                base: "Idle.\n".into(),
+                ours: "Agent status.\n".into(),
+                theirs: "User status.\n".into(),
+            },
+        };
+        let text = render_summary(content, &[record], &doc);
+        let line = text.lines().collect::<Vec<_>>().iter().rposition(|l| *l == "User status.").unwrap() + 1;
+        assert!(text.contains(&format!("#1 — `status`, line {} ", line)));
+    }
+
+    #[test]
+    fn summary_disabled_by_frontmatter() {
+        let content = "---\nagent_doc_conflicts: none\n---\nBody\n";
+        let (_dir, doc) = setup(content);
+        let record = ConflictRecord {
+            id: 1,
+            component: None,
+            line: 0,
+            placement: ConflictPlacement::Markers,
+            hunk: ConflictHunk { base_line: 0, base: "a\n".into(), ours: "b\n".into(), theirs: "c\n".into() },
+        };
+        assert_eq!(render_summary(content, &[record], &doc), content);
+    }
+
+    #[test]
+    fn resolve_strict_conflict_with_ours() {
+        let (_dir, doc) = setup(BASE);
+        let ours = BASE.replace("Idle.", "Agent status.");
+        let theirs = BASE.replace("Idle.", "User status.");
+        merge_and_record(&doc, &ours, &theirs, &[("status", MergeStrategy::Strict)]);
+
+        run(&doc, Some(Resolution::Ours), None).unwrap();
+        let text = std::fs::read_to_string(&doc).unwrap();
+        assert!(text.contains("<!-- agent:status -->\nAgent status.\n"));
+        assert!(!text.contains("User status."));
+        assert!(load_store(&doc).unwrap().conflicts.is_empty());
+        assert!(!snapshot::conflicts_path_for(&doc).unwrap().exists());
+    }
+
+    #[test]
+    fn resolve_markers_one_hunk_at_a_time() {
+        let (_dir, doc) = setup(BASE);
+        let ours = BASE.replace("Idle.", "Agent status.").replace("entry 2", "agent entry 2");
+        let theirs = BASE.replace("Idle.", "User status.").replace("entry 2", "user entry 2");
+        let text = merge_and_record(&doc, &ours, &theirs, &[]);
+        assert_eq!(text.matches("<<<<<<< agent-response").count(), 2);
+        assert_eq!(load_store(&doc).unwrap().conflicts.len(), 2);
+
+        run(&doc, Some(Resolution::Theirs), None).unwrap();
+        let text = std::fs::read_to_string(&doc).unwrap();
+        assert!(text.contains("<!-- agent:status -->\nUser status.\n"));
+        assert_eq!(text.matches("<<<<<<< agent-response").count(), 1);
+
+        run(&doc, Some(Resolution::Both), Some(2)).unwrap();
+        let text = std::fs::read_to_string(&doc).unwrap();
+        assert!(text.contains("entry 1\nagent entry 2\nuser entry 2\n"));
+        assert!(!text.contains("<<<<<<<"));
+        assert!(load_store(&doc).unwrap().conflicts.is_empty());
+    }
+
+    #[test]
+    fn resolve_hand_edited_conflict_requires_drop() {
+        let (_dir, doc) = setup(BASE);
+        let ours = BASE.replace("entry 2", "agent entry 2");
+        let theirs = BASE.replace("entry 2", "user entry 2");
+        let text = merge_and_record(&doc, &ours, &theirs, &[]);
+
+        let start = text.find("<<<<<<<").unwrap();
+        let end = text.find(">>>>>>> your-edits\n").unwrap() + ">>>>>>> your-edits\n".len();
+        let fixed = format!("{}fixed by hand\n{}", &text[..start], &text[end..]);
+        std::fs::write(&doc, &fixed).unwrap();
+
+        let err = run(&doc, Some(Resolution::Ours), None).unwrap_err();
+        assert!(err.to_string().contains("--drop"));
+        assert_eq!(load_store(&doc).unwrap().conflicts.len(), 1);
+
+        run(&doc, Some(Resolution::Drop), None).unwrap();
+        assert!(load_store(&doc).unwrap().conflicts.is_empty());
+        let text = std::fs::read_to_string(&doc).unwrap();
+        assert!(text.contains("fixed by hand"));
+    }
+
+    #[test]
+    fn resolve_updates_snapshot() {
+        let (_dir, doc) = setup(BASE);
+        let ours = BASE.replace("Idle.", "Agent status.");
+        let theirs = BASE.replace("Idle.", "User status.");
+        merge_and_record(&doc, &ours, &theirs, &[("status", MergeStrategy::Strict)]);
+
+        snapshot::save(&doc, &ours).unwrap();
+        let text = std::fs::read_to_string(&doc).unwrap();
+        std::fs::write(&doc, text.replace("entry 2\n", "entry 2\nunsubmitted\n")).unwrap();
+
+        run(&doc, Some(Resolution::Both), None).unwrap();
+        let snap = snapshot::load(&doc).unwrap().unwrap();
+        assert!(snap.contains("<!-- agent:status -->\nAgent status.\nUser status.\n<!-- /agent:status -->"));
+        assert!(!snap.contains("unsubmitted"));
+    }
+
+    #[test]
+    fn fence_outlasts_tildes() {
+        assert_eq!(fence("a\n"), "~~~~text\na\n~~~~");
+        assert_eq!(fence("~~~~~\n"), "~~~~~~text\n~~~~~\n~~~~~~");
+    }
+
+    #[test]
+    fn line_offset_counts_lines() {
+        assert_eq!(line_offset("a\nb\nc\n", 0), Some(0));
+        assert_eq!(line_offset("a\nb\nc\n", 2), Some(4));
+        assert_eq!(line_offset("a\n", 3), None);
+    }
+}
@@ -56,10 +56,10 @@ pub fn run(
     if format_change {
         match target_format {
             AgentDocFormat::Template => {
-                convert_to_template(file, &content, fm, body, &current, target_write)?;
+                convert_to_template(file, &content, body, &current, target_write)?;
             }
             AgentDocFormat::Append => {
-                convert_to_append(file, fm, body, &current, target_write)?;
+                convert_to_append(file, &content, body, &current, target_write)?;
             }
         }
     } else {
@@ -79,7 +79,6 @@ pub fn run(
 fn convert_to_template(
     file: &Path,
     content: &str,
-    fm: frontmatter::Frontmatter,
     body: &str,
     resolved: &frontmatter::ResolvedMode,
     target_write: AgentDocWrite,
@@ -92,13 +91,9 @@ fn convert_to_template(
         eprintln!("Mode is template but no component markers found, adding exchange component");
     }
 
-    let mut fm = fm;
-    fm.format = Some(AgentDocFormat::Template);
-    fm.write_mode = Some(target_write);
-    fm.mode = None; // clear deprecated field
-
     let exchange_content = append_to_template_body(body);
-    let new_doc = frontmatter::write(&fm, &exchange_content)?;
+    let updated = frontmatter::set_format_and_write(content, AgentDocFormat::Template, target_write)?;
+    let new_doc = frontmatter::replace_body(&updated, &exchange_content)?;
 
     write::atomic_write_pub(file, &new_doc)?;
     snapshot::save(file, &new_doc)?;
@@ -109,7 +104,7 @@ fn convert_to_template(
 
 fn convert_to_append(
     file: &Path,
-    fm: frontmatter::Frontmatter,
+    content: &str,
     body: &str,
     resolved: &frontmatter::ResolvedMode,
     target_write: AgentDocWrite,
@@ -118,13 +113,9 @@ fn convert_to_append(
         anyhow::bail!("{} is already in append mode", file.display());
     }
 
-    let mut fm = fm;
-    fm.format = Some(AgentDocFormat::Append);
-    fm.write_mode = Some(target_write);
-    fm.mode = None; // clear deprecated field
-
     let append_content = template_to_append_body(body);
-    let new_doc = frontmatter::write(&fm, &append_content)?;
+    let updated = frontmatter::set_format_and_write(content, AgentDocFormat::Append, target_write)?;
+    let new_doc = frontmatter::replace_body(&updated, &append_content)?;
 
     write::atomic_write_pub(file, &new_doc)?;
     snapshot::save(file, &new_doc)?;
@@ -1,8 +1,16 @@
 use anyhow::{Context, Result};
 use yrs::updates::decoder::Decode;
-use yrs::{Doc, GetString, ReadTxn, Text, TextRef, Transact, Update};
+use yrs::updates::encoder::Encode;
+use yrs::{Doc, GetString, Map, MapRef, Out, ReadTxn, StateVector, Text, TextPrelim, TextRef, Transact, Update};
+
+use crate::component::{self, Component};
+use crate::frontmatter::CrdtLayout;
 
 const TEXT_KEY: &str = "content";
+/// Skeleton text (document with top-level components emptied) in the component layout.
+const SKELETON_KEY: &str = "skeleton";
+/// Map of component name → component text in the component layout.
+const COMPONENTS_KEY: &str = "components";
 
 /// CRDT document wrapping a Yjs `Doc` for conflict-free merging.
 pub struct CrdtDoc {
@@ -20,13 +28,75 @@ impl CrdtDoc {
         CrdtDoc { doc }
     }
 
+    /// Create a CRDT document using the component layout: a `Y.Map` of
+    /// per-component texts plus a skeleton text. Falls back to the single-text
+    /// layout if the document can't be split into components.
+    pub fn from_components(content: &str) -> Self {
+        let Some(split) = ComponentSplit::parse(content) else {
+            return Self::from_text(content);
+        };
+        let doc = Doc::new();
+        let skeleton = doc.get_or_insert_text(SKELETON_KEY);
+        let components = doc.get_or_insert_map(COMPONENTS_KEY);
+        let mut txn = doc.transact_mut();
+        skeleton.insert(&mut txn, 0, &split.skeleton);
+        for (name, text) in &split.components {
+            components.insert(&mut txn, name.as_str(), TextPrelim::new(text.as_str()));
+        }
+        drop(txn);
+        CrdtDoc { doc }
+    }
+
+    /// Create a CRDT document with the given layout.
+    pub fn from_layout(content: &str, layout: CrdtLayout) -> Self {
+        match layout {
+            CrdtLayout::Text => Self::from_text(content),
+            CrdtLayout::Components => Self::from_components(content),
+        }
+    }
+
+    /// Create a CRDT document with the layout declared in the document's frontmatter.
+    pub fn for_document(content: &str) -> Self {
+        Self::from_layout(content, crate::frontmatter::crdt_layout(content))
+    }
+
     /// Extract the current text content from the CRDT document.
     pub fn to_text(&self) -> String {
+        if self.is_component_layout() {
+            return self.component_split().join();
+        }
         let text = self.doc.get_or_insert_text(TEXT_KEY);
         let txn = self.doc.transact();
         text.get_string(&txn)
     }
 
+    /// True if the document uses the component layout.
+    pub fn is_component_layout(&self) -> bool {
+        let text = self.doc.get_or_insert_text(TEXT_KEY);
+        let skeleton = self.doc.get_or_insert_text(SKELETON_KEY);
+        let components = self.doc.get_or_insert_map(COMPONENTS_KEY);
+        let txn = self.doc.transact();
+        text.len(&txn) == 0 && (skeleton.len(&txn) > 0 || components.len(&txn) > 0)
+    }
+
+    /// Read the skeleton and component texts of a component-layout document.
+    fn component_split(&self) -> ComponentSplit {
+        let skeleton = self.doc.get_or_insert_text(SKELETON_KEY);
+        let components = self.doc.get_or_insert_map(COMPONENTS_KEY);
+        let txn = self.doc.transact();
+        let components = components
+            .iter(&txn)
+            .filter_map(|(name, value)| match value {
+                Out::YText(text) => Some((name.to_string(), text.get_string(&txn))),
+                _ => None,
+            })
+            .collect();
+        ComponentSplit {
+            skeleton: skeleton.get_string(&txn),
+            components,
+        }
+    }
+
     /// Apply a local edit: delete `delete_len` chars at `offset`, then insert `insert` there.
     #[allow(dead_code)] // Used in tests and Phase 4 stream write-back
     pub fn apply_edit(&self, offset: u32, delete_len: u32, insert: &str) {
@@ -43,22 +113,164 @@ impl CrdtDoc {
     /// Encode the full document state (for persistence).
     pub fn encode_state(&self) -> Vec<u8> {
         let txn = self.doc.transact();
-        txn.encode_state_as_update_v1(&yrs::StateVector::default())
+        txn.encode_state_as_update_v1(&StateVector::default())
     }
 
     /// Decode a previously encoded state into a new CrdtDoc.
     pub fn decode_state(bytes: &[u8]) -> Result<Self> {
-        let doc = Doc::new();
+        let doc = CrdtDoc { doc: Doc::new() };
+        doc.apply_update(bytes)?;
+        Ok(doc)
+    }
+
+    /// Apply an encoded v1 update (full state or incremental) to this document.
+    pub fn apply_update(&self, bytes: &[u8]) -> Result<()> {
         let update = Update::decode_v1(bytes)
             .map_err(|e| anyhow::anyhow!("failed to decode CRDT state: {}", e))?;
-        let mut txn = doc.transact_mut();
+        let mut txn = self.doc.transact_mut();
         txn.apply_update(update)
             .map_err(|e| anyhow::anyhow!("failed to apply CRDT update: {}", e))?;
-        drop(txn);
-        Ok(CrdtDoc { doc })
+        Ok(())
+    }
+
+    /// Current state vector, used to encode incremental updates.
+    pub fn state_vector(&self) -> StateVector {
+        self.doc.transact().state_vector()
+    }
+
+    /// Encode only the changes made since `since` as a v1 update.
+    pub fn encode_diff(&self, since: &StateVector) -> Vec<u8> {
+        self.doc.transact().encode_state_as_update_v1(since)
+    }
+
+    /// Encode the state vector as v1 bytes (y-sync step 1 payload).
+    pub fn encode_state_vector(&self) -> Vec<u8> {
+        self.state_vector().encode_v1()
+    }
+
+    /// Encode the changes a peer with the given v1 state vector is missing
+    /// (y-sync step 2 payload).
+    pub fn encode_diff_v1(&self, state_vector: &[u8]) -> Result<Vec<u8>> {
+        let sv = StateVector::decode_v1(state_vector)
+            .map_err(|e| anyhow::anyhow!("failed to decode state vector: {}", e))?;
+        Ok(self.encode_diff(&sv))
+    }
+
+    /// Replace the text content with `content`, editing only the changed ranges
+    /// so the resulting update stays proportional to the change.
+    pub fn set_text(&self, content: &str) {
+        if self.is_component_layout() {
+            let current = self.component_split();
+            let skeleton = self.doc.get_or_insert_text(SKELETON_KEY);
+            let components = self.doc.get_or_insert_map(COMPONENTS_KEY);
+            let mut txn = self.doc.transact_mut();
+            match ComponentSplit::parse(content) {
+                Some(split) => {
+                    apply_ops(&skeleton, &mut txn, &compute_edit_ops(&current.skeleton, &split.skeleton));
+                    for (name, _) in &current.components {
+                        if split.get(name).is_none() {
+                            components.remove(&mut txn, name);
+                        }
+                    }
+                    for (name, text) in &split.components {
+                        match components.get(&txn, name) {
+                            Some(Out::YText(existing)) => {
+                                let old = existing.get_string(&txn);
+                                apply_ops(&existing, &mut txn, &compute_edit_ops(&old, text));
+                            }
+                            _ => {
+                                components.insert(&mut txn, name.as_str(), TextPrelim::new(text.as_str()));
+                            }
+                        }
+                    }
+                    return;
+                }
+                None => {
+                    // Markers no longer split cleanly — fall back to the single-text layout.
+                    let len = skeleton.len(&txn);
+                    skeleton.remove_range(&mut txn, 0, len);
+                    components.clear(&mut txn);
+                }
+            }
+        }
+        let ops = compute_edit_ops(&self.to_text(), content);
+        let text = self.doc.get_or_insert_text(TEXT_KEY);
+        let mut txn = self.doc.transact_mut();
+        apply_ops(&text, &mut txn, &ops);
     }
 }
 
+/// A document split into a skeleton and its top-level component contents.
+///
+/// The skeleton is the document with every top-level component emptied, so
+/// markers live only in the skeleton and edits inside a component can't
+/// interleave with its neighbours or duplicate markers.
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) struct ComponentSplit {
+    pub(crate) skeleton: String,
+    pub(crate) components: Vec<(String, String)>,
+}
+
+impl ComponentSplit {
+    /// Split a document. Returns `None` if the markers don't parse or a
+    /// top-level component name repeats.
+    pub(crate) fn parse(content: &str) -> Option<Self> {
+        let mut skeleton = String::with_capacity(content.len());
+        let mut components: Vec<(String, String)> = Vec::new();
+        let mut pos = 0;
+        for comp in top_level_components(content)? {
+            if components.iter().any(|(name, _)| *name == comp.name) {
+                return None;
+            }
+            skeleton.push_str(&content[pos..comp.open_end]);
+            components.push((comp.name.clone(), comp.content(content).to_string()));
+            pos = comp.close_start;
+        }
+        skeleton.push_str(&content[pos..]);
+        Some(ComponentSplit { skeleton, components })
+    }
+
+    /// Reassemble the document by filling each component in the skeleton.
+    pub(crate) fn join(&self) -> String {
+        let Some(slots) = top_level_components(&self.skeleton) else {
+            return self.skeleton.clone();
+        };
+        let mut result = String::with_capacity(self.skeleton.len());
+        let mut pos = 0;
+        for comp in slots {
+            result.push_str(&self.skeleton[pos..comp.open_end]);
+            result.push_str(self.get(&comp.name).unwrap_or(""));
+            pos = comp.close_start;
+        }
+        result.push_str(&self.skeleton[pos..]);
+        result
+    }
+
+    pub(crate) fn get(&self, name: &str) -> Option<&str> {
+        self.components
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, text)| text.as_str())
+    }
+
+    pub(crate) fn names(&self) -> Vec<&str> {
+        self.components.iter().map(|(n, _)| n.as_str()).collect()
+    }
+}
+
+/// Components not nested inside another component, in document order.
+fn top_level_components(content: &str) -> Option<Vec<Component>> {
+    let mut top = Vec::new();
+    let mut end = 0;
+    for comp in component::parse(content).ok()? {
+        if comp.open_start >= end {
+            end = comp.close_end;
+            top.push(comp);
+        }
+    }
+    Some(top)
+}
+
 /// Merge two concurrent text versions against a common base using CRDT.
 ///
 /// Creates three CRDT actors: base, ours, theirs.
@@ -217,6 +429,70 @@ pub fn merge(base_state: Option<&[u8]>, ours_text: &str, theirs_text: &str) -> R
     Ok(text.get_string(&txn))
 }
 
+/// Merge two concurrent versions component by component.
+///
+/// The skeleton and each top-level component are merged independently with
+/// [`merge`], so agent and user edits near component boundaries can't
+/// interleave and markers can't be duplicated. Falls back to a whole-document
+/// merge when base, ours and theirs don't share the same top-level components.
+pub fn merge_components(base_state: Option<&[u8]>, ours_text: &str, theirs_text: &str) -> Result<String> {
+    if ours_text == theirs_text {
+        eprintln!("[crdt] ours == theirs, skipping merge");
+        return Ok(ours_text.to_string());
+    }
+
+    let base_text = match base_state {
+        Some(bytes) => CrdtDoc::decode_state(bytes)
+            .context("failed to decode base CRDT state")?
+            .to_text(),
+        None => String::new(),
+    };
+
+    let splits = (
+        ComponentSplit::parse(&base_text),
+        ComponentSplit::parse(ours_text),
+        ComponentSplit::parse(theirs_text),
+    );
+    let (Some(base), Some(ours), Some(theirs)) = splits else {
+        eprintln!("[crdt] Component split failed, merging whole document");
+        return merge(base_state, ours_text, theirs_text);
+    };
+    if ours.names() != theirs.names() || ours.names() != base.names() {
+        eprintln!("[crdt] Component structure changed, merging whole document");
+        return merge(base_state, ours_text, theirs_text);
+    }
+
+    let skeleton = merge_part(&base.skeleton, &ours.skeleton, &theirs.skeleton)?;
+    if ComponentSplit::parse(&skeleton).is_none_or(|s| s.names() != ours.names()) {
+        eprintln!("[crdt] Skeleton merge broke component markers, merging whole document");
+        return merge(base_state, ours_text, theirs_text);
+    }
+
+    let mut components = Vec::with_capacity(ours.components.len());
+    for (((name, b), (_, o)), (_, t)) in base
+        .components
+        .iter()
+        .zip(&ours.components)
+        .zip(&theirs.components)
+    {
+        components.push((name.clone(), merge_part(b, o, t)?));
+    }
+
+    eprintln!("[crdt] Merged {} component(s) independently", components.len());
+    Ok(ComponentSplit { skeleton, components }.join())
+}
+
+/// Merge one part (skeleton or component), skipping the CRDT when only one side changed.
+fn merge_part(base: &str, ours: &str, theirs: &str) -> Result<String> {
+    if ours == theirs || theirs == base {
+        return Ok(ours.to_string());
+    }
+    if ours == base {
+        return Ok(theirs.to_string());
+    }
+    merge(Some(&CrdtDoc::from_text(base).encode_state()), ours, theirs)
+}
+
 /// Compact a CRDT state by re-encoding (GC tombstones where possible).
 pub fn compact(state: &[u8]) -> Result<Vec<u8>> {
     let doc = CrdtDoc::decode_state(state)?;
@@ -384,6 +660,109 @@ mod tests {
         assert!(merged.contains("User wrote this."));
     }
 
+    #[test]
+    fn set_text_encodes_incremental_diff() {
+        let base = "# Doc\n\n".to_string() + &"Existing paragraph.\n".repeat(50);
+        let doc = CrdtDoc::from_text(&base);
+        let checkpoint = doc.encode_state();
+        let sv = doc.state_vector();
+
+        let updated = format!("{}New line.\n", base);
+        doc.set_text(&updated);
+        let delta = doc.encode_diff(&sv);
+        assert!(delta.len() < checkpoint.len());
+
+        let restored = CrdtDoc::decode_state(&checkpoint).unwrap();
+        restored.apply_update(&delta).unwrap();
+        assert_eq!(restored.to_text(), updated);
+    }
+
+    const COMPONENT_DOC: &str = "# Doc\n\n<!-- agent:status -->\nIdle.\n<!-- /agent:status -->\n\n<!-- agent:exchange -->\nQuestion?\n<!-- /agent:exchange -->\n";
+
+    #[test]
+    fn component_split_roundtrip() {
+        let split = ComponentSplit::parse(COMPONENT_DOC).unwrap();
+        assert_eq!(split.names(), vec!["status", "exchange"]);
+        assert!(!split.skeleton.contains("Idle."));
+        assert_eq!(split.join(), COMPONENT_DOC);
+    }
+
+    #[test]
+    fn component_split_keeps_nested_in_parent() {
+        let doc = "<!-- agent:outer -->\n<!-- agent:inner -->\nx\n<!-- /agent:inner -->\n<!-- /agent:outer -->\n";
+        let split = ComponentSplit::parse(doc).unwrap();
+        assert_eq!(split.names(), vec!["outer"]);
+        assert_eq!(split.join(), doc);
+    }
+
+    #[test]
+    fn component_layout_roundtrip() {
+        let doc = CrdtDoc::from_components(COMPONENT_DOC);
+        assert!(doc.is_component_layout());
+        assert_eq!(doc.to_text(), COMPONENT_DOC);
+
+        let decoded = CrdtDoc::decode_state(&doc.encode_state()).unwrap();
+        assert!(decoded.is_component_layout());
+        assert_eq!(decoded.to_text(), COMPONENT_DOC);
+    }
+
+    #[test]
+    fn component_layout_falls_back_without_markers() {
+        let doc = CrdtDoc::from_components("plain text\n");
+        assert!(!doc.is_component_layout());
+        assert_eq!(doc.to_text(), "plain text\n");
+    }
+
+    #[test]
+    fn component_layout_set_text_updates_components() {
+        let doc = CrdtDoc::from_components(COMPONENT_DOC);
+        let updated = COMPONENT_DOC.replace("Idle.", "Working.");
+        doc.set_text(&updated);
+        assert!(doc.is_component_layout());
+        assert_eq!(doc.to_text(), updated);
+
+        let added = format!("{}<!-- agent:log -->\nentry\n<!-- /agent:log -->\n", updated);
+        doc.set_text(&added);
+        assert_eq!(doc.to_text(), added);
+    }
+
+    #[test]
+    fn merge_components_independent_edits() {
+        let base = CrdtDoc::from_components(COMPONENT_DOC).encode_state();
+        let ours = COMPONENT_DOC.replace("Question?\n", "Question?\n\nAnswer.\n");
+        let theirs = COMPONENT_DOC.replace("Idle.", "Busy.");
+
+        let merged = merge_components(Some(&base), &ours, &theirs).unwrap();
+        assert!(merged.contains("Answer."));
+        assert!(merged.contains("Busy."));
+        assert!(!merged.contains("Idle."));
+        assert_eq!(merged.matches("<!-- agent:exchange -->").count(), 1);
+    }
+
+    #[test]
+    fn merge_components_same_component() {
+        let base = CrdtDoc::from_components(COMPONENT_DOC).encode_state();
+        let ours = COMPONENT_DOC.replace("Question?\n", "Question?\n\nAgent answer.\n");
+        let theirs = COMPONENT_DOC.replace("Question?\n", "Question?\nMore detail.\n");
+
+        let merged = merge_components(Some(&base), &ours, &theirs).unwrap();
+        assert!(merged.contains("Agent answer."));
+        assert!(merged.contains("More detail."));
+        assert!(merged.contains("Idle."));
+        assert_eq!(merged.matches("<!-- /agent:exchange -->").count(), 1);
+    }
+
+    #[test]
+    fn merge_components_falls_back_on_structure_change() {
+        let base = CrdtDoc::from_components(COMPONENT_DOC).encode_state();
+        let ours = COMPONENT_DOC.replace("Question?\n", "Question?\nAnswer.\n");
+        let theirs = format!("{}\nUser note.\n", COMPONENT_DOC.replace("<!-- agent:status -->\nIdle.\n<!-- /agent:status -->\n", ""));
+
+        let merged = merge_components(Some(&base), &ours, &theirs).unwrap();
+        assert!(merged.contains("Answer."));
+        assert!(merged.contains("User note."));
+    }
+
     #[test]
     fn compact_preserves_content() {
         let doc = CrdtDoc::from_text("Hello");
@@ -0,0 +1,39 @@
+//! `agent-doc crdt-log` — Inspect a document's CRDT update log.
+//!
+//! Usage: agent-doc crdt-log <file.md> [--at N]
+//!
+//! Lists the updates appended since the last checkpoint. With `--at N`, prints
+//! the document text after the first N updates (`--at 0` is the checkpoint),
+//! for debugging how a document evolved between compactions.
+
+use anyhow::Result;
+use std::path::Path;
+
+use crate::snapshot;
+
+pub fn run(file: &Path, at: Option<usize>) -> Result<()> {
+    if !file.exists() {
+        anyhow::bail!("file not found: {}", file.display());
+    }
+
+    if let Some(n) = at {
+        match snapshot::crdt_text_at(file, n)? {
+            Some(text) => print!("{}", text),
+            None => eprintln!("[crdt-log] No CRDT state for {}", file.display()),
+        }
+        return Ok(());
+    }
+
+    let checkpoint = snapshot::crdt_path_for(file)?;
+    let entries = snapshot::crdt_log(file)?;
+    if !checkpoint.exists() && entries.is_empty() {
+        eprintln!("[crdt-log] No CRDT state for {}", file.display());
+        return Ok(());
+    }
+    let size = std::fs::metadata(&checkpoint).map(|m| m.len()).unwrap_or(0);
+    println!("checkpoint  {} bytes", size);
+    for (i, entry) in entries.iter().enumerate() {
+        println!("{:>10}  {}  {} bytes", i + 1, entry.timestamp_ms, entry.update.len());
+    }
+    Ok(())
+}
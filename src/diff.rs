@@ -2,7 +2,7 @@ use anyhow::Result;
 use similar::{ChangeTag, TextDiff};
 use std::path::Path;
 
-use crate::{component, snapshot};
+use crate::{component, frontmatter, generate, snapshot};
 
 /// Strip comments from document content for diff comparison.
 ///
@@ -107,6 +107,20 @@ fn match_html_comment(content: &str, pos: usize) -> Option<(usize, &str)> {
     None
 }
 
+/// Document content as compared for the submit diff: comments stripped, and
+/// generated components (toc, budget, ...) and the conflicts summary emptied,
+/// since they change on every write and never count as user edits.
+fn strip_for_diff(content: &str) -> String {
+    let mut stripped = generate::strip_generated(content);
+    if let Some(name) = frontmatter::conflicts_component(&stripped)
+        && let Ok(components) = component::parse(&stripped)
+        && let Some(comp) = components.iter().find(|c| c.name == name)
+    {
+        stripped = comp.replace_content(&stripped, "");
+    }
+    strip_comments(&stripped)
+}
+
 /// Compute a unified diff between the snapshot and the current document.
 /// Returns None if there are no changes.
 ///
@@ -126,8 +140,8 @@ pub fn compute(doc: &Path) -> Result<Option<String>> {
         previous.len(),
     );
 
-    let current_stripped = strip_comments(&current);
-    let previous_stripped = strip_comments(&previous);
+    let current_stripped = strip_for_diff(&current);
+    let previous_stripped = strip_for_diff(&previous);
 
     eprintln!(
         "[diff] stripped: doc_len={} snap_len={}",
@@ -296,8 +310,8 @@ fn truncate_for_log(s: &str, max: usize) -> String {
 ///
 /// Returns `false` if there is any new user content that needs a response.
 pub fn is_stale_snapshot(snapshot_content: &str, document_content: &str) -> bool {
-    let snap_stripped = strip_comments(snapshot_content);
-    let doc_stripped = strip_comments(document_content);
+    let snap_stripped = strip_for_diff(snapshot_content);
+    let doc_stripped = strip_for_diff(document_content);
 
     // Document must be longer than snapshot
     if doc_stripped.len() <= snap_stripped.len() {
@@ -468,6 +482,18 @@ mod tests {
         assert_eq!(strip_comments(""), "");
     }
 
+    #[test]
+    fn conflicts_summary_not_a_user_edit() {
+        let snapshot = "# Doc\n\n<!-- agent:conflicts -->\n<!-- /agent:conflicts -->\n";
+        let document = "# Doc\n\n<!-- agent:conflicts -->\n**1 unresolved merge conflict(s).**\n<!-- /agent:conflicts -->\n";
+        assert_eq!(strip_for_diff(snapshot), strip_for_diff(document));
+
+        let renamed = "---\nagent_doc_conflicts: issues\n---\n<!-- agent:issues -->\nsummary\n<!-- /agent:issues -->\n<!-- agent:conflicts -->\nkept\n<!-- /agent:conflicts -->\n";
+        let stripped = strip_for_diff(renamed);
+        assert!(!stripped.contains("summary"));
+        assert!(stripped.contains("kept"));
+    }
+
     // --- Stale snapshot detection tests ---
 
     #[test]
@@ -0,0 +1,490 @@
+//! `agent-doc doctor` — Consistency checker for `.agent-doc/` state.
+//!
+//! Checks each session document for snapshot drift, corrupt or mismatched
+//! CRDT state, leftover pending responses and invalid component markers, plus
+//! project-level problems: stale locks and registry entries pointing at
+//! missing files or dead panes. With `--fix`, repairs what can be repaired.
+
+use anyhow::{Context, Result};
+use fs2::FileExt;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use crate::crdt::CrdtDoc;
+use crate::sessions::{self, RegistryLock, Tmux};
+use crate::{component, diff, frontmatter, gc, recover, snapshot, watch};
+
+const WATCH_PID_FILE: &str = ".agent-doc/watch.pid";
+
+/// A detected problem and how to repair it.
+#[derive(Debug)]
+pub struct Problem {
+    /// Document the problem belongs to (None for project-level problems).
+    pub file: Option<PathBuf>,
+    /// Stable identifier for the kind of problem (e.g. `snapshot-drift`).
+    pub kind: &'static str,
+    pub message: String,
+    pub fix: Option<Fix>,
+}
+
+/// Repair action for a problem.
+#[derive(Debug)]
+pub enum Fix {
+    /// Save the current document as the snapshot.
+    SyncSnapshot,
+    /// Rebuild CRDT state from the snapshot (or document if no snapshot).
+    RebuildCrdt,
+    /// Apply the pending response via `recover`.
+    RecoverPending,
+    /// Delete a file.
+    RemoveFile(PathBuf),
+    /// Remove a session from `sessions.json`.
+    RemoveRegistryEntry(String),
+}
+
+/// Run the doctor command.
+///
+/// Checks the given files, or every session document under the project root
+/// when `files` is empty. Returns an error if unresolved problems remain.
+pub fn run(files: &[PathBuf], fix: bool) -> Result<()> {
+    let cwd = std::env::current_dir()?;
+    let root = snapshot::find_project_root(&cwd);
+
+    let docs = if files.is_empty() {
+        let root = root
+            .as_ref()
+            .ok_or_else(|| anyhow::anyhow!("no .agent-doc/ directory found from {}", cwd.display()))?;
+        session_documents(root)?
+    } else {
+        files.to_vec()
+    };
+
+    let mut problems = Vec::new();
+    for doc in &docs {
+        problems.extend(check_document(doc)?);
+    }
+    problems.extend(check_duplicate_sessions(&docs));
+    if let Some(root) = &root {
+        problems.extend(check_project(root)?);
+    }
+
+    if problems.is_empty() {
+        eprintln!("[doctor] No problems found ({} document(s) checked)", docs.len());
+        return Ok(());
+    }
+
+    let mut unresolved = 0usize;
+    for problem in &problems {
+        let label = problem
+            .file
+            .as_ref()
+            .map(|f| f.display().to_string())
+            .unwrap_or_else(|| ".agent-doc".to_string());
+        let status = match (&problem.fix, fix) {
+            (Some(f), true) => match apply_fix(problem.file.as_deref(), f) {
+                Ok(()) => "fixed".to_string(),
+                Err(e) => {
+                    unresolved += 1;
+                    format!("fix failed: {:#}", e)
+                }
+            },
+            (Some(_), false) => {
+                unresolved += 1;
+                "fixable with --fix".to_string()
+            }
+            (None, _) => {
+                unresolved += 1;
+                "manual fix required".to_string()
+            }
+        };
+        println!("{}: [{}] {} ({})", label, problem.kind, problem.message, status);
+    }
+
+    if unresolved > 0 {
+        anyhow::bail!("{} problem(s) found, {} unresolved", problems.len(), unresolved);
+    }
+    eprintln!("[doctor] Fixed {} problem(s)", problems.len());
+    Ok(())
+}
+
+/// Find session documents: markdown files under `root` with an `agent_doc_session`.
+/// Hidden directories are included, as in gc's live-state scan.
+fn session_documents(root: &Path) -> Result<Vec<PathBuf>> {
+    let mut candidates = Vec::new();
+    gc::walk_markdown(root, &mut candidates, false)?;
+    let mut docs: Vec<PathBuf> = candidates
+        .into_iter()
+        .filter(|path| {
+            std::fs::read_to_string(path)
+                .ok()
+                .and_then(|c| frontmatter::parse(&c).ok().map(|(fm, _)| fm.session.is_some()))
+                .unwrap_or(false)
+        })
+        .collect();
+    docs.sort();
+    Ok(docs)
+}
+
+/// Check a single document's sidecar state.
+pub fn check_document(doc: &Path) -> Result<Vec<Problem>> {
+    let mut problems = Vec::new();
+    let problem = |kind, message: String, fix| Problem {
+        file: Some(doc.to_path_buf()),
+        kind,
+        message,
+        fix,
+    };
+
+    if !doc.exists() {
+        problems.push(problem("missing-file", "document not found".to_string(), None));
+        return Ok(problems);
+    }
+    let content = std::fs::read_to_string(doc)
+        .with_context(|| format!("failed to read {}", doc.display()))?;
+
+    if let Err(e) = frontmatter::parse(&content) {
+        problems.push(problem("invalid-frontmatter", format!("{:#}", e), None));
+    }
+    if let Err(e) = component::parse(&content) {
+        problems.push(problem("invalid-markers", format!("{:#}", e), None));
+    }
+
+    let snap = snapshot::load(doc)?;
+    if let Some(ref snap) = snap
+        && diff::is_stale_snapshot(snap, &content)
+    {
+        problems.push(problem(
+            "snapshot-drift",
+            "snapshot is behind the document (response written but snapshot not updated)".to_string(),
+            Some(Fix::SyncSnapshot),
+        ));
+    }
+
+    let crdt = snapshot::load_crdt(doc).and_then(|state| state.map(|s| CrdtDoc::decode_state(&s)).transpose());
+    match crdt {
+        Err(e) => problems.push(problem(
+            "crdt-corrupt",
+            format!("CRDT state cannot be decoded: {:#}", e),
+            Some(Fix::RebuildCrdt),
+        )),
+        Ok(None) => {}
+        Ok(Some(crdt)) => {
+            // The CRDT state holds the merged result of the last write, so
+            // it may legitimately match the document instead of the snapshot.
+            let text = crdt.to_text();
+            if let Some(ref snap) = snap
+                && text != *snap
+                && text != content
+            {
+                problems.push(problem(
+                    "crdt-mismatch",
+                    "CRDT state matches neither the snapshot nor the document".to_string(),
+                    Some(Fix::RebuildCrdt),
+                ));
+            }
+        }
+    }
+
+    let pending = snapshot::pending_path_for(doc)?;
+    if pending.exists() {
+        let response = std::fs::read_to_string(&pending).unwrap_or_default();
+        if response.trim().is_empty() {
+            problems.push(problem(
+                "pending-response",
+                "empty pending response file".to_string(),
+                Some(Fix::RemoveFile(pending)),
+            ));
+        } else {
+            problems.push(problem(
+                "pending-response",
+                format!("pending response was never written back ({} bytes)", response.len()),
+                Some(Fix::RecoverPending),
+            ));
+        }
+    }
+
+    Ok(problems)
+}
+
+/// Find documents that share an `agent_doc_session`. Sidecar state is keyed
+/// by session ID, so such documents would overwrite each other's snapshots.
+pub fn check_duplicate_sessions(docs: &[PathBuf]) -> Vec<Problem> {
+    let mut by_session: BTreeMap<String, Vec<&PathBuf>> = BTreeMap::new();
+    for doc in docs {
+        let session = std::fs::read_to_string(doc)
+            .ok()
+            .and_then(|c| frontmatter::parse(&c).ok().and_then(|(fm, _)| fm.session))
+            .filter(|s| !s.trim().is_empty());
+        if let Some(session) = session {
+            by_session.entry(session).or_default().push(doc);
+        }
+    }
+    by_session
+        .into_iter()
+        .filter(|(_, docs)| docs.len() > 1)
+        .map(|(session, docs)| Problem {
+            file: Some(docs[0].clone()),
+            kind: "duplicate-session",
+            message: format!(
+                "session {} is also used by {}",
+                session,
+                docs[1..].iter().map(|d| d.display().to_string()).collect::<Vec<_>>().join(", ")
+            ),
+            fix: None,
+        })
+        .collect()
+}
+
+/// Check project-level state: orphaned locks, watch PID file and the session registry.
+pub fn check_project(root: &Path) -> Result<Vec<Problem>> {
+    let mut problems = Vec::new();
+
+    for orphan in gc::collect(root, None)? {
+        // Advisory locks die with their process, so a lock that can't be
+        // taken still has a live owner and isn't stale yet.
+        let is_lock = orphan.path.extension().is_some_and(|e| e == "lock");
+        if is_lock && !lock_held(&orphan.path) {
+            problems.push(Problem {
+                file: None,
+                kind: "stale-lock",
+                message: format!("lock file {} has no live document", orphan.path.display()),
+                fix: Some(Fix::RemoveFile(orphan.path)),
+            });
+        }
+    }
+
+    let pid_file = root.join(WATCH_PID_FILE);
+    if let Ok(pid) = std::fs::read_to_string(&pid_file)
+        && let Ok(pid) = pid.trim().parse::<u32>()
+        && !watch::pid_alive(pid)
+    {
+        problems.push(Problem {
+            file: None,
+            kind: "stale-lock",
+            message: format!("watch.pid points at dead process {}", pid),
+            fix: Some(Fix::RemoveFile(pid_file)),
+        });
+    }
+
+    let registry = sessions::load().unwrap_or_default();
+    let tmux = Tmux::default_server();
+    for (session_id, entry) in &registry {
+        if !entry.file.is_empty() {
+            let path = PathBuf::from(&entry.file);
+            let path = if path.is_relative() && !entry.cwd.is_empty() {
+                Path::new(&entry.cwd).join(path)
+            } else {
+                path
+            };
+            if !path.exists() {
+                problems.push(Problem {
+                    file: None,
+                    kind: "registry-missing-file",
+                    message: format!("session {} points at missing file {}", session_id, entry.file),
+                    fix: Some(Fix::RemoveRegistryEntry(session_id.clone())),
+                });
+                continue;
+            }
+        }
+        if !entry.pane.is_empty() && !tmux.pane_alive(&entry.pane) {
+            problems.push(Problem {
+                file: None,
+                kind: "registry-dead-pane",
+                message: format!("session {} points at dead pane {}", session_id, entry.pane),
+                fix: Some(Fix::RemoveRegistryEntry(session_id.clone())),
+            });
+        }
+    }
+
+    Ok(problems)
+}
+
+/// Whether another process holds the advisory lock on `path`.
+fn lock_held(path: &Path) -> bool {
+    std::fs::File::open(path).is_ok_and(|file| file.try_lock_exclusive().is_err())
+}
+
+/// Apply a fix for a problem.
+fn apply_fix(doc: Option<&Path>, fix: &Fix) -> Result<()> {
+    let doc_required = || doc.ok_or_else(|| anyhow::anyhow!("fix requires a document"));
+    match fix {
+        Fix::SyncSnapshot => {
+            let doc = doc_required()?;
+            let content = std::fs::read_to_string(doc)?;
+            snapshot::save(doc, &content)
+        }
+        Fix::RebuildCrdt => {
+            let doc = doc_required()?;
+            let base = match snapshot::load(doc)? {
+                Some(snap) => snap,
+                None => std::fs::read_to_string(doc)?,
+            };
+            snapshot::save_crdt_text(doc, &base)
+        }
+        Fix::RecoverPending => {
+            recover::run(doc_required()?)?;
+            Ok(())
+        }
+        Fix::RemoveFile(path) => {
+            std::fs::remove_file(path).with_context(|| format!("failed to remove {}", path.display()))
+        }
+        Fix::RemoveRegistryEntry(session_id) => {
+            let _lock = RegistryLock::acquire(&sessions::registry_path())?;
+            let mut registry = sessions::load()?;
+            registry.remove(session_id);
+            sessions::save(&registry)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+
+    fn setup_project() -> (TempDir, PathBuf) {
+        let dir = TempDir::new().unwrap();
+        fs::create_dir_all(dir.path().join(".agent-doc")).unwrap();
+        let doc = dir.path().join("session.md");
+        fs::write(&doc, "---\nagent_doc_session: doc-1\n---\n# Doc\n").unwrap();
+        (dir, doc)
+    }
+
+    fn kinds(problems: &[Problem]) -> Vec<&'static str> {
+        problems.iter().map(|p| p.kind).collect()
+    }
+
+    #[test]
+    fn clean_document_has_no_problems() {
+        let (_dir, doc) = setup_project();
+        let content = fs::read_to_string(&doc).unwrap();
+        snapshot::save(&doc, &content).unwrap();
+        snapshot::save_crdt_text(&doc, &content).unwrap();
+
+        assert!(check_document(&doc).unwrap().is_empty());
+    }
+
+    #[test]
+    fn detects_and_fixes_snapshot_drift() {
+        let (_dir, doc) = setup_project();
+        let snap = "---\nagent_doc_session: doc-1\n---\n# Doc\n\n## User\n\nQuestion?\n";
+        let current = format!("{}\n## Assistant\n\nAnswer.\n\n## User\n\n", snap);
+        fs::write(&doc, &current).unwrap();
+        snapshot::save(&doc, snap).unwrap();
+
+        let problems = check_document(&doc).unwrap();
+        assert_eq!(kinds(&problems), vec!["snapshot-drift"]);
+
+        apply_fix(Some(&doc), problems[0].fix.as_ref().unwrap()).unwrap();
+        assert_eq!(snapshot::load(&doc).unwrap().as_deref(), Some(current.as_str()));
+        assert!(check_document(&doc).unwrap().is_empty());
+    }
+
+    #[test]
+    fn detects_and_fixes_corrupt_crdt() {
+        let (_dir, doc) = setup_project();
+        let checkpoint = snapshot::crdt_path_for(&doc).unwrap();
+        fs::create_dir_all(checkpoint.parent().unwrap()).unwrap();
+        fs::write(&checkpoint, [0xff, 0xfe, 0xfd]).unwrap();
+
+        let problems = check_document(&doc).unwrap();
+        assert_eq!(kinds(&problems), vec!["crdt-corrupt"]);
+
+        apply_fix(Some(&doc), problems[0].fix.as_ref().unwrap()).unwrap();
+        let state = snapshot::load_crdt(&doc).unwrap().unwrap();
+        assert_eq!(CrdtDoc::decode_state(&state).unwrap().to_text(), fs::read_to_string(&doc).unwrap());
+    }
+
+    #[test]
+    fn detects_crdt_mismatch() {
+        let (_dir, doc) = setup_project();
+        let content = fs::read_to_string(&doc).unwrap();
+        snapshot::save(&doc, &content).unwrap();
+        snapshot::save_crdt_text(&doc, "something else").unwrap();
+
+        assert_eq!(kinds(&check_document(&doc).unwrap()), vec!["crdt-mismatch"]);
+    }
+
+    #[test]
+    fn detects_pending_response() {
+        let (_dir, doc) = setup_project();
+        recover::save_pending(&doc, "orphaned response").unwrap();
+
+        let problems = check_document(&doc).unwrap();
+        assert_eq!(kinds(&problems), vec!["pending-response"]);
+        assert!(matches!(problems[0].fix, Some(Fix::RecoverPending)));
+    }
+
+    #[test]
+    fn detects_invalid_markers() {
+        let (_dir, doc) = setup_project();
+        fs::write(&doc, "---\nagent_doc_session: doc-1\n---\n<!-- agent:open -->\nno close\n").unwrap();
+
+        let problems = check_document(&doc).unwrap();
+        assert_eq!(kinds(&problems), vec!["invalid-markers"]);
+        assert!(problems[0].fix.is_none());
+    }
+
+    #[test]
+    fn detects_orphaned_lock_files() {
+        let (dir, _doc) = setup_project();
+        let locks = dir.path().join(".agent-doc/locks");
+        fs::create_dir_all(&locks).unwrap();
+        let orphan = locks.join(format!("{}.lock", "0".repeat(64)));
+        fs::write(&orphan, "").unwrap();
+
+        let problems: Vec<Problem> = check_project(dir.path())
+            .unwrap()
+            .into_iter()
+            .filter(|p| p.kind == "stale-lock")
+            .collect();
+        assert_eq!(problems.len(), 1);
+        apply_fix(None, problems[0].fix.as_ref().unwrap()).unwrap();
+        assert!(!orphan.exists());
+    }
+
+    #[test]
+    fn held_orphaned_lock_is_not_stale() {
+        let (dir, _doc) = setup_project();
+        let locks = dir.path().join(".agent-doc/locks");
+        fs::create_dir_all(&locks).unwrap();
+        let orphan = locks.join(format!("{}.lock", "0".repeat(64)));
+        fs::write(&orphan, "").unwrap();
+        let held = fs::File::open(&orphan).unwrap();
+        held.lock_exclusive().unwrap();
+
+        let problems = check_project(dir.path()).unwrap();
+        assert!(!kinds(&problems).contains(&"stale-lock"));
+    }
+
+    #[test]
+    fn detects_duplicate_session_ids() {
+        let (dir, doc) = setup_project();
+        let copy = dir.path().join("copy.md");
+        fs::copy(&doc, &copy).unwrap();
+        let other = dir.path().join("other.md");
+        fs::write(&other, "---\nagent_doc_session: doc-2\n---\n# Other\n").unwrap();
+
+        let problems = check_duplicate_sessions(&[copy.clone(), doc.clone(), other]);
+        assert_eq!(kinds(&problems), vec!["duplicate-session"]);
+        assert_eq!(problems[0].file.as_deref(), Some(copy.as_path()));
+        assert!(problems[0].message.contains("doc-1"));
+        assert!(problems[0].message.contains(&doc.display().to_string()));
+    }
+
+    #[test]
+    fn session_documents_requires_session_id() {
+        let (dir, doc) = setup_project();
+        fs::write(dir.path().join("plain.md"), "# Not a session\n").unwrap();
+
+        let docs = session_documents(dir.path()).unwrap();
+        assert_eq!(docs, vec![doc.clone()]);
+
+        fs::create_dir_all(dir.path().join(".notes")).unwrap();
+        let hidden = dir.path().join(".notes/hidden.md");
+        fs::write(&hidden, "---\nagent_doc_session: hidden-session\n---\n").unwrap();
+        let docs = session_documents(dir.path()).unwrap();
+        assert_eq!(docs, vec![hidden, doc]);
+    }
+}
@@ -0,0 +1,417 @@
+//! Shell-backed components: `<!-- agent:tests run="cargo test 2>&1 | tail -20" -->`.
+//!
+//! Before `submit` and `stream` build the prompt, each component with a `run`
+//! attribute is replaced with the output of its command, so the agent sees
+//! fresh output and the user's pre-submit commit records it.
+//!
+//! Commands only run if they appear in the project's allow-list,
+//! `.agent-doc/run.toml`:
+//!
+//! ```toml
+//! allow = ["cargo test 2>&1 | tail -20", "git status *"]
+//! timeout = 10       # seconds (default 10)
+//! max_bytes = 4096   # output cap (default 4096)
+//! ```
+//!
+//! An entry ending in `*` matches any command with that prefix whose remainder
+//! has no shell metacharacters (`;`, `&`, `|`, `<`, `>`, `$`, `(`, `)`, `\`,
+//! backticks or newlines); other entries must match exactly. Commands run with
+//! `sh -c` in the project root, in their own process group, with `COMPONENT`
+//! and `FILE` set and stderr merged into stdout. `timeout=`, `max_bytes=` and
+//! `fence=false` on the marker override the defaults per component.
+//!
+//! Agents can't add commands: `run`, `timeout` and `max_bytes` are stripped
+//! from markers in agent responses (see [`strip_run_attrs`]).
+
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::io::Read;
+use std::path::Path;
+use std::process::{Child, Command, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::{component, snapshot};
+
+const RUN_CONFIG_FILENAME: &str = ".agent-doc/run.toml";
+const DEFAULT_TIMEOUT_SECS: u64 = 10;
+const DEFAULT_MAX_BYTES: usize = 4096;
+/// Marker attributes only the user may set.
+const RUN_ATTRS: [&str; 3] = ["run", "timeout", "max_bytes"];
+/// Characters that would let a command run more than the allow-listed prefix.
+const SHELL_METACHARS: &[char] = &[';', '&', '|', '<', '>', '`', '$', '(', ')', '\\', '\n', '\r'];
+
+/// `.agent-doc/run.toml`.
+#[derive(Debug, Default, Deserialize)]
+struct RunConfig {
+    #[serde(default)]
+    allow: Vec<String>,
+    timeout: Option<u64>,
+    max_bytes: Option<usize>,
+}
+
+impl RunConfig {
+    fn allows(&self, command: &str) -> bool {
+        self.allow.iter().any(|entry| match entry.strip_suffix('*') {
+            Some(prefix) => command
+                .strip_prefix(prefix)
+                .is_some_and(|rest| !rest.contains(SHELL_METACHARS)),
+            None => entry == command,
+        })
+    }
+}
+
+/// Refresh every `run=` component in `file`. Returns true if the document changed.
+pub fn refresh(file: &Path) -> Result<bool> {
+    let doc = std::fs::read_to_string(file)
+        .with_context(|| format!("failed to read {}", file.display()))?;
+    let components = component::parse(&doc)?;
+    if !components.iter().any(|c| c.attrs.contains_key("run")) {
+        return Ok(false);
+    }
+
+    let canonical = file.canonicalize()?;
+    let root = snapshot::find_project_root(&canonical);
+    let config = match &root {
+        Some(root) => load_config(root)?,
+        None => RunConfig::default(),
+    };
+    let cwd = root.as_deref().or(canonical.parent()).unwrap_or(Path::new("."));
+
+    // Run the commands without holding the document lock
+    let mut outputs: Vec<(String, String, String)> = Vec::new();
+    for comp in &components {
+        let Some(command) = comp.attrs.get("run") else {
+            continue;
+        };
+        if !config.allows(command) {
+            eprintln!(
+                "[run] component '{}': command not in {} allow-list, skipped: {}",
+                comp.name, RUN_CONFIG_FILENAME, command
+            );
+            continue;
+        }
+        let timeout = match comp.attrs.get("timeout") {
+            Some(v) => v.parse().with_context(|| format!("invalid timeout '{}' on <!-- agent:{} -->", v, comp.name))?,
+            None => config.timeout.unwrap_or(DEFAULT_TIMEOUT_SECS),
+        };
+        let max_bytes = match comp.attrs.get("max_bytes") {
+            Some(v) => v.parse().with_context(|| format!("invalid max_bytes '{}' on <!-- agent:{} -->", v, comp.name))?,
+            None => config.max_bytes.unwrap_or(DEFAULT_MAX_BYTES),
+        };
+        let output = run_command(command, &comp.name, file, cwd, Duration::from_secs(timeout))?;
+        let output = cap(&output, max_bytes);
+        let fenced = comp.attrs.get("fence").is_none_or(|v| v != "false");
+        let content = if fenced { fence(&output) } else { output };
+        eprintln!("[run] refreshed component '{}' ({} bytes)", comp.name, content.len());
+        outputs.push((comp.name.clone(), command.clone(), content));
+    }
+    if outputs.is_empty() {
+        return Ok(false);
+    }
+
+    // Apply to the current document, in case it was edited while the commands ran
+    let _lock = crate::write::acquire_doc_lock(file)?;
+    let doc = std::fs::read_to_string(file)
+        .with_context(|| format!("failed to read {}", file.display()))?;
+    let mut result = doc.clone();
+    // Last first, so earlier offsets stay valid
+    for comp in component::parse(&doc)?.iter().rev() {
+        let output = outputs
+            .iter()
+            .find(|(name, command, _)| *name == comp.name && comp.attrs.get("run") == Some(command));
+        if let Some((_, _, content)) = output {
+            result = comp.replace_content(&result, content);
+        }
+    }
+
+    if result == doc {
+        return Ok(false);
+    }
+    crate::write::atomic_write_pub(file, &result)?;
+    Ok(true)
+}
+
+/// Remove `run`, `timeout` and `max_bytes` from component markers in an
+/// agent response, so a response can't add or change shell commands.
+/// Markers in code are left alone.
+pub fn strip_run_attrs(text: &str) -> String {
+    let code_ranges = component::find_code_ranges(text);
+    let mut result = String::with_capacity(text.len());
+    let mut last = 0;
+    let mut pos = 0;
+    while let Some(start) = text[pos..].find("<!--").map(|i| pos + i) {
+        let Some(end) = text[start + 4..].find("-->").map(|i| start + 4 + i + 3) else {
+            break;
+        };
+        pos = end;
+        if code_ranges.iter().any(|&(s, e)| start >= s && start < e) {
+            continue;
+        }
+        let Some(rest) = text[start + 4..end - 3].trim().strip_prefix("agent:") else {
+            continue;
+        };
+        let (name, attr_text) = component::split_name(rest);
+        let Ok(mut attrs) = component::parse_attrs(attr_text) else {
+            continue;
+        };
+        let stripped: Vec<&str> = RUN_ATTRS.into_iter().filter(|k| attrs.remove(*k).is_some()).collect();
+        if stripped.is_empty() {
+            continue;
+        }
+        eprintln!(
+            "[run] ignoring {} on <!-- agent:{} --> in agent response",
+            stripped.join(", "),
+            name
+        );
+        result.push_str(&text[last..start]);
+        result.push_str("<!-- agent:");
+        result.push_str(name);
+        for (key, value) in &attrs {
+            if value == "true" {
+                result.push_str(&format!(" {}", key));
+            } else if value.is_empty() || value.contains(char::is_whitespace) {
+                result.push_str(&format!(" {}=\"{}\"", key, value));
+            } else {
+                result.push_str(&format!(" {}={}", key, value));
+            }
+        }
+        result.push_str(" -->");
+        last = end;
+    }
+    result.push_str(&text[last..]);
+    result
+}
+
+fn load_config(root: &Path) -> Result<RunConfig> {
+    let path = root.join(RUN_CONFIG_FILENAME);
+    if !path.exists() {
+        return Ok(RunConfig::default());
+    }
+    let content = std::fs::read_to_string(&path)
+        .with_context(|| format!("failed to read {}", path.display()))?;
+    toml::from_str(&content).with_context(|| format!("failed to parse {}", path.display()))
+}
+
+/// Run `command`, returning its output. A non-zero exit or timeout is noted
+/// at the end of the output rather than failing the submit.
+fn run_command(command: &str, component: &str, file: &Path, cwd: &Path, timeout: Duration) -> Result<String> {
+    let mut child = in_own_group(Command::new("sh").args(["-c", &format!("exec 2>&1\n{}", command)]))
+        .current_dir(cwd)
+        .env("COMPONENT", component)
+        .env("FILE", file.to_string_lossy().as_ref())
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .with_context(|| format!("failed to run command for component '{}': {}", component, command))?;
+
+    // Read on a thread: a command can outlive `sh` (pipelines, background
+    // jobs), so the pipe may stay open until the process group is killed
+    let buffer = Arc::new(Mutex::new(Vec::new()));
+    let drained = Arc::new(AtomicBool::new(false));
+    if let Some(mut stdout) = child.stdout.take() {
+        let buffer = Arc::clone(&buffer);
+        let drained = Arc::clone(&drained);
+        std::thread::spawn(move || {
+            let mut chunk = [0u8; 4096];
+            while let Ok(n) = stdout.read(&mut chunk) {
+                if n == 0 {
+                    break;
+                }
+                buffer.lock().unwrap().extend_from_slice(&chunk[..n]);
+            }
+            drained.store(true, Ordering::SeqCst);
+        });
+    }
+
+    let deadline = Instant::now() + timeout;
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break Some(status);
+        }
+        if Instant::now() >= deadline {
+            kill_group(&mut child);
+            break None;
+        }
+        std::thread::sleep(Duration::from_millis(20));
+    };
+    // Give the reader a moment to drain the pipe
+    let grace = Instant::now() + Duration::from_millis(500);
+    while !drained.load(Ordering::SeqCst) && Instant::now() < grace {
+        std::thread::sleep(Duration::from_millis(10));
+    }
+
+    let mut output = String::from_utf8_lossy(&buffer.lock().unwrap()).into_owned();
+    let note = match status {
+        None => {
+            eprintln!("[run] component '{}' timed out after {}s", component, timeout.as_secs());
+            Some(format!("[timed out after {}s]", timeout.as_secs()))
+        }
+        Some(status) if !status.success() => Some(format!("[exit status {}]", status.code().unwrap_or(-1))),
+        Some(_) => None,
+    };
+    if let Some(note) = note {
+        if !output.is_empty() && !output.ends_with('\n') {
+            output.push('\n');
+        }
+        output.push_str(&note);
+        output.push('\n');
+    }
+    Ok(output)
+}
+
+/// Make `command` the leader of a new process group (on Unix), so
+/// [`kill_group`] also reaches the processes it starts.
+pub(crate) fn in_own_group(command: &mut Command) -> &mut Command {
+    #[cfg(unix)]
+    std::os::unix::process::CommandExt::process_group(command, 0);
+    command
+}
+
+/// Kill a child started with [`in_own_group`] along with its process group, and reap it.
+pub(crate) fn kill_group(child: &mut Child) {
+    #[cfg(unix)]
+    let _ = Command::new("kill")
+        .args(["-KILL", "--", &format!("-{}", child.id())])
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status();
+    let _ = child.kill();
+    let _ = child.wait();
+}
+
+/// Keep at most `max_bytes` of output (on a char boundary), noting what was cut.
+fn cap(output: &str, max_bytes: usize) -> String {
+    if output.len() <= max_bytes {
+        return output.to_string();
+    }
+    let mut end = max_bytes;
+    while !output.is_char_boundary(end) {
+        end -= 1;
+    }
+    let mut capped = output[..end].to_string();
+    if !capped.ends_with('\n') {
+        capped.push('\n');
+    }
+    capped.push_str(&format!("[truncated {} bytes]\n", output.len() - end));
+    capped
+}
+
+/// Wrap output in a code fence longer than any backtick run inside it.
+fn fence(output: &str) -> String {
+    let longest = output
+        .split(|c| c != '`')
+        .map(str::len)
+        .max()
+        .unwrap_or(0);
+    let fence = "`".repeat(longest.max(2) + 1);
+    let body = output.trim_end_matches('\n');
+    format!("{}text\n{}\n{}\n", fence, body, fence)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn setup_project(run_toml: &str) -> TempDir {
+        let dir = TempDir::new().unwrap();
+        std::fs::create_dir_all(dir.path().join(".agent-doc")).unwrap();
+        std::fs::write(dir.path().join(RUN_CONFIG_FILENAME), run_toml).unwrap();
+        dir
+    }
+
+    #[test]
+    fn refreshes_allowed_commands() {
+        let dir = setup_project("allow = [\"echo hello $COMPONENT\"]\n");
+        let doc = dir.path().join("doc.md");
+        std::fs::write(
+            &doc,
+            "<!-- agent:greeting run=\"echo hello $COMPONENT\" -->\nold\n<!-- /agent:greeting -->\n\
+             <!-- agent:listing run=\"ls\" -->\nkept\n<!-- /agent:listing -->\n",
+        )
+        .unwrap();
+
+        assert!(refresh(&doc).unwrap());
+        let content = std::fs::read_to_string(&doc).unwrap();
+        assert!(content.contains("-->\n```text\nhello greeting\n```\n<!-- /agent:greeting -->"));
+        assert!(content.contains("-->\nkept\n<!-- /agent:listing -->"), "not allow-listed");
+
+        assert!(!refresh(&doc).unwrap(), "unchanged output leaves the file alone");
+    }
+
+    #[test]
+    fn failures_timeouts_and_caps() {
+        let dir = setup_project(
+            "allow = [\"echo oops >&2; exit 3\", \"sleep 5\", \"printf 'abcdefghij'\"]\ntimeout = 1\n",
+        );
+        let doc = dir.path().join("doc.md");
+        std::fs::write(
+            &doc,
+            "<!-- agent:fail run=\"echo oops >&2; exit 3\" fence=false -->\n<!-- /agent:fail -->\n\
+             <!-- agent:slow run=\"sleep 5\" fence=false -->\n<!-- /agent:slow -->\n\
+             <!-- agent:big run=\"printf 'abcdefghij'\" max_bytes=4 fence=false -->\n<!-- /agent:big -->\n",
+        )
+        .unwrap();
+
+        refresh(&doc).unwrap();
+        let content = std::fs::read_to_string(&doc).unwrap();
+        assert!(content.contains("-->\noops\n[exit status 3]\n<!-- /agent:fail -->"));
+        assert!(content.contains("-->\n[timed out after 1s]\n<!-- /agent:slow -->"));
+        assert!(content.contains("-->\nabcd\n[truncated 6 bytes]\n<!-- /agent:big -->"));
+    }
+
+    #[test]
+    fn timeout_kills_background_jobs() {
+        let dir = setup_project("allow = [\"sleep 30 & echo $! > bg.pid; wait\"]\ntimeout = 1\n");
+        let doc = dir.path().join("doc.md");
+        std::fs::write(
+            &doc,
+            "<!-- agent:bg run=\"sleep 30 & echo $! > bg.pid; wait\" fence=false -->\n<!-- /agent:bg -->\n",
+        )
+        .unwrap();
+
+        let start = Instant::now();
+        refresh(&doc).unwrap();
+        assert!(start.elapsed() < Duration::from_secs(5));
+        assert!(std::fs::read_to_string(&doc).unwrap().contains("[timed out after 1s]"));
+
+        let pid = std::fs::read_to_string(dir.path().join("bg.pid")).unwrap();
+        std::thread::sleep(Duration::from_millis(200));
+        // Gone, or a zombie waiting to be reaped
+        let stat = std::fs::read_to_string(format!("/proc/{}/stat", pid.trim())).unwrap_or_default();
+        assert!(stat.is_empty() || stat.rsplit(") ").next().is_some_and(|s| s.starts_with('Z')), "{}", stat);
+    }
+
+    #[test]
+    fn wildcard_entries_stop_at_metacharacters() {
+        let config = RunConfig { allow: vec!["git status *".into(), "ls".into()], ..Default::default() };
+        assert!(config.allows("git status --short"));
+        assert!(config.allows("ls"));
+        assert!(!config.allows("ls -la"));
+        for command in ["git status; rm x", "git status && rm x", "git status | sh", "git status $(rm x)", "git status `rm x`", "git status > x", "git status\nrm x"] {
+            assert!(!config.allows(command), "{}", command);
+        }
+    }
+
+    #[test]
+    fn strips_run_attrs_from_agent_markers() {
+        let response = "<!-- agent:evil run=\"rm -rf ~\" timeout=99 mode=append label=\"two words\" -->\n<!-- /agent:evil -->\n\
+                        `<!-- agent:code run=\"ls\" -->`\n<!-- agent:plain -->\n";
+        assert_eq!(
+            strip_run_attrs(response),
+            "<!-- agent:evil label=\"two words\" mode=append -->\n<!-- /agent:evil -->\n\
+             `<!-- agent:code run=\"ls\" -->`\n<!-- agent:plain -->\n"
+        );
+    }
+
+    #[test]
+    fn fence_outlasts_backticks() {
+        assert_eq!(fence("a ```` b\n"), "`````text\na ```` b\n`````\n");
+        assert_eq!(fence(""), "```text\n\n```\n");
+        assert!(RunConfig { allow: vec!["git status".into()], ..Default::default() }.allows("git status"));
+        assert!(!RunConfig { allow: vec!["git status".into()], ..Default::default() }.allows("git status; rm x"));
+    }
+}
@@ -1,6 +1,7 @@
 use anyhow::Result;
 use serde::{Deserialize, Serialize};
 use std::fmt;
+use std::ops::Range;
 use uuid::Uuid;
 
 /// Document format: controls document structure.
@@ -26,7 +27,7 @@ impl fmt::Display for AgentDocFormat {
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum)]
 #[serde(rename_all = "lowercase")]
 pub enum AgentDocWrite {
-    /// 3-way merge via git merge-file
+    /// 3-way line merge (diff3)
     Merge,
     /// CRDT-based conflict-free merge (yrs)
     Crdt,
@@ -41,6 +42,26 @@ impl fmt::Display for AgentDocWrite {
     }
 }
 
+/// CRDT layout: how document text is organized in the Yjs document.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CrdtLayout {
+    /// Whole document in a single shared text
+    #[default]
+    Text,
+    /// One shared text per top-level component plus a skeleton text
+    Components,
+}
+
+impl fmt::Display for CrdtLayout {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Text => write!(f, "text"),
+            Self::Components => write!(f, "components"),
+        }
+    }
+}
+
 /// Resolved mode pair — the canonical representation after deprecation migration.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct ResolvedMode {
@@ -140,6 +161,29 @@ pub struct Frontmatter {
         rename = "agent_doc_stream"
     )]
     pub stream_config: Option<StreamConfig>,
+    /// CRDT layout for merges (text | components). Default: text.
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        rename = "agent_doc_crdt_layout"
+    )]
+    pub crdt_layout: Option<CrdtLayout>,
+    /// Component that summarizes unresolved merge conflicts (default: conflicts).
+    /// `none` disables the summary; conflict records are still written.
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        rename = "agent_doc_conflicts"
+    )]
+    pub conflicts: Option<String>,
+    /// Component names agents may create with placement patches
+    /// (`<!-- patch:name after=status -->`). A trailing `*` matches a prefix.
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        rename = "agent_doc_creatable"
+    )]
+    pub creatable: Option<Vec<String>>,
 }
 
 impl Frontmatter {
@@ -185,6 +229,30 @@ impl Frontmatter {
     }
 }
 
+/// CRDT layout declared in a document's frontmatter (default: text).
+pub fn crdt_layout(content: &str) -> CrdtLayout {
+    parse(content)
+        .ok()
+        .and_then(|(fm, _)| fm.crdt_layout)
+        .unwrap_or_default()
+}
+
+/// Component that summarizes unresolved merge conflicts, or `None` if the
+/// document sets `agent_doc_conflicts: none`.
+pub fn conflicts_component(content: &str) -> Option<String> {
+    let configured = parse(content).ok().and_then(|(fm, _)| fm.conflicts);
+    match configured.as_deref().map(str::trim) {
+        None | Some("") => Some("conflicts".to_string()),
+        Some("none") => None,
+        Some(name) => Some(name.to_string()),
+    }
+}
+
+/// Component names (or `prefix-*` patterns) the document lets agents create.
+pub fn creatable_components(content: &str) -> Vec<String> {
+    parse(content).ok().and_then(|(fm, _)| fm.creatable).unwrap_or_default()
+}
+
 /// Parse YAML frontmatter from a document. Returns (frontmatter, body).
 /// If no frontmatter block is present, returns defaults and the full content as body.
 pub fn parse(content: &str) -> Result<(Frontmatter, &str)> {
@@ -207,24 +275,243 @@ pub fn parse(content: &str) -> Result<(Frontmatter, &str)> {
     Ok((fm, body))
 }
 
-/// Write frontmatter back into a document, preserving the body.
-pub fn write(fm: &Frontmatter, body: &str) -> Result<String> {
-    let yaml = serde_yaml::to_string(fm)?;
-    Ok(format!("---\n{}---\n{}", yaml, body))
+/// Locate the frontmatter block: the byte range of its YAML (including the
+/// final newline) and the offset just past the closing `---` line. Mirrors
+/// [`parse`]; `None` if the document has no frontmatter.
+pub(crate) fn block(content: &str) -> Result<Option<(Range<usize>, usize)>> {
+    let Some(rest) = content.strip_prefix("---\n") else {
+        return Ok(None);
+    };
+    if rest == "---" || rest.starts_with("---\n") {
+        return Ok(Some((4..4, content.len().min(8))));
+    }
+    let end = rest
+        .find("\n---\n")
+        .or_else(|| rest.find("\n---"))
+        .ok_or_else(|| anyhow::anyhow!("Unterminated frontmatter block"))?;
+    Ok(Some((4..4 + end + 1, content.len().min(4 + end + 5))))
+}
+
+/// Set a top-level frontmatter key, or remove it with `None`, editing only
+/// that key's lines. Other keys, comments and ordering are kept as written.
+/// `aliases` are other names the key may appear under (e.g. legacy
+/// `session`); the first existing spelling is updated in place and any
+/// duplicates are removed. Missing keys are appended; frontmatter is created
+/// if the document has none.
+pub fn set_field(content: &str, key: &str, aliases: &[&str], value: Option<&str>) -> Result<String> {
+    let Some((yaml, _)) = block(content)? else {
+        return Ok(match value {
+            Some(value) => format!("---\n{}: {}\n---\n{}", key, yaml_scalar(value), content),
+            None => content.to_string(),
+        });
+    };
+
+    let lines: Vec<&str> = content[yaml.clone()].split_inclusive('\n').collect();
+    let mut out = String::new();
+    let mut replaced = false;
+    let mut i = 0;
+    while i < lines.len() {
+        let end = entry_end(&lines, i);
+        let found = top_level_key(lines[i]).filter(|k| *k == key || aliases.contains(k));
+        match found {
+            Some(found) => {
+                if let Some(value) = value
+                    && !replaced
+                {
+                    let comment = if end == i + 1 { trailing_comment(lines[i]) } else { "" };
+                    out.push_str(&format!("{}: {}{}\n", found, yaml_scalar(value), comment));
+                }
+                replaced = true;
+            }
+            None => lines[i..end].iter().for_each(|l| out.push_str(l)),
+        }
+        i = end;
+    }
+    if !replaced && let Some(value) = value {
+        if !out.is_empty() && !out.ends_with('\n') {
+            out.push('\n');
+        }
+        out.push_str(&format!("{}: {}\n", key, yaml_scalar(value)));
+    }
+
+    let mut result = String::with_capacity(content.len() + 64);
+    result.push_str(&content[..yaml.start]);
+    result.push_str(&out);
+    result.push_str(&content[yaml.end..]);
+    // The edit must leave valid frontmatter behind
+    parse(&result).map_err(|e| anyhow::anyhow!("frontmatter edit of '{}' produced invalid YAML: {}", key, e))?;
+    Ok(result)
+}
+
+/// Top-level frontmatter keys as written, in order.
+pub fn keys(content: &str) -> Result<Vec<String>> {
+    let Some((yaml, _)) = block(content)? else {
+        return Ok(Vec::new());
+    };
+    Ok(content[yaml]
+        .split_inclusive('\n')
+        .filter_map(top_level_key)
+        .map(str::to_string)
+        .collect())
+}
+
+/// Rename a top-level key in place, keeping its position, comment and (when
+/// `value` is `None`) its value and continuation lines. If `to` already
+/// exists, `from` is removed instead.
+pub fn rename_field(content: &str, from: &str, to: &str, value: Option<&str>) -> Result<String> {
+    let existing = keys(content)?;
+    if !existing.iter().any(|k| k == from) {
+        return Ok(content.to_string());
+    }
+    if existing.iter().any(|k| k == to) {
+        return set_field(content, from, &[], None);
+    }
+    let Some((yaml, _)) = block(content)? else {
+        return Ok(content.to_string());
+    };
+    let lines: Vec<&str> = content[yaml.clone()].split_inclusive('\n').collect();
+    let mut out = String::new();
+    let mut i = 0;
+    while i < lines.len() {
+        let end = entry_end(&lines, i);
+        if top_level_key(lines[i]) == Some(from) {
+            let (_, rest) = lines[i].split_once(':').unwrap_or((lines[i], ""));
+            match value {
+                Some(value) => {
+                    let comment = if end == i + 1 { trailing_comment(lines[i]) } else { "" };
+                    out.push_str(&format!("{}: {}{}\n", to, yaml_scalar(value), comment));
+                }
+                None => {
+                    out.push_str(&format!("{}:{}", to, rest));
+                    lines[i + 1..end].iter().for_each(|l| out.push_str(l));
+                }
+            }
+        } else {
+            lines[i..end].iter().for_each(|l| out.push_str(l));
+        }
+        i = end;
+    }
+    let result = format!("{}{}{}", &content[..yaml.start], out, &content[yaml.end..]);
+    parse(&result).map_err(|e| anyhow::anyhow!("renaming '{}' to '{}' produced invalid YAML: {}", from, to, e))?;
+    Ok(result)
+}
+
+/// Like [`set_field`], but a missing key is inserted after the entry for
+/// `after` (appended if that is missing too).
+pub fn insert_field(content: &str, key: &str, after: &str, value: &str) -> Result<String> {
+    let existing = keys(content)?;
+    if existing.iter().any(|k| k == key) || !existing.iter().any(|k| k == after) {
+        return set_field(content, key, &[], Some(value));
+    }
+    let Some((yaml, _)) = block(content)? else {
+        return set_field(content, key, &[], Some(value));
+    };
+    let lines: Vec<&str> = content[yaml.clone()].split_inclusive('\n').collect();
+    let mut out = String::new();
+    let mut i = 0;
+    while i < lines.len() {
+        let end = entry_end(&lines, i);
+        lines[i..end].iter().for_each(|l| out.push_str(l));
+        if top_level_key(lines[i]) == Some(after) {
+            if !out.ends_with('\n') {
+                out.push('\n');
+            }
+            out.push_str(&format!("{}: {}\n", key, yaml_scalar(value)));
+        }
+        i = end;
+    }
+    let result = format!("{}{}{}", &content[..yaml.start], out, &content[yaml.end..]);
+    parse(&result).map_err(|e| anyhow::anyhow!("frontmatter edit of '{}' produced invalid YAML: {}", key, e))?;
+    Ok(result)
+}
+
+/// Replace the document body, keeping the frontmatter block verbatim.
+/// `body` is in [`parse`]'s form, starting with the newline that ends the
+/// closing `---` line.
+pub fn replace_body(content: &str, body: &str) -> Result<String> {
+    let Some((_, body_start)) = block(content)? else {
+        return Ok(body.to_string());
+    };
+    let mut result = content[..body_start].to_string();
+    if !result.ends_with('\n') {
+        result.push('\n');
+    }
+    result.push_str(body.strip_prefix('\n').unwrap_or(body));
+    Ok(result)
+}
+
+/// The key of a top-level `key: value` line, if it is one.
+fn top_level_key(line: &str) -> Option<&str> {
+    if line.starts_with([' ', '\t', '#', '-']) {
+        return None;
+    }
+    let (key, _) = line.split_once(':')?;
+    let key = key.trim_end();
+    let key = key
+        .strip_prefix('"')
+        .and_then(|k| k.strip_suffix('"'))
+        .or_else(|| key.strip_prefix('\'').and_then(|k| k.strip_suffix('\'')))
+        .unwrap_or(key);
+    (!key.is_empty()).then_some(key)
+}
+
+/// End (exclusive) of the entry starting at `lines[start]`: the line itself
+/// plus nested lines (indented, indentless `- ` items, blank lines inside a
+/// block). Trailing blank lines belong to whatever follows.
+fn entry_end(lines: &[&str], start: usize) -> usize {
+    let mut end = start + 1;
+    let mut last_content = end;
+    while end < lines.len() {
+        let line = lines[end];
+        if line.trim().is_empty() {
+            end += 1;
+        } else if line.starts_with([' ', '\t', '-']) {
+            end += 1;
+            last_content = end;
+        } else {
+            break;
+        }
+    }
+    last_content
+}
+
+/// A ` # comment` after a plain scalar value, kept when the value changes.
+fn trailing_comment(line: &str) -> &str {
+    let line = line.trim_end_matches(['\n', '\r']);
+    let Some((_, value)) = line.split_once(':') else {
+        return "";
+    };
+    if value.trim_start().starts_with(['"', '\'']) {
+        return "";
+    }
+    match value.find(" #") {
+        Some(pos) => &value[value[..pos].trim_end().len()..],
+        None => "",
+    }
+}
+
+/// Format a string as a YAML scalar: plain when it reads back as the same
+/// string, double-quoted otherwise.
+fn yaml_scalar(value: &str) -> String {
+    let plain = !value.is_empty()
+        && !value.contains(['\n', '#', '"', '\''])
+        && value.trim() == value
+        && matches!(serde_yaml::from_str::<serde_yaml::Value>(value), Ok(serde_yaml::Value::String(s)) if s == value);
+    if plain {
+        value.to_string()
+    } else {
+        serde_json::to_string(value).unwrap_or_else(|_| format!("{:?}", value))
+    }
 }
 
 /// Update the session ID in a document string. Creates frontmatter if missing.
 pub fn set_session_id(content: &str, session_id: &str) -> Result<String> {
-    let (mut fm, body) = parse(content)?;
-    fm.session = Some(session_id.to_string());
-    write(&fm, body)
+    set_field(content, "agent_doc_session", &["session"], Some(session_id))
 }
 
 /// Update the resume (agent conversation) ID in a document string.
 pub fn set_resume_id(content: &str, resume_id: &str) -> Result<String> {
-    let (mut fm, body) = parse(content)?;
-    fm.resume = Some(resume_id.to_string());
-    write(&fm, body)
+    set_field(content, "resume", &[], Some(resume_id))
 }
 
 /// Set both agent_doc_format and agent_doc_write, clearing deprecated agent_doc_mode.
@@ -233,63 +520,53 @@ pub fn set_format_and_write(
     format: AgentDocFormat,
     write_mode: AgentDocWrite,
 ) -> Result<String> {
-    let (mut fm, body) = parse(content)?;
-    fm.format = Some(format);
-    fm.write_mode = Some(write_mode);
-    fm.mode = None;
-    write(&fm, body)
+    let content = set_field(content, "agent_doc_format", &[], Some(&format.to_string()))?;
+    let content = set_field(&content, "agent_doc_write", &[], Some(&write_mode.to_string()))?;
+    set_field(&content, "agent_doc_mode", &["mode", "response_mode"], None)
 }
 
 /// Merge YAML key/value pairs into a document's frontmatter.
 ///
-/// Takes a YAML string of fields to merge (additive — never removes keys).
-/// Only known frontmatter fields are applied; unknown keys are ignored.
-/// Returns the updated document content.
+/// Takes a YAML string of fields to merge (additive — never removes keys,
+/// except a field set to null). Only known frontmatter fields are applied;
+/// unknown keys are ignored. Returns the updated document content.
 pub fn merge_fields(content: &str, yaml_fields: &str) -> Result<String> {
-    let (mut fm, body) = parse(content)?;
     let patch: serde_yaml::Value = serde_yaml::from_str(yaml_fields)
         .unwrap_or(serde_yaml::Value::Mapping(serde_yaml::Mapping::new()));
     let mapping = patch.as_mapping().unwrap_or(&serde_yaml::Mapping::new()).clone();
 
+    let mut content = content.to_string();
     for (key, value) in &mapping {
         let key_str = key.as_str().unwrap_or("");
-        let val_str = || value.as_str().map(|s| s.to_string());
-        match key_str {
-            "agent_doc_session" | "session" => fm.session = val_str(),
-            "resume" => fm.resume = val_str(),
-            "agent" => fm.agent = val_str(),
-            "model" => fm.model = val_str(),
-            "branch" => fm.branch = val_str(),
-            "tmux_session" => fm.tmux_session = val_str(),
-            "agent_doc_mode" | "mode" | "response_mode" => fm.mode = val_str(),
+        let (key, aliases): (&str, &[&str]) = match key_str {
+            "agent_doc_session" | "session" => ("agent_doc_session", &["session"]),
+            "resume" | "agent" | "model" | "branch" | "tmux_session" => (key_str, &[]),
+            "agent_doc_mode" | "mode" | "response_mode" => ("agent_doc_mode", &["mode", "response_mode"]),
             "agent_doc_format" => {
-                if let Some(s) = value.as_str()
-                    && let Ok(f) = serde_yaml::from_str::<AgentDocFormat>(&format!("\"{}\"", s))
-                {
-                    fm.format = Some(f);
+                if value.as_str().and_then(|s| serde_yaml::from_str::<AgentDocFormat>(s).ok()).is_none() {
+                    continue;
                 }
+                (key_str, &[])
             }
             "agent_doc_write" => {
-                if let Some(s) = value.as_str()
-                    && let Ok(w) = serde_yaml::from_str::<AgentDocWrite>(&format!("\"{}\"", s))
-                {
-                    fm.write_mode = Some(w);
+                if value.as_str().and_then(|s| serde_yaml::from_str::<AgentDocWrite>(s).ok()).is_none() {
+                    continue;
                 }
+                (key_str, &[])
             }
             _ => {
                 eprintln!("[frontmatter] ignoring unknown patch field: {}", key_str);
+                continue;
             }
-        }
+        };
+        content = set_field(&content, key, aliases, value.as_str())?;
     }
-
-    write(&fm, body)
+    Ok(content)
 }
 
 /// Update the tmux_session name in a document string.
 pub fn set_tmux_session(content: &str, session_name: &str) -> Result<String> {
-    let (mut fm, body) = parse(content)?;
-    fm.tmux_session = Some(session_name.to_string());
-    write(&fm, body)
+    set_field(content, "tmux_session", &[], Some(session_name))
 }
 
 /// Ensure the document has a session ID. If no frontmatter exists, creates one
@@ -311,6 +588,39 @@ pub fn ensure_session(content: &str) -> Result<(String, String)> {
 mod tests {
     use super::*;
 
+    /// Serialize a typed frontmatter block ahead of `body`.
+    fn write(fm: &Frontmatter, body: &str) -> Result<String> {
+        Ok(format!("---\n{}---\n{}", serde_yaml::to_string(fm)?, body))
+    }
+
+    /// A frontmatter block as other tools (static site generators, note
+    /// apps) leave it: comments, lists, nested maps, quoting and blank lines.
+    const REAL_WORLD: &str = "---\n\
+# Planning doc — edited by hand
+title: \"Q3 roadmap: search\"
+tags:
+  - planning
+  - search
+date: 2024-06-01
+agent_doc_session: 7f3c2a10-0000-4000-8000-000000000001   # routing key
+author:
+  name: Dana
+  email: dana@example.com
+
+draft: true
+aliases: [roadmap, q3]
+description: |
+  Multi-line
+
+  summary.
+agent: claude
+---
+
+# Q3 roadmap
+
+Body text.
+";
+
     #[test]
     fn parse_no_frontmatter() {
         let content = "# Hello\n\nBody text.\n";
@@ -389,6 +699,9 @@ mod tests {
             format: None,
             write_mode: None,
             stream_config: None,
+            crdt_layout: None,
+            conflicts: None,
+            creatable: None,
         };
         let body = "# Hello\n\nBody text.\n";
         let written = write(&fm, body).unwrap();
@@ -631,6 +944,26 @@ mod tests {
         assert_eq!(fm.write_mode, Some(AgentDocWrite::Crdt));
     }
 
+    #[test]
+    fn parse_crdt_layout() {
+        let content = "---\nagent_doc_crdt_layout: components\n---\nBody\n";
+        let (fm, _) = parse(content).unwrap();
+        assert_eq!(fm.crdt_layout, Some(CrdtLayout::Components));
+
+        let (fm, _) = parse("---\nagent_doc_session: x\n---\nBody\n").unwrap();
+        assert_eq!(fm.crdt_layout.unwrap_or_default(), CrdtLayout::Text);
+    }
+
+    #[test]
+    fn conflicts_component_default_and_override() {
+        assert_eq!(conflicts_component("Body\n").as_deref(), Some("conflicts"));
+        assert_eq!(
+            conflicts_component("---\nagent_doc_conflicts: merge-issues\n---\nBody\n").as_deref(),
+            Some("merge-issues")
+        );
+        assert_eq!(conflicts_component("---\nagent_doc_conflicts: none\n---\nBody\n"), None);
+    }
+
     #[test]
     fn write_uses_new_format_write_fields() {
         let fm = Frontmatter {
@@ -726,4 +1059,89 @@ mod tests {
         assert_eq!(fm.format, Some(AgentDocFormat::Template));
         assert_eq!(fm.write_mode, Some(AgentDocWrite::Crdt));
     }
+
+    // --- format-preserving edits ---
+
+    /// `content` with `from` replaced by `to` exactly once.
+    fn with(content: &str, from: &str, to: &str) -> String {
+        assert_eq!(content.matches(from).count(), 1, "{:?} not unique", from);
+        content.replace(from, to)
+    }
+
+    #[test]
+    fn set_field_edits_only_its_line() {
+        let result = set_session_id(REAL_WORLD, "new-id").unwrap();
+        assert_eq!(
+            result,
+            with(
+                REAL_WORLD,
+                "7f3c2a10-0000-4000-8000-000000000001   # routing key",
+                "new-id   # routing key"
+            )
+        );
+
+        let result = set_resume_id(REAL_WORLD, "resume-1").unwrap();
+        assert_eq!(result, with(REAL_WORLD, "agent: claude\n---", "agent: claude\nresume: resume-1\n---"));
+
+        let result = set_tmux_session(&result, "work").unwrap();
+        assert!(result.contains("resume: resume-1\ntmux_session: work\n---\n\n# Q3 roadmap\n"));
+    }
+
+    #[test]
+    fn set_field_removes_multiline_entries() {
+        let result = set_field(REAL_WORLD, "author", &[], None).unwrap();
+        assert_eq!(result, with(REAL_WORLD, "author:\n  name: Dana\n  email: dana@example.com\n", ""));
+
+        let result = set_field(REAL_WORLD, "description", &[], Some("short")).unwrap();
+        assert_eq!(result, with(REAL_WORLD, "description: |\n  Multi-line\n\n  summary.\n", "description: short\n"));
+
+        let result = set_field(REAL_WORLD, "tags", &[], None).unwrap();
+        assert_eq!(result, with(REAL_WORLD, "tags:\n  - planning\n  - search\n", ""));
+    }
+
+    #[test]
+    fn edits_round_trip_unknown_keys() {
+        let edited = merge_fields(REAL_WORLD, "model: opus\nagent: codex").unwrap();
+        let edited = set_format_and_write(&edited, AgentDocFormat::Template, AgentDocWrite::Crdt).unwrap();
+        let edited = set_field(&edited, "model", &[], None).unwrap();
+        let edited = set_field(&edited, "agent", &[], Some("claude")).unwrap();
+        let edited = set_field(&edited, "agent_doc_format", &[], None).unwrap();
+        let edited = set_field(&edited, "agent_doc_write", &[], None).unwrap();
+        assert_eq!(edited, REAL_WORLD, "undoing every edit restores the original bytes");
+
+        let value: serde_yaml::Value = serde_yaml::from_str(REAL_WORLD.split("---\n").nth(1).unwrap()).unwrap();
+        assert_eq!(value["title"], "Q3 roadmap: search");
+        assert_eq!(value["aliases"][1], "q3");
+    }
+
+    #[test]
+    fn set_field_quotes_when_needed() {
+        let content = "---\nagent: claude\n---\nBody\n";
+        for value in ["123", "true", "a: b", "x # y", "", " padded", "null", "say \"hi\"", "line\nbreak"] {
+            let result = set_field(content, "model", &[], Some(value)).unwrap();
+            let (fm, body) = parse(&result).unwrap();
+            assert_eq!(fm.model.as_deref(), Some(value), "{}", result);
+            assert_eq!(body, "\nBody\n");
+        }
+        assert_eq!(set_field(content, "model", &[], Some("opus-4")).unwrap(), "---\nagent: claude\nmodel: opus-4\n---\nBody\n");
+    }
+
+    #[test]
+    fn set_field_replaces_legacy_alias_in_place() {
+        let content = "---\n# keep me\nsession: old\nagent: claude\n---\nBody\n";
+        let result = set_session_id(content, "new").unwrap();
+        assert_eq!(result, "---\n# keep me\nsession: new\nagent: claude\n---\nBody\n");
+
+        let content = "---\nmode: append # legacy\ntitle: T\n---\nBody\n";
+        let result = set_format_and_write(content, AgentDocFormat::Append, AgentDocWrite::Crdt).unwrap();
+        assert_eq!(result, "---\ntitle: T\nagent_doc_format: append\nagent_doc_write: crdt\n---\nBody\n");
+    }
+
+    #[test]
+    fn replace_body_keeps_frontmatter() {
+        let result = replace_body(REAL_WORLD, "\n\nNew body.\n").unwrap();
+        let (_, old_body) = parse(REAL_WORLD).unwrap();
+        assert_eq!(result, with(REAL_WORLD, &old_body[1..], "\nNew body.\n"));
+        assert_eq!(replace_body("No frontmatter\n", "Other\n").unwrap(), "Other\n");
+    }
 }
@@ -0,0 +1,388 @@
+//! `agent-doc gc` — Garbage-collect orphaned sidecar state.
+//!
+//! Snapshots, CRDT state, locks, pending responses and conflict records under `.agent-doc/` are
+//! keyed by `snapshot::doc_key` (session ID, or path hash for documents without
+//! one). When a document is deleted, its sidecar files are never cleaned up.
+//! This module finds entries whose key doesn't match any live document and
+//! removes them.
+//!
+//! Archives under `.agent-doc/archives/` are intentional history and are only
+//! considered when `--older-than` is given.
+
+use anyhow::{Context, Result};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use crate::{sessions, snapshot};
+
+/// Sidecar directories keyed by `snapshot::doc_key`.
+const KEYED_DIRS: &[&str] = &[
+    ".agent-doc/snapshots",
+    ".agent-doc/crdt",
+    ".agent-doc/locks",
+    ".agent-doc/pending",
+    ".agent-doc/conflicts",
+];
+
+const ARCHIVE_DIR: &str = ".agent-doc/archives";
+
+/// Directories skipped when scanning the project for live documents.
+const SKIP_DIRS: &[&str] = &["target", "node_modules"];
+
+/// Hidden directories that never contain documents, skipped even when
+/// scanning hidden directories for live documents.
+const SKIP_HIDDEN_DIRS: &[&str] = &[".git", ".agent-doc"];
+
+/// A sidecar file selected for removal.
+#[derive(Debug)]
+pub struct Orphan {
+    pub path: PathBuf,
+    pub size: u64,
+    pub reason: &'static str,
+}
+
+/// Run the gc command from the project root containing the current directory.
+///
+/// `older_than` is a duration like `30d`, `12h` or `2w`; when set, archives
+/// older than that are removed as well.
+pub fn run(dry_run: bool, older_than: Option<&str>) -> Result<()> {
+    let cwd = std::env::current_dir()?;
+    let root = snapshot::find_project_root(&cwd)
+        .ok_or_else(|| anyhow::anyhow!("no .agent-doc/ directory found from {}", cwd.display()))?;
+    let max_age = older_than.map(parse_duration).transpose()?;
+
+    let orphans = collect(&root, max_age)?;
+    if orphans.is_empty() {
+        eprintln!("[gc] No stale files found under {}", root.join(".agent-doc").display());
+        return Ok(());
+    }
+
+    let mut total = 0u64;
+    for orphan in &orphans {
+        let rel = orphan.path.strip_prefix(&root).unwrap_or(&orphan.path);
+        println!("{:>10}  {}  ({})", format_size(orphan.size), rel.display(), orphan.reason);
+        total += orphan.size;
+    }
+
+    if dry_run {
+        eprintln!(
+            "[gc] Dry run: {} stale file(s), {} would be freed",
+            orphans.len(),
+            format_size(total)
+        );
+        return Ok(());
+    }
+
+    let mut removed = 0usize;
+    for orphan in &orphans {
+        match std::fs::remove_file(&orphan.path) {
+            Ok(()) => removed += 1,
+            Err(e) => eprintln!("[gc] failed to remove {}: {}", orphan.path.display(), e),
+        }
+    }
+    eprintln!("[gc] Removed {} stale file(s), freed {}", removed, format_size(total));
+    Ok(())
+}
+
+/// Collect stale sidecar files under `root/.agent-doc/`.
+///
+/// Keyed entries are stale when their hash matches no live document. Archives
+/// are stale only when `max_age` is set and their mtime is older than it.
+pub fn collect(root: &Path, max_age: Option<Duration>) -> Result<Vec<Orphan>> {
+    let live = live_keys(root)?;
+    let mut orphans = Vec::new();
+
+    for dir in KEYED_DIRS {
+        for (path, size) in list_files(&root.join(dir))? {
+            let Some(key) = file_key(&path) else {
+                continue;
+            };
+            if !live.contains(&key) {
+                orphans.push(Orphan { path, size, reason: "no live document" });
+            }
+        }
+    }
+
+    if let Some(max_age) = max_age {
+        let now = SystemTime::now();
+        for (path, size) in list_files(&root.join(ARCHIVE_DIR))? {
+            let age = std::fs::metadata(&path)
+                .and_then(|m| m.modified())
+                .ok()
+                .and_then(|mtime| now.duration_since(mtime).ok());
+            if age.is_some_and(|age| age > max_age) {
+                orphans.push(Orphan { path, size, reason: "archive older than threshold" });
+            }
+        }
+    }
+
+    orphans.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok(orphans)
+}
+
+/// Compute the sidecar keys of every live document.
+///
+/// Live documents are markdown files under the project root plus any file
+/// referenced by the session registry (which may live outside the root).
+fn live_keys(root: &Path) -> Result<HashSet<String>> {
+    // Documents under hidden directories (`.notes/`, `.github/`) still own
+    // sidecar state, so they count as live here.
+    let mut docs = Vec::new();
+    walk_markdown(root, &mut docs, false)?;
+
+    if let Ok(registry) = sessions::load() {
+        for entry in registry.values() {
+            if entry.file.is_empty() {
+                continue;
+            }
+            let path = PathBuf::from(&entry.file);
+            let path = if path.is_relative() && !entry.cwd.is_empty() {
+                Path::new(&entry.cwd).join(path)
+            } else {
+                path
+            };
+            if path.exists() {
+                docs.push(path);
+            }
+        }
+    }
+
+    // Keep both keys: session-keyed state, plus path-hashed state that
+    // hasn't been migrated yet.
+    let mut keys = HashSet::new();
+    for doc in &docs {
+        if let Ok(key) = snapshot::doc_key(doc) {
+            keys.insert(key);
+        }
+        if let Ok(hash) = snapshot::doc_hash(doc) {
+            keys.insert(hash);
+        }
+    }
+    Ok(keys)
+}
+
+/// Recursively collect `.md` files, skipping hidden and build directories.
+pub(crate) fn find_markdown(dir: &Path, out: &mut Vec<PathBuf>) -> Result<()> {
+    walk_markdown(dir, out, true)
+}
+
+/// Recursively collect `.md` files, skipping build directories and, when
+/// `skip_hidden` is set, every hidden directory.
+pub(crate) fn walk_markdown(dir: &Path, out: &mut Vec<PathBuf>, skip_hidden: bool) -> Result<()> {
+    let entries = match std::fs::read_dir(dir) {
+        Ok(e) => e,
+        Err(_) => return Ok(()),
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let name = entry.file_name();
+        let name = name.to_string_lossy();
+        let Ok(file_type) = entry.file_type() else {
+            continue;
+        };
+        if file_type.is_dir() {
+            let hidden = name.starts_with('.');
+            if (hidden && (skip_hidden || SKIP_HIDDEN_DIRS.contains(&name.as_ref())))
+                || SKIP_DIRS.contains(&name.as_ref())
+            {
+                continue;
+            }
+            walk_markdown(&path, out, skip_hidden)?;
+        } else if name.ends_with(".md") {
+            out.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// List regular files in a directory with their sizes. Missing dirs yield nothing.
+fn list_files(dir: &Path) -> Result<Vec<(PathBuf, u64)>> {
+    let mut files = Vec::new();
+    if !dir.is_dir() {
+        return Ok(files);
+    }
+    for entry in std::fs::read_dir(dir).with_context(|| format!("failed to read {}", dir.display()))? {
+        let entry = entry?;
+        let meta = entry.metadata()?;
+        if meta.is_file() {
+            files.push((entry.path(), meta.len()));
+        }
+    }
+    Ok(files)
+}
+
+/// Extract the document key from a sidecar filename.
+///
+/// Handles `<key>.md`, `<key>.md.lock`, `<key>.yrs`, `<key>.yrs.lock` and `<key>.lock`.
+/// Returns `None` for temp files and anything that doesn't look like a key.
+fn file_key(path: &Path) -> Option<String> {
+    let name = path.file_name()?.to_str()?;
+    let key = name.split('.').next()?;
+    if key.len() == 64 && key.chars().all(|c| c.is_ascii_hexdigit()) {
+        Some(key.to_string())
+    } else {
+        None
+    }
+}
+
+/// Parse a duration like `90s`, `15m`, `12h`, `30d` or `2w`.
+pub fn parse_duration(s: &str) -> Result<Duration> {
+    let s = s.trim();
+    let split = s
+        .find(|c: char| !c.is_ascii_digit())
+        .ok_or_else(|| anyhow::anyhow!("missing unit in duration '{}' (use s, m, h, d or w)", s))?;
+    let (num, unit) = s.split_at(split);
+    let n: u64 = num
+        .parse()
+        .with_context(|| format!("invalid duration '{}'", s))?;
+    let unit_secs: u64 = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 3600,
+        "d" => 86400,
+        "w" => 7 * 86400,
+        _ => anyhow::bail!("unknown duration unit '{}' in '{}' (use s, m, h, d or w)", unit, s),
+    };
+    let secs = n
+        .checked_mul(unit_secs)
+        .ok_or_else(|| anyhow::anyhow!("duration '{}' is too large", s))?;
+    Ok(Duration::from_secs(secs))
+}
+
+/// Format a byte count for display.
+fn format_size(bytes: u64) -> String {
+    const KIB: f64 = 1024.0;
+    let b = bytes as f64;
+    if b < KIB {
+        format!("{} B", bytes)
+    } else if b < KIB * KIB {
+        format!("{:.1} KiB", b / KIB)
+    } else {
+        format!("{:.1} MiB", b / (KIB * KIB))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+
+    const ORPHAN_KEY: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+
+    fn setup_project() -> TempDir {
+        let dir = TempDir::new().unwrap();
+        for d in KEYED_DIRS {
+            fs::create_dir_all(dir.path().join(d)).unwrap();
+        }
+        dir
+    }
+
+    #[test]
+    fn live_document_state_is_kept() {
+        let dir = setup_project();
+        let doc = dir.path().join("notes/session.md");
+        fs::create_dir_all(doc.parent().unwrap()).unwrap();
+        fs::write(&doc, "# Session\n").unwrap();
+        snapshot::save(&doc, "# Session\n").unwrap();
+        snapshot::save_crdt_text(&doc, "# Session\n").unwrap();
+
+        let orphans = collect(dir.path(), None).unwrap();
+        assert!(orphans.is_empty(), "unexpected orphans: {:?}", orphans);
+    }
+
+    #[test]
+    fn state_of_documents_in_hidden_dirs_is_kept() {
+        let dir = setup_project();
+        let doc = dir.path().join(".notes/session.md");
+        fs::create_dir_all(doc.parent().unwrap()).unwrap();
+        fs::write(&doc, "# Session\n").unwrap();
+        snapshot::save(&doc, "# Session\n").unwrap();
+
+        let orphans = collect(dir.path(), None).unwrap();
+        assert!(orphans.is_empty(), "unexpected orphans: {:?}", orphans);
+    }
+
+    #[test]
+    fn unmigrated_path_hashed_state_is_kept() {
+        let dir = setup_project();
+        let doc = dir.path().join("session.md");
+        fs::write(&doc, "---\nagent_doc_session: abc\n---\n# Doc\n").unwrap();
+        let hash = snapshot::doc_hash(&doc).unwrap();
+        fs::write(dir.path().join(format!(".agent-doc/snapshots/{}.md", hash)), "legacy").unwrap();
+
+        let orphans = collect(dir.path(), None).unwrap();
+        assert!(orphans.is_empty(), "unexpected orphans: {:?}", orphans);
+    }
+
+    #[test]
+    fn orphaned_files_are_reported() {
+        let dir = setup_project();
+        let root = dir.path();
+        fs::write(root.join(format!(".agent-doc/snapshots/{}.md", ORPHAN_KEY)), "old").unwrap();
+        fs::write(root.join(format!(".agent-doc/snapshots/{}.md.lock", ORPHAN_KEY)), "").unwrap();
+        fs::write(root.join(format!(".agent-doc/crdt/{}.yrs", ORPHAN_KEY)), [1, 2]).unwrap();
+        fs::write(root.join(format!(".agent-doc/locks/{}.lock", ORPHAN_KEY)), "").unwrap();
+        fs::write(root.join(format!(".agent-doc/pending/{}.md", ORPHAN_KEY)), "resp").unwrap();
+
+        let orphans = collect(root, None).unwrap();
+        assert_eq!(orphans.len(), 5);
+        let snap = orphans.iter().find(|o| o.path.ends_with(format!("snapshots/{}.md", ORPHAN_KEY))).unwrap();
+        assert_eq!(snap.size, 3);
+    }
+
+    #[test]
+    fn unrelated_files_are_ignored() {
+        let dir = setup_project();
+        fs::write(dir.path().join(".agent-doc/snapshots/README"), "keep").unwrap();
+        fs::write(dir.path().join(".agent-doc/snapshots/.tmpXYZ"), "keep").unwrap();
+
+        let orphans = collect(dir.path(), None).unwrap();
+        assert!(orphans.is_empty());
+    }
+
+    #[test]
+    fn archives_only_considered_with_older_than() {
+        let dir = setup_project();
+        let archives = dir.path().join(ARCHIVE_DIR);
+        fs::create_dir_all(&archives).unwrap();
+        fs::write(archives.join(format!("{}-20250101-000000.md", ORPHAN_KEY)), "old").unwrap();
+
+        assert!(collect(dir.path(), None).unwrap().is_empty());
+        // Freshly written archive is not older than an hour
+        assert!(collect(dir.path(), Some(Duration::from_secs(3600))).unwrap().is_empty());
+        // Everything is older than zero seconds (allow mtime granularity)
+        std::thread::sleep(Duration::from_millis(20));
+        assert_eq!(collect(dir.path(), Some(Duration::ZERO)).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn file_key_extraction() {
+        let key = "a".repeat(64);
+        assert_eq!(file_key(Path::new(&format!("{}.md", key))), Some(key.clone()));
+        assert_eq!(file_key(Path::new(&format!("{}.yrs.lock", key))), Some(key.clone()));
+        assert_eq!(file_key(Path::new("short.md")), None);
+        assert_eq!(file_key(Path::new(".tmp123")), None);
+    }
+
+    #[test]
+    fn parse_duration_units() {
+        assert_eq!(parse_duration("90s").unwrap(), Duration::from_secs(90));
+        assert_eq!(parse_duration("15m").unwrap(), Duration::from_secs(900));
+        assert_eq!(parse_duration("12h").unwrap(), Duration::from_secs(43200));
+        assert_eq!(parse_duration("30d").unwrap(), Duration::from_secs(30 * 86400));
+        assert_eq!(parse_duration("2w").unwrap(), Duration::from_secs(14 * 86400));
+        assert!(parse_duration("30").is_err());
+        assert!(parse_duration("3y").is_err());
+        assert!(parse_duration("d").is_err());
+        assert!(parse_duration("99999999999999999w").is_err());
+    }
+
+    #[test]
+    fn format_size_units() {
+        assert_eq!(format_size(512), "512 B");
+        assert_eq!(format_size(2048), "2.0 KiB");
+        assert_eq!(format_size(3 * 1024 * 1024), "3.0 MiB");
+    }
+}
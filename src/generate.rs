@@ -0,0 +1,263 @@
+//! Derived components, maintained locally without an agent call.
+//!
+//! A component is generated when its marker has `generate=KIND`, or when its
+//! name is one of the kinds:
+//!
+//! - `toc` — links to the document's headings (from `outline::parse_sections`);
+//! - `budget` — approximate token count against `max_tokens` (default 100000);
+//! - `session-card` — agent, model, session and resume IDs, last submit time;
+//! - `exchange-index` — links to the headings inside the `exchange` component
+//!   (or the component named by `source=`).
+//!
+//! Generated content is rebuilt on every `write`, `submit` and `patch`, and is
+//! left out of the submit diff so it never triggers a response on its own.
+
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::component::{self, Component};
+use crate::{frontmatter, outline};
+
+const KINDS: &[&str] = &["toc", "budget", "session-card", "exchange-index"];
+const DEFAULT_MAX_TOKENS: usize = 100_000;
+const GAUGE_WIDTH: usize = 20;
+
+/// The generator kind for a component, if it is generated.
+pub fn kind(comp: &Component) -> Option<&'static str> {
+    let wanted = comp.attrs.get("generate").map_or(comp.name.as_str(), String::as_str);
+    KINDS.iter().copied().find(|k| *k == wanted)
+}
+
+/// Rebuild every generated component in `doc`. `file` is used for the
+/// session card's last submit time.
+pub fn regenerate(doc: &str, file: &Path) -> String {
+    let Ok(components) = component::parse(doc) else {
+        return doc.to_string();
+    };
+    let generated: Vec<(&Component, &'static str)> = components
+        .iter()
+        .filter_map(|c| {
+            if let Some(value) = c.attrs.get("generate")
+                && !KINDS.contains(&value.as_str())
+            {
+                eprintln!("[generate] unknown generator '{}' on <!-- agent:{} -->", value, c.name);
+            }
+            kind(c).map(|k| (c, k))
+        })
+        .collect();
+
+    let mut result = doc.to_string();
+    // Last first, so earlier offsets stay valid. A generated component nested
+    // in another one is rebuilt with its parent.
+    for &(comp, kind) in generated.iter().rev() {
+        let nested = generated
+            .iter()
+            .any(|(outer, _)| outer.open_start < comp.open_start && outer.close_start > comp.open_start);
+        if nested {
+            continue;
+        }
+        let content = match kind {
+            "toc" => toc(doc),
+            "budget" => budget(doc, comp),
+            "session-card" => session_card(doc, file),
+            _ => exchange_index(doc, &components, comp),
+        };
+        if content != comp.content(doc) {
+            result = comp.replace_content(&result, &content);
+        }
+    }
+    result
+}
+
+/// Empty the content of generated components, for diffing.
+pub fn strip_generated(doc: &str) -> String {
+    let Ok(components) = component::parse(doc) else {
+        return doc.to_string();
+    };
+    let mut result = doc.to_string();
+    let mut last_start = usize::MAX;
+    for comp in components.iter().rev() {
+        // Skip components inside one already emptied (their offsets are stale)
+        if kind(comp).is_none() || comp.close_start > last_start {
+            continue;
+        }
+        result = comp.replace_content(&result, "");
+        last_start = comp.open_start;
+    }
+    result
+}
+
+fn body(doc: &str) -> &str {
+    frontmatter::parse(doc).map_or(doc, |(_, body)| body)
+}
+
+fn heading_text(line: &str) -> &str {
+    line.trim().trim_start_matches('#').trim()
+}
+
+/// GitHub-style heading anchor.
+fn slug(text: &str) -> String {
+    text.to_lowercase()
+        .chars()
+        .filter_map(|c| match c {
+            ' ' => Some('-'),
+            c if c.is_alphanumeric() || c == '-' || c == '_' => Some(c),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Links for headings, indented relative to the shallowest one. Repeated
+/// anchors get `-1`, `-2`, ... like GitHub's.
+fn heading_list(headings: &[(usize, &str)], empty: &str) -> String {
+    let Some(min_depth) = headings.iter().map(|(d, _)| *d).min() else {
+        return format!("{}\n", empty);
+    };
+    let mut seen: Vec<String> = Vec::new();
+    let mut out = String::new();
+    for (depth, text) in headings {
+        let base = slug(text);
+        let count = seen.iter().filter(|s| **s == base).count();
+        let anchor = if count == 0 { base.clone() } else { format!("{}-{}", base, count) };
+        seen.push(base);
+        out.push_str(&format!("{}- [{}](#{})\n", "  ".repeat(depth - min_depth), text, anchor));
+    }
+    out
+}
+
+fn toc(doc: &str) -> String {
+    let sections = outline::parse_sections(body(doc));
+    let headings: Vec<(usize, &str)> = sections
+        .iter()
+        .filter(|s| s.depth > 0)
+        .map(|s| (s.depth, heading_text(&s.heading)))
+        .collect();
+    heading_list(&headings, "_No headings yet._")
+}
+
+fn budget(doc: &str, comp: &Component) -> String {
+    let max = comp
+        .attrs
+        .get("max_tokens")
+        .and_then(|v| v.parse::<usize>().ok())
+        .filter(|&m| m > 0)
+        .unwrap_or(DEFAULT_MAX_TOKENS);
+    // Measured without generated content, so the figure doesn't drift as
+    // the gauge itself is rewritten
+    let tokens = strip_generated(doc).len().div_ceil(4);
+    let percent = tokens * 100 / max;
+    let filled = (tokens * GAUGE_WIDTH / max).min(GAUGE_WIDTH);
+    let mut out = format!(
+        "`{}{}` ~{} / {} tokens ({}%)\n",
+        "█".repeat(filled),
+        "░".repeat(GAUGE_WIDTH - filled),
+        tokens,
+        max,
+        percent
+    );
+    if percent >= 80 {
+        out.push_str("\nNearing the budget — consider `agent-doc compact`.\n");
+    }
+    out
+}
+
+fn session_card(doc: &str, file: &Path) -> String {
+    let fm = frontmatter::parse(doc).map(|(fm, _)| fm).unwrap_or_default();
+    let last_submit = crate::git::last_commit_mtime(file)
+        .ok()
+        .flatten()
+        .map_or_else(|| "—".to_string(), format_utc);
+    let value = |v: Option<&str>| v.unwrap_or("—").to_string();
+    format!(
+        "| Field | Value |\n|-------|-------|\n| Agent | {} |\n| Model | {} |\n| Session | {} |\n| Resume ID | {} |\n| Last submit | {} |\n",
+        value(fm.agent.as_deref()),
+        value(fm.model.as_deref()),
+        value(fm.session.as_deref()),
+        value(fm.resume.as_deref()),
+        last_submit
+    )
+}
+
+fn exchange_index(doc: &str, components: &[Component], comp: &Component) -> String {
+    let source = comp.attrs.get("source").map_or("exchange", String::as_str);
+    let Some(exchange) = component::find_by_path(components, source) else {
+        return format!("_No `{}` component._\n", source);
+    };
+    let headings: Vec<(usize, &str)> = exchange
+        .content(doc)
+        .lines()
+        .filter_map(|line| outline::heading_depth(line).map(|d| (d, heading_text(line))))
+        .collect();
+    heading_list(&headings, "_No exchanges yet._")
+}
+
+/// `YYYY-MM-DD HH:MM UTC`.
+fn format_utc(time: SystemTime) -> String {
+    let secs = time.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+    let (days, rem) = ((secs / 86_400) as i64, secs % 86_400);
+    // Civil date from days since the epoch (Howard Hinnant's algorithm)
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!("{:04}-{:02}-{:02} {:02}:{:02} UTC", year, month, day, rem / 3_600, rem % 3_600 / 60)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn selects_generators_by_name_or_attribute() {
+        let doc = "<!-- agent:toc -->\n<!-- /agent:toc -->\n<!-- agent:nav generate=toc -->\n<!-- /agent:nav -->\n\
+                   <!-- agent:notes -->\n<!-- /agent:notes -->\n<!-- agent:x generate=bogus -->\n<!-- /agent:x -->\n";
+        let kinds: Vec<Option<&str>> = component::parse(doc).unwrap().iter().map(kind).collect();
+        assert_eq!(kinds, vec![Some("toc"), Some("toc"), None, None]);
+    }
+
+    #[test]
+    fn toc_and_exchange_index() {
+        let doc = "---\nagent: claude\n---\n# Plan\n\n<!-- agent:toc -->\nstale\n<!-- /agent:toc -->\n\n## Goals & Scope\n\n## Exchange\n\n\
+                   <!-- agent:exchange -->\n### Re: setup\n\n### Re: setup\n<!-- /agent:exchange -->\n\
+                   <!-- agent:index generate=exchange-index -->\n<!-- /agent:index -->\n";
+        let result = regenerate(doc, Path::new("plan.md"));
+        assert!(result.contains(
+            "<!-- agent:toc -->\n- [Plan](#plan)\n  - [Goals & Scope](#goals--scope)\n  - [Exchange](#exchange)\n    - [Re: setup](#re-setup)\n    - [Re: setup](#re-setup-1)\n<!-- /agent:toc -->"
+        ));
+        assert!(result.contains("<!-- agent:index generate=exchange-index -->\n- [Re: setup](#re-setup)\n- [Re: setup](#re-setup-1)\n<!-- /agent:index -->"));
+        assert_eq!(regenerate(&result, Path::new("plan.md")), result, "idempotent");
+    }
+
+    #[test]
+    fn budget_and_session_card() {
+        let doc = format!(
+            "---\nagent_doc_session: abc\nagent: claude\n---\n<!-- agent:budget max_tokens=100 -->\n<!-- /agent:budget -->\n{}\n<!-- agent:session-card -->\n<!-- /agent:session-card -->\n",
+            "x".repeat(200)
+        );
+        let result = regenerate(&doc, Path::new("/nonexistent/doc.md"));
+        let tokens = strip_generated(&doc).len().div_ceil(4);
+        assert!(result.contains(&format!("` ~{} / 100 tokens ({}%)\n\nNearing the budget", tokens, tokens)));
+        assert!(result.contains("| Agent | claude |\n| Model | — |\n| Session | abc |\n| Resume ID | — |\n| Last submit | — |\n"));
+    }
+
+    #[test]
+    fn strip_generated_content() {
+        let doc = "<!-- agent:toc -->\n- [A](#a)\n<!-- /agent:toc -->\n# A\n<!-- agent:notes -->\nkeep\n<!-- /agent:notes -->\n";
+        assert_eq!(
+            strip_generated(doc),
+            "<!-- agent:toc -->\n<!-- /agent:toc -->\n# A\n<!-- agent:notes -->\nkeep\n<!-- /agent:notes -->\n"
+        );
+    }
+
+    #[test]
+    fn formats_utc_dates() {
+        assert_eq!(format_utc(UNIX_EPOCH), "1970-01-01 00:00 UTC");
+        assert_eq!(format_utc(UNIX_EPOCH + Duration::from_secs(1_709_210_096)), "2024-02-29 12:34 UTC");
+    }
+}
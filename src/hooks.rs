@@ -0,0 +1,341 @@
+//! Project lifecycle hooks: `.agent-doc/hooks.toml`.
+//!
+//! ```toml
+//! timeout = 30                  # default seconds per hook (default 30)
+//!
+//! [[pre-submit]]
+//! command = "scripts/check-secrets.sh"
+//! timeout = 5
+//! on_failure = "abort"          # abort | warn | ignore
+//!
+//! [[post-write]]
+//! command = "make docs"
+//! ```
+//!
+//! Each hook runs with `sh -c` in the project root, in its own process group
+//! (a timeout kills the whole group). It gets a JSON payload on
+//! stdin (`event`, `file`, plus event-specific fields) and `AGENT_DOC_EVENT`,
+//! `AGENT_DOC_FILE` and `AGENT_DOC_ROOT` in its environment. A hook fails if it
+//! exits non-zero or runs past its timeout; `on_failure` decides what happens
+//! then (default `abort` for `pre-submit`, `warn` otherwise).
+//!
+//! `pre-submit` hooks can rewrite the prompt: non-empty stdout replaces it for
+//! the next hook and the agent. Aborting a `pre-submit` hook vetoes the submit.
+
+use anyhow::{Context, Result, bail};
+use serde::Deserialize;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::mpsc;
+use std::time::{Duration, Instant};
+
+use crate::{dynamic, snapshot};
+
+const HOOKS_FILENAME: &str = ".agent-doc/hooks.toml";
+const DEFAULT_TIMEOUT_SECS: u64 = 30;
+
+/// A point in the session lifecycle that hooks can attach to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Event {
+    /// Before the prompt is sent to the agent (`submit`, `stream`, `watch`)
+    PreSubmit,
+    /// After the agent's response is received
+    PostResponse,
+    /// After agent-doc writes a response into the document
+    PostWrite,
+    /// After a merge records conflicts
+    OnConflict,
+    /// When a diff with user changes is found
+    OnPromptDetected,
+    /// When a streamed response finishes
+    OnStreamComplete,
+}
+
+impl Event {
+    pub fn name(self) -> &'static str {
+        match self {
+            Event::PreSubmit => "pre-submit",
+            Event::PostResponse => "post-response",
+            Event::PostWrite => "post-write",
+            Event::OnConflict => "on-conflict",
+            Event::OnPromptDetected => "on-prompt-detected",
+            Event::OnStreamComplete => "on-stream-complete",
+        }
+    }
+}
+
+/// What to do when a hook fails or times out.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum FailurePolicy {
+    /// Fail the command (for `pre-submit`: veto the submit)
+    Abort,
+    /// Log a warning and continue
+    Warn,
+    /// Continue silently
+    Ignore,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Hook {
+    command: String,
+    timeout: Option<u64>,
+    on_failure: Option<FailurePolicy>,
+}
+
+/// `.agent-doc/hooks.toml`.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+struct HooksConfig {
+    timeout: Option<u64>,
+    #[serde(default)]
+    pre_submit: Vec<Hook>,
+    #[serde(default)]
+    post_response: Vec<Hook>,
+    #[serde(default)]
+    post_write: Vec<Hook>,
+    #[serde(default)]
+    on_conflict: Vec<Hook>,
+    #[serde(default)]
+    on_prompt_detected: Vec<Hook>,
+    #[serde(default)]
+    on_stream_complete: Vec<Hook>,
+}
+
+impl HooksConfig {
+    fn hooks(&self, event: Event) -> &[Hook] {
+        match event {
+            Event::PreSubmit => &self.pre_submit,
+            Event::PostResponse => &self.post_response,
+            Event::PostWrite => &self.post_write,
+            Event::OnConflict => &self.on_conflict,
+            Event::OnPromptDetected => &self.on_prompt_detected,
+            Event::OnStreamComplete => &self.on_stream_complete,
+        }
+    }
+}
+
+/// Run the hooks for `event`. `data` (a JSON object) is merged into the
+/// payload. Hook stdout is echoed to stderr.
+pub fn fire(file: &Path, event: Event, data: serde_json::Value) -> Result<()> {
+    let Some((root, config)) = load(file)? else {
+        return Ok(());
+    };
+    let payload = payload(file, event, data);
+    for hook in config.hooks(event) {
+        if let Some(stdout) = run_one(hook, &config, event, file, &root, &payload)?
+            && !stdout.is_empty()
+        {
+            eprint!("{}", stdout);
+        }
+    }
+    Ok(())
+}
+
+/// Run the `pre-submit` hooks and return the prompt to send. Each hook sees
+/// the current prompt in its payload; non-empty stdout replaces it.
+pub fn pre_submit(file: &Path, prompt: &str, diff: &str, agent: &str) -> Result<String> {
+    let mut prompt = prompt.to_string();
+    let Some((root, config)) = load(file)? else {
+        return Ok(prompt);
+    };
+    for hook in config.hooks(Event::PreSubmit) {
+        let payload = payload(file, Event::PreSubmit, serde_json::json!({
+            "prompt": prompt,
+            "diff": diff,
+            "agent": agent,
+        }));
+        if let Some(stdout) = run_one(hook, &config, Event::PreSubmit, file, &root, &payload)?
+            && !stdout.trim().is_empty()
+        {
+            eprintln!("[hooks] pre-submit hook rewrote the prompt: {}", hook.command);
+            prompt = stdout;
+        }
+    }
+    Ok(prompt)
+}
+
+/// The project root and its hooks, if the file is in a project with a hooks file.
+fn load(file: &Path) -> Result<Option<(PathBuf, HooksConfig)>> {
+    let canonical = file.canonicalize().unwrap_or_else(|_| file.to_path_buf());
+    let Some(root) = snapshot::find_project_root(&canonical) else {
+        return Ok(None);
+    };
+    let path = root.join(HOOKS_FILENAME);
+    if !path.exists() {
+        return Ok(None);
+    }
+    let content = std::fs::read_to_string(&path)
+        .with_context(|| format!("failed to read {}", path.display()))?;
+    let config = toml::from_str(&content).with_context(|| format!("failed to parse {}", path.display()))?;
+    Ok(Some((root, config)))
+}
+
+fn payload(file: &Path, event: Event, data: serde_json::Value) -> serde_json::Value {
+    let mut payload = serde_json::json!({
+        "event": event.name(),
+        "file": file.to_string_lossy(),
+    });
+    if let (Some(map), serde_json::Value::Object(data)) = (payload.as_object_mut(), data) {
+        map.extend(data);
+    }
+    payload
+}
+
+/// Run one hook. Returns its stdout, or None if it failed under a
+/// non-aborting policy.
+fn run_one(
+    hook: &Hook,
+    config: &HooksConfig,
+    event: Event,
+    file: &Path,
+    root: &Path,
+    payload: &serde_json::Value,
+) -> Result<Option<String>> {
+    let timeout = Duration::from_secs(hook.timeout.or(config.timeout).unwrap_or(DEFAULT_TIMEOUT_SECS));
+    let policy = hook.on_failure.unwrap_or(match event {
+        Event::PreSubmit => FailurePolicy::Abort,
+        _ => FailurePolicy::Warn,
+    });
+    let failure = match execute(hook, event, file, root, payload, timeout) {
+        Ok(Ok(stdout)) => return Ok(Some(stdout)),
+        Ok(Err(reason)) => reason,
+        Err(e) => format!("{:#}", e),
+    };
+    match policy {
+        FailurePolicy::Abort if event == Event::PreSubmit => {
+            bail!("submit vetoed by pre-submit hook ({}): {}", failure, hook.command)
+        }
+        FailurePolicy::Abort => bail!("{} hook failed ({}): {}", event.name(), failure, hook.command),
+        FailurePolicy::Warn => {
+            eprintln!("[hooks] {} hook failed ({}), continuing: {}", event.name(), failure, hook.command);
+            Ok(None)
+        }
+        FailurePolicy::Ignore => Ok(None),
+    }
+}
+
+/// Spawn the hook and wait up to `timeout`. The inner error describes a
+/// hook failure (exit status or timeout); the outer one a spawn/IO error.
+fn execute(
+    hook: &Hook,
+    event: Event,
+    file: &Path,
+    root: &Path,
+    payload: &serde_json::Value,
+    timeout: Duration,
+) -> Result<std::result::Result<String, String>> {
+    let mut child = dynamic::in_own_group(Command::new("sh").args(["-c", &hook.command]))
+        .current_dir(root)
+        .env("AGENT_DOC_EVENT", event.name())
+        .env("AGENT_DOC_FILE", file.to_string_lossy().as_ref())
+        .env("AGENT_DOC_ROOT", root.to_string_lossy().as_ref())
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::inherit())
+        .spawn()
+        .with_context(|| format!("failed to run {} hook: {}", event.name(), hook.command))?;
+
+    // Write and read on threads so a hook that ignores stdin, or fills the
+    // stdout pipe, can't block us past the timeout
+    if let Some(mut stdin) = child.stdin.take() {
+        let input = payload.to_string();
+        std::thread::spawn(move || {
+            let _ = stdin.write_all(input.as_bytes());
+        });
+    }
+    let (tx, rx) = mpsc::channel();
+    if let Some(mut stdout) = child.stdout.take() {
+        std::thread::spawn(move || {
+            let mut buf = Vec::new();
+            let _ = stdout.read_to_end(&mut buf);
+            let _ = tx.send(buf);
+        });
+    }
+
+    let deadline = Instant::now() + timeout;
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break status;
+        }
+        if Instant::now() >= deadline {
+            dynamic::kill_group(&mut child);
+            return Ok(Err(format!("timed out after {}s", timeout.as_secs())));
+        }
+        std::thread::sleep(Duration::from_millis(20));
+    };
+    if !status.success() {
+        return Ok(Err(format!("exit status {}", status.code().unwrap_or(-1))));
+    }
+    let stdout = rx.recv_timeout(Duration::from_millis(500)).unwrap_or_default();
+    Ok(Ok(String::from_utf8_lossy(&stdout).into_owned()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn setup_project(hooks_toml: &str) -> (TempDir, PathBuf) {
+        let dir = TempDir::new().unwrap();
+        std::fs::create_dir_all(dir.path().join(".agent-doc")).unwrap();
+        std::fs::write(dir.path().join(HOOKS_FILENAME), hooks_toml).unwrap();
+        let doc = dir.path().join("doc.md");
+        std::fs::write(&doc, "# Doc\n").unwrap();
+        (dir, doc)
+    }
+
+    #[test]
+    fn pre_submit_rewrites_and_vetoes() {
+        let (_dir, doc) = setup_project(
+            "[[pre-submit]]\ncommand = \"true\"\n\n[[pre-submit]]\ncommand = \"echo rewritten\"\n",
+        );
+        assert_eq!(pre_submit(&doc, "original", "+x", "claude").unwrap(), "rewritten\n");
+
+        let (_dir, doc) = setup_project("[[pre-submit]]\ncommand = \"exit 3\"\n");
+        let err = pre_submit(&doc, "original", "+x", "claude").unwrap_err();
+        assert!(err.to_string().contains("vetoed by pre-submit hook (exit status 3)"), "{}", err);
+
+        let (_dir, doc) = setup_project("[[pre-submit]]\ncommand = \"exit 3\"\non_failure = \"warn\"\n");
+        assert_eq!(pre_submit(&doc, "original", "+x", "claude").unwrap(), "original");
+    }
+
+    #[test]
+    fn payload_and_environment() {
+        let (dir, doc) = setup_project(
+            "[[post-write]]\ncommand = \"cat > payload.json; echo $AGENT_DOC_EVENT > event.txt\"\n",
+        );
+        fire(&doc, Event::PostWrite, serde_json::json!({ "source": "write" })).unwrap();
+
+        let payload: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(dir.path().join("payload.json")).unwrap()).unwrap();
+        assert_eq!(payload["event"], "post-write");
+        assert_eq!(payload["source"], "write");
+        assert!(payload["file"].as_str().unwrap().ends_with("doc.md"));
+        assert_eq!(std::fs::read_to_string(dir.path().join("event.txt")).unwrap(), "post-write\n");
+    }
+
+    #[test]
+    fn timeouts_and_policies() {
+        let (_dir, doc) = setup_project("timeout = 1\n\n[[on-conflict]]\ncommand = \"sleep 5\"\n");
+        let start = Instant::now();
+        fire(&doc, Event::OnConflict, serde_json::json!({})).unwrap();
+        assert!(start.elapsed() < Duration::from_secs(4), "hook killed at the timeout");
+
+        let (dir, doc) = setup_project("timeout = 1\n\n[[post-write]]\ncommand = \"sleep 30 & echo $! > bg.pid; wait\"\n");
+        fire(&doc, Event::PostWrite, serde_json::json!({})).unwrap();
+        let pid = std::fs::read_to_string(dir.path().join("bg.pid")).unwrap();
+        std::thread::sleep(Duration::from_millis(200));
+        let stat = std::fs::read_to_string(format!("/proc/{}/stat", pid.trim())).unwrap_or_default();
+        assert!(stat.is_empty() || stat.rsplit(") ").next().is_some_and(|s| s.starts_with('Z')), "background job killed");
+
+        let (_dir, doc) = setup_project("[[post-response]]\ncommand = \"exit 1\"\non_failure = \"abort\"\n");
+        let err = fire(&doc, Event::PostResponse, serde_json::json!({})).unwrap_err();
+        assert!(err.to_string().contains("post-response hook failed (exit status 1)"));
+
+        let (_dir, doc) = setup_project("[[post-write]]\ncommand = \"true\"\non_failure = \"later\"\n");
+        assert!(fire(&doc, Event::PostWrite, serde_json::json!({})).is_err(), "invalid policy");
+    }
+}
@@ -1,14 +1,74 @@
-use anyhow::Result;
-use std::path::Path;
+//! `agent-doc init`: scaffold a new session document.
+//!
+//! Without `--template`, writes the built-in append or template skeleton.
+//! With `--template NAME`, renders a scaffold from `.agent-doc/templates/`
+//! (`NAME.md`, plus an optional `NAME.toml` components fragment) or from the
+//! built-in set. A project template shadows a built-in of the same name.
+//!
+//! A template is a markdown document. An optional first line
+//! `<!-- description -->` is shown by `--list-templates` and not copied. Its
+//! frontmatter provides defaults; `agent_doc_session` and `agent` are set by
+//! `init`. `{{title}}`, `{{agent}}`, `{{session}}` and `--var KEY=VALUE`
+//! placeholders are substituted.
+
+use anyhow::{Context, Result};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
 use uuid::Uuid;
 
-use crate::config::Config;
+use crate::config::{Config, Layers};
+
+const TEMPLATES_DIR: &str = ".agent-doc/templates";
+const COMPONENTS_FILENAME: &str = ".agent-doc/components.toml";
+
+/// Built-in templates: (name, document, components fragment).
+const BUILTIN_TEMPLATES: &[(&str, &str, &str)] = &[
+    ("research", include_str!("templates/research.md"), include_str!("templates/research.toml")),
+    ("code-review", include_str!("templates/code-review.md"), include_str!("templates/code-review.toml")),
+    ("planning", include_str!("templates/planning.md"), include_str!("templates/planning.toml")),
+    ("bug-triage", include_str!("templates/bug-triage.md"), include_str!("templates/bug-triage.toml")),
+];
+
+/// A document scaffold.
+#[derive(Debug, Clone)]
+struct Template {
+    name: String,
+    description: String,
+    /// Template text without the description line.
+    text: String,
+    /// `components.toml` fragment, if any.
+    components: Option<String>,
+    /// Path of a project template; `None` for built-ins.
+    path: Option<PathBuf>,
+}
+
+impl Template {
+    fn new(name: &str, source: &str, components: Option<String>, path: Option<PathBuf>) -> Self {
+        let (description, text) = match source.split_once('\n') {
+            Some((first, rest)) if is_description(first) => {
+                let inner = first.trim().trim_start_matches("<!--").trim_end_matches("-->");
+                (inner.trim().to_string(), rest.to_string())
+            }
+            _ => (String::new(), source.to_string()),
+        };
+        Self { name: name.to_string(), description, text, components, path }
+    }
+}
+
+fn is_description(line: &str) -> bool {
+    let line = line.trim();
+    line.starts_with("<!--")
+        && line.ends_with("-->")
+        && !line.trim_start_matches("<!--").trim_start().starts_with("agent:")
+}
 
 pub fn run(
     file: &Path,
     title: Option<&str>,
     agent: Option<&str>,
     mode: Option<&str>,
+    template: Option<&str>,
+    vars: &[String],
     config: &Config,
 ) -> Result<()> {
     if file.exists() {
@@ -16,22 +76,31 @@ pub fn run(
     }
 
     let title = title.unwrap_or("Untitled Session");
-    let agent = agent
-        .or(config.default_agent.as_deref())
-        .unwrap_or("claude");
     let session_id = Uuid::new_v4();
-    let mode = mode.unwrap_or("append");
+    let default_agent = Layers::for_path(config, file)?.agent();
 
-    let content = if mode == "template" || mode == "stream" {
-        format!(
-            "---\nagent_doc_session: {}\nagent: {}\nagent_doc_format: template\nagent_doc_write: crdt\n---\n\n# {}\n\n## Exchange\n\n<!-- agent:exchange -->\n<!-- /agent:exchange -->\n",
-            session_id, agent, title
-        )
-    } else {
-        format!(
-            "---\nagent_doc_session: {}\nagent: {}\n---\n\n# Session: {}\n\n## User\n\n",
-            session_id, agent, title
-        )
+    let (content, components) = match template {
+        Some(name) => {
+            let template = find_template(name, file)?;
+            let content = render(&template, title, agent, &session_id.to_string(), vars, &default_agent)?;
+            (content, template.components.map(|c| (template.name, c)))
+        }
+        None => {
+            let agent = agent.unwrap_or(&default_agent);
+            let mode = mode.unwrap_or("append");
+            let content = if mode == "template" || mode == "stream" {
+                format!(
+                    "---\nagent_doc_session: {}\nagent: {}\nagent_doc_format: template\nagent_doc_write: crdt\n---\n\n# {}\n\n## Exchange\n\n<!-- agent:exchange -->\n<!-- /agent:exchange -->\n",
+                    session_id, agent, title
+                )
+            } else {
+                format!(
+                    "---\nagent_doc_session: {}\nagent: {}\n---\n\n# Session: {}\n\n## User\n\n",
+                    session_id, agent, title
+                )
+            };
+            (content, None)
+        }
     };
 
     if let Some(parent) = file.parent()
@@ -41,5 +110,295 @@ pub fn run(
     }
     std::fs::write(file, content)?;
     eprintln!("Created {}", file.display());
+
+    if let Some((name, fragment)) = components {
+        add_component_configs(file, &name, &fragment)?;
+    }
+    Ok(())
+}
+
+/// `agent-doc init --list-templates`: print the available templates.
+pub fn list_templates() -> Result<()> {
+    let cwd = std::env::current_dir()?;
+    for template in templates(&cwd)? {
+        let source = match &template.path {
+            Some(path) => path.display().to_string(),
+            None => "built-in".to_string(),
+        };
+        println!("{:<14} {:<60} ({})", template.name, template.description, source);
+    }
+    Ok(())
+}
+
+/// The nearest directory containing `.agent-doc/`, searching up from `path`
+/// (or its nearest existing ancestor).
+fn project_root(path: &Path) -> Option<PathBuf> {
+    let start = path.ancestors().find(|d| !d.as_os_str().is_empty() && d.exists()).unwrap_or(Path::new("."));
+    let start = start.canonicalize().ok()?;
+    start.ancestors().find(|d| d.join(".agent-doc").is_dir()).map(Path::to_path_buf)
+}
+
+/// Project templates followed by the built-ins they don't shadow, by name.
+fn templates(near: &Path) -> Result<Vec<Template>> {
+    let mut templates = Vec::new();
+    if let Some(root) = project_root(near) {
+        let dir = root.join(TEMPLATES_DIR);
+        if dir.is_dir() {
+            let mut paths: Vec<PathBuf> = std::fs::read_dir(&dir)
+                .with_context(|| format!("failed to read {}", dir.display()))?
+                .filter_map(|entry| entry.ok().map(|e| e.path()))
+                .filter(|p| p.extension().is_some_and(|ext| ext == "md"))
+                .collect();
+            paths.sort();
+            for path in paths {
+                let Some(name) = path.file_stem().and_then(|s| s.to_str()) else {
+                    continue;
+                };
+                let source = std::fs::read_to_string(&path)
+                    .with_context(|| format!("failed to read {}", path.display()))?;
+                let fragment_path = path.with_extension("toml");
+                let components = if fragment_path.exists() {
+                    Some(
+                        std::fs::read_to_string(&fragment_path)
+                            .with_context(|| format!("failed to read {}", fragment_path.display()))?,
+                    )
+                } else {
+                    None
+                };
+                templates.push(Template::new(name, &source, components, Some(path.clone())));
+            }
+        }
+    }
+    for (name, source, components) in BUILTIN_TEMPLATES {
+        if !templates.iter().any(|t| t.name == *name) {
+            templates.push(Template::new(name, source, Some(components.to_string()), None));
+        }
+    }
+    Ok(templates)
+}
+
+fn find_template(name: &str, file: &Path) -> Result<Template> {
+    let templates = templates(file)?;
+    let names: Vec<&str> = templates.iter().map(|t| t.name.as_str()).collect();
+    templates
+        .iter()
+        .find(|t| t.name == name)
+        .cloned()
+        .with_context(|| format!("unknown template '{}' (available: {})", name, names.join(", ")))
+}
+
+/// Render a template into document content.
+fn render(
+    template: &Template,
+    title: &str,
+    agent: Option<&str>,
+    session_id: &str,
+    vars: &[String],
+    default_agent: &str,
+) -> Result<String> {
+    let (yaml, body) = split_frontmatter(&template.text);
+    let mut defaults: Vec<&str> = Vec::new();
+    let mut template_agent = None;
+    for line in yaml.lines() {
+        match line.split_once(':').map(|(key, value)| (key.trim_end(), value.trim())) {
+            Some(("agent_doc_session", _)) if !line.starts_with(' ') => {}
+            Some(("agent", value)) if !line.starts_with(' ') => template_agent = Some(value),
+            _ => defaults.push(line),
+        }
+    }
+    let agent = agent
+        .or(template_agent.filter(|a| !a.is_empty()))
+        .unwrap_or(default_agent);
+
+    let mut values: HashMap<String, String> = HashMap::from([
+        ("title".to_string(), title.to_string()),
+        ("agent".to_string(), agent.to_string()),
+        ("session".to_string(), session_id.to_string()),
+    ]);
+    for var in vars {
+        let (key, value) = var
+            .split_once('=')
+            .with_context(|| format!("invalid --var '{}' (expected KEY=VALUE)", var))?;
+        values.insert(key.trim().to_string(), value.to_string());
+    }
+
+    let mut content = format!("---\nagent_doc_session: {}\nagent: {}\n", session_id, agent);
+    for line in defaults {
+        content.push_str(line);
+        content.push('\n');
+    }
+    content.push_str("---\n");
+    if yaml.is_empty() {
+        content.push('\n');
+    }
+    content.push_str(body);
+    let content = substitute(&content, &values)
+        .with_context(|| format!("failed to render template '{}' (define variables with --var NAME=VALUE)", template.name))?;
+    crate::frontmatter::parse(&content)
+        .with_context(|| format!("template '{}' has invalid frontmatter", template.name))?;
+    Ok(content)
+}
+
+/// Split template text into its frontmatter YAML (without the `---` lines)
+/// and the body after the closing `---`.
+fn split_frontmatter(text: &str) -> (&str, &str) {
+    if let Some(rest) = text.strip_prefix("---\n")
+        && let Some(end) = rest.find("\n---\n")
+    {
+        return (&rest[..end + 1], &rest[end + 5..]);
+    }
+    ("", text)
+}
+
+/// Replace `{{name}}` placeholders. An unknown name is an error.
+pub(crate) fn substitute(text: &str, values: &HashMap<String, String>) -> Result<String> {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find("{{") {
+        let Some(len) = rest[start + 2..].find("}}") else {
+            break;
+        };
+        let name = rest[start + 2..start + 2 + len].trim();
+        let value = values
+            .get(name)
+            .with_context(|| format!("undefined variable '{}'", name))?;
+        out.push_str(&rest[..start]);
+        out.push_str(value);
+        rest = &rest[start + 4 + len..];
+    }
+    out.push_str(rest);
+    Ok(out)
+}
+
+/// Add a template's component settings to the project's `components.toml`.
+/// Components the project already configures are left alone.
+fn add_component_configs(file: &Path, template: &str, fragment: &str) -> Result<()> {
+    let fragment: toml::Table = toml::from_str(fragment)
+        .with_context(|| format!("template '{}' has an invalid components fragment", template))?;
+    if fragment.is_empty() {
+        return Ok(());
+    }
+    let root = match project_root(file) {
+        Some(root) => root,
+        None => file
+            .parent()
+            .filter(|p| !p.as_os_str().is_empty())
+            .unwrap_or(Path::new("."))
+            .to_path_buf(),
+    };
+    let path = root.join(COMPONENTS_FILENAME);
+    let existing = if path.exists() {
+        std::fs::read_to_string(&path).with_context(|| format!("failed to read {}", path.display()))?
+    } else {
+        String::new()
+    };
+    let configured: toml::Table = toml::from_str(&existing)
+        .with_context(|| format!("failed to parse {}", path.display()))?;
+
+    let mut added = toml::Table::new();
+    for (name, config) in fragment {
+        if configured.contains_key(&name) {
+            eprintln!("[init] {} already configures '{}', keeping it", COMPONENTS_FILENAME, name);
+        } else {
+            added.insert(name, config);
+        }
+    }
+    if added.is_empty() {
+        return Ok(());
+    }
+    let names: Vec<String> = added.keys().cloned().collect();
+    let mut content = existing;
+    if !content.is_empty() {
+        if !content.ends_with('\n') {
+            content.push('\n');
+        }
+        content.push('\n');
+    }
+    content.push_str(&toml::to_string(&added)?);
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::write(&path, content).with_context(|| format!("failed to write {}", path.display()))?;
+    eprintln!("[init] added [{}] to {}", names.join("], ["), path.display());
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn setup_project() -> TempDir {
+        let dir = TempDir::new().unwrap();
+        std::fs::create_dir_all(dir.path().join(".agent-doc")).unwrap();
+        dir
+    }
+
+    #[test]
+    fn builtin_templates_render() {
+        let dir = setup_project();
+        for (name, _, _) in BUILTIN_TEMPLATES {
+            let file = dir.path().join(format!("{}.md", name));
+            run(&file, Some("Topic"), None, None, Some(name), &[], &Config::default()).unwrap();
+            let content = std::fs::read_to_string(&file).unwrap();
+            let (fm, body) = crate::frontmatter::parse(&content).unwrap();
+            assert!(fm.session.is_some(), "{}", name);
+            assert_eq!(fm.agent.as_deref(), Some("claude"));
+            assert!(body.contains("Topic"), "{}", name);
+            assert!(body.contains("<!-- agent:exchange -->"), "{}", name);
+            assert!(!content.contains("{{"), "{}", name);
+            crate::component::parse(&content).unwrap();
+        }
+        let configs = std::fs::read_to_string(dir.path().join(COMPONENTS_FILENAME)).unwrap();
+        assert!(configs.contains("[open-questions]"));
+    }
+
+    #[test]
+    fn project_template_with_vars() {
+        let dir = setup_project();
+        let template_dir = dir.path().join(TEMPLATES_DIR);
+        std::fs::create_dir_all(&template_dir).unwrap();
+        std::fs::write(
+            template_dir.join("standup.md"),
+            "<!-- Daily standup -->\n---\nagent: codex\nagent_doc_format: template\n---\n\n# {{title}} ({{ team }})\n",
+        )
+        .unwrap();
+        std::fs::write(template_dir.join("standup.toml"), "[blockers]\nmode = \"checklist\"\n").unwrap();
+        std::fs::write(dir.path().join(COMPONENTS_FILENAME), "[status]\nmode = \"replace\"\n").unwrap();
+
+        let file = dir.path().join("notes/today.md");
+        let vars = vec!["team=core".to_string()];
+        run(&file, Some("Standup"), None, None, Some("standup"), &vars, &Config::default()).unwrap();
+        let content = std::fs::read_to_string(&file).unwrap();
+        assert!(content.starts_with("---\nagent_doc_session: "));
+        assert!(content.contains("\nagent: codex\nagent_doc_format: template\n---\n\n# Standup (core)\n"));
+        assert!(!content.contains("Daily standup"));
+
+        let configs = std::fs::read_to_string(dir.path().join(COMPONENTS_FILENAME)).unwrap();
+        assert_eq!(configs, "[status]\nmode = \"replace\"\n\n[blockers]\nmode = \"checklist\"\n");
+
+        let listed = templates(&file).unwrap();
+        let standup = listed.iter().find(|t| t.name == "standup").unwrap();
+        assert_eq!(standup.description, "Daily standup");
+        assert!(listed.iter().any(|t| t.name == "research" && t.path.is_none()));
+    }
+
+    #[test]
+    fn template_errors() {
+        let dir = setup_project();
+        let file = dir.path().join("doc.md");
+        let err = run(&file, None, None, None, Some("nope"), &[], &Config::default()).unwrap_err();
+        assert!(err.to_string().contains("unknown template 'nope'"));
+
+        let templates = dir.path().join(TEMPLATES_DIR);
+        std::fs::create_dir_all(&templates).unwrap();
+        std::fs::write(templates.join("x.md"), "# {{missing}}\n").unwrap();
+        let err = run(&file, None, None, None, Some("x"), &[], &Config::default()).unwrap_err();
+        assert!(format!("{:#}", err).contains("undefined variable 'missing'"));
+        assert!(!file.exists());
+
+        let vars = vec!["novalue".to_string()];
+        let err = run(&file, None, None, None, Some("research"), &vars, &Config::default()).unwrap_err();
+        assert!(err.to_string().contains("invalid --var 'novalue'"));
+    }
+}
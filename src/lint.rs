@@ -0,0 +1,388 @@
+//! `agent-doc lint` — Report malformed component markers and related mistakes.
+//!
+//! `component::parse` stops at the first bad marker, and many mistakes (a
+//! marker inside a code fence, a duplicate name) aren't errors at all — they
+//! just make patches land somewhere unexpected. Lint scans the whole document
+//! and reports every problem with its line and column:
+//!
+//! - unclosed, unmatched and crossing markers, invalid names and attributes;
+//! - duplicate component names and markers inside code fences;
+//! - invalid frontmatter, and deprecated, unknown or misspelled frontmatter
+//!   keys (see [`crate::schema`]);
+//! - patch blocks in a pending response that match no component;
+//! - `components.toml` entries that match no component in the project.
+
+use anyhow::{Context, Result};
+use serde::Serialize;
+use std::collections::{BTreeSet, HashMap};
+use std::path::{Path, PathBuf};
+
+use crate::{component, frontmatter, gc, schema, snapshot, template};
+
+const COMPONENTS_FILENAME: &str = ".agent-doc/components.toml";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+/// A problem at a position in a file.
+#[derive(Debug, Clone, Serialize)]
+pub struct Diagnostic {
+    pub file: PathBuf,
+    /// 1-based line.
+    pub line: usize,
+    /// 1-based column (in characters).
+    pub column: usize,
+    pub severity: Severity,
+    /// Stable identifier for the kind of problem (e.g. `unclosed-marker`).
+    pub code: &'static str,
+    pub message: String,
+}
+
+/// Run the lint command. Returns an error if any error-level problems are found.
+pub fn run(files: &[PathBuf], json: bool) -> Result<()> {
+    let mut diagnostics = Vec::new();
+    let mut roots = BTreeSet::new();
+    for file in files {
+        diagnostics.extend(check_file(file)?);
+        let path = file.canonicalize().unwrap_or_else(|_| file.clone());
+        if let Some(root) = snapshot::find_project_root(&path) {
+            roots.insert(root);
+        }
+    }
+    for root in &roots {
+        diagnostics.extend(check_configs(root)?);
+    }
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&diagnostics)?);
+    } else {
+        for d in &diagnostics {
+            let severity = match d.severity {
+                Severity::Error => "error",
+                Severity::Warning => "warning",
+            };
+            println!("{}:{}:{}: {}[{}]: {}", d.file.display(), d.line, d.column, severity, d.code, d.message);
+        }
+    }
+
+    let errors = diagnostics.iter().filter(|d| d.severity == Severity::Error).count();
+    let warnings = diagnostics.len() - errors;
+    if errors > 0 {
+        anyhow::bail!("{} error(s), {} warning(s)", errors, warnings);
+    }
+    if !json {
+        eprintln!("[lint] {} file(s) checked, {} warning(s)", files.len(), warnings);
+    }
+    Ok(())
+}
+
+/// Lint a single document: markers, frontmatter and its pending response.
+pub fn check_file(file: &Path) -> Result<Vec<Diagnostic>> {
+    let content = std::fs::read_to_string(file)
+        .with_context(|| format!("failed to read {}", file.display()))?;
+    let mut diagnostics = check_frontmatter(file, &content);
+    diagnostics.extend(check_markers(file, &content));
+    diagnostics.extend(check_pending(file, &content)?);
+    Ok(diagnostics)
+}
+
+/// 1-based (line, column) of a byte offset.
+fn position(text: &str, offset: usize) -> (usize, usize) {
+    let before = &text[..offset];
+    let line = before.matches('\n').count() + 1;
+    let column = before.rsplit('\n').next().unwrap_or("").chars().count() + 1;
+    (line, column)
+}
+
+fn diagnostic(file: &Path, text: &str, offset: usize, severity: Severity, code: &'static str, message: String) -> Diagnostic {
+    let (line, column) = position(text, offset);
+    Diagnostic { file: file.to_path_buf(), line, column, severity, code, message }
+}
+
+fn check_frontmatter(file: &Path, content: &str) -> Vec<Diagnostic> {
+    let mut diagnostics = Vec::new();
+    if let Err(e) = frontmatter::parse(content) {
+        diagnostics.push(diagnostic(file, content, 0, Severity::Error, "invalid-frontmatter", format!("{:#}", e)));
+        return diagnostics;
+    }
+    for problem in schema::validate(content) {
+        diagnostics.push(diagnostic(file, content, problem.offset, Severity::Warning, problem.code, problem.message));
+    }
+    diagnostics
+}
+
+fn check_markers(file: &Path, doc: &str) -> Vec<Diagnostic> {
+    let mut diagnostics = Vec::new();
+    let code_ranges = component::find_code_ranges(doc);
+    // Open markers: (name, offset)
+    let mut stack: Vec<(String, usize)> = Vec::new();
+    let mut defined: HashMap<String, usize> = HashMap::new();
+    let mut pos = 0;
+
+    while let Some(found) = doc[pos..].find("<!--") {
+        let start = pos + found;
+        let Some(end) = component::find_comment_end(doc.as_bytes(), start + 4) else {
+            break;
+        };
+        pos = end;
+        let inner = doc[start + 4..end - 3].trim();
+        if !inner.starts_with("agent:") && !inner.starts_with("/agent:") {
+            continue;
+        }
+        let at = |severity, code, message| diagnostic(file, doc, start, severity, code, message);
+
+        if let Some(&(range_start, _)) = code_ranges.iter().find(|&&(s, e)| start >= s && start < e) {
+            // Markers in inline code are documentation; in a fence they're usually a mistake
+            let at_line_start = range_start == 0 || doc.as_bytes()[range_start - 1] == b'\n';
+            if at_line_start && (doc[range_start..].starts_with("```") || doc[range_start..].starts_with("~~~")) {
+                diagnostics.push(at(
+                    Severity::Warning,
+                    "marker-in-code",
+                    format!("<!-- {} --> is inside a code fence and is ignored", inner),
+                ));
+            }
+            continue;
+        }
+
+        if let Some(name) = inner.strip_prefix("/agent:") {
+            let name = name.trim();
+            if !component::is_valid_name(name) {
+                diagnostics.push(at(Severity::Error, "invalid-name", invalid_name(name)));
+                continue;
+            }
+            match stack.iter().rposition(|(n, _)| n == name) {
+                Some(i) => {
+                    let (line, _) = position(doc, start);
+                    for (open, open_start) in stack.drain(i + 1..) {
+                        diagnostics.push(diagnostic(
+                            file,
+                            doc,
+                            open_start,
+                            Severity::Error,
+                            "crossing-markers",
+                            format!("<!-- agent:{} --> is still open when <!-- /agent:{} --> (line {}) closes", open, name, line),
+                        ));
+                    }
+                    stack.pop();
+                }
+                None => diagnostics.push(at(
+                    Severity::Error,
+                    "unmatched-close",
+                    format!("<!-- /agent:{} --> has no matching <!-- agent:{} -->", name, name),
+                )),
+            }
+        } else if let Some(rest) = inner.strip_prefix("agent:") {
+            let (name, attrs) = component::split_name(rest);
+            if !component::is_valid_name(name) {
+                diagnostics.push(at(Severity::Error, "invalid-name", invalid_name(name)));
+                continue;
+            }
+            if let Err(e) = component::parse_attrs(attrs) {
+                diagnostics.push(at(Severity::Error, "invalid-attributes", format!("<!-- agent:{} -->: {}", name, e)));
+            }
+            match defined.get(name) {
+                Some(&first) => diagnostics.push(at(
+                    Severity::Warning,
+                    "duplicate-name",
+                    format!(
+                        "component '{}' is already defined on line {}; patches by name target the top-level one, else the first",
+                        name,
+                        position(doc, first).0
+                    ),
+                )),
+                None => {
+                    defined.insert(name.to_string(), start);
+                }
+            }
+            stack.push((name.to_string(), start));
+        }
+    }
+
+    for (name, start) in stack {
+        diagnostics.push(diagnostic(
+            file,
+            doc,
+            start,
+            Severity::Error,
+            "unclosed-marker",
+            format!("<!-- agent:{} --> has no matching <!-- /agent:{} -->", name, name),
+        ));
+    }
+    diagnostics.sort_by_key(|d| (d.line, d.column));
+    diagnostics
+}
+
+fn invalid_name(name: &str) -> String {
+    format!("invalid component name '{}' (expected [a-zA-Z0-9][a-zA-Z0-9-]*)", name)
+}
+
+/// Check patch blocks in the document's pending response (if any) against
+/// the document's components.
+fn check_pending(file: &Path, doc: &str) -> Result<Vec<Diagnostic>> {
+    let mut diagnostics = Vec::new();
+    let Ok(pending) = snapshot::pending_path_for(file) else {
+        return Ok(diagnostics);
+    };
+    if !pending.exists() {
+        return Ok(diagnostics);
+    }
+    let response = std::fs::read_to_string(&pending)
+        .with_context(|| format!("failed to read {}", pending.display()))?;
+    let (patches, _, invalid) = template::parse_patches_checked(&response);
+    for bad in invalid {
+        diagnostics.push(diagnostic(&pending, &response, bad.offset, Severity::Error, "invalid-patch", bad.error));
+    }
+    let components = component::parse(doc).unwrap_or_default();
+    let available: Vec<&str> = components.iter().map(|c| c.name.as_str()).collect();
+    for patch in &patches {
+        if patch.name == "frontmatter"
+            || patch.placement.is_some()
+            || component::find_by_path(&components, &patch.name).is_some()
+        {
+            continue;
+        }
+        let offset = response.find(&format!("<!-- patch:{} ", patch.name)).unwrap_or(0);
+        diagnostics.push(diagnostic(
+            &pending,
+            &response,
+            offset,
+            Severity::Warning,
+            "unknown-patch-target",
+            format!(
+                "pending patch '{}' matches no component in {} and would go to exchange (available: {})",
+                patch.name,
+                file.display(),
+                available.join(", ")
+            ),
+        ));
+    }
+    Ok(diagnostics)
+}
+
+/// Check the project's `components.toml` against the components used by its documents.
+fn check_configs(root: &Path) -> Result<Vec<Diagnostic>> {
+    let mut diagnostics = Vec::new();
+    let path = root.join(COMPONENTS_FILENAME);
+    if !path.exists() {
+        return Ok(diagnostics);
+    }
+    let content = std::fs::read_to_string(&path)
+        .with_context(|| format!("failed to read {}", path.display()))?;
+    let table: toml::Table = match toml::from_str(&content) {
+        Ok(table) => table,
+        Err(e) => {
+            let offset = e.span().map_or(0, |span| span.start);
+            diagnostics.push(diagnostic(&path, &content, offset, Severity::Error, "invalid-config", e.message().to_string()));
+            return Ok(diagnostics);
+        }
+    };
+
+    let mut docs = Vec::new();
+    gc::find_markdown(root, &mut docs)?;
+    let mut used = BTreeSet::new();
+    for doc in docs {
+        if let Ok(text) = std::fs::read_to_string(&doc) {
+            used.extend(component::parse(&text).unwrap_or_default().into_iter().map(|c| c.name));
+        }
+    }
+
+    for name in table.keys() {
+        if used.contains(name) {
+            continue;
+        }
+        let offset = content
+            .find(&format!("[{}]", name))
+            .or_else(|| content.find(&format!("[\"{}\"]", name)))
+            .unwrap_or(0);
+        diagnostics.push(diagnostic(
+            &path,
+            &content,
+            offset,
+            Severity::Warning,
+            "unknown-component",
+            format!("[{}] matches no component in the project's documents", name),
+        ));
+    }
+    Ok(diagnostics)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn codes(diagnostics: &[Diagnostic]) -> Vec<(&'static str, usize, usize)> {
+        diagnostics.iter().map(|d| (d.code, d.line, d.column)).collect()
+    }
+
+    #[test]
+    fn reports_marker_problems_with_positions() {
+        let doc = "\
+<!-- agent:a -->
+<!-- agent:b -->
+<!-- /agent:a -->
+  <!-- /agent:c -->
+<!-- agent:_bad -->
+<!-- agent:a mode=\"x -->
+";
+        let diagnostics = check_markers(Path::new("doc.md"), doc);
+        assert_eq!(
+            codes(&diagnostics),
+            vec![
+                ("crossing-markers", 2, 1),
+                ("unmatched-close", 4, 3),
+                ("invalid-name", 5, 1),
+                ("invalid-attributes", 6, 1),
+                ("duplicate-name", 6, 1),
+                ("unclosed-marker", 6, 1),
+            ]
+        );
+        assert!(diagnostics[0].message.contains("(line 3)"));
+    }
+
+    #[test]
+    fn markers_in_code() {
+        let doc = "```\n<!-- agent:x -->\n```\n\nUse `<!-- agent:y -->` markers.\n<!-- agent:ok -->\n<!-- /agent:ok -->\n";
+        let diagnostics = check_markers(Path::new("doc.md"), doc);
+        assert_eq!(codes(&diagnostics), vec![("marker-in-code", 2, 1)]);
+    }
+
+    #[test]
+    fn deprecated_frontmatter_keys() {
+        let doc = "---\nsession: abc\nagent: claude\nresponse_mode: template\n---\n\nBody\n";
+        let diagnostics = check_frontmatter(Path::new("doc.md"), doc);
+        assert_eq!(codes(&diagnostics), vec![("deprecated-key", 2, 1), ("deprecated-key", 4, 1)]);
+        assert!(diagnostics[0].message.contains("use agent_doc_session"));
+
+        let doc = "---\ntitle: Notes\nagnet: claude\nagent_doc_stream:\n  intervall: 50\n---\n";
+        let diagnostics = check_frontmatter(Path::new("doc.md"), doc);
+        assert_eq!(codes(&diagnostics), vec![("unknown-key", 3, 1), ("unknown-key", 5, 1)]);
+
+        let diagnostics = check_frontmatter(Path::new("doc.md"), "---\nagent: [\n---\n");
+        assert_eq!(diagnostics[0].code, "invalid-frontmatter");
+    }
+
+    #[test]
+    fn pending_patches_and_configs() {
+        let dir = TempDir::new().unwrap();
+        std::fs::create_dir_all(dir.path().join(".agent-doc")).unwrap();
+        let doc = dir.path().join("doc.md");
+        std::fs::write(&doc, "<!-- agent:status -->\n<!-- /agent:status -->\n").unwrap();
+        std::fs::write(dir.path().join(COMPONENTS_FILENAME), "[status]\nmode = \"replace\"\n\n[old-log]\nmode = \"append\"\n")
+            .unwrap();
+        crate::recover::save_pending(&doc, "<!-- patch:status -->\nok\n<!-- /patch:status -->\n<!-- patch:stat -->\nx\n<!-- /patch:stat -->\n")
+            .unwrap();
+
+        let diagnostics = check_file(&doc).unwrap();
+        assert_eq!(codes(&diagnostics), vec![("unknown-patch-target", 4, 1)]);
+        assert!(diagnostics[0].message.contains("pending patch 'stat'"));
+
+        let diagnostics = check_configs(dir.path()).unwrap();
+        assert_eq!(codes(&diagnostics), vec![("unknown-component", 4, 1)]);
+        assert!(diagnostics[0].message.contains("[old-log]"));
+    }
+}
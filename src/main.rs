@@ -1,23 +1,35 @@
 mod agent;
 mod audit_docs;
 mod autoclaim;
+mod checklist;
 mod claim;
 mod clean;
 mod commands;
 mod compact;
 mod config;
+mod config_cmd;
+mod conflicts;
 mod crdt;
+mod crdt_log;
 mod convert;
 mod diff;
+mod doctor;
+mod dynamic;
 mod focus;
 mod frontmatter;
+mod gc;
+mod generate;
 mod git;
+mod hooks;
 mod init;
 mod layout;
+mod lint;
 mod merge;
+mod migrate;
 mod mode;
 mod outline;
 mod patch;
+mod patch_ops;
 mod plugin;
 mod prompt;
 mod component;
@@ -25,6 +37,7 @@ mod recover;
 mod reset;
 mod resync;
 mod route;
+mod schema;
 mod sessions;
 mod skill;
 mod snapshot;
@@ -32,10 +45,13 @@ mod start;
 mod stream;
 mod submit;
 mod sync;
+mod table;
 mod template;
 mod upgrade;
 mod watch;
 mod write;
+#[cfg(unix)]
+mod ysync;
 
 use anyhow::Context;
 use clap::{Parser, Subcommand, ValueEnum};
@@ -83,16 +99,25 @@ enum Commands {
     },
     /// Scaffold a new session document
     Init {
-        /// Path for the new session document
-        file: PathBuf,
+        /// Path for the new session document (omit with --list-templates)
+        file: Option<PathBuf>,
         /// Session title
         title: Option<String>,
         /// Agent backend to use
         #[arg(long)]
         agent: Option<String>,
         /// Document mode: append (default) or template
-        #[arg(long)]
+        #[arg(long, conflicts_with = "template")]
         mode: Option<String>,
+        /// Scaffold from a template (.agent-doc/templates/ or built-in)
+        #[arg(long)]
+        template: Option<String>,
+        /// Template variable (repeatable)
+        #[arg(long = "var", value_name = "KEY=VALUE", requires = "template")]
+        vars: Vec<String>,
+        /// List available templates instead of creating a document
+        #[arg(long)]
+        list_templates: bool,
     },
     /// Preview the diff that would be sent
     Diff {
@@ -196,7 +221,7 @@ enum Commands {
     Patch {
         /// Path to the document
         file: PathBuf,
-        /// Component name (e.g. "status", "log")
+        /// Component name or nested path (e.g. "status", "plan/step-1")
         component: String,
         /// Replacement content (reads from stdin if omitted)
         content: Option<String>,
@@ -209,13 +234,19 @@ enum Commands {
         /// Show watch daemon status
         #[arg(long)]
         status: bool,
-        /// Debounce delay in milliseconds
-        #[arg(long, default_value = "500")]
-        debounce: u64,
+        /// Debounce delay in milliseconds [default: watch_debounce_ms from config, or 500]
+        #[arg(long)]
+        debounce: Option<u64>,
         /// Maximum agent-triggered cycles per file
         #[arg(long, default_value = "3")]
         max_cycles: u32,
     },
+    /// Serve a document's CRDT over a local y-sync socket for editor plugins
+    #[cfg(unix)]
+    Serve {
+        /// Path to the session document
+        file: PathBuf,
+    },
     /// Display markdown outline with section structure and token counts
     Outline {
         /// Path to the markdown document
@@ -226,6 +257,74 @@ enum Commands {
     },
     /// Validate sessions.json against live tmux panes, remove stale entries
     Resync,
+    /// Remove orphaned snapshot, CRDT, lock and pending files
+    Gc {
+        /// Report stale files without removing them
+        #[arg(long)]
+        dry_run: bool,
+        /// Also remove archives older than this (e.g. 30d, 12h, 2w)
+        #[arg(long)]
+        older_than: Option<String>,
+    },
+    /// Report malformed component markers, unknown patch targets and frontmatter key problems
+    Lint {
+        /// Documents to check
+        #[arg(required = true)]
+        files: Vec<PathBuf>,
+        /// Output diagnostics as a JSON array
+        #[arg(long)]
+        json: bool,
+    },
+    /// Rewrite deprecated frontmatter keys (session, mode, response_mode, agent_doc_mode)
+    Migrate {
+        /// Documents or directories (searched recursively for .md files)
+        #[arg(required = true)]
+        paths: Vec<PathBuf>,
+        /// Report what would change without writing
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Check .agent-doc/ state for drift, corruption and stale entries
+    Doctor {
+        /// Documents to check (defaults to all session documents in the project)
+        files: Vec<PathBuf>,
+        /// Repair problems that can be fixed automatically
+        #[arg(long)]
+        fix: bool,
+    },
+    /// List a document's CRDT update log, or print its text at an earlier entry
+    CrdtLog {
+        /// Path to the document
+        file: PathBuf,
+        /// Print the text after the first N logged updates (0 = the checkpoint)
+        #[arg(long)]
+        at: Option<usize>,
+    },
+    /// Settle merge conflicts recorded for a document, one hunk at a time
+    Resolve {
+        /// Path to the document
+        file: PathBuf,
+        /// Keep the agent's version
+        #[arg(long, conflicts_with_all = ["theirs", "both", "drop"])]
+        ours: bool,
+        /// Keep your version
+        #[arg(long, conflicts_with_all = ["both", "drop"])]
+        theirs: bool,
+        /// Keep both versions (agent's first)
+        #[arg(long, conflicts_with = "drop")]
+        both: bool,
+        /// Discard the record without changing the document
+        #[arg(long)]
+        drop: bool,
+        /// Conflict to resolve (defaults to the oldest)
+        #[arg(long)]
+        id: Option<u32>,
+    },
+    /// Inspect and edit global and project configuration
+    Config {
+        #[command(subcommand)]
+        command: ConfigCommands,
+    },
     /// Manage the Claude Code skill definition
     Skill {
         #[command(subcommand)]
@@ -257,9 +356,9 @@ enum Commands {
     Stream {
         /// Path to the session document
         file: PathBuf,
-        /// Write-back interval in milliseconds
-        #[arg(long, default_value = "200")]
-        interval: u64,
+        /// Write-back interval in milliseconds [default: from frontmatter or config, or 200]
+        #[arg(long)]
+        interval: Option<u64>,
         /// Agent backend to use
         #[arg(long)]
         agent: Option<String>,
@@ -342,6 +441,68 @@ enum PluginAction {
     List,
 }
 
+#[derive(Subcommand)]
+enum ConfigCommands {
+    /// Show each effective setting for a document and the layer it came from
+    Show {
+        /// Path to the document
+        file: PathBuf,
+    },
+    /// Print a setting (the effective value unless --global or --project is given)
+    Get {
+        /// Dotted key, e.g. model or agents.codex.command
+        key: String,
+        /// Read the global config only
+        #[arg(long, conflicts_with = "project")]
+        global: bool,
+        /// Read the project config only
+        #[arg(long)]
+        project: bool,
+    },
+    /// Set a setting (in the project config inside a project, else the global config)
+    Set {
+        /// Dotted key, e.g. model or agents.codex.command
+        key: String,
+        /// Value; arrays as TOML (`["-p", "--json"]`) or space-separated words
+        value: String,
+        /// Edit the global config
+        #[arg(long, conflicts_with = "project")]
+        global: bool,
+        /// Edit the project config
+        #[arg(long)]
+        project: bool,
+    },
+    /// Remove a setting
+    Unset {
+        /// Dotted key, e.g. model or agents.codex
+        key: String,
+        /// Edit the global config
+        #[arg(long, conflicts_with = "project")]
+        global: bool,
+        /// Edit the project config
+        #[arg(long)]
+        project: bool,
+    },
+    /// List the settings in the project and global config files
+    List {
+        /// List the global config only
+        #[arg(long, conflicts_with = "project")]
+        global: bool,
+        /// List the project config only
+        #[arg(long)]
+        project: bool,
+    },
+    /// Check config files for unknown keys, bad values and agent commands missing from PATH
+    Validate {
+        /// Check the global config only
+        #[arg(long, conflicts_with = "project")]
+        global: bool,
+        /// Check the project config only
+        #[arg(long)]
+        project: bool,
+    },
+}
+
 #[derive(Subcommand)]
 enum SkillCommands {
     /// Install the skill definition to .claude/skills/agent-doc/SKILL.md
@@ -362,6 +523,27 @@ fn main() -> anyhow::Result<()> {
         upgrade::warn_if_outdated();
     }
 
+    // `config` subcommands read the files themselves, so they still work
+    // (and can report or fix problems) when the global config doesn't parse.
+    if let Commands::Config { command } = cli.command {
+        return match command {
+            ConfigCommands::Show { file } => config::show(&config::load()?, &file),
+            ConfigCommands::Get { key, global, project } => {
+                config_cmd::get(&key, config_cmd::Scope::from_flags(global, project))
+            }
+            ConfigCommands::Set { key, value, global, project } => {
+                config_cmd::set(&key, &value, config_cmd::Scope::from_flags(global, project))
+            }
+            ConfigCommands::Unset { key, global, project } => {
+                config_cmd::unset(&key, config_cmd::Scope::from_flags(global, project))
+            }
+            ConfigCommands::List { global, project } => config_cmd::list(config_cmd::Scope::from_flags(global, project)),
+            ConfigCommands::Validate { global, project } => {
+                config_cmd::validate(config_cmd::Scope::from_flags(global, project))
+            }
+        };
+    }
+
     let config = config::load()?;
 
     match cli.command {
@@ -373,8 +555,20 @@ fn main() -> anyhow::Result<()> {
             dry_run,
             no_git,
         } => submit::run(&file, branch, agent.as_deref(), model.as_deref(), dry_run, no_git, &config),
-        Commands::Init { file, title, agent, mode } => {
-            init::run(&file, title.as_deref(), agent.as_deref(), mode.as_deref(), &config)
+        Commands::Init { file, title, agent, mode, template, vars, list_templates } => {
+            if list_templates {
+                return init::list_templates();
+            }
+            let file = file.context("FILE required when not using --list-templates")?;
+            init::run(
+                &file,
+                title.as_deref(),
+                agent.as_deref(),
+                mode.as_deref(),
+                template.as_deref(),
+                &vars,
+                &config,
+            )
         }
         Commands::Diff { file } => diff::run(&file),
         Commands::Reset { file } => reset::run(&file),
@@ -434,7 +628,29 @@ fn main() -> anyhow::Result<()> {
             }
         }
         Commands::Outline { file, json } => outline::run(&file, json),
+        #[cfg(unix)]
+        Commands::Serve { file } => ysync::run(&file),
         Commands::Resync => resync::run(),
+        Commands::Gc { dry_run, older_than } => gc::run(dry_run, older_than.as_deref()),
+        Commands::Lint { files, json } => lint::run(&files, json),
+        Commands::Migrate { paths, dry_run } => migrate::run(&paths, dry_run),
+        Commands::Doctor { files, fix } => doctor::run(&files, fix),
+        Commands::CrdtLog { file, at } => crdt_log::run(&file, at),
+        Commands::Resolve { file, ours, theirs, both, drop, id } => {
+            let resolution = if ours {
+                Some(conflicts::Resolution::Ours)
+            } else if theirs {
+                Some(conflicts::Resolution::Theirs)
+            } else if both {
+                Some(conflicts::Resolution::Both)
+            } else if drop {
+                Some(conflicts::Resolution::Drop)
+            } else {
+                None
+            };
+            conflicts::run(&file, resolution, id)
+        }
+        Commands::Config { .. } => unreachable!("handled above"),
         Commands::Skill { command } => match command {
             SkillCommands::Install { reload } => {
                 let updated = skill::install_and_check_updated()?;
@@ -467,7 +683,7 @@ fn main() -> anyhow::Result<()> {
                 .map(std::fs::read_to_string)
                 .transpose()
                 .context("failed to read baseline file")?;
-            if is_ipc {
+            let written = if is_ipc {
                 write::run_ipc(&file, baseline.as_deref())
             } else if is_stream {
                 write::run_stream(&file, baseline.as_deref())
@@ -483,7 +699,9 @@ fn main() -> anyhow::Result<()> {
                 } else {
                     write::run(&file, baseline.as_deref())
                 }
-            }
+            };
+            written?;
+            hooks::fire(&file, hooks::Event::PostWrite, serde_json::json!({ "source": "write" }))
         }
         Commands::Stream { file, interval, agent, model, no_git } => {
             stream::run(&file, interval, agent.as_deref(), model.as_deref(), no_git, &config)
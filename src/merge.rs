@@ -1,204 +1,557 @@
 //! 3-way merge with append-friendly conflict resolution.
 //!
-//! Uses `git merge-file --diff3` for the base merge, then post-processes
-//! to auto-resolve append-only conflicts (where both sides added content
-//! at the same position without modifying existing lines).
+//! `merge3` is an in-process diff3 built on `similar` that returns structured
+//! hunks; append-only conflicts (where both sides added content at the same
+//! position without modifying existing lines) are resolved on those hunks.
+//!
+//! `merge_document` splits the three versions by top-level component and
+//! merges each one with its `merge_strategy` from `.agent-doc/components.toml`.
 
 use anyhow::{Context, Result};
-use std::process::Command;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::Path;
+
+use crate::conflicts::RecordedMerge;
+use crate::crdt::{ComponentSplit, CrdtDoc};
+use crate::frontmatter::CrdtLayout;
 
-/// CRDT-based merge: conflict-free merge using Yrs CRDT.
+/// CRDT-based merge of a document on disk: conflict-free merge using Yrs CRDT.
 ///
-/// Returns (merged_text, new_crdt_state).
+/// Returns the merged text, which callers record with
+/// [`crate::snapshot::save_crdt_text`] so the stored state is edited in place,
+/// and any conflicts recorded for line-merged components.
 /// `base_state` is the CRDT state from the last write (None on first use).
-pub fn merge_contents_crdt(
+/// Documents with `agent_doc_crdt_layout: components` are merged per component.
+///
+/// Checklist components (from the marker's `mode=checklist` or the
+/// `.agent-doc/components.toml` found for `file`) are reconciled with
+/// [`crate::checklist::merge3`] instead of character by character.
+pub fn merge_document_crdt(
+    file: &Path,
     base_state: Option<&[u8]>,
     ours: &str,
     theirs: &str,
-) -> Result<(String, Vec<u8>)> {
-    let merged = crate::crdt::merge(base_state, ours, theirs)
-        .context("CRDT merge failed")?;
-    // Build fresh CRDT state from the merged result
-    let doc = crate::crdt::CrdtDoc::from_text(&merged);
-    let state = doc.encode_state();
+) -> Result<RecordedMerge> {
+    let checklists = crate::patch::checklist_components(Some(file), ours)?;
+    let mut merged = match crate::frontmatter::crdt_layout(ours) {
+        CrdtLayout::Text => crate::crdt::merge(base_state, ours, theirs),
+        CrdtLayout::Components => crate::crdt::merge_components(base_state, ours, theirs),
+    }
+    .context("CRDT merge failed")?;
+    let base = base_state.map(CrdtDoc::decode_state).transpose()?.map(|doc| doc.to_text());
+    if !checklists.is_empty() {
+        merged = merge_checklists(base.as_deref(), ours, theirs, &merged, &checklists)?;
+    }
+    // `strict`, `ours` and `theirs` components are merged line by line as in
+    // `merge_document`; `append-friendly` and `crdt` keep the CRDT result.
+    let strategies: HashMap<String, MergeStrategy> = crate::patch::load_merge_strategies(file, ours)?
+        .into_iter()
+        .filter(|(name, strategy)| {
+            matches!(strategy, MergeStrategy::Strict | MergeStrategy::Ours | MergeStrategy::Theirs)
+                && !checklists.contains(name)
+        })
+        .collect();
+    if let Some(base) = base.as_deref()
+        && !strategies.is_empty()
+    {
+        let result = apply_strategies(base, ours, theirs, MergedDocument { text: merged, conflicts: Vec::new() }, &strategies)?;
+        if !result.conflicts.is_empty() {
+            log_conflicts(&result.text, &result.conflicts);
+            return crate::conflicts::record(file, &result.text, &result.conflicts);
+        }
+        merged = result.text;
+    }
     eprintln!("[write] CRDT merge successful — no conflicts possible.");
-    Ok((merged, state))
+    Ok(RecordedMerge::clean(merged))
 }
 
-/// 3-way merge using `git merge-file --diff3`.
-///
-/// Returns merged content. Append-only conflicts are auto-resolved by
-/// concatenating both additions (ours first, then theirs).
-/// True conflicts (where existing content was modified differently)
-/// retain standard conflict markers.
-pub fn merge_contents(base: &str, ours: &str, theirs: &str) -> Result<String> {
-    let tmp = tempfile::TempDir::new()
-        .context("failed to create temp dir for merge")?;
-
-    let base_path = tmp.path().join("base");
-    let ours_path = tmp.path().join("ours");
-    let theirs_path = tmp.path().join("theirs");
-
-    std::fs::write(&base_path, base)?;
-    std::fs::write(&ours_path, ours)?;
-    std::fs::write(&theirs_path, theirs)?;
-
-    let output = Command::new("git")
-        .current_dir(tmp.path())
-        .args([
-            "merge-file",
-            "-p",
-            "--diff3",
-            "-L", "agent-response",
-            "-L", "original",
-            "-L", "your-edits",
-            &ours_path.to_string_lossy(),
-            &base_path.to_string_lossy(),
-            &theirs_path.to_string_lossy(),
-        ])
-        .output()?;
-
-    let merged = String::from_utf8(output.stdout)
-        .map_err(|e| anyhow::anyhow!("merge produced invalid UTF-8: {}", e))?;
-
-    if output.status.success() {
-        eprintln!("[write] Merge successful — user edits preserved.");
-        return Ok(merged);
+/// Replace each checklist component in `merged` with the item-level merge of
+/// its base, agent (`ours`) and user (`theirs`) versions.
+fn merge_checklists(
+    base: Option<&str>,
+    ours: &str,
+    theirs: &str,
+    merged: &str,
+    checklists: &[String],
+) -> Result<String> {
+    let mut result = merged.to_string();
+    for name in checklists {
+        let (Some(ours_list), Some(theirs_list)) = (content_of(ours, name), content_of(theirs, name)) else {
+            continue;
+        };
+        let base_list = base.and_then(|b| content_of(b, name));
+        let components = crate::component::parse(&result)?;
+        let Some(comp) = components.iter().find(|c| &c.name == name) else {
+            continue;
+        };
+        let list = crate::checklist::merge3(base_list.as_deref(), &ours_list, &theirs_list);
+        result = comp.replace_content(&result, &list);
     }
+    Ok(result)
+}
 
-    if output.status.code() == Some(1) {
-        // Conflicts detected — try append-friendly resolution
-        let (resolved, remaining_conflicts) = resolve_append_conflicts(&merged);
-        if remaining_conflicts {
-            eprintln!("[write] WARNING: True merge conflicts remain. Please resolve conflict markers manually.");
-        } else {
-            eprintln!("[write] Merge conflicts auto-resolved (append-friendly).");
+/// Content of the first component named `name` in `doc`.
+fn content_of(doc: &str, name: &str) -> Option<String> {
+    let components = crate::component::parse(doc).ok()?;
+    components.iter().find(|c| c.name == name).map(|c| c.content(doc).to_string())
+}
+
+/// How conflicting edits inside a component are merged.
+///
+/// Configured per component with `merge_strategy` in `.agent-doc/components.toml`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum MergeStrategy {
+    /// Line merge; append-only conflicts keep both additions (agent first),
+    /// other conflicts are left as conflict markers.
+    #[default]
+    AppendFriendly,
+    /// Line merge without auto-resolution. Conflicting regions keep the
+    /// user's version and are reported in the `conflicts` component.
+    Strict,
+    /// Line merge; conflicting regions take the agent's version.
+    Ours,
+    /// Line merge; conflicting regions take the user's version.
+    Theirs,
+    /// Conflict-free CRDT merge of the component text.
+    Crdt,
+}
+
+impl MergeStrategy {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "append-friendly" => Some(Self::AppendFriendly),
+            "strict" => Some(Self::Strict),
+            "ours" => Some(Self::Ours),
+            "theirs" => Some(Self::Theirs),
+            "crdt" => Some(Self::Crdt),
+            _ => None,
         }
-        return Ok(resolved);
     }
-
-    anyhow::bail!(
-        "git merge-file failed: {}",
-        String::from_utf8_lossy(&output.stderr)
-    )
 }
 
-/// Resolve append-only conflicts in `git merge-file --diff3` output.
+/// Three-way merge of a document using the component strategies configured
+/// for `file`. This is the entry point for the `write` and `submit` merges.
 ///
-/// With `--diff3`, conflict blocks look like:
-/// ```text
-/// <<<<<<< agent-response
-/// content added by agent
-/// ||||||| original
-/// (empty if both sides only appended)
-/// =======
-/// content added by user
-/// >>>>>>> your-edits
-/// ```
+/// Conflicts that remain are recorded in `.agent-doc/conflicts/` and
+/// summarized in the document's conflicts component (see [`crate::conflicts`]).
+pub fn merge_document(file: &Path, base: &str, ours: &str, theirs: &str) -> Result<RecordedMerge> {
+    let strategies = crate::patch::load_merge_strategies(file, ours)?;
+    let merged = merge_by_component(base, ours, theirs, &strategies)?;
+    if merged.conflicts.is_empty() {
+        return Ok(RecordedMerge::clean(merged.text));
+    }
+    crate::conflicts::record(file, &merged.text, &merged.conflicts)
+}
+
+/// Where a conflict's text was left in the merged document.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ConflictPlacement {
+    /// Rendered with diff3-style conflict markers.
+    Markers,
+    /// Resolved to the user's version (`strict` strategy); the agent's
+    /// version only exists in the conflict record.
+    KeptTheirs,
+}
+
+/// A conflict left by a document merge.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DocumentConflict {
+    /// Component containing the conflict; `None` for text outside components
+    /// (or a whole-document merge).
+    pub component: Option<String>,
+    /// 0-based line in the merged component (or document) where it starts.
+    pub line: usize,
+    pub placement: ConflictPlacement,
+    pub hunk: ConflictHunk,
+}
+
+/// A merged document and the conflicts left in it.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MergedDocument {
+    pub text: String,
+    pub conflicts: Vec<DocumentConflict>,
+}
+
+/// Merge each top-level component with its strategy (default: append-friendly).
 ///
-/// When the "original" section is empty (both sides added at the same
-/// insertion point without modifying existing content), auto-resolve by
-/// concatenating: ours (agent) first, then theirs (user).
+/// The skeleton (everything outside components) is merged append-friendly.
+/// Falls back to a whole-document append-friendly merge when the document has
+/// no components, base, ours and theirs don't share the same top-level
+/// components, or the skeleton conflicts; components found in all three are
+/// then still re-merged with their own strategy.
+pub fn merge_by_component(
+    base: &str,
+    ours: &str,
+    theirs: &str,
+    strategies: &HashMap<String, MergeStrategy>,
+) -> Result<MergedDocument> {
+    let splits = (
+        ComponentSplit::parse(base),
+        ComponentSplit::parse(ours),
+        ComponentSplit::parse(theirs),
+    );
+    let (Some(b), Some(o), Some(t)) = splits else {
+        return merge_whole(base, ours, theirs, strategies);
+    };
+    if o.components.is_empty() || o.names() != t.names() || o.names() != b.names() {
+        return merge_whole(base, ours, theirs, strategies);
+    }
+
+    let skeleton = merge_part(MergeStrategy::AppendFriendly, &b.skeleton, &o.skeleton, &t.skeleton)?;
+    if !skeleton.conflicts.is_empty()
+        || ComponentSplit::parse(&skeleton.text).is_none_or(|s| s.names() != o.names())
+    {
+        eprintln!("[write] Skeleton merge conflicted or broke component markers, merging whole document");
+        return merge_whole(base, ours, theirs, strategies);
+    }
+
+    let mut components = Vec::with_capacity(o.components.len());
+    let mut conflicts = Vec::new();
+    for (((name, bc), (_, oc)), (_, tc)) in b.components.iter().zip(&o.components).zip(&t.components) {
+        let strategy = strategies.get(name).copied().unwrap_or_default();
+        let merged = merge_part(strategy, bc, oc, tc)
+            .with_context(|| format!("failed to merge component '{}'", name))?;
+        conflicts.extend(merged.conflicts.into_iter().map(|mut c| {
+            c.component = Some(name.clone());
+            c
+        }));
+        components.push((name.clone(), merged.text));
+    }
+
+    let text = ComponentSplit { skeleton: skeleton.text, components }.join();
+    log_conflicts(&text, &conflicts);
+    Ok(MergedDocument { text, conflicts })
+}
+
+/// Append-friendly merge of the whole document, after which components with
+/// another strategy are re-merged on their own, so e.g. a `strict` component
+/// never ends up with conflict markers.
+fn merge_whole(
+    base: &str,
+    ours: &str,
+    theirs: &str,
+    strategies: &HashMap<String, MergeStrategy>,
+) -> Result<MergedDocument> {
+    let merged = merge_part(MergeStrategy::AppendFriendly, base, ours, theirs)?;
+    let overrides: HashMap<String, MergeStrategy> = strategies
+        .iter()
+        .filter(|(_, strategy)| **strategy != MergeStrategy::AppendFriendly)
+        .map(|(name, strategy)| (name.clone(), *strategy))
+        .collect();
+    let merged = apply_strategies(base, ours, theirs, merged, &overrides)?;
+    log_conflicts(&merged.text, &merged.conflicts);
+    Ok(merged)
+}
+
+/// Re-merge components of an already merged document with their own
+/// strategies, replacing their content in `merged`.
 ///
-/// Returns (resolved_content, has_remaining_conflicts).
-fn resolve_append_conflicts(merged: &str) -> (String, bool) {
-    let mut result = String::new();
-    let mut has_remaining = false;
-    let lines: Vec<&str> = merged.lines().collect();
-    let len = lines.len();
-    let mut i = 0;
-
-    while i < len {
-        if !lines[i].starts_with("<<<<<<< ") {
-            result.push_str(lines[i]);
-            result.push('\n');
-            i += 1;
+/// Components missing from any of the versions are left alone. Whole-document
+/// conflicts inside a re-merged component are superseded by the component's
+/// own, and those after it are shifted to the new line numbers.
+fn apply_strategies(
+    base: &str,
+    ours: &str,
+    theirs: &str,
+    mut merged: MergedDocument,
+    strategies: &HashMap<String, MergeStrategy>,
+) -> Result<MergedDocument> {
+    let mut names: Vec<&String> = strategies.keys().collect();
+    names.sort();
+    for name in names {
+        let (Some(bc), Some(oc), Some(tc)) = (content_of(base, name), content_of(ours, name), content_of(theirs, name)) else {
+            continue;
+        };
+        let Ok(components) = crate::component::parse(&merged.text) else {
+            break;
+        };
+        let Some(comp) = components.iter().find(|c| &c.name == name) else {
             continue;
+        };
+        let part = merge_part(strategies[name], &bc, &oc, &tc)
+            .with_context(|| format!("failed to merge component '{}'", name))?;
+
+        let start = merged.text[..comp.open_end].matches('\n').count();
+        let end = start + comp.content(&merged.text).matches('\n').count();
+        let new_end = start + part.text.matches('\n').count();
+        merged.conflicts.retain(|c| c.component.is_some() || c.line < start || c.line >= end);
+        for c in &mut merged.conflicts {
+            if c.component.is_none() && c.line >= end {
+                c.line = c.line - end + new_end;
+            }
         }
+        merged.text = comp.replace_content(&merged.text, &part.text);
+        merged.conflicts.extend(part.conflicts.into_iter().map(|mut c| {
+            c.component = Some(name.clone());
+            c
+        }));
+    }
+    Ok(merged)
+}
 
-        // Parse conflict block
-        let conflict_start = i;
-        i += 1; // skip <<<<<<< marker
+/// 1-based line in `text` of a conflict at 0-based `line` of `component`
+/// (or of the whole text when `component` is `None`).
+pub(crate) fn document_line(text: &str, component: Option<&str>, line: usize) -> usize {
+    let start = component
+        .and_then(|name| {
+            let components = crate::component::parse(text).ok()?;
+            components.iter().find(|c| c.name == name).map(|c| c.open_end)
+        })
+        .unwrap_or(0);
+    text[..start].matches('\n').count() + line + 1
+}
 
-        // Collect "ours" section
-        let mut ours_lines: Vec<&str> = Vec::new();
-        while i < len && !lines[i].starts_with("||||||| ") && !lines[i].starts_with("=======") {
-            ours_lines.push(lines[i]);
-            i += 1;
+fn log_conflicts(text: &str, conflicts: &[DocumentConflict]) {
+    if conflicts.is_empty() {
+        eprintln!("[write] Merge successful — user edits preserved.");
+        return;
+    }
+    if conflicts.iter().any(|c| c.placement == ConflictPlacement::Markers) {
+        eprintln!("[write] WARNING: True merge conflicts remain. Please resolve conflict markers manually.");
+    }
+    for c in conflicts {
+        let kept = match c.placement {
+            ConflictPlacement::Markers => "markers",
+            ConflictPlacement::KeptTheirs => "your version kept",
+        };
+        let line = document_line(text, c.component.as_deref(), c.line);
+        match &c.component {
+            Some(name) => eprintln!("[write]   conflict in '{}' at line {} ({})", name, line, kept),
+            None => eprintln!("[write]   conflict at line {} ({})", line, kept),
+        }
+    }
+}
+
+/// Merge one part (skeleton, component or whole document) with `strategy`.
+/// Conflicts are returned without a component; the caller fills it in.
+fn merge_part(strategy: MergeStrategy, base: &str, ours: &str, theirs: &str) -> Result<MergedDocument> {
+    let clean = |text: &str| MergedDocument { text: text.to_string(), conflicts: Vec::new() };
+    if ours == theirs || theirs == base {
+        return Ok(clean(ours));
+    }
+    if ours == base {
+        return Ok(clean(theirs));
+    }
+    if strategy == MergeStrategy::Crdt {
+        let state = CrdtDoc::from_text(base).encode_state();
+        return Ok(clean(&crate::crdt::merge(Some(&state), ours, theirs)?));
+    }
+
+    let mut result = merge3(base, ours, theirs);
+    let conflicts = match strategy {
+        MergeStrategy::AppendFriendly => {
+            result.resolve_append_only();
+            result.conflict_positions(ConflictPlacement::Markers)
+        }
+        MergeStrategy::Strict => {
+            let conflicts = result.conflict_positions(ConflictPlacement::KeptTheirs);
+            result.resolve_with(|c| Some(c.theirs.clone()));
+            conflicts
+        }
+        MergeStrategy::Ours => {
+            result.resolve_with(|c| Some(c.ours.clone()));
+            Vec::new()
+        }
+        MergeStrategy::Theirs => {
+            result.resolve_with(|c| Some(c.theirs.clone()));
+            Vec::new()
+        }
+        MergeStrategy::Crdt => unreachable!("handled above"),
+    };
+    Ok(MergedDocument { text: result.to_text(), conflicts })
+}
+
+/// A region of a three-way merge.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MergeHunk {
+    /// Text both sides agree on: unchanged, changed on one side only, or
+    /// changed identically on both.
+    Resolved(String),
+    /// A region both sides changed differently.
+    Conflict(ConflictHunk),
+}
+
+/// A conflicting region, with each side's version of it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ConflictHunk {
+    /// 0-based line number in the base where the region starts.
+    pub base_line: usize,
+    pub base: String,
+    /// Agent's version.
+    pub ours: String,
+    /// User's version.
+    pub theirs: String,
+}
+
+impl ConflictHunk {
+    /// Render the region with diff3-style conflict markers.
+    pub fn to_markers(&self) -> String {
+        format!(
+            "<<<<<<< agent-response\n{}||||||| original\n{}=======\n{}>>>>>>> your-edits\n",
+            with_newline(&self.ours),
+            with_newline(&self.base),
+            with_newline(&self.theirs),
+        )
+    }
+
+    /// True if both sides only inserted text at the same position (the base
+    /// region is empty or whitespace-only).
+    pub fn is_append_only(&self) -> bool {
+        self.base.trim().is_empty()
+    }
+}
+
+/// Result of a three-way merge as a sequence of hunks.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MergeResult {
+    pub hunks: Vec<MergeHunk>,
+}
+
+impl MergeResult {
+    /// Resolve conflicts with `resolve`; returning `None` keeps the conflict.
+    pub fn resolve_with(&mut self, mut resolve: impl FnMut(&ConflictHunk) -> Option<String>) {
+        for hunk in &mut self.hunks {
+            if let MergeHunk::Conflict(c) = hunk
+                && let Some(text) = resolve(c)
+            {
+                *hunk = MergeHunk::Resolved(text);
+            }
         }
+    }
 
-        // Collect "original" section (diff3)
-        let mut original_lines: Vec<&str> = Vec::new();
-        if i < len && lines[i].starts_with("||||||| ") {
-            i += 1; // skip ||||||| marker
-            while i < len && !lines[i].starts_with("=======") {
-                original_lines.push(lines[i]);
-                i += 1;
+    /// Auto-resolve append-only conflicts: ours (agent) first, then theirs (user).
+    pub fn resolve_append_only(&mut self) {
+        self.resolve_with(|c| {
+            c.is_append_only()
+                .then(|| format!("{}{}", with_newline(&c.ours), c.theirs))
+        });
+    }
+
+    /// Render the merged text, with diff3-style markers around remaining conflicts.
+    pub fn to_text(&self) -> String {
+        let mut out = String::new();
+        for hunk in &self.hunks {
+            match hunk {
+                MergeHunk::Resolved(text) => out.push_str(text),
+                MergeHunk::Conflict(c) => out.push_str(&c.to_markers()),
             }
         }
+        out
+    }
 
-        // Skip ======= marker
-        if i < len && lines[i].starts_with("=======") {
-            i += 1;
+    /// Unresolved conflicts with the output line each starts at, assuming
+    /// they render as markers or (for `KeptTheirs`) as the user's version.
+    fn conflict_positions(&self, placement: ConflictPlacement) -> Vec<DocumentConflict> {
+        let mut line = 0;
+        let mut conflicts = Vec::new();
+        for hunk in &self.hunks {
+            match hunk {
+                MergeHunk::Resolved(text) => line += text.matches('\n').count(),
+                MergeHunk::Conflict(c) => {
+                    conflicts.push(DocumentConflict {
+                        component: None,
+                        line,
+                        placement,
+                        hunk: c.clone(),
+                    });
+                    line += match placement {
+                        ConflictPlacement::Markers => c.to_markers().matches('\n').count(),
+                        ConflictPlacement::KeptTheirs => c.theirs.matches('\n').count(),
+                    };
+                }
+            }
         }
+        conflicts
+    }
 
-        // Collect "theirs" section
-        let mut theirs_lines: Vec<&str> = Vec::new();
-        while i < len && !lines[i].starts_with(">>>>>>> ") {
-            theirs_lines.push(lines[i]);
-            i += 1;
+    fn push_resolved(&mut self, text: &str) {
+        if text.is_empty() {
+            return;
         }
+        if let Some(MergeHunk::Resolved(prev)) = self.hunks.last_mut() {
+            prev.push_str(text);
+        } else {
+            self.hunks.push(MergeHunk::Resolved(text.to_string()));
+        }
+    }
+}
+
+/// Ensure non-empty text ends with a newline (so markers start on their own line).
+pub(crate) fn with_newline(text: &str) -> String {
+    if text.is_empty() || text.ends_with('\n') {
+        text.to_string()
+    } else {
+        format!("{}\n", text)
+    }
+}
+
+/// In-process three-way line merge (diff3) built on `similar`.
+///
+/// Lines unchanged on both sides anchor the merge. Between anchors, a region
+/// changed on only one side (or identically on both) takes that change;
+/// a region changed differently on both sides becomes a [`ConflictHunk`].
+pub fn merge3(base: &str, ours: &str, theirs: &str) -> MergeResult {
+    let base_lines: Vec<&str> = base.split_inclusive('\n').collect();
+    let ours_lines: Vec<&str> = ours.split_inclusive('\n').collect();
+    let theirs_lines: Vec<&str> = theirs.split_inclusive('\n').collect();
 
-        // Skip >>>>>>> marker
-        if i < len && lines[i].starts_with(">>>>>>> ") {
+    let ours_map = unchanged_lines(&base_lines, &ours_lines);
+    let theirs_map = unchanged_lines(&base_lines, &theirs_lines);
+
+    let mut result = MergeResult::default();
+    let (mut i, mut j, mut k) = (0, 0, 0);
+    loop {
+        // Stable region: base line unchanged on both sides.
+        while i < base_lines.len() && ours_map[i] == Some(j) && theirs_map[i] == Some(k) {
+            result.push_resolved(base_lines[i]);
             i += 1;
+            j += 1;
+            k += 1;
+        }
+        if i == base_lines.len() && j == ours_lines.len() && k == theirs_lines.len() {
+            break;
         }
 
-        // Check if append-only: original section is empty or whitespace-only
-        let is_append_only = original_lines.iter().all(|l| l.trim().is_empty());
+        // Next anchor: the first base line at or after `i` unchanged on both sides.
+        let (ni, nj, nk) = (i..base_lines.len())
+            .find_map(|n| Some((n, ours_map[n]?, theirs_map[n]?)))
+            .unwrap_or((base_lines.len(), ours_lines.len(), theirs_lines.len()));
 
-        if is_append_only {
-            // Auto-resolve: ours (agent) first, then theirs (user)
-            for line in &ours_lines {
-                result.push_str(line);
-                result.push('\n');
-            }
-            for line in &theirs_lines {
-                result.push_str(line);
-                result.push('\n');
-            }
+        let b = base_lines[i..ni].concat();
+        let o = ours_lines[j..nj].concat();
+        let t = theirs_lines[k..nk].concat();
+        if o == b || o == t {
+            result.push_resolved(&t);
+        } else if t == b {
+            result.push_resolved(&o);
         } else {
-            // True conflict — preserve markers
-            has_remaining = true;
-            result.push_str(lines[conflict_start]);
-            result.push('\n');
-            for line in &ours_lines {
-                result.push_str(line);
-                result.push('\n');
-            }
-            // Reconstruct ||||||| section
-            if !original_lines.is_empty() {
-                result.push_str("||||||| original\n");
-                for line in &original_lines {
-                    result.push_str(line);
-                    result.push('\n');
-                }
-            }
-            result.push_str("=======\n");
-            for line in &theirs_lines {
-                result.push_str(line);
-                result.push('\n');
-            }
-            result.push_str(">>>>>>> your-edits\n");
+            result.hunks.push(MergeHunk::Conflict(ConflictHunk {
+                base_line: i,
+                base: b,
+                ours: o,
+                theirs: t,
+            }));
         }
+        (i, j, k) = (ni, nj, nk);
     }
+    result
+}
 
-    // Handle trailing: if original didn't end with newline but we added one
-    if !merged.ends_with('\n') && result.ends_with('\n') {
-        result.pop();
-    }
+/// For each base line, the index of the matching line on `side` if unchanged.
+fn unchanged_lines(base: &[&str], side: &[&str]) -> Vec<Option<usize>> {
+    use similar::{capture_diff_slices, Algorithm, DiffOp};
 
-    (result, has_remaining)
+    let mut map = vec![None; base.len()];
+    for op in capture_diff_slices(Algorithm::Myers, base, side) {
+        if let DiffOp::Equal { old_index, new_index, len } = op {
+            for n in 0..len {
+                map[old_index + n] = Some(new_index + n);
+            }
+        }
+    }
+    map
 }
 
 #[cfg(test)]
@@ -207,18 +560,17 @@ mod tests {
 
     #[test]
     fn resolve_append_only_conflict() {
-        let merged = "\
-Before conflict
-<<<<<<< agent-response
-Agent added this line.
-||||||| original
-=======
-User added this line.
->>>>>>> your-edits
-After conflict
-";
-        let (resolved, has_remaining) = resolve_append_conflicts(merged);
-        assert!(!has_remaining);
+        let base = "Before conflict\nAfter conflict\n";
+        let ours = "Before conflict\nAgent added this line.\nAfter conflict\n";
+        let theirs = "Before conflict\nUser added this line.\nAfter conflict\n";
+        let mut result = merge3(base, ours, theirs);
+        let conflicts = result.conflict_positions(ConflictPlacement::Markers);
+        assert!(!conflicts.is_empty());
+        assert!(conflicts.iter().all(|c| c.hunk.is_append_only()));
+
+        result.resolve_append_only();
+        assert!(result.conflict_positions(ConflictPlacement::Markers).is_empty());
+        let resolved = result.to_text();
         assert!(resolved.contains("Agent added this line."));
         assert!(resolved.contains("User added this line."));
         assert!(!resolved.contains("<<<<<<<"));
@@ -231,47 +583,39 @@ After conflict
 
     #[test]
     fn preserve_true_conflict() {
-        let merged = "\
-<<<<<<< agent-response
-Agent changed this.
-||||||| original
-Original line that both sides modified.
-=======
-User changed this differently.
->>>>>>> your-edits
-";
-        let (resolved, has_remaining) = resolve_append_conflicts(merged);
-        assert!(has_remaining);
-        assert!(resolved.contains("<<<<<<<"));
-        assert!(resolved.contains(">>>>>>>"));
-        assert!(resolved.contains("Original line that both sides modified."));
+        let base = "Original line that both sides modified.\n";
+        let ours = "Agent changed this.\n";
+        let theirs = "User changed this differently.\n";
+        let mut result = merge3(base, ours, theirs);
+        result.resolve_append_only();
+        let conflicts = result.conflict_positions(ConflictPlacement::Markers);
+        assert_eq!(conflicts.len(), 1);
+
+        let conflict = &conflicts[0].hunk;
+        assert_eq!(conflict.base_line, 0);
+        assert_eq!(conflict.base, base);
+        assert_eq!(conflict.ours, ours);
+        assert_eq!(conflict.theirs, theirs);
+
+        let rendered = result.to_text();
+        assert!(rendered.contains("<<<<<<<"));
+        assert!(rendered.contains(">>>>>>>"));
+        assert!(rendered.contains("Original line that both sides modified."));
     }
 
     #[test]
     fn mixed_append_and_true_conflicts() {
-        let merged = "\
-Clean line.
-<<<<<<< agent-response
-Agent appended here.
-||||||| original
-=======
-User appended here.
->>>>>>> your-edits
-Middle line.
-<<<<<<< agent-response
-Agent rewrote this.
-||||||| original
-Was originally this.
-=======
-User rewrote this differently.
->>>>>>> your-edits
-End line.
-";
-        let (resolved, has_remaining) = resolve_append_conflicts(merged);
-        assert!(has_remaining);
+        let base = "Clean line.\nMiddle line.\nWas originally this.\nEnd line.\n";
+        let ours = "Clean line.\nAgent appended here.\nMiddle line.\nAgent rewrote this.\nEnd line.\n";
+        let theirs = "Clean line.\nUser appended here.\nMiddle line.\nUser rewrote this differently.\nEnd line.\n";
+        let mut result = merge3(base, ours, theirs);
+        assert_eq!(result.conflict_positions(ConflictPlacement::Markers).len(), 2);
+
+        result.resolve_append_only();
+        assert_eq!(result.conflict_positions(ConflictPlacement::Markers).len(), 1);
+        let resolved = result.to_text();
         // Append-only conflict was resolved
-        assert!(resolved.contains("Agent appended here."));
-        assert!(resolved.contains("User appended here."));
+        assert!(resolved.contains("Agent appended here.\nUser appended here.\n"));
         // True conflict kept markers
         assert!(resolved.contains("<<<<<<<"));
         assert!(resolved.contains("Was originally this."));
@@ -279,27 +623,21 @@ End line.
 
     #[test]
     fn no_conflicts_passthrough() {
-        let merged = "Line one.\nLine two.\nLine three.\n";
-        let (resolved, has_remaining) = resolve_append_conflicts(merged);
-        assert!(!has_remaining);
-        assert_eq!(resolved, merged);
+        let text = "Line one.\nLine two.\nLine three.\n";
+        let result = merge3(text, text, text);
+        assert!(result.conflict_positions(ConflictPlacement::Markers).is_empty());
+        assert_eq!(result.to_text(), text);
     }
 
     #[test]
     fn multiline_append_conflict() {
-        let merged = "\
-<<<<<<< agent-response
-Agent line 1.
-Agent line 2.
-Agent line 3.
-||||||| original
-=======
-User line 1.
-User line 2.
->>>>>>> your-edits
-";
-        let (resolved, has_remaining) = resolve_append_conflicts(merged);
-        assert!(!has_remaining);
+        let base = "# Doc\n";
+        let ours = "# Doc\nAgent line 1.\nAgent line 2.\nAgent line 3.\n";
+        let theirs = "# Doc\nUser line 1.\nUser line 2.\n";
+        let mut result = merge3(base, ours, theirs);
+        result.resolve_append_only();
+        assert!(result.conflict_positions(ConflictPlacement::Markers).is_empty());
+        let resolved = result.to_text();
         assert!(resolved.contains("Agent line 1.\nAgent line 2.\nAgent line 3.\n"));
         assert!(resolved.contains("User line 1.\nUser line 2.\n"));
         // Agent before user
@@ -307,16 +645,65 @@ User line 2.
     }
 
     #[test]
-    fn merge_contents_clean() {
+    fn merge3_one_sided_changes() {
+        let base = "a\nb\nc\nd\n";
+        let ours = "a\nB\nc\nd\n";
+        let theirs = "a\nb\nc\nD\ne\n";
+        let result = merge3(base, ours, theirs);
+        assert!(result.conflict_positions(ConflictPlacement::Markers).is_empty());
+        assert_eq!(result.to_text(), "a\nB\nc\nD\ne\n");
+    }
+
+    #[test]
+    fn merge3_identical_changes_merge_cleanly() {
+        let base = "a\nb\n";
+        let both = "a\nchanged\n";
+        let result = merge3(base, both, both);
+        assert!(result.conflict_positions(ConflictPlacement::Markers).is_empty());
+        assert_eq!(result.to_text(), both);
+    }
+
+    #[test]
+    fn merge3_deletion_vs_unchanged() {
+        let base = "keep\ndrop\nkeep too\n";
+        let ours = "keep\nkeep too\n";
+        let result = merge3(base, ours, base);
+        assert_eq!(result.to_text(), ours);
+    }
+
+    #[test]
+    fn merge3_missing_trailing_newline() {
+        let base = "a\nb";
+        let ours = "a\nours";
+        let theirs = "a\ntheirs";
+        let result = merge3(base, ours, theirs);
+        assert!(result.to_text().contains("ours\n||||||| original\nb\n=======\ntheirs\n>>>>>>>"));
+    }
+
+    #[test]
+    fn append_friendly_clean_merge() {
         let base = "Line 1\nLine 2\n";
         let ours = "Line 1\nLine 2\nAgent added\n";
         let theirs = "Line 1\nLine 2\n";
-        let result = merge_contents(base, ours, theirs).unwrap();
+        let mut merged = merge3(base, ours, theirs);
+        merged.resolve_append_only();
+        let result = merged.to_text();
         assert!(result.contains("Agent added"));
     }
 
+    /// One write cycle as the writers run it: merge against the stored CRDT
+    /// state, then record the result in place.
+    fn crdt_write_cycle(doc: &Path, ours: &str, theirs: &str) -> String {
+        let base_state = crate::snapshot::load_crdt(doc).unwrap();
+        let merged = merge_document_crdt(doc, base_state.as_deref(), ours, theirs).unwrap().text;
+        crate::snapshot::save_crdt_text(doc, &merged).unwrap();
+        merged
+    }
+
     #[test]
     fn crdt_merge_agent_and_user_append() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let doc = dir.path().join("doc.md");
         let base = "# Doc\n\nBase content.\n";
         let ours = "# Doc\n\nBase content.\n\nAgent response.\n";
         let theirs = "# Doc\n\nBase content.\n\nUser addition.\n";
@@ -324,7 +711,7 @@ User line 2.
         let base_doc = crate::crdt::CrdtDoc::from_text(base);
         let base_state = base_doc.encode_state();
 
-        let (merged, _state) = merge_contents_crdt(Some(&base_state), ours, theirs).unwrap();
+        let merged = merge_document_crdt(&doc, Some(&base_state), ours, theirs).unwrap().text;
         assert!(merged.contains("Agent response."));
         assert!(merged.contains("User addition."));
         assert!(merged.contains("Base content."));
@@ -333,6 +720,8 @@ User line 2.
 
     #[test]
     fn crdt_merge_concurrent_same_line() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let doc = dir.path().join("doc.md");
         let base = "Line 1\nLine 3\n";
         let ours = "Line 1\nAgent\nLine 3\n";
         let theirs = "Line 1\nUser\nLine 3\n";
@@ -340,7 +729,7 @@ User line 2.
         let base_doc = crate::crdt::CrdtDoc::from_text(base);
         let base_state = base_doc.encode_state();
 
-        let (merged, _state) = merge_contents_crdt(Some(&base_state), ours, theirs).unwrap();
+        let merged = merge_document_crdt(&doc, Some(&base_state), ours, theirs).unwrap().text;
         // Both preserved, deterministic ordering, no conflict
         assert!(merged.contains("Agent"));
         assert!(merged.contains("User"));
@@ -348,34 +737,56 @@ User line 2.
         assert!(merged.contains("Line 3"));
     }
 
+    #[test]
+    fn crdt_merge_reconciles_checklists() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let doc = dir.path().join("doc.md");
+        let base = "<!-- agent:todo mode=checklist -->\n- [ ] A\n- [ ] B\n<!-- /agent:todo -->\n";
+        // Agent drops B and adds C; the user checks A and adds D
+        let ours = "<!-- agent:todo mode=checklist -->\n- [ ] A\n- [ ] C\n<!-- /agent:todo -->\n";
+        let theirs = "<!-- agent:todo mode=checklist -->\n- [x] A\n- [ ] B\n- [ ] D\n<!-- /agent:todo -->\n";
+
+        let base_state = crate::crdt::CrdtDoc::from_text(base).encode_state();
+        let merged = merge_document_crdt(&doc, Some(&base_state), ours, theirs).unwrap().text;
+        assert_eq!(
+            merged,
+            "<!-- agent:todo mode=checklist -->\n- [x] A\n- [ ] ~~B~~\n- [ ] D\n- [ ] C\n<!-- /agent:todo -->\n"
+        );
+    }
+
     #[test]
     fn crdt_merge_no_base_state_bootstrap() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let doc = dir.path().join("doc.md");
         let ours = "Agent content.\n";
         let theirs = "User content.\n";
 
-        let (merged, state) = merge_contents_crdt(None, ours, theirs).unwrap();
+        let merged = merge_document_crdt(&doc, None, ours, theirs).unwrap().text;
         assert!(merged.contains("Agent content."));
         assert!(merged.contains("User content."));
-        assert!(!state.is_empty());
     }
 
     #[test]
     fn crdt_merge_one_side_unchanged() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let doc = dir.path().join("doc.md");
         let base = "Original.\n";
         let base_doc = crate::crdt::CrdtDoc::from_text(base);
         let base_state = base_doc.encode_state();
 
         let ours = "Original.\nAgent added.\n";
-        let (merged, _) = merge_contents_crdt(Some(&base_state), ours, base).unwrap();
+        let merged = merge_document_crdt(&doc, Some(&base_state), ours, base).unwrap().text;
         assert_eq!(merged, ours);
     }
 
     #[test]
-    fn merge_contents_both_append() {
+    fn append_friendly_both_append() {
         let base = "Line 1\n";
         let ours = "Line 1\nAgent response\n";
         let theirs = "Line 1\nUser edit\n";
-        let result = merge_contents(base, ours, theirs).unwrap();
+        let mut merged = merge3(base, ours, theirs);
+        merged.resolve_append_only();
+        let result = merged.to_text();
         // Both should be present, no conflict markers
         assert!(result.contains("Agent response"));
         assert!(result.contains("User edit"));
@@ -389,7 +800,7 @@ User line 2.
     /// state. On the next cycle, the merge saw user edits as new insertions
     /// relative to the stale base, producing duplicate text.
     ///
-    /// This test simulates two consecutive merge cycles:
+    /// This test simulates two consecutive write cycles through the stored state:
     /// 1. Agent writes response while user edits concurrently → merge
     /// 2. Agent writes another response using the CRDT state from cycle 1
     ///
@@ -397,18 +808,16 @@ User line 2.
     #[test]
     fn crdt_state_includes_user_edits_no_duplicates() {
         // --- Cycle 1: Initial state, agent responds, user edits concurrently ---
-        let initial = "Why were the videos not public?\n";
-        let initial_doc = crate::crdt::CrdtDoc::from_text(initial);
-        let initial_state = initial_doc.encode_state();
+        let dir = tempfile::TempDir::new().unwrap();
+        let doc = dir.path().join("doc.md");
+        crate::snapshot::save_crdt_text(&doc, "Why were the videos not public?\n").unwrap();
 
         // Agent appends a response
         let ours_cycle1 = "Why were the videos not public?\nAlways publish public videos.\n";
         // User also edits concurrently (adds a line)
         let theirs_cycle1 = "Why were the videos not public?\nuser-edit-abc\n";
 
-        let (merged1, state1) = merge_contents_crdt(
-            Some(&initial_state), ours_cycle1, theirs_cycle1
-        ).unwrap();
+        let merged1 = crdt_write_cycle(&doc, ours_cycle1, theirs_cycle1);
 
         // Both edits present after cycle 1
         assert!(merged1.contains("Always publish public videos."), "missing agent response");
@@ -420,9 +829,7 @@ User line 2.
         // No user edits this time — theirs is the same as what was written to disk
         let theirs_cycle2 = merged1.clone();
 
-        let (merged2, _state2) = merge_contents_crdt(
-            Some(&state1), &ours_cycle2, &theirs_cycle2
-        ).unwrap();
+        let merged2 = crdt_write_cycle(&doc, &ours_cycle2, &theirs_cycle2);
 
         // The user's edit should appear exactly ONCE, not duplicated
         let edit_count = merged2.matches("user-edit-abc").count();
@@ -443,21 +850,21 @@ User line 2.
     /// partial responses multiple times while the user keeps editing.
     #[test]
     fn crdt_multi_flush_no_duplicates() {
-        let base = "# Doc\n\nQuestion here.\n";
-        let base_doc = crate::crdt::CrdtDoc::from_text(base);
-        let state0 = base_doc.encode_state();
+        let dir = tempfile::TempDir::new().unwrap();
+        let doc = dir.path().join("doc.md");
+        crate::snapshot::save_crdt_text(&doc, "# Doc\n\nQuestion here.\n").unwrap();
 
         // Flush 1: Agent starts responding, user adds a note
         let ours1 = "# Doc\n\nQuestion here.\n\n### Re: Answer\n\nFirst paragraph.\n";
         let theirs1 = "# Doc\n\nQuestion here.\n\n> user note\n";
-        let (merged1, state1) = merge_contents_crdt(Some(&state0), ours1, theirs1).unwrap();
+        let merged1 = crdt_write_cycle(&doc, ours1, theirs1);
         assert!(merged1.contains("First paragraph."));
         assert!(merged1.contains("> user note"));
 
         // Flush 2: Agent continues, user adds another note
         let ours2 = format!("{}\nSecond paragraph.\n", merged1);
         let theirs2 = format!("{}\n> another note\n", merged1);
-        let (merged2, _state2) = merge_contents_crdt(Some(&state1), &ours2, &theirs2).unwrap();
+        let merged2 = crdt_write_cycle(&doc, &ours2, &theirs2);
 
         // Each piece of content appears exactly once
         assert_eq!(merged2.matches("First paragraph.").count(), 1,
@@ -467,4 +874,176 @@ User line 2.
         assert!(merged2.contains("Second paragraph."));
         assert!(merged2.contains("> another note"));
     }
+
+    #[test]
+    fn crdt_merge_component_layout() {
+        let base = "---\nagent_doc_crdt_layout: components\n---\n<!-- agent:status -->\nIdle.\n<!-- /agent:status -->\n<!-- agent:exchange -->\nQ?\n<!-- /agent:exchange -->\n";
+        let dir = tempfile::TempDir::new().unwrap();
+        let doc = dir.path().join("doc.md");
+        crate::snapshot::save_crdt_text(&doc, base).unwrap();
+        let ours = base.replace("Q?\n", "Q?\nAnswer.\n");
+        let theirs = base.replace("Idle.", "Busy.");
+
+        let merged = crdt_write_cycle(&doc, &ours, &theirs);
+        assert!(merged.contains("Answer."));
+        assert!(merged.contains("Busy."));
+        let state = crate::snapshot::load_crdt(&doc).unwrap().unwrap();
+        let stored = crate::crdt::CrdtDoc::decode_state(&state).unwrap();
+        assert!(stored.is_component_layout());
+        assert_eq!(stored.to_text(), merged);
+    }
+
+    const STRATEGY_BASE: &str = "# Doc\n\n<!-- agent:status -->\nIdle.\n<!-- /agent:status -->\n\n<!-- agent:log -->\nentry 1\n<!-- /agent:log -->\n";
+
+    fn strategies(pairs: &[(&str, MergeStrategy)]) -> HashMap<String, MergeStrategy> {
+        pairs.iter().map(|(n, s)| (n.to_string(), *s)).collect()
+    }
+
+    #[test]
+    fn document_line_offsets_component_lines() {
+        let doc = "# Doc\n\n<!-- agent:status -->\nIdle.\nBusy.\n<!-- /agent:status -->\n";
+        assert_eq!(document_line(doc, Some("status"), 0), 4);
+        assert_eq!(document_line(doc, Some("status"), 1), 5);
+        assert_eq!(document_line(doc, None, 2), 3);
+    }
+
+    #[test]
+    fn merge_strategy_parse() {
+        assert_eq!(MergeStrategy::parse("append-friendly"), Some(MergeStrategy::AppendFriendly));
+        assert_eq!(MergeStrategy::parse("strict"), Some(MergeStrategy::Strict));
+        assert_eq!(MergeStrategy::parse("ours"), Some(MergeStrategy::Ours));
+        assert_eq!(MergeStrategy::parse("theirs"), Some(MergeStrategy::Theirs));
+        assert_eq!(MergeStrategy::parse("crdt"), Some(MergeStrategy::Crdt));
+        assert_eq!(MergeStrategy::parse("append_friendly"), None);
+    }
+
+    #[test]
+    fn by_component_ours_and_theirs() {
+        let ours = STRATEGY_BASE.replace("Idle.", "Agent status.").replace("entry 1", "agent entry");
+        let theirs = STRATEGY_BASE.replace("Idle.", "User status.").replace("entry 1", "user entry");
+        let merged = merge_by_component(
+            STRATEGY_BASE,
+            &ours,
+            &theirs,
+            &strategies(&[("status", MergeStrategy::Ours), ("log", MergeStrategy::Theirs)]),
+        )
+        .unwrap();
+        assert!(merged.text.contains("Agent status."));
+        assert!(merged.text.contains("user entry"));
+        assert!(!merged.text.contains("<<<<<<<"));
+        assert!(merged.conflicts.is_empty());
+    }
+
+    #[test]
+    fn by_component_default_is_append_friendly() {
+        let ours = STRATEGY_BASE.replace("entry 1\n", "entry 1\nagent entry\n");
+        let theirs = STRATEGY_BASE.replace("entry 1\n", "entry 1\nuser entry\n");
+        let merged = merge_by_component(STRATEGY_BASE, &ours, &theirs, &HashMap::new()).unwrap();
+        assert!(merged.text.contains("entry 1\nagent entry\nuser entry\n"));
+        assert_eq!(merged.text.matches("<!-- agent:log -->").count(), 1);
+    }
+
+    #[test]
+    fn by_component_reports_marker_conflicts() {
+        let base = STRATEGY_BASE.replace("entry 1\n", "entry 1\nentry 2\n");
+        let ours = base.replace("entry 2", "agent entry 2");
+        let theirs = base.replace("entry 2", "user entry 2");
+        let merged = merge_by_component(&base, &ours, &theirs, &HashMap::new()).unwrap();
+
+        assert_eq!(merged.conflicts.len(), 1);
+        let conflict = &merged.conflicts[0];
+        assert_eq!(conflict.component.as_deref(), Some("log"));
+        assert_eq!(conflict.placement, ConflictPlacement::Markers);
+        assert_eq!(conflict.line, 1);
+        let split = ComponentSplit::parse(&merged.text).unwrap();
+        let log: Vec<&str> = split.get("log").unwrap().lines().collect();
+        assert_eq!(log[conflict.line], "<<<<<<< agent-response");
+    }
+
+    #[test]
+    fn by_component_strict_keeps_user_version() {
+        let ours = STRATEGY_BASE.replace("Idle.", "Agent status.");
+        let theirs = STRATEGY_BASE.replace("Idle.", "User status.");
+        let merged = merge_by_component(
+            STRATEGY_BASE,
+            &ours,
+            &theirs,
+            &strategies(&[("status", MergeStrategy::Strict)]),
+        )
+        .unwrap();
+        assert!(!merged.text.contains("<<<<<<<"));
+        let split = ComponentSplit::parse(&merged.text).unwrap();
+        assert_eq!(split.get("status"), Some("User status.\n"));
+
+        assert_eq!(merged.conflicts.len(), 1);
+        let conflict = &merged.conflicts[0];
+        assert_eq!(conflict.component.as_deref(), Some("status"));
+        assert_eq!(conflict.placement, ConflictPlacement::KeptTheirs);
+        assert_eq!(conflict.line, 0);
+        assert_eq!(conflict.hunk.ours, "Agent status.\n");
+    }
+
+    #[test]
+    fn by_component_crdt_strategy() {
+        let ours = STRATEGY_BASE.replace("Idle.", "Idle. Agent.");
+        let theirs = STRATEGY_BASE.replace("Idle.", "User: Idle.");
+        let merged = merge_by_component(
+            STRATEGY_BASE,
+            &ours,
+            &theirs,
+            &strategies(&[("status", MergeStrategy::Crdt)]),
+        )
+        .unwrap();
+        assert!(merged.text.contains("Agent."));
+        assert!(merged.text.contains("User:"));
+        assert!(merged.conflicts.is_empty());
+    }
+
+    #[test]
+    fn by_component_falls_back_when_structure_changes() {
+        let ours = format!("{}<!-- agent:new -->\nx\n<!-- /agent:new -->\n", STRATEGY_BASE);
+        let theirs = STRATEGY_BASE.replace("# Doc", "# Doc (edited)");
+        let merged = merge_by_component(STRATEGY_BASE, &ours, &theirs, &HashMap::new()).unwrap();
+        assert!(merged.text.contains("# Doc (edited)"));
+        assert!(merged.text.contains("<!-- agent:new -->"));
+    }
+
+    #[test]
+    fn by_component_fallback_keeps_strategies() {
+        let base = STRATEGY_BASE.replace("entry 1\n", "entry 1\nentry 2\n");
+        let ours = format!("{}<!-- agent:new -->\nx\n<!-- /agent:new -->\n", base.replace("Idle.", "Agent status.").replace("entry 2", "agent entry 2"));
+        let theirs = base.replace("Idle.", "User status.").replace("entry 2", "user entry 2");
+        let merged = merge_by_component(&base, &ours, &theirs, &strategies(&[("status", MergeStrategy::Strict)])).unwrap();
+
+        let split = ComponentSplit::parse(&merged.text).unwrap();
+        assert_eq!(split.get("status"), Some("User status.\n"), "no markers in a strict component");
+        assert!(merged.text.contains("<!-- agent:new -->"));
+        assert_eq!(merged.conflicts.len(), 2);
+
+        let strict = merged.conflicts.iter().find(|c| c.component.as_deref() == Some("status")).unwrap();
+        assert_eq!(strict.placement, ConflictPlacement::KeptTheirs);
+        assert_eq!(strict.hunk.ours, "Agent status.\n");
+
+        // The whole-document conflict in `log` still points at its markers.
+        let markers = merged.conflicts.iter().find(|c| c.component.is_none()).unwrap();
+        let line = merged.text.lines().nth(markers.line).unwrap();
+        assert_eq!(line, "<<<<<<< agent-response");
+    }
+
+    #[test]
+    fn crdt_merge_applies_line_strategies() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let doc = dir.path().join("doc.md");
+        let base = STRATEGY_BASE.replace("<!-- agent:status -->", "<!-- agent:status merge_strategy=strict -->");
+        let base_state = crate::crdt::CrdtDoc::from_text(&base).encode_state();
+        let ours = base.replace("Idle.", "Agent status.").replace("entry 1\n", "entry 1\nagent entry\n");
+        let theirs = base.replace("Idle.", "User status.").replace("entry 1\n", "entry 1\nuser entry\n");
+
+        let merged = merge_document_crdt(&doc, Some(&base_state), &ours, &theirs).unwrap().text;
+        let split = ComponentSplit::parse(&merged).unwrap();
+        assert_eq!(split.get("status"), Some("User status.\n"));
+        assert!(merged.contains("agent entry") && merged.contains("user entry"));
+        assert!(!merged.contains("<<<<<<<"));
+        assert!(merged.contains("**1 unresolved merge conflict(s).**"), "strict conflict not recorded: {}", merged);
+    }
 }
@@ -0,0 +1,169 @@
+//! `agent-doc migrate` — Rewrite deprecated frontmatter keys.
+//!
+//! Usage: agent-doc migrate [--dry-run] <PATHS...>
+//!
+//! Directories are searched recursively for `.md` files (skipping hidden and
+//! build directories). In each document:
+//!
+//! - `session` is renamed to `agent_doc_session`;
+//! - `agent_doc_mode` / `mode` / `response_mode` become `agent_doc_format`
+//!   and `agent_doc_write`, resolved the way `Frontmatter::resolve_mode`
+//!   reads them (explicit new keys win).
+//!
+//! Only those lines change; other keys, comments and order are kept. The
+//! document's snapshot is migrated too, so the edit isn't sent as a diff.
+
+use anyhow::{Context, Result};
+use std::path::{Path, PathBuf};
+
+use crate::{frontmatter, gc, snapshot, write};
+
+const LEGACY_MODE_KEYS: &[&str] = &["agent_doc_mode", "mode", "response_mode"];
+
+pub fn run(paths: &[PathBuf], dry_run: bool) -> Result<()> {
+    let mut files = Vec::new();
+    for path in paths {
+        if path.is_dir() {
+            gc::find_markdown(path, &mut files)?;
+        } else if path.exists() {
+            files.push(path.clone());
+        } else {
+            anyhow::bail!("path not found: {}", path.display());
+        }
+    }
+    files.sort();
+    files.dedup();
+
+    let mut migrated = 0;
+    let mut failed = 0;
+    for file in &files {
+        match migrate_file(file, dry_run) {
+            Ok(changes) if changes.is_empty() => {}
+            Ok(changes) => {
+                migrated += 1;
+                println!("{}: {}", file.display(), changes.join("; "));
+            }
+            Err(e) => {
+                failed += 1;
+                eprintln!("[migrate] {}: {:#}", file.display(), e);
+            }
+        }
+    }
+
+    let verb = if dry_run { "would be migrated" } else { "migrated" };
+    eprintln!("[migrate] {} of {} file(s) {}", migrated, files.len(), verb);
+    if failed > 0 {
+        anyhow::bail!("{} file(s) could not be migrated", failed);
+    }
+    Ok(())
+}
+
+/// Migrate one document (and its snapshot) in place. Returns the changes made.
+fn migrate_file(file: &Path, dry_run: bool) -> Result<Vec<String>> {
+    let content = std::fs::read_to_string(file).with_context(|| format!("failed to read {}", file.display()))?;
+    let (updated, changes) = migrate_content(&content)?;
+    if changes.is_empty() || dry_run {
+        return Ok(changes);
+    }
+    write::atomic_write_pub(file, &updated)?;
+    if let Some(snap) = snapshot::load(file)?
+        && let Ok((snap_updated, snap_changes)) = migrate_content(&snap)
+        && !snap_changes.is_empty()
+    {
+        snapshot::save(file, &snap_updated)?;
+    }
+    Ok(changes)
+}
+
+/// Rewrite deprecated keys in a document. Returns the new content and a
+/// description of each change (empty if there was nothing to migrate).
+pub fn migrate_content(content: &str) -> Result<(String, Vec<String>)> {
+    let (fm, _) = frontmatter::parse(content)?;
+    let keys = frontmatter::keys(content)?;
+    let mut updated = content.to_string();
+    let mut changes = Vec::new();
+
+    if keys.iter().any(|k| k == "session") {
+        updated = frontmatter::rename_field(&updated, "session", "agent_doc_session", None)?;
+        changes.push("session → agent_doc_session".to_string());
+    }
+
+    let legacy: Vec<&str> = LEGACY_MODE_KEYS.iter().copied().filter(|k| keys.iter().any(|key| key == k)).collect();
+    if let Some(&first) = legacy.first() {
+        if let Some(mode) = fm.mode.as_deref()
+            && !matches!(mode, "append" | "template" | "stream")
+        {
+            anyhow::bail!("unknown {} value '{}'; set agent_doc_format and agent_doc_write by hand", first, mode);
+        }
+        let resolved = fm.resolve_mode();
+        let format = resolved.format.to_string();
+        let write = resolved.write.to_string();
+        updated = if fm.format.is_none() {
+            frontmatter::rename_field(&updated, first, "agent_doc_format", Some(&format))?
+        } else {
+            frontmatter::set_field(&updated, first, &[], None)?
+        };
+        for key in &legacy[1..] {
+            updated = frontmatter::set_field(&updated, key, &[], None)?;
+        }
+        if fm.write_mode.is_none() {
+            updated = frontmatter::insert_field(&updated, "agent_doc_write", "agent_doc_format", &write)?;
+        }
+        changes.push(format!(
+            "{} → agent_doc_format: {}, agent_doc_write: {}",
+            legacy.join(", "),
+            format,
+            write
+        ));
+    }
+    Ok((updated, changes))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn migrates_legacy_keys_in_place() {
+        let doc = "---\ntitle: Notes  # mine\nsession: abc   # routing\nagent: claude\nmode: append\ntags:\n  - x\n---\nBody\n";
+        let (updated, changes) = migrate_content(doc).unwrap();
+        assert_eq!(
+            updated,
+            "---\ntitle: Notes  # mine\nagent_doc_session: abc   # routing\nagent: claude\nagent_doc_format: append\nagent_doc_write: crdt\ntags:\n  - x\n---\nBody\n"
+        );
+        assert_eq!(changes.len(), 2);
+        assert_eq!(migrate_content(&updated).unwrap().1, Vec::<String>::new(), "idempotent");
+    }
+
+    #[test]
+    fn explicit_keys_win_over_legacy_mode() {
+        let doc = "---\nresponse_mode: stream\nagent_doc_format: append\n---\n";
+        let (updated, _) = migrate_content(doc).unwrap();
+        assert_eq!(updated, "---\nagent_doc_format: append\nagent_doc_write: crdt\n---\n");
+
+        let err = migrate_content("---\nagent_doc_mode: bogus\n---\n").unwrap_err();
+        assert!(err.to_string().contains("unknown agent_doc_mode value 'bogus'"));
+    }
+
+    #[test]
+    fn run_walks_directories_and_respects_dry_run() {
+        let dir = TempDir::new().unwrap();
+        std::fs::create_dir_all(dir.path().join("notes/.hidden")).unwrap();
+        let legacy = "---\nsession: abc\n---\nBody\n";
+        std::fs::write(dir.path().join("notes/a.md"), legacy).unwrap();
+        std::fs::write(dir.path().join("notes/.hidden/b.md"), legacy).unwrap();
+        std::fs::write(dir.path().join("notes/c.txt"), legacy).unwrap();
+
+        run(&[dir.path().to_path_buf()], true).unwrap();
+        assert_eq!(std::fs::read_to_string(dir.path().join("notes/a.md")).unwrap(), legacy);
+
+        run(&[dir.path().to_path_buf()], false).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(dir.path().join("notes/a.md")).unwrap(),
+            "---\nagent_doc_session: abc\n---\nBody\n"
+        );
+        assert_eq!(std::fs::read_to_string(dir.path().join("notes/.hidden/b.md")).unwrap(), legacy);
+        assert_eq!(std::fs::read_to_string(dir.path().join("notes/c.txt")).unwrap(), legacy);
+    }
+}
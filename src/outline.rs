@@ -2,17 +2,17 @@ use anyhow::Result;
 use std::path::Path;
 
 /// A heading-delimited section of a markdown document.
-struct Section {
+pub(crate) struct Section {
     /// Heading text (e.g. "## User")
-    heading: String,
+    pub(crate) heading: String,
     /// Heading depth (1 for #, 2 for ##, etc.)
-    depth: usize,
+    pub(crate) depth: usize,
     /// Line number where the heading appears (1-based)
-    line: usize,
+    pub(crate) line: usize,
     /// Number of content lines (excluding the heading itself)
-    lines: usize,
+    pub(crate) lines: usize,
     /// Approximate token count (bytes / 4)
-    tokens: usize,
+    pub(crate) tokens: usize,
 }
 
 pub fn run(file: &Path, json: bool) -> Result<()> {
@@ -34,7 +34,7 @@ pub fn run(file: &Path, json: bool) -> Result<()> {
     Ok(())
 }
 
-fn parse_sections(body: &str) -> Vec<Section> {
+pub(crate) fn parse_sections(body: &str) -> Vec<Section> {
     let mut sections: Vec<Section> = Vec::new();
     let lines: Vec<&str> = body.lines().collect();
 
@@ -96,7 +96,7 @@ fn parse_sections(body: &str) -> Vec<Section> {
     sections
 }
 
-fn heading_depth(line: &str) -> Option<usize> {
+pub(crate) fn heading_depth(line: &str) -> Option<usize> {
     let trimmed = line.trim_start();
     if !trimmed.starts_with('#') {
         return None;
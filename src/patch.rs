@@ -1,33 +1,44 @@
 use anyhow::{bail, Context, Result};
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::io::Read;
 use std::path::Path;
 use std::process::Command;
 
-use crate::{component, snapshot};
+use crate::merge::MergeStrategy;
+use crate::{component, generate, snapshot};
 
 const COMPONENTS_FILENAME: &str = ".agent-doc/components.toml";
 
-/// Component configuration from `.agent-doc/components.toml`.
-#[derive(Debug, Deserialize, Default)]
-struct ComponentConfig {
-    /// Patch mode: "replace" (default), "append", "prepend"
-    #[serde(default = "default_mode")]
-    mode: String,
-    /// Merge strategy: "append-friendly" (default) or "strict".
-    /// "append-friendly" auto-resolves conflicts where both sides only appended.
-    /// "strict" preserves all conflict markers for manual resolution.
-    /// Currently parsed for config validation; merge runs at document level.
+/// Component configuration from `.agent-doc/components.toml`, optionally
+/// overridden by inline marker attributes (see [`config_for`]).
+#[derive(Debug, Clone, Deserialize, Default)]
+pub(crate) struct ComponentConfig {
+    /// Patch mode: "replace", "append", "prepend", "table" or "checklist". Unset means the
+    /// caller's default ("replace", or "append" for `exchange` in template patches).
+    #[serde(default)]
+    pub(crate) mode: Option<String>,
+    /// Key column for table mode (default: the first column). See [`crate::table`].
+    #[serde(default)]
+    pub(crate) key: Option<String>,
+    /// Merge strategy used when `write`/`submit` merge concurrent edits:
+    /// "append-friendly" (default), "strict", "ours", "theirs" or "crdt".
+    /// See [`MergeStrategy`].
     #[serde(default = "default_merge_strategy")]
-    #[allow(dead_code)]
-    merge_strategy: String,
+    pub(crate) merge_strategy: String,
     /// Auto-prefix entries with ISO timestamp (for append/prepend modes)
     #[serde(default)]
-    timestamp: bool,
+    pub(crate) timestamp: bool,
     /// Auto-trim old entries in append/prepend modes (0 = unlimited)
     #[serde(default)]
-    max_entries: usize,
+    pub(crate) max_entries: usize,
+    /// Who may write the component (see [`Owner`]).
+    #[serde(default)]
+    pub(crate) owner: Owner,
+    /// Agents may create this component with a placement patch
+    /// (`<!-- patch:name after=status -->`). Only read from `components.toml`.
+    #[serde(default)]
+    pub(crate) creatable: bool,
     /// Shell command to run before patching (stdin: content, stdout: transformed)
     #[serde(default)]
     pre_patch: Option<String>,
@@ -36,12 +47,144 @@ struct ComponentConfig {
     post_patch: Option<String>,
 }
 
+/// Who owns a component's content.
+///
+/// Agent patches (template and stream writes, IPC) to a `user`-owned component,
+/// or to a component nested in or containing one, are rejected. `agent-doc patch`
+/// is a user/script command and ignores ownership.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Owner {
+    /// Written by the user only (requirements, hand-written specs).
+    User,
+    /// Maintained by the agent; plugins may render it read-only.
+    Agent,
+    /// Written by both (default).
+    #[default]
+    Shared,
+}
+
+impl Owner {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "user" => Some(Self::User),
+            "agent" => Some(Self::Agent),
+            "shared" => Some(Self::Shared),
+            _ => None,
+        }
+    }
+
+    pub fn is_shared(&self) -> bool {
+        *self == Self::Shared
+    }
+}
+
 fn default_merge_strategy() -> String {
     "append-friendly".to_string()
 }
 
-fn default_mode() -> String {
-    "replace".to_string()
+impl ComponentConfig {
+    /// Override settings with the component's inline marker attributes.
+    ///
+    /// Hooks can only be set in `components.toml`, so a copied document can't
+    /// run shell commands; other unknown attributes are ignored.
+    fn with_attrs(mut self, comp: &component::Component) -> Result<Self> {
+        for (key, value) in &comp.attrs {
+            match key.as_str() {
+                "mode" => {
+                    if !matches!(value.as_str(), "replace" | "append" | "prepend" | "table" | "checklist") {
+                        bail!("invalid mode '{}' on <!-- agent:{} -->", value, comp.name);
+                    }
+                    self.mode = Some(value.clone());
+                }
+                "key" => self.key = Some(value.clone()),
+                "timestamp" => {
+                    self.timestamp = value.parse().map_err(|_| {
+                        anyhow::anyhow!("invalid timestamp '{}' on <!-- agent:{} -->", value, comp.name)
+                    })?;
+                }
+                "max_entries" => {
+                    self.max_entries = value.parse().map_err(|_| {
+                        anyhow::anyhow!("invalid max_entries '{}' on <!-- agent:{} -->", value, comp.name)
+                    })?;
+                }
+                "merge_strategy" => {
+                    if MergeStrategy::parse(value).is_none() {
+                        bail!("invalid merge_strategy '{}' on <!-- agent:{} -->", value, comp.name);
+                    }
+                    self.merge_strategy = value.clone();
+                }
+                "owner" => {
+                    self.owner = Owner::parse(value).ok_or_else(|| {
+                        anyhow::anyhow!(
+                            "invalid owner '{}' on <!-- agent:{} --> (expected user, agent or shared)",
+                            value,
+                            comp.name
+                        )
+                    })?;
+                }
+                "pre_patch" | "post_patch" => eprintln!(
+                    "[patch] Ignoring inline {} on <!-- agent:{} -->; hooks can only be set in {}",
+                    key, comp.name, COMPONENTS_FILENAME
+                ),
+                _ => {}
+            }
+        }
+        Ok(self)
+    }
+}
+
+/// Effective config for `comp`, addressed as `path`: its `components.toml`
+/// entry (by path, then by name) with inline marker attributes on top.
+pub(crate) fn config_for(
+    configs: &HashMap<String, ComponentConfig>,
+    path: &str,
+    comp: &component::Component,
+) -> Result<ComponentConfig> {
+    configs
+        .get(path)
+        .or_else(|| configs.get(&comp.name))
+        .cloned()
+        .unwrap_or_else(|| ComponentConfig {
+            merge_strategy: default_merge_strategy(),
+            ..Default::default()
+        })
+        .with_attrs(comp)
+}
+
+/// Combine `existing` content with `replacement` according to the config's
+/// mode (`default_mode` if unset), timestamp and max_entries.
+///
+/// Table mode upserts the replacement's rows by key and fails if the rows don't
+/// fit the table; checklist mode merges items (see [`crate::checklist`]). Both
+/// ignore timestamp and max_entries.
+pub(crate) fn apply_config(
+    config: &ComponentConfig,
+    default_mode: &str,
+    existing: &str,
+    replacement: &str,
+) -> Result<String> {
+    match config.mode.as_deref().unwrap_or(default_mode) {
+        "table" => return crate::table::upsert(existing, replacement, config.key.as_deref()),
+        "checklist" => return Ok(crate::checklist::apply(existing, replacement)),
+        _ => {}
+    }
+    let entry = if config.timestamp {
+        format!("[{}] {}", iso_now(), replacement)
+    } else {
+        replacement.to_string()
+    };
+    let combined = match config.mode.as_deref().unwrap_or(default_mode) {
+        "append" => format!("{}{}", existing, entry),
+        "prepend" => format!("{}{}", entry, existing),
+        // "replace" (default)
+        _ => return Ok(entry),
+    };
+    if config.max_entries > 0 {
+        Ok(trim_entries(&combined, config.max_entries))
+    } else {
+        Ok(combined)
+    }
 }
 
 /// Find the project root by walking up from a file path looking for `.agent-doc/`.
@@ -57,7 +200,7 @@ fn find_project_root(file: &Path) -> Option<std::path::PathBuf> {
 }
 
 /// Load component configs from `.agent-doc/components.toml` relative to project root.
-fn load_configs(file: &Path) -> Result<HashMap<String, ComponentConfig>> {
+pub(crate) fn load_configs(file: &Path) -> Result<HashMap<String, ComponentConfig>> {
     let root = match find_project_root(file) {
         Some(r) => r,
         None => return Ok(HashMap::new()),
@@ -73,6 +216,62 @@ fn load_configs(file: &Path) -> Result<HashMap<String, ComponentConfig>> {
     Ok(configs)
 }
 
+/// Names of checklist-mode components, from the `.agent-doc/components.toml`
+/// found for `file` (if given) and `mode=` attributes on the markers in `doc`.
+pub(crate) fn checklist_components(file: Option<&Path>, doc: &str) -> Result<Vec<String>> {
+    let configs = match file {
+        Some(file) => load_configs(file)?,
+        None => HashMap::new(),
+    };
+    let mut names: Vec<String> = configs
+        .into_iter()
+        .filter(|(_, config)| config.mode.as_deref() == Some("checklist"))
+        .map(|(name, _)| name)
+        .collect();
+    for comp in component::parse(doc).unwrap_or_default() {
+        match comp.attrs.get("mode").map(String::as_str) {
+            Some("checklist") if !names.contains(&comp.name) => names.push(comp.name.clone()),
+            Some(mode) if mode != "checklist" => names.retain(|n| *n != comp.name),
+            _ => {}
+        }
+    }
+    Ok(names)
+}
+
+/// Load per-component merge strategies from `.agent-doc/components.toml`,
+/// overridden by `merge_strategy=` attributes on the markers in `doc`.
+///
+/// Components without either use [`MergeStrategy::AppendFriendly`].
+pub(crate) fn load_merge_strategies(file: &Path, doc: &str) -> Result<HashMap<String, MergeStrategy>> {
+    let mut strategies = HashMap::new();
+    for (name, config) in load_configs(file)? {
+        match MergeStrategy::parse(&config.merge_strategy) {
+            Some(strategy) => {
+                strategies.insert(name, strategy);
+            }
+            None => eprintln!(
+                "[patch] invalid merge_strategy '{}' for component '{}' in {} \
+                 (expected append-friendly, strict, ours, theirs or crdt), using append-friendly",
+                config.merge_strategy, name, COMPONENTS_FILENAME
+            ),
+        }
+    }
+    for comp in component::parse(doc).unwrap_or_default() {
+        if let Some(value) = comp.attrs.get("merge_strategy") {
+            match MergeStrategy::parse(value) {
+                Some(strategy) => {
+                    strategies.insert(comp.name.clone(), strategy);
+                }
+                None => eprintln!(
+                    "[patch] invalid merge_strategy '{}' on <!-- agent:{} -->, using the configured strategy",
+                    value, comp.name
+                ),
+            }
+        }
+    }
+    Ok(strategies)
+}
+
 /// Replace content in a named component.
 ///
 /// If `content` is None, reads replacement content from stdin.
@@ -88,9 +287,7 @@ pub fn run(file: &Path, component_name: &str, content: Option<&str>) -> Result<(
     let components = component::parse(&doc)
         .with_context(|| format!("failed to parse components in {}", file.display()))?;
 
-    let comp = components
-        .iter()
-        .find(|c| c.name == component_name)
+    let comp = component::find_by_path(&components, component_name)
         .ok_or_else(|| {
             anyhow::anyhow!(
                 "component '{}' not found in {}",
@@ -100,7 +297,7 @@ pub fn run(file: &Path, component_name: &str, content: Option<&str>) -> Result<(
         })?;
 
     let configs = load_configs(file).unwrap_or_default();
-    let config = configs.get(component_name);
+    let config = config_for(&configs, component_name, comp)?;
 
     let mut replacement = match content {
         Some(text) => text.to_string(),
@@ -114,53 +311,15 @@ pub fn run(file: &Path, component_name: &str, content: Option<&str>) -> Result<(
     };
 
     // Run pre_patch hook (transforms content)
-    if let Some(script) = config.and_then(|c| c.pre_patch.as_ref()) {
+    if let Some(script) = &config.pre_patch {
         replacement = run_pre_hook(script, component_name, file, &replacement)?;
     }
 
-    // Apply mode
-    let mode = config.map(|c| c.mode.as_str()).unwrap_or("replace");
-    let timestamp = config.is_some_and(|c| c.timestamp);
-    let max_entries = config.map(|c| c.max_entries).unwrap_or(0);
-
-    let final_content = match mode {
-        "append" => {
-            let existing = comp.content(&doc);
-            let entry = if timestamp {
-                format!("[{}] {}", iso_now(), replacement)
-            } else {
-                replacement
-            };
-            let mut combined = format!("{}{}", existing, entry);
-            if max_entries > 0 {
-                combined = trim_entries(&combined, max_entries);
-            }
-            combined
-        }
-        "prepend" => {
-            let existing = comp.content(&doc);
-            let entry = if timestamp {
-                format!("[{}] {}", iso_now(), replacement)
-            } else {
-                replacement
-            };
-            let mut combined = format!("{}{}", entry, existing);
-            if max_entries > 0 {
-                combined = trim_entries(&combined, max_entries);
-            }
-            combined
-        }
-        _ => {
-            // "replace" (default)
-            if timestamp {
-                format!("[{}] {}", iso_now(), replacement)
-            } else {
-                replacement
-            }
-        }
-    };
+    let mode = config.mode.as_deref().unwrap_or("replace");
+    let final_content = apply_config(&config, "replace", comp.content(&doc), &replacement)
+        .with_context(|| format!("failed to patch component '{}'", component_name))?;
 
-    let new_doc = comp.replace_content(&doc, &final_content);
+    let new_doc = generate::regenerate(&comp.replace_content(&doc, &final_content), file);
 
     std::fs::write(file, &new_doc)
         .with_context(|| format!("failed to write {}", file.display()))?;
@@ -182,7 +341,7 @@ pub fn run(file: &Path, component_name: &str, content: Option<&str>) -> Result<(
     }
 
     // Run post_patch hook (fire-and-forget)
-    if let Some(script) = config.and_then(|c| c.post_patch.as_ref()) {
+    if let Some(script) = &config.post_patch {
         run_post_hook(script, component_name, file);
     }
 
@@ -306,6 +465,21 @@ mod tests {
         assert!(result.contains("Footer"));
     }
 
+    #[test]
+    fn regenerates_derived_components() {
+        let dir = setup_project();
+        let doc = write_doc(
+            dir.path(),
+            "test.md",
+            "# Plan\n<!-- agent:toc -->\n<!-- /agent:toc -->\n<!-- agent:notes -->\nold\n<!-- /agent:notes -->\n",
+        );
+
+        run(&doc, "notes", Some("## Findings\n")).unwrap();
+
+        let result = std::fs::read_to_string(&doc).unwrap();
+        assert!(result.contains("<!-- agent:toc -->\n- [Plan](#plan)\n  - [Findings](#findings)\n<!-- /agent:toc -->"));
+    }
+
     #[test]
     fn preserve_surrounding() {
         let dir = setup_project();
@@ -465,4 +639,140 @@ mod tests {
 
         assert!(marker.exists(), "post_patch hook should have created marker file");
     }
+
+    #[test]
+    fn load_merge_strategies_from_config() {
+        let dir = setup_project();
+        write_config(
+            dir.path(),
+            "[log]\nmode = \"append\"\n\n[status]\nmerge_strategy = \"strict\"\n\n[notes]\nmerge_strategy = \"crdt\"\n",
+        );
+        let doc = write_doc(dir.path(), "test.md", "# Doc\n");
+
+        let strategies = load_merge_strategies(&doc, "").unwrap();
+        assert_eq!(strategies["log"], MergeStrategy::AppendFriendly);
+        assert_eq!(strategies["status"], MergeStrategy::Strict);
+        assert_eq!(strategies["notes"], MergeStrategy::Crdt);
+    }
+
+    #[test]
+    fn load_merge_strategies_ignores_unknown() {
+        let dir = setup_project();
+        write_config(dir.path(), "[status]\nmerge_strategy = \"mine\"\n\n[log]\nmerge_strategy = \"ours\"\n");
+        let doc = write_doc(dir.path(), "test.md", "# Doc\n");
+
+        let strategies = load_merge_strategies(&doc, "<!-- agent:log merge_strategy=nope -->\n<!-- /agent:log -->\n").unwrap();
+        assert!(!strategies.contains_key("status"));
+        assert_eq!(strategies["log"], MergeStrategy::Ours);
+    }
+
+    #[test]
+    fn patch_nested_path() {
+        let dir = setup_project();
+        let doc = write_doc(
+            dir.path(),
+            "test.md",
+            "<!-- agent:plan -->\n<!-- agent:plan-step-1 -->\ntodo\n<!-- /agent:plan-step-1 -->\n<!-- /agent:plan -->\n",
+        );
+
+        run(&doc, "plan/step-1", Some("done\n")).unwrap();
+
+        let result = std::fs::read_to_string(&doc).unwrap();
+        assert!(result.contains("<!-- agent:plan-step-1 -->\ndone\n<!-- /agent:plan-step-1 -->"));
+    }
+
+    #[test]
+    fn inline_attributes_override_config() {
+        let dir = setup_project();
+        write_config(dir.path(), "[log]\nmode = \"prepend\"\nmax_entries = 10\n");
+        let doc = write_doc(
+            dir.path(),
+            "test.md",
+            "<!-- agent:log mode=append max_entries=2 -->\nline1\nline2\n<!-- /agent:log -->\n",
+        );
+
+        run(&doc, "log", Some("line3\n")).unwrap();
+
+        let result = std::fs::read_to_string(&doc).unwrap();
+        assert!(result.contains("<!-- agent:log mode=append max_entries=2 -->\nline2\nline3\n"));
+        assert!(!result.contains("line1"));
+    }
+
+    #[test]
+    fn inline_hooks_are_ignored() {
+        let dir = setup_project();
+        let marker = dir.path().join("hook-ran");
+        let doc = write_doc(
+            dir.path(),
+            "test.md",
+            &format!(
+                "<!-- agent:x post_patch=\"touch {}\" -->\nold\n<!-- /agent:x -->\n",
+                marker.to_string_lossy()
+            ),
+        );
+
+        run(&doc, "x", Some("new\n")).unwrap();
+        assert!(!marker.exists(), "inline hooks must not run");
+    }
+
+    #[test]
+    fn inline_invalid_mode_errors() {
+        let dir = setup_project();
+        let doc = write_doc(dir.path(), "test.md", "<!-- agent:x mode=sideways -->\nold\n<!-- /agent:x -->\n");
+        let err = run(&doc, "x", Some("new\n")).unwrap_err();
+        assert!(err.to_string().contains("invalid mode 'sideways'"));
+    }
+
+    #[test]
+    fn merge_strategies_from_marker_attributes() {
+        let dir = setup_project();
+        write_config(dir.path(), "[status]\nmerge_strategy = \"strict\"\n");
+        let doc = write_doc(dir.path(), "test.md", "");
+        let content = "<!-- agent:status merge_strategy=ours -->\n<!-- /agent:status -->\n";
+        let strategies = load_merge_strategies(&doc, content).unwrap();
+        assert_eq!(strategies["status"], MergeStrategy::Ours);
+    }
+
+    #[test]
+    fn owner_from_config_and_attributes() {
+        let dir = setup_project();
+        write_config(dir.path(), "[spec]\nowner = \"user\"\n");
+        let doc = write_doc(dir.path(), "test.md", "");
+        let configs = load_configs(&doc).unwrap();
+        let components = component::parse("<!-- agent:spec -->\n<!-- /agent:spec -->\n").unwrap();
+        assert_eq!(config_for(&configs, "spec", &components[0]).unwrap().owner, Owner::User);
+
+        let components = component::parse("<!-- agent:spec owner=agent -->\n<!-- /agent:spec -->\n").unwrap();
+        assert_eq!(config_for(&configs, "spec", &components[0]).unwrap().owner, Owner::Agent);
+
+        let components = component::parse("<!-- agent:spec owner=me -->\n<!-- /agent:spec -->\n").unwrap();
+        let err = config_for(&configs, "spec", &components[0]).unwrap_err();
+        assert!(err.to_string().contains("invalid owner 'me'"));
+    }
+
+    #[test]
+    fn patch_command_ignores_ownership() {
+        let dir = setup_project();
+        let doc = write_doc(dir.path(), "test.md", "<!-- agent:spec owner=user -->\nold\n<!-- /agent:spec -->\n");
+        run(&doc, "spec", Some("new\n")).unwrap();
+        assert!(std::fs::read_to_string(&doc).unwrap().contains("\nnew\n"));
+    }
+
+    #[test]
+    fn table_mode_upserts_rows() {
+        let dir = setup_project();
+        write_config(dir.path(), "[decisions]\nmode = \"table\"\nkey = \"Key\"\n");
+        let doc = write_doc(
+            dir.path(),
+            "test.md",
+            "<!-- agent:decisions -->\n| Key | Decision |\n|-----|----------|\n| D-1 | Use yrs  |\n<!-- /agent:decisions -->\n",
+        );
+
+        run(&doc, "decisions", Some("| D-2 | Use similar |\n")).unwrap();
+        let result = std::fs::read_to_string(&doc).unwrap();
+        assert!(result.contains("| D-1 | Use yrs  |\n| D-2 | Use similar |\n<!-- /agent:decisions -->"));
+
+        let err = run(&doc, "decisions", Some("| | orphan |\n")).unwrap_err();
+        assert!(format!("{:#}", err).contains("row has no 'Key' key"));
+    }
 }
@@ -0,0 +1,462 @@
+//! Patch operations beyond replace/append/prepend.
+//!
+//! A patch block can name an operation with attributes on its opening marker:
+//!
+//! ```text
+//! <!-- patch:plan op=insert-after anchor="## Next steps" -->
+//! - Write the migration
+//! <!-- /patch:plan -->
+//! ```
+//!
+//! Blocks without `op` combine with the component per its mode.
+//!
+//! A block for a component that doesn't exist yet can create it with a
+//! [`Placement`] (`<!-- patch:risks after=plan -->`), if the name is allowed.
+
+use anyhow::{bail, Context, Result};
+use regex::{NoExpand, Regex};
+use std::collections::BTreeMap;
+use std::fmt;
+
+/// Operation a patch block applies to its target component.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum PatchOp {
+    /// Replace, append or prepend per the component's mode.
+    #[default]
+    Mode,
+    /// Apply the block as a unified diff against the component content.
+    Diff,
+    /// Insert the block after the first line containing `anchor`.
+    InsertAfter { anchor: String },
+    /// Insert the block before the first line containing `anchor`.
+    InsertBefore { anchor: String },
+    /// Delete `count` lines starting at the first line containing `anchor`.
+    Delete { anchor: String, count: usize },
+    /// Replace every match of `pattern` with the block, taken literally unless
+    /// `expand` is set (then `$1`, `${name}` expand captures and `$$` is a `$`).
+    Replace { pattern: String, expand: bool },
+}
+
+impl fmt::Display for PatchOp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PatchOp::Mode => write!(f, "mode"),
+            PatchOp::Diff => write!(f, "diff"),
+            PatchOp::InsertAfter { anchor } => write!(f, "insert-after {:?}", anchor),
+            PatchOp::InsertBefore { anchor } => write!(f, "insert-before {:?}", anchor),
+            PatchOp::Delete { anchor, count } => write!(f, "delete {:?} ({} line(s))", anchor, count),
+            PatchOp::Replace { pattern, .. } => write!(f, "replace /{}/", pattern),
+        }
+    }
+}
+
+impl PatchOp {
+    /// Build the operation from patch marker attributes.
+    pub fn from_attrs(attrs: &BTreeMap<String, String>) -> Result<Self> {
+        let anchor = || {
+            attrs
+                .get("anchor")
+                .cloned()
+                .filter(|a| !a.is_empty())
+                .context("missing anchor=\"...\" attribute")
+        };
+        let Some(op) = attrs.get("op") else {
+            return Ok(PatchOp::Mode);
+        };
+        Ok(match op.as_str() {
+            "diff" => PatchOp::Diff,
+            "insert-after" => PatchOp::InsertAfter { anchor: anchor()? },
+            "insert-before" => PatchOp::InsertBefore { anchor: anchor()? },
+            "delete" => {
+                let count = match attrs.get("count") {
+                    Some(n) => n
+                        .parse()
+                        .ok()
+                        .filter(|&n| n > 0)
+                        .with_context(|| format!("invalid count '{}'", n))?,
+                    None => 1,
+                };
+                PatchOp::Delete { anchor: anchor()?, count }
+            }
+            "replace" => {
+                let pattern = attrs
+                    .get("pattern")
+                    .context("op=replace needs pattern=\"...\" (omit op to replace the whole content)")?;
+                Regex::new(pattern).with_context(|| format!("invalid pattern /{}/", pattern))?;
+                let expand = match attrs.get("expand") {
+                    Some(value) => value
+                        .parse()
+                        .ok()
+                        .with_context(|| format!("invalid expand '{}' (expected true or false)", value))?,
+                    None => false,
+                };
+                PatchOp::Replace { pattern: pattern.clone(), expand }
+            }
+            other => bail!(
+                "unknown op '{}' (expected diff, insert-after, insert-before, delete or replace)",
+                other
+            ),
+        })
+    }
+}
+
+/// Where a patch block creates its component if the document doesn't have it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Placement {
+    /// Right after the component at this name or path (`after=status`).
+    After(String),
+    /// Right before the component at this name or path (`before=status`).
+    Before(String),
+    /// At the end of the section under this heading (`under="## Risks"`).
+    Under(String),
+}
+
+impl fmt::Display for Placement {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Placement::After(path) => write!(f, "after={}", path),
+            Placement::Before(path) => write!(f, "before={}", path),
+            Placement::Under(heading) => write!(f, "under={:?}", heading),
+        }
+    }
+}
+
+impl Placement {
+    /// Read the placement from patch marker attributes; at most one may be given.
+    pub fn from_attrs(attrs: &BTreeMap<String, String>) -> Result<Option<Self>> {
+        let mut found = None;
+        for (key, value) in attrs {
+            let placement = match key.as_str() {
+                "after" => Placement::After(value.clone()),
+                "before" => Placement::Before(value.clone()),
+                "under" => Placement::Under(value.clone()),
+                _ => continue,
+            };
+            if value.is_empty() || value == "true" {
+                bail!("{} needs a value", key);
+            }
+            if found.is_some() {
+                bail!("only one of after, before or under may be given");
+            }
+            found = Some(placement);
+        }
+        Ok(found)
+    }
+}
+
+/// Apply `op` to a component's `existing` content with the patch block `content`.
+///
+/// Fails with a description of what didn't match (anchor, pattern or diff hunk).
+/// `PatchOp::Mode` is the caller's responsibility and returns `content` unchanged.
+pub fn apply(op: &PatchOp, existing: &str, content: &str) -> Result<String> {
+    match op {
+        PatchOp::Mode => Ok(content.to_string()),
+        PatchOp::Diff => apply_unified_diff(existing, content),
+        PatchOp::InsertAfter { anchor } => {
+            let (_, end) = find_anchor_line(existing, anchor)?;
+            Ok(splice(existing, end, end, &with_newline(content), true))
+        }
+        PatchOp::InsertBefore { anchor } => {
+            let (start, _) = find_anchor_line(existing, anchor)?;
+            Ok(splice(existing, start, start, &with_newline(content), false))
+        }
+        PatchOp::Delete { anchor, count } => {
+            let (start, mut end) = find_anchor_line(existing, anchor)?;
+            for n in 1..*count {
+                if end >= existing.len() {
+                    bail!(
+                        "only {} line(s) from anchor {:?} to the end of the component, cannot delete {}",
+                        n,
+                        anchor,
+                        count
+                    );
+                }
+                end = line_end(existing, end);
+            }
+            Ok(splice(existing, start, end, "", false))
+        }
+        PatchOp::Replace { pattern, expand } => {
+            let re = Regex::new(pattern).with_context(|| format!("invalid pattern /{}/", pattern))?;
+            if !re.is_match(existing) {
+                bail!("pattern /{}/ matched nothing", pattern);
+            }
+            let replacement = content.strip_suffix('\n').unwrap_or(content);
+            if *expand {
+                Ok(re.replace_all(existing, replacement).into_owned())
+            } else {
+                Ok(re.replace_all(existing, NoExpand(replacement)).into_owned())
+            }
+        }
+    }
+}
+
+/// Byte range of the first line containing `anchor` (end includes the newline).
+fn find_anchor_line(text: &str, anchor: &str) -> Result<(usize, usize)> {
+    let mut start = 0;
+    for line in text.split_inclusive('\n') {
+        if line.contains(anchor) {
+            return Ok((start, start + line.len()));
+        }
+        start += line.len();
+    }
+    bail!("anchor {:?} not found", anchor)
+}
+
+/// Byte offset just past the line starting at `start`.
+fn line_end(text: &str, start: usize) -> usize {
+    text[start..].find('\n').map_or(text.len(), |i| start + i + 1)
+}
+
+/// Replace `text[start..end]` with `insert`. With `after_line`, a final line
+/// without a newline gets one so the insertion starts on its own line.
+fn splice(text: &str, start: usize, end: usize, insert: &str, after_line: bool) -> String {
+    let mut out = String::with_capacity(text.len() + insert.len() + 1);
+    out.push_str(&text[..start]);
+    if after_line && start > 0 && !text[..start].ends_with('\n') {
+        out.push('\n');
+    }
+    out.push_str(insert);
+    out.push_str(&text[end..]);
+    out
+}
+
+fn with_newline(text: &str) -> String {
+    if text.is_empty() || text.ends_with('\n') {
+        text.to_string()
+    } else {
+        format!("{}\n", text)
+    }
+}
+
+/// One `@@ -a,b +c,d @@` hunk of a unified diff.
+struct Hunk {
+    header: String,
+    /// 1-based start line in the original (0 for an insertion at the top).
+    old_start: usize,
+    old: Vec<String>,
+    new: Vec<String>,
+}
+
+fn parse_hunks(diff: &str) -> Result<Vec<Hunk>> {
+    let mut hunks: Vec<Hunk> = Vec::new();
+    for line in diff.lines() {
+        if let Some(rest) = line.strip_prefix("@@") {
+            let old_range = rest
+                .split_whitespace()
+                .find_map(|part| part.strip_prefix('-'))
+                .with_context(|| format!("malformed hunk header '{}'", line))?;
+            let old_start = old_range
+                .split(',')
+                .next()
+                .and_then(|n| n.parse().ok())
+                .with_context(|| format!("malformed hunk header '{}'", line))?;
+            hunks.push(Hunk { header: line.to_string(), old_start, old: Vec::new(), new: Vec::new() });
+            continue;
+        }
+        let Some(hunk) = hunks.last_mut() else {
+            // File headers (`---`/`+++`) and anything else before the first hunk.
+            continue;
+        };
+        if line.starts_with('\\') {
+            continue; // "\ No newline at end of file"
+        }
+        if let Some(text) = line.strip_prefix('-') {
+            hunk.old.push(text.to_string());
+        } else if let Some(text) = line.strip_prefix('+') {
+            hunk.new.push(text.to_string());
+        } else if line.is_empty() || line.starts_with(' ') {
+            // Context line; editors often strip the leading space from blank lines.
+            let text = line.strip_prefix(' ').unwrap_or(line);
+            hunk.old.push(text.to_string());
+            hunk.new.push(text.to_string());
+        } else {
+            bail!("unexpected line in hunk '{}': {:?}", hunk.header, line);
+        }
+    }
+    if hunks.is_empty() {
+        bail!("no @@ hunks found in diff");
+    }
+    Ok(hunks)
+}
+
+/// Apply a unified diff. Each hunk is matched at its stated line (adjusted by
+/// earlier hunks), or else at the first later position where its context and
+/// removed lines match.
+fn apply_unified_diff(existing: &str, diff: &str) -> Result<String> {
+    let lines: Vec<&str> = existing.lines().collect();
+    let mut out: Vec<String> = Vec::with_capacity(lines.len());
+    let mut cursor = 0;
+    let mut offset: isize = 0;
+
+    for hunk in parse_hunks(diff)? {
+        let expected = if hunk.old.is_empty() {
+            hunk.old_start
+        } else {
+            hunk.old_start.saturating_sub(1)
+        };
+        let expected = expected.saturating_add_signed(offset);
+        let matches_at = |pos: usize| {
+            pos >= cursor
+                && pos + hunk.old.len() <= lines.len()
+                && hunk.old.iter().zip(&lines[pos..]).all(|(a, b)| a.trim_end() == b.trim_end())
+        };
+        let pos = if matches_at(expected) {
+            expected
+        } else {
+            (cursor..=lines.len()).find(|&p| matches_at(p)).with_context(|| {
+                format!(
+                    "hunk '{}' does not match: expected {:?} at line {}",
+                    hunk.header,
+                    hunk.old.first().map(String::as_str).unwrap_or(""),
+                    expected + 1
+                )
+            })?
+        };
+        out.extend(lines[cursor..pos].iter().map(|l| l.to_string()));
+        out.extend(hunk.new.iter().cloned());
+        cursor = pos + hunk.old.len();
+        offset += hunk.new.len() as isize - hunk.old.len() as isize;
+    }
+    out.extend(lines[cursor..].iter().map(|l| l.to_string()));
+
+    let mut result = out.join("\n");
+    if !result.is_empty() && (existing.ends_with('\n') || existing.is_empty()) {
+        result.push('\n');
+    }
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PLAN: &str = "## Done\n- one\n## Next\n- two\n- three\n";
+
+    fn attrs(pairs: &[(&str, &str)]) -> BTreeMap<String, String> {
+        pairs.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect()
+    }
+
+    #[test]
+    fn from_attrs_variants() {
+        assert_eq!(PatchOp::from_attrs(&attrs(&[])).unwrap(), PatchOp::Mode);
+        assert_eq!(PatchOp::from_attrs(&attrs(&[("op", "diff")])).unwrap(), PatchOp::Diff);
+        assert_eq!(
+            PatchOp::from_attrs(&attrs(&[("op", "delete"), ("anchor", "x"), ("count", "2")])).unwrap(),
+            PatchOp::Delete { anchor: "x".into(), count: 2 }
+        );
+        assert_eq!(
+            PatchOp::from_attrs(&attrs(&[("op", "replace"), ("pattern", "x")])).unwrap(),
+            PatchOp::Replace { pattern: "x".into(), expand: false }
+        );
+        assert_eq!(
+            PatchOp::from_attrs(&attrs(&[("op", "replace"), ("pattern", "x"), ("expand", "true")])).unwrap(),
+            PatchOp::Replace { pattern: "x".into(), expand: true }
+        );
+    }
+
+    #[test]
+    fn from_attrs_errors() {
+        let err = PatchOp::from_attrs(&attrs(&[("op", "insert-after")])).unwrap_err();
+        assert!(err.to_string().contains("missing anchor"));
+        let err = PatchOp::from_attrs(&attrs(&[("op", "shuffle")])).unwrap_err();
+        assert!(err.to_string().contains("unknown op 'shuffle'"));
+        let err = PatchOp::from_attrs(&attrs(&[("op", "replace")])).unwrap_err();
+        assert!(err.to_string().contains("needs pattern"));
+        let err = PatchOp::from_attrs(&attrs(&[("op", "replace"), ("pattern", "(")])).unwrap_err();
+        assert!(err.to_string().contains("invalid pattern"));
+        let err = PatchOp::from_attrs(&attrs(&[("op", "replace"), ("pattern", "x"), ("expand", "yes")])).unwrap_err();
+        assert!(err.to_string().contains("invalid expand 'yes'"));
+    }
+
+    #[test]
+    fn insert_after_and_before() {
+        let op = PatchOp::InsertAfter { anchor: "## Next".into() };
+        assert_eq!(apply(&op, PLAN, "- zero").unwrap(), "## Done\n- one\n## Next\n- zero\n- two\n- three\n");
+
+        let op = PatchOp::InsertBefore { anchor: "## Next".into() };
+        assert_eq!(apply(&op, PLAN, "- 1.5\n").unwrap(), "## Done\n- one\n- 1.5\n## Next\n- two\n- three\n");
+    }
+
+    #[test]
+    fn insert_after_last_line_without_newline() {
+        let op = PatchOp::InsertAfter { anchor: "end".into() };
+        assert_eq!(apply(&op, "start\nend", "more\n").unwrap(), "start\nend\nmore\n");
+    }
+
+    #[test]
+    fn anchor_not_found_reports_anchor() {
+        let op = PatchOp::InsertAfter { anchor: "## Missing".into() };
+        let err = apply(&op, PLAN, "x\n").unwrap_err();
+        assert_eq!(err.to_string(), "anchor \"## Missing\" not found");
+    }
+
+    #[test]
+    fn delete_lines() {
+        let op = PatchOp::Delete { anchor: "- two".into(), count: 2 };
+        assert_eq!(apply(&op, PLAN, "").unwrap(), "## Done\n- one\n## Next\n");
+
+        let op = PatchOp::Delete { anchor: "- three".into(), count: 3 };
+        let err = apply(&op, PLAN, "").unwrap_err();
+        assert!(err.to_string().contains("cannot delete 3"));
+    }
+
+    #[test]
+    fn regex_replace() {
+        let op = PatchOp::Replace { pattern: r"- (t\w+)".into(), expand: true };
+        assert_eq!(apply(&op, PLAN, "* $1\n").unwrap(), "## Done\n- one\n## Next\n* two\n* three\n");
+
+        let op = PatchOp::Replace { pattern: "- two".into(), expand: false };
+        assert_eq!(apply(&op, PLAN, "- $5 budget\n").unwrap(), "## Done\n- one\n## Next\n- $5 budget\n- three\n");
+
+        let op = PatchOp::Replace { pattern: "nothing".into(), expand: false };
+        assert!(apply(&op, PLAN, "x").unwrap_err().to_string().contains("matched nothing"));
+    }
+
+    #[test]
+    fn unified_diff_applies() {
+        let diff = "--- a\n+++ b\n@@ -3,2 +3,3 @@\n ## Next\n-- two\n+- 2\n+- 2.5\n";
+        assert_eq!(
+            apply(&PatchOp::Diff, PLAN, diff).unwrap(),
+            "## Done\n- one\n## Next\n- 2\n- 2.5\n- three\n"
+        );
+    }
+
+    #[test]
+    fn unified_diff_finds_shifted_hunk() {
+        // Stated line is off by two; the hunk is found by its context.
+        let diff = "@@ -1,2 +1,2 @@\n ## Next\n-- three\n+- 3\n";
+        let existing = "## Done\n- one\n## Next\n- three\n";
+        assert_eq!(apply(&PatchOp::Diff, existing, diff).unwrap(), "## Done\n- one\n## Next\n- 3\n");
+    }
+
+    #[test]
+    fn unified_diff_mismatch_reports_hunk() {
+        let diff = "@@ -2,1 +2,1 @@\n-- missing\n+- found\n";
+        let err = apply(&PatchOp::Diff, PLAN, diff).unwrap_err().to_string();
+        assert!(err.contains("@@ -2,1 +2,1 @@"), "{}", err);
+        assert!(err.contains("- missing"), "{}", err);
+    }
+
+    #[test]
+    fn unified_diff_requires_hunks() {
+        let err = apply(&PatchOp::Diff, PLAN, "just text\n").unwrap_err();
+        assert!(err.to_string().contains("no @@ hunks"));
+    }
+
+    #[test]
+    fn placement_from_attrs() {
+        assert_eq!(Placement::from_attrs(&attrs(&[("op", "diff")])).unwrap(), None);
+        assert_eq!(
+            Placement::from_attrs(&attrs(&[("after", "plan/step-1")])).unwrap(),
+            Some(Placement::After("plan/step-1".into()))
+        );
+        assert_eq!(
+            Placement::from_attrs(&attrs(&[("under", "## Risks")])).unwrap(),
+            Some(Placement::Under("## Risks".into()))
+        );
+        let err = Placement::from_attrs(&attrs(&[("after", "a"), ("before", "b")])).unwrap_err();
+        assert!(err.to_string().contains("only one of"));
+        let err = Placement::from_attrs(&attrs(&[("after", "true")])).unwrap_err();
+        assert!(err.to_string().contains("after needs a value"));
+    }
+}
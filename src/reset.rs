@@ -10,9 +10,7 @@ pub fn run(file: &Path) -> Result<()> {
 
     // Clear agent conversation ID (resume) — keep session (routing key)
     let content = std::fs::read_to_string(file)?;
-    let (mut fm, body) = frontmatter::parse(&content)?;
-    fm.resume = None;
-    let updated = frontmatter::write(&fm, body)?;
+    let updated = frontmatter::set_field(&content, "resume", &[], None)?;
     std::fs::write(file, updated)?;
 
     // Delete snapshot
@@ -0,0 +1,252 @@
+//! Frontmatter schema and key validation.
+//!
+//! `docs/reference/frontmatter.schema.json` describes every key agent-doc
+//! reads. [`validate`] checks a document's frontmatter against it and reports:
+//!
+//! - deprecated keys (`session`, `mode`, `response_mode`, `agent_doc_mode`);
+//! - unknown keys in the reserved `agent_doc_` namespace and in objects that
+//!   allow no other keys (`agent_doc_stream`);
+//! - other unknown keys that look like a misspelled known key (`agnet`);
+//! - values outside a key's `enum`.
+//!
+//! Any other key is the user's (`title`, `tags`, another tool's metadata) and
+//! is left alone.
+
+use serde_json::Value as Json;
+use serde_yaml::Value as Yaml;
+use std::path::Path;
+
+use crate::frontmatter;
+
+/// The published frontmatter JSON Schema.
+pub const SCHEMA: &str = include_str!("../docs/reference/frontmatter.schema.json");
+
+const RESERVED_PREFIX: &str = "agent_doc_";
+
+/// Deprecated frontmatter keys and what replaces them.
+pub const DEPRECATED_KEYS: &[(&str, &str)] = &[
+    ("session", "agent_doc_session"),
+    ("mode", "agent_doc_format and agent_doc_write"),
+    ("response_mode", "agent_doc_format and agent_doc_write"),
+    ("agent_doc_mode", "agent_doc_format and agent_doc_write"),
+];
+
+/// A frontmatter key that doesn't match the schema.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Problem {
+    /// Dotted key path, e.g. `agent_doc_stream.intervall`.
+    pub key: String,
+    /// Byte offset of the key's line in the document.
+    pub offset: usize,
+    /// `deprecated-key`, `unknown-key` or `invalid-value`.
+    pub code: &'static str,
+    pub message: String,
+}
+
+/// Check a document's frontmatter against the schema. Frontmatter that
+/// doesn't parse yields no problems; [`frontmatter::parse`] reports those.
+pub fn validate(content: &str) -> Vec<Problem> {
+    let Ok(Some((yaml, _))) = frontmatter::block(content) else {
+        return Vec::new();
+    };
+    let Ok(Yaml::Mapping(map)) = serde_yaml::from_str::<Yaml>(&content[yaml.clone()]) else {
+        return Vec::new();
+    };
+    let schema: Json = serde_json::from_str(SCHEMA).expect("bundled frontmatter schema is valid JSON");
+    let mut problems = Vec::new();
+    check_object(&map, &schema, &[], &mut problems);
+    for problem in &mut problems {
+        let path: Vec<&str> = problem.key.split('.').collect();
+        problem.offset = key_offset(content, yaml.clone(), &path);
+    }
+    problems
+}
+
+/// Print validation problems as warnings.
+pub fn warn(file: &Path, content: &str) {
+    for problem in validate(content) {
+        eprintln!("[frontmatter] warning: {}: {}", file.display(), problem.message);
+    }
+}
+
+fn check_object(map: &serde_yaml::Mapping, schema: &Json, path: &[&str], problems: &mut Vec<Problem>) {
+    let properties = schema.get("properties").and_then(Json::as_object);
+    let closed = schema.get("additionalProperties") == Some(&Json::Bool(false));
+    for (key, value) in map {
+        let Some(key) = key.as_str() else {
+            continue;
+        };
+        let full_key = path.iter().copied().chain([key]).collect::<Vec<_>>().join(".");
+        let mut report = |code: &'static str, message: String| {
+            problems.push(Problem { key: full_key.clone(), offset: 0, code, message });
+        };
+
+        let Some(property) = properties.and_then(|p| p.get(key)) else {
+            let suggestion = properties.and_then(|p| suggest(key, p.keys().map(String::as_str)));
+            let reserved = path.is_empty() && key.starts_with(RESERVED_PREFIX);
+            let hint = suggestion.map_or(String::new(), |s| format!(" (did you mean '{}'?)", s));
+            if closed || reserved || suggestion.is_some() {
+                report("unknown-key", format!("unknown frontmatter key '{}'{}", full_key, hint));
+            }
+            continue;
+        };
+
+        if property.get("deprecated") == Some(&Json::Bool(true)) {
+            let replacement = DEPRECATED_KEYS
+                .iter()
+                .find(|(k, _)| *k == key)
+                .map_or("its replacement", |(_, r)| *r);
+            report(
+                "deprecated-key",
+                format!("frontmatter key '{}' is deprecated, use {} (see `agent-doc migrate`)", full_key, replacement),
+            );
+        }
+        if let Some(allowed) = property.get("enum").and_then(Json::as_array)
+            && let Some(value) = value.as_str()
+            && !allowed.iter().any(|a| a.as_str() == Some(value))
+        {
+            let expected: Vec<&str> = allowed.iter().filter_map(Json::as_str).collect();
+            report(
+                "invalid-value",
+                format!("'{}' is not a valid value for '{}' (expected {})", value, full_key, expected.join(", ")),
+            );
+        }
+        if let Yaml::Mapping(inner) = value
+            && property.get("properties").is_some()
+        {
+            let inner_path: Vec<&str> = path.iter().copied().chain([key]).collect();
+            check_object(inner, property, &inner_path, problems);
+        }
+    }
+}
+
+/// The known key closest to `key`, if it's close enough to be a typo.
+/// Deprecated keys suggest their replacement.
+pub(crate) fn suggest<'a>(key: &str, known: impl Iterator<Item = &'a str>) -> Option<&'a str> {
+    let max = if key.chars().count() > 5 { 2 } else { 1 };
+    let (best, distance) = known.map(|k| (k, edit_distance(key, k))).min_by_key(|(_, d)| *d)?;
+    if distance > max {
+        return None;
+    }
+    let replacement = DEPRECATED_KEYS.iter().find(|(k, _)| *k == best).map(|(_, r)| *r);
+    Some(replacement.map_or(best, |r| r.split(' ').next().unwrap_or(r)))
+}
+
+/// Edit distance counting an adjacent transposition as one edit.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    let mut rows = vec![vec![0; b.len() + 1]; a.len() + 1];
+    rows[0] = (0..=b.len()).collect();
+    for (i, row) in rows.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            let mut d = (rows[i - 1][j] + 1).min(rows[i][j - 1] + 1).min(rows[i - 1][j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                d = d.min(rows[i - 2][j - 2] + 1);
+            }
+            rows[i][j] = d;
+        }
+    }
+    rows[a.len()][b.len()]
+}
+
+/// Offset of the line holding the key at `path` inside the YAML block,
+/// falling back to the deepest ancestor found (or the block start).
+fn key_offset(content: &str, yaml: std::ops::Range<usize>, path: &[&str]) -> usize {
+    let mut found = yaml.start;
+    let mut depth = 0;
+    let mut offset = yaml.start;
+    for line in content[yaml].split_inclusive('\n') {
+        let indented = line.starts_with([' ', '\t']);
+        if depth > 0 && !indented && !line.trim().is_empty() {
+            break;
+        }
+        let Some(segment) = path.get(depth) else {
+            break;
+        };
+        if indented == (depth > 0)
+            && let Some(rest) = line.trim_start().strip_prefix(segment)
+            && rest.trim_start().starts_with(':')
+        {
+            found = offset;
+            depth += 1;
+        }
+        offset += line.len();
+    }
+    found
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn codes(content: &str) -> Vec<(String, &'static str)> {
+        validate(content).into_iter().map(|p| (p.key, p.code)).collect()
+    }
+
+    #[test]
+    fn schema_covers_frontmatter_fields() {
+        let schema: Json = serde_json::from_str(SCHEMA).unwrap();
+        let properties = schema["properties"].as_object().unwrap();
+        let fm = frontmatter::Frontmatter {
+            session: Some("s".into()),
+            resume: Some("r".into()),
+            agent: Some("a".into()),
+            model: Some("m".into()),
+            branch: Some("b".into()),
+            tmux_session: Some("t".into()),
+            mode: Some("append".into()),
+            format: Some(frontmatter::AgentDocFormat::Append),
+            write_mode: Some(frontmatter::AgentDocWrite::Crdt),
+            stream_config: Some(frontmatter::StreamConfig::default()),
+            crdt_layout: Some(frontmatter::CrdtLayout::Text),
+            conflicts: Some("c".into()),
+            creatable: Some(vec![]),
+        };
+        let Yaml::Mapping(fields) = serde_yaml::to_value(&fm).unwrap() else {
+            panic!("frontmatter serializes to a mapping");
+        };
+        for key in fields.keys() {
+            assert!(properties.contains_key(key.as_str().unwrap()), "schema lacks {:?}", key);
+        }
+        for (key, _) in DEPRECATED_KEYS {
+            assert_eq!(properties[*key]["deprecated"], Json::Bool(true), "{} not marked deprecated", key);
+        }
+    }
+
+    #[test]
+    fn reports_deprecated_unknown_and_misspelled_keys() {
+        let doc = "---\ntitle: Notes\ntags: [a]\nsession: abc\nagnet: claude\nmodle: opus\nagent_doc_fromat: template\n\
+                   agent_doc_stream:\n  intervall: 100\n  target: log\nmode: bogus\n---\nBody\n";
+        assert_eq!(
+            codes(doc),
+            vec![
+                ("session".to_string(), "deprecated-key"),
+                ("agnet".to_string(), "unknown-key"),
+                ("modle".to_string(), "unknown-key"),
+                ("agent_doc_fromat".to_string(), "unknown-key"),
+                ("agent_doc_stream.intervall".to_string(), "unknown-key"),
+                ("mode".to_string(), "deprecated-key"),
+                ("mode".to_string(), "invalid-value"),
+            ]
+        );
+        let problems = validate(doc);
+        assert!(problems[1].message.contains("did you mean 'agent'?"));
+        assert!(problems[3].message.contains("did you mean 'agent_doc_format'?"));
+        assert_eq!(&doc[problems[4].offset..problems[4].offset + 13], "  intervall: ");
+        assert_eq!(&doc[problems[5].offset..problems[5].offset + 5], "mode:");
+    }
+
+    #[test]
+    fn user_keys_and_clean_frontmatter_pass() {
+        let doc = "---\ntitle: Notes\nnote: keep\ndate: 2024-01-01\nagent_doc_session: abc\nagent_doc_format: append\n---\n";
+        assert!(validate(doc).is_empty());
+        assert!(validate("# No frontmatter\n").is_empty());
+        assert_eq!(suggest("sesion", ["session", "agent"].into_iter()), Some("agent_doc_session"));
+        assert_eq!(edit_distance("agnet", "agent"), 1);
+    }
+}
@@ -4,13 +4,17 @@ use sha2::{Digest, Sha256};
 use std::fs::{File, OpenOptions};
 use std::path::{Path, PathBuf};
 
+use crate::crdt::{self, CrdtDoc};
+
 const SNAP_DIR: &str = ".agent-doc/snapshots";
 const LOCK_DIR: &str = ".agent-doc/locks";
 const PENDING_DIR: &str = ".agent-doc/pending";
 const CRDT_DIR: &str = ".agent-doc/crdt";
+const CONFLICTS_DIR: &str = ".agent-doc/conflicts";
 
 /// Compute the SHA256 hex hash of a document's canonical path.
-/// Used for both snapshot filenames and lock filenames.
+/// Used as the sidecar key for documents without a session ID, and as the
+/// legacy key that `doc_key` migrates from.
 pub fn doc_hash(doc: &Path) -> Result<String> {
     let canonical = doc.canonicalize()?;
     let mut hasher = Sha256::new();
@@ -18,26 +22,146 @@ pub fn doc_hash(doc: &Path) -> Result<String> {
     Ok(hex::encode(hasher.finalize()))
 }
 
+/// Compute the sidecar key for a session ID.
+pub fn session_key(session_id: &str) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(b"session:");
+    hasher.update(session_id.as_bytes());
+    hex::encode(hasher.finalize())
+}
+
+/// Compute the key used for a document's snapshot, CRDT state, pending
+/// response and lock filenames.
+///
+/// Documents with an `agent_doc_session` are keyed by the session ID so their
+/// state survives renames and moves. Documents without a session fall back to
+/// `doc_hash` (canonical path).
+pub fn doc_key(doc: &Path) -> Result<String> {
+    let canonical = doc.canonicalize()?;
+    match read_session_id(&canonical) {
+        Some(id) => Ok(session_key(&id)),
+        None => doc_hash(&canonical),
+    }
+}
+
+/// Read the session ID from a document's frontmatter, if any.
+fn read_session_id(doc: &Path) -> Option<String> {
+    let content = std::fs::read_to_string(doc).ok()?;
+    let (fm, _) = crate::frontmatter::parse(&content).ok()?;
+    fm.session.filter(|s| !s.trim().is_empty())
+}
+
+/// Project root for a document, falling back to its parent directory.
+fn root_or_parent(doc: &Path) -> Result<PathBuf> {
+    let canonical = doc.canonicalize()?;
+    Ok(find_project_root(&canonical)
+        .unwrap_or_else(|| canonical.parent().unwrap_or(Path::new(".")).to_path_buf()))
+}
+
+/// Compute `<dir>/<doc_key>.<ext>`.
+///
+/// When the document has a session ID but only a legacy path-hashed file
+/// exists, that file is returned so its state is still found. Nothing is
+/// renamed here; writers call [`migrate`] under the doc lock.
+fn keyed_path(doc: &Path, dir: &Path, ext: &str) -> Result<PathBuf> {
+    let key = doc_key(doc)?;
+    let path = dir.join(format!("{}.{}", key, ext));
+    let legacy = dir.join(format!("{}.{}", doc_hash(doc)?, ext));
+    if !path.exists() && legacy.exists() {
+        return Ok(legacy);
+    }
+    Ok(path)
+}
+
+/// Sidecar directories and extensions that share a document's key.
+const KEYED_FILES: &[(&str, &str)] = &[
+    (SNAP_DIR, "md"),
+    (CRDT_DIR, "yrs"),
+    (CRDT_DIR, "yrs.log"),
+    (PENDING_DIR, "md"),
+    (CONFLICTS_DIR, "json"),
+    (LOCK_DIR, "lock"),
+];
+
+/// Move a session document's legacy path-hashed state to its session key.
+///
+/// Called by writers once they hold the doc lock, so read-only commands
+/// never rename anything.
+pub fn migrate(doc: &Path) -> Result<()> {
+    let key = doc_key(doc)?;
+    let legacy = doc_hash(doc)?;
+    if legacy == key {
+        return Ok(());
+    }
+    let root = root_or_parent(doc)?;
+    for (dir, ext) in KEYED_FILES {
+        let dir = root.join(dir);
+        if dir.is_dir() {
+            migrate_legacy(&dir, &legacy, &key, ext)?;
+        }
+    }
+    Ok(())
+}
+
+/// Rename `<legacy>.<ext>` (and its `<ext>.lock` sibling) to the session key.
+///
+/// Runs under an exclusive lock on `<dir>/.migrate.lock` so concurrent
+/// processes don't race on the rename, and never replaces an existing
+/// session-keyed file. A lock file held by another process keeps working
+/// after the rename: the lock belongs to the file, not the path.
+fn migrate_legacy(dir: &Path, legacy: &str, key: &str, ext: &str) -> Result<()> {
+    let mut exts = vec![ext.to_string()];
+    if !ext.ends_with("lock") {
+        exts.push(format!("{}.lock", ext));
+    }
+    let pairs: Vec<(PathBuf, PathBuf)> = exts
+        .iter()
+        .map(|ext| (dir.join(format!("{}.{}", legacy, ext)), dir.join(format!("{}.{}", key, ext))))
+        .collect();
+    if !pairs.iter().any(|(from, to)| from.exists() && !to.exists()) {
+        return Ok(());
+    }
+
+    let lock_path = dir.join(".migrate.lock");
+    let lock = OpenOptions::new()
+        .create(true)
+        .write(true)
+        .truncate(false)
+        .open(&lock_path)
+        .with_context(|| format!("failed to open migration lock {}", lock_path.display()))?;
+    lock.lock_exclusive()
+        .with_context(|| format!("failed to acquire migration lock on {}", lock_path.display()))?;
+    for (from, to) in &pairs {
+        // Re-check under the lock: another process may have migrated already.
+        if !from.exists() || to.exists() {
+            continue;
+        }
+        std::fs::rename(from, to)
+            .with_context(|| format!("failed to migrate {} to {}", from.display(), to.display()))?;
+        eprintln!("[snapshot] Migrated {} to session key {}", from.display(), to.display());
+    }
+    let _ = lock.unlock();
+    Ok(())
+}
+
 /// Compute the advisory lock file path for a given document.
 /// Walks up from the document to find the `.agent-doc/` project root.
-/// Returns `<project_root>/.agent-doc/locks/<sha256_hash>.lock`.
+/// Returns `<project_root>/.agent-doc/locks/<doc_key>.lock`.
 /// Falls back to the document's parent directory if no project root found.
 pub fn lock_path_for(doc: &Path) -> Result<PathBuf> {
-    let hash = doc_hash(doc)?;
-    let canonical = doc.canonicalize()?;
-    let project_root = find_project_root(&canonical)
-        .unwrap_or_else(|| canonical.parent().unwrap_or(Path::new(".")).to_path_buf());
-    Ok(project_root.join(LOCK_DIR).join(format!("{}.lock", hash)))
+    keyed_path(doc, &root_or_parent(doc)?.join(LOCK_DIR), "lock")
 }
 
 /// Compute the pending response file path for a given document.
-/// Returns `<project_root>/.agent-doc/pending/<sha256_hash>.md`.
+/// Returns `<project_root>/.agent-doc/pending/<doc_key>.md`.
 pub fn pending_path_for(doc: &Path) -> Result<PathBuf> {
-    let hash = doc_hash(doc)?;
-    let canonical = doc.canonicalize()?;
-    let project_root = find_project_root(&canonical)
-        .unwrap_or_else(|| canonical.parent().unwrap_or(Path::new(".")).to_path_buf());
-    Ok(project_root.join(PENDING_DIR).join(format!("{}.md", hash)))
+    keyed_path(doc, &root_or_parent(doc)?.join(PENDING_DIR), "md")
+}
+
+/// Compute the conflict record file path for a given document.
+/// Returns `<project_root>/.agent-doc/conflicts/<doc_key>.json`.
+pub fn conflicts_path_for(doc: &Path) -> Result<PathBuf> {
+    keyed_path(doc, &root_or_parent(doc)?.join(CONFLICTS_DIR), "json")
 }
 
 /// Walk up from a path to find the directory containing `.agent-doc/`.
@@ -96,19 +220,17 @@ impl Drop for SnapshotLock {
 }
 
 /// Compute the snapshot file path for a given document.
-/// Returns an absolute path: `<project_root>/.agent-doc/snapshots/<hash>.md`.
+/// Returns an absolute path: `<project_root>/.agent-doc/snapshots/<doc_key>.md`.
 /// Falls back to relative path if no project root found (e.g., tests without `.agent-doc/`).
 pub fn path_for(doc: &Path) -> Result<PathBuf> {
-    let hash = doc_hash(doc)?;
-    let filename = format!("{}.md", hash);
     // Try to find project root for absolute path (consistent with lock_path_for/pending_path_for)
     if let Ok(canonical) = doc.canonicalize()
         && let Some(root) = find_project_root(&canonical)
     {
-        return Ok(root.join(SNAP_DIR).join(filename));
+        return keyed_path(doc, &root.join(SNAP_DIR), "md");
     }
     // Fallback: relative path (legacy behavior for tests without .agent-doc/)
-    Ok(PathBuf::from(SNAP_DIR).join(filename))
+    Ok(PathBuf::from(SNAP_DIR).join(format!("{}.md", doc_key(doc)?)))
 }
 
 /// Load the snapshot content under an exclusive lock.
@@ -200,57 +322,282 @@ fn save_unlocked(doc: &Path, content: &str) -> Result<()> {
 // CRDT state persistence (for stream mode)
 // ---------------------------------------------------------------------------
 
-/// Compute the CRDT state file path for a given document.
-/// Returns `<project_root>/.agent-doc/crdt/<hash>.yrs`.
+/// Compute the CRDT checkpoint file path for a given document.
+/// Returns `<project_root>/.agent-doc/crdt/<doc_key>.yrs`.
 /// Falls back to doc's parent directory if no project root found.
 pub fn crdt_path_for(doc: &Path) -> Result<PathBuf> {
-    let hash = doc_hash(doc)?;
-    let filename = format!("{}.yrs", hash);
-    let canonical = doc.canonicalize()?;
-    let project_root = find_project_root(&canonical)
-        .unwrap_or_else(|| canonical.parent().unwrap_or(Path::new(".")).to_path_buf());
-    Ok(project_root.join(CRDT_DIR).join(filename))
+    keyed_path(doc, &root_or_parent(doc)?.join(CRDT_DIR), "yrs")
+}
+
+/// Compute the CRDT update log path for a given document.
+/// Returns `<project_root>/.agent-doc/crdt/<doc_key>.yrs.log`.
+pub fn crdt_log_path_for(doc: &Path) -> Result<PathBuf> {
+    keyed_path(doc, &root_or_parent(doc)?.join(CRDT_DIR), "yrs.log")
+}
+
+/// One entry of the CRDT update log.
+#[derive(Debug, Clone)]
+pub struct CrdtLogEntry {
+    /// When the update was appended (milliseconds since the Unix epoch).
+    pub timestamp_ms: u64,
+    /// Encoded v1 update.
+    pub update: Vec<u8>,
 }
 
 /// Load CRDT state bytes for a document (if any).
+///
+/// Returns the checkpoint with every logged update applied, encoded as a
+/// single full state.
 pub fn load_crdt(doc: &Path) -> Result<Option<Vec<u8>>> {
-    let path = crdt_path_for(doc)?;
-    if !path.exists() {
+    let checkpoint = crdt_path_for(doc)?;
+    let log = crdt_log_path_for(doc)?;
+    if !checkpoint.exists() && !log.exists() {
+        return Ok(None);
+    }
+    let _lock = acquire_crdt_lock(doc)?;
+    if !log.exists() {
+        let bytes = std::fs::read(&checkpoint)
+            .with_context(|| format!("failed to read CRDT state {}", checkpoint.display()))?;
+        return Ok(Some(bytes));
+    }
+    Ok(load_crdt_unlocked(doc)?.map(|d| d.encode_state()))
+}
+
+/// Record `text` as the document's CRDT content.
+///
+/// Loads the stored state and edits it in place with [`CrdtDoc::set_text`],
+/// so only the resulting ops are appended to the update log and replicas
+/// descended from the stored state keep merging cleanly. Without stored
+/// state, or when the layout changed, a fresh checkpoint is written.
+pub fn save_crdt_text(doc: &Path, text: &str) -> Result<()> {
+    let _lock = acquire_crdt_lock(doc)?;
+    let fresh = || write_crdt_checkpoint(doc, &CrdtDoc::for_document(text).encode_state());
+
+    let components = crate::frontmatter::crdt_layout(text) == crate::frontmatter::CrdtLayout::Components;
+    let (current, entries) = match load_crdt_and_log_len(doc) {
+        Ok(Some(loaded)) if loaded.0.is_component_layout() == components => loaded,
+        Ok(_) => return fresh(),
+        Err(e) => {
+            eprintln!("[snapshot] Stored CRDT state unreadable ({:#}), writing fresh checkpoint", e);
+            return fresh();
+        }
+    };
+    if current.to_text() == text {
+        return Ok(());
+    }
+    let sv = current.state_vector();
+    current.set_text(text);
+    if current.to_text() != text {
+        return fresh();
+    }
+    append_crdt_update(doc, &current, &current.encode_diff(&sv), entries)
+}
+
+/// Persist an update received from a replica of the stored state.
+///
+/// Only `update` is appended to the log, so live editing costs O(change) per
+/// message. Updates are idempotent, so one the stored state already contains
+/// is harmless.
+pub fn save_crdt_update(doc: &Path, update: &[u8]) -> Result<()> {
+    let _lock = acquire_crdt_lock(doc)?;
+    let entries = read_crdt_log(&crdt_log_path_for(doc)?)?.len();
+    append_crdt_log(doc, update)?;
+    if entries + 1 >= CRDT_LOG_COMPACT_ENTRIES
+        && let Some(current) = load_crdt_unlocked(doc)?
+    {
+        write_crdt_checkpoint(doc, &crdt::compact(&current.encode_state())?)?;
+    }
+    Ok(())
+}
+
+/// Append `update` (already applied to `current`) to the log, compacting
+/// once the log reaches `CRDT_LOG_COMPACT_ENTRIES`.
+fn append_crdt_update(doc: &Path, current: &CrdtDoc, update: &[u8], entries: usize) -> Result<()> {
+    append_crdt_log(doc, update)?;
+    if entries + 1 >= CRDT_LOG_COMPACT_ENTRIES {
+        write_crdt_checkpoint(doc, &crdt::compact(&current.encode_state())?)?;
+    }
+    Ok(())
+}
+
+/// Fold the update log into a fresh checkpoint.
+///
+/// Returns `(before, after)` sizes in bytes (checkpoint + log), or `None`
+/// if the document has no CRDT state.
+pub fn compact_crdt(doc: &Path) -> Result<Option<(u64, u64)>> {
+    let _lock = acquire_crdt_lock(doc)?;
+    let Some(current) = load_crdt_unlocked(doc)? else {
+        return Ok(None);
+    };
+    let before = crdt_storage_size(doc)?;
+    write_crdt_checkpoint(doc, &crdt::compact(&current.encode_state())?)?;
+    Ok(Some((before, crdt_storage_size(doc)?)))
+}
+
+/// Read the entries of the CRDT update log, oldest first.
+pub fn crdt_log(doc: &Path) -> Result<Vec<CrdtLogEntry>> {
+    let _lock = acquire_crdt_lock(doc)?;
+    read_crdt_log(&crdt_log_path_for(doc)?)
+}
+
+/// Reconstruct the document text after the first `n` logged updates.
+///
+/// `n = 0` yields the checkpoint text. For debugging how a document evolved
+/// since the last compaction (`agent-doc crdt-log --at N`).
+pub fn crdt_text_at(doc: &Path, n: usize) -> Result<Option<String>> {
+    let _lock = acquire_crdt_lock(doc)?;
+    let checkpoint = crdt_path_for(doc)?;
+    let log = read_crdt_log(&crdt_log_path_for(doc)?)?;
+    if !checkpoint.exists() && log.is_empty() {
         return Ok(None);
     }
+    if n > log.len() {
+        anyhow::bail!("update log has only {} entries", log.len());
+    }
+    let state = load_crdt_checkpoint(&checkpoint)?;
+    for entry in &log[..n] {
+        state.apply_update(&entry.update)?;
+    }
+    Ok(Some(state.to_text()))
+}
+
+/// Delete CRDT state (checkpoint and update log) for a document.
+pub fn delete_crdt(doc: &Path) -> Result<()> {
     let _lock = acquire_crdt_lock(doc)?;
-    let bytes = std::fs::read(&path)
+    for path in [crdt_path_for(doc)?, crdt_log_path_for(doc)?] {
+        if path.exists() {
+            std::fs::remove_file(&path)?;
+        }
+    }
+    Ok(())
+}
+
+/// Number of logged updates after which the log is compacted into a checkpoint.
+const CRDT_LOG_COMPACT_ENTRIES: usize = 64;
+
+/// Size of the per-entry header in the update log: u64 timestamp + u32 length.
+const CRDT_LOG_HEADER: usize = 12;
+
+/// Load checkpoint + log without taking the CRDT lock.
+fn load_crdt_unlocked(doc: &Path) -> Result<Option<CrdtDoc>> {
+    Ok(load_crdt_and_log_len(doc)?.map(|(state, _)| state))
+}
+
+/// Load checkpoint + log without taking the CRDT lock, along with the number
+/// of log entries applied.
+fn load_crdt_and_log_len(doc: &Path) -> Result<Option<(CrdtDoc, usize)>> {
+    let checkpoint = crdt_path_for(doc)?;
+    let log_path = crdt_log_path_for(doc)?;
+    if !checkpoint.exists() && !log_path.exists() {
+        return Ok(None);
+    }
+    let state = load_crdt_checkpoint(&checkpoint)?;
+    let log = read_crdt_log(&log_path)?;
+    for entry in &log {
+        state.apply_update(&entry.update)?;
+    }
+    Ok(Some((state, log.len())))
+}
+
+/// Decode the checkpoint file, or an empty document if there is none.
+fn load_crdt_checkpoint(path: &Path) -> Result<CrdtDoc> {
+    if !path.exists() {
+        return Ok(CrdtDoc::from_text(""));
+    }
+    let bytes = std::fs::read(path)
         .with_context(|| format!("failed to read CRDT state {}", path.display()))?;
-    Ok(Some(bytes))
+    CrdtDoc::decode_state(&bytes)
+        .with_context(|| format!("corrupt CRDT checkpoint {}", path.display()))
 }
 
-/// Save CRDT state bytes for a document.
-pub fn save_crdt(doc: &Path, state: &[u8]) -> Result<()> {
-    let path = crdt_path_for(doc)?;
+/// Parse the update log. A truncated trailing entry (interrupted append) is ignored.
+fn read_crdt_log(path: &Path) -> Result<Vec<CrdtLogEntry>> {
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+    let bytes = std::fs::read(path)
+        .with_context(|| format!("failed to read CRDT log {}", path.display()))?;
+    let mut entries = Vec::new();
+    let mut pos = 0;
+    while pos + CRDT_LOG_HEADER <= bytes.len() {
+        let timestamp_ms = u64::from_le_bytes(bytes[pos..pos + 8].try_into().unwrap());
+        let len = u32::from_le_bytes(bytes[pos + 8..pos + 12].try_into().unwrap()) as usize;
+        let start = pos + CRDT_LOG_HEADER;
+        if start + len > bytes.len() {
+            break;
+        }
+        entries.push(CrdtLogEntry {
+            timestamp_ms,
+            update: bytes[start..start + len].to_vec(),
+        });
+        pos = start + len;
+    }
+    if pos < bytes.len() {
+        eprintln!(
+            "[snapshot] Ignoring {} trailing bytes of truncated CRDT log {}",
+            bytes.len() - pos,
+            path.display()
+        );
+    }
+    Ok(entries)
+}
+
+/// Append an update to the log.
+fn append_crdt_log(doc: &Path, update: &[u8]) -> Result<()> {
+    use std::io::Write;
+
+    let path = crdt_log_path_for(doc)?;
     if let Some(parent) = path.parent() {
         std::fs::create_dir_all(parent)?;
     }
-    let _lock = acquire_crdt_lock(doc)?;
+    let timestamp_ms = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0);
+    let mut record = Vec::with_capacity(CRDT_LOG_HEADER + update.len());
+    record.extend_from_slice(&timestamp_ms.to_le_bytes());
+    record.extend_from_slice(&(update.len() as u32).to_le_bytes());
+    record.extend_from_slice(update);
+
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .with_context(|| format!("failed to open CRDT log {}", path.display()))?;
+    file.write_all(&record)
+        .with_context(|| format!("failed to append to CRDT log {}", path.display()))?;
+    file.sync_data()?;
+    Ok(())
+}
+
+/// Atomically replace the checkpoint with `state` and truncate the update log.
+fn write_crdt_checkpoint(doc: &Path, state: &[u8]) -> Result<()> {
+    let path = crdt_path_for(doc)?;
     let parent = path.parent().unwrap_or(Path::new("."));
+    std::fs::create_dir_all(parent)?;
     let mut tmp = tempfile::NamedTempFile::new_in(parent)
         .with_context(|| format!("failed to create temp file in {}", parent.display()))?;
     std::io::Write::write_all(&mut tmp, state)
         .with_context(|| "failed to write CRDT state temp file")?;
     tmp.persist(&path)
         .with_context(|| format!("failed to rename temp file to {}", path.display()))?;
+    let log = crdt_log_path_for(doc)?;
+    if log.exists() {
+        std::fs::remove_file(&log)
+            .with_context(|| format!("failed to remove CRDT log {}", log.display()))?;
+    }
     Ok(())
 }
 
-/// Delete CRDT state for a document.
-pub fn delete_crdt(doc: &Path) -> Result<()> {
-    let path = crdt_path_for(doc)?;
-    if path.exists() {
-        let _lock = acquire_crdt_lock(doc)?;
-        if path.exists() {
-            std::fs::remove_file(&path)?;
+/// Combined on-disk size of the checkpoint and update log.
+fn crdt_storage_size(doc: &Path) -> Result<u64> {
+    let mut total = 0;
+    for path in [crdt_path_for(doc)?, crdt_log_path_for(doc)?] {
+        if let Ok(meta) = std::fs::metadata(&path) {
+            total += meta.len();
         }
     }
-    Ok(())
+    Ok(total)
 }
 
 /// Acquire an advisory lock for CRDT state operations.
@@ -513,7 +860,7 @@ mod tests {
     fn crdt_save_and_load_roundtrip() {
         let (_dir, doc) = setup();
         let state = vec![1u8, 2, 3, 4, 5];
-        save_crdt(&doc, &state).unwrap();
+        write_crdt_checkpoint(&doc, &state).unwrap();
         let loaded = load_crdt(&doc).unwrap();
         assert_eq!(loaded, Some(state));
     }
@@ -528,7 +875,7 @@ mod tests {
     #[test]
     fn crdt_delete_removes_file() {
         let (_dir, doc) = setup();
-        save_crdt(&doc, &[1, 2, 3]).unwrap();
+        save_crdt_text(&doc, "text\n").unwrap();
         assert!(load_crdt(&doc).unwrap().is_some());
         delete_crdt(&doc).unwrap();
         assert!(load_crdt(&doc).unwrap().is_none());
@@ -540,6 +887,117 @@ mod tests {
         delete_crdt(&doc).unwrap();
     }
 
+    #[test]
+    fn crdt_save_appends_incremental_updates() {
+        let (_dir, doc) = setup();
+        let v1 = "# Doc\n\n".to_string() + &"Paragraph.\n".repeat(100);
+        let v2 = format!("{}Streamed line.\n", v1);
+        save_crdt_text(&doc, &v1).unwrap();
+        let checkpoint_len = fs::metadata(crdt_path_for(&doc).unwrap()).unwrap().len();
+
+        save_crdt_text(&doc, &v2).unwrap();
+        let log = crdt_log(&doc).unwrap();
+        assert_eq!(log.len(), 1);
+        assert!((log[0].update.len() as u64) < checkpoint_len);
+        assert_eq!(
+            fs::metadata(crdt_path_for(&doc).unwrap()).unwrap().len(),
+            checkpoint_len,
+            "checkpoint must not be rewritten"
+        );
+
+        let loaded = CrdtDoc::decode_state(&load_crdt(&doc).unwrap().unwrap()).unwrap();
+        assert_eq!(loaded.to_text(), v2);
+    }
+
+    #[test]
+    fn crdt_save_skips_unchanged_text() {
+        let (_dir, doc) = setup();
+        save_crdt_text(&doc, "same").unwrap();
+        save_crdt_text(&doc, "same").unwrap();
+        assert!(crdt_log(&doc).unwrap().is_empty());
+    }
+
+    #[test]
+    fn crdt_save_update_keeps_replica_operations() {
+        let (_dir, doc) = setup();
+        save_crdt_text(&doc, "# Doc\n").unwrap();
+        let replica = CrdtDoc::decode_state(&load_crdt(&doc).unwrap().unwrap()).unwrap();
+        let sv = replica.state_vector();
+        replica.set_text("# Doc\n\nStreamed line.\n");
+        save_crdt_update(&doc, &replica.encode_diff(&sv)).unwrap();
+        assert_eq!(crdt_log(&doc).unwrap().len(), 1);
+
+        // Merging the reloaded state back into the replica must not duplicate text.
+        let reloaded = load_crdt(&doc).unwrap().unwrap();
+        replica.apply_update(&reloaded).unwrap();
+        assert_eq!(replica.to_text(), "# Doc\n\nStreamed line.\n");
+    }
+
+    #[test]
+    fn crdt_layout_switch_replaces_checkpoint() {
+        let (_dir, doc) = setup();
+        save_crdt_text(&doc, "a\n").unwrap();
+        save_crdt_text(&doc, "a\nb\n").unwrap();
+        let components = "---\nagent_doc_crdt_layout: components\n---\n<!-- agent:a -->\nb\n<!-- /agent:a -->\n";
+        save_crdt_text(&doc, components).unwrap();
+        assert!(!crdt_log_path_for(&doc).unwrap().exists());
+        let loaded = CrdtDoc::decode_state(&load_crdt(&doc).unwrap().unwrap()).unwrap();
+        assert!(loaded.is_component_layout());
+        assert_eq!(loaded.to_text(), components);
+    }
+
+    #[test]
+    fn crdt_text_at_reconstructs_past_states() {
+        let (_dir, doc) = setup();
+        for text in ["one\n", "one\ntwo\n", "one\ntwo\nthree\n"] {
+            save_crdt_text(&doc, text).unwrap();
+        }
+        assert_eq!(crdt_text_at(&doc, 0).unwrap().as_deref(), Some("one\n"));
+        assert_eq!(crdt_text_at(&doc, 1).unwrap().as_deref(), Some("one\ntwo\n"));
+        assert_eq!(crdt_text_at(&doc, 2).unwrap().as_deref(), Some("one\ntwo\nthree\n"));
+        assert!(crdt_text_at(&doc, 3).is_err());
+    }
+
+    #[test]
+    fn crdt_log_compacts_into_checkpoint() {
+        let (_dir, doc) = setup();
+        let mut text = String::new();
+        for i in 0..=CRDT_LOG_COMPACT_ENTRIES {
+            text.push_str(&format!("line {}\n", i));
+            save_crdt_text(&doc, &text).unwrap();
+        }
+        // First save wrote the checkpoint; the 64th append triggered compaction.
+        assert!(crdt_log(&doc).unwrap().is_empty());
+        assert!(!crdt_log_path_for(&doc).unwrap().exists());
+        let loaded = CrdtDoc::decode_state(&load_crdt(&doc).unwrap().unwrap()).unwrap();
+        assert_eq!(loaded.to_text(), text);
+    }
+
+    #[test]
+    fn compact_crdt_folds_log() {
+        let (_dir, doc) = setup();
+        save_crdt_text(&doc, "a\n").unwrap();
+        save_crdt_text(&doc, "a\nb\n").unwrap();
+        assert!(compact_crdt(&doc).unwrap().is_some());
+        assert!(!crdt_log_path_for(&doc).unwrap().exists());
+        let loaded = CrdtDoc::decode_state(&load_crdt(&doc).unwrap().unwrap()).unwrap();
+        assert_eq!(loaded.to_text(), "a\nb\n");
+    }
+
+    #[test]
+    fn crdt_log_ignores_truncated_entry() {
+        let (_dir, doc) = setup();
+        save_crdt_text(&doc, "a\n").unwrap();
+        save_crdt_text(&doc, "a\nb\n").unwrap();
+        let log_path = crdt_log_path_for(&doc).unwrap();
+        let mut bytes = fs::read(&log_path).unwrap();
+        bytes.extend_from_slice(&[0, 0, 0, 0, 0, 0, 0, 0, 99, 0, 0, 0, 1]);
+        fs::write(&log_path, bytes).unwrap();
+
+        assert_eq!(crdt_log(&doc).unwrap().len(), 1);
+        assert_eq!(crdt_text_at(&doc, 1).unwrap().as_deref(), Some("a\nb\n"));
+    }
+
     #[test]
     fn concurrent_atomic_writes_no_partial_content() {
         use std::sync::{Arc, Barrier};
@@ -594,4 +1052,124 @@ mod tests {
         assert_eq!(resolved.as_deref(), Some(snapshot_content),
             "resolve() should always prefer snapshot file when it exists");
     }
+
+    // -----------------------------------------------------------------------
+    // Session-keyed state
+    // -----------------------------------------------------------------------
+
+    fn setup_session_project() -> (TempDir, PathBuf) {
+        let dir = TempDir::new().unwrap();
+        fs::create_dir_all(dir.path().join(".agent-doc")).unwrap();
+        let doc = dir.path().join("session.md");
+        fs::write(&doc, "---\nagent_doc_session: 11111111-2222-3333-4444-555555555555\n---\n# Doc\n").unwrap();
+        (dir, doc)
+    }
+
+    #[test]
+    fn doc_key_uses_session_id() {
+        let (_dir, doc) = setup_session_project();
+        let key = doc_key(&doc).unwrap();
+        assert_eq!(key, session_key("11111111-2222-3333-4444-555555555555"));
+        assert_ne!(key, doc_hash(&doc).unwrap());
+        assert!(path_for(&doc).unwrap().to_string_lossy().contains(&key));
+    }
+
+    #[test]
+    fn doc_key_falls_back_to_path_hash() {
+        let (_dir, doc) = setup();
+        assert_eq!(doc_key(&doc).unwrap(), doc_hash(&doc).unwrap());
+    }
+
+    #[test]
+    fn session_state_survives_rename() {
+        let (dir, doc) = setup_session_project();
+        save(&doc, "baseline").unwrap();
+        write_crdt_checkpoint(&doc, &[7, 8, 9]).unwrap();
+
+        let moved = dir.path().join("renamed.md");
+        fs::rename(&doc, &moved).unwrap();
+
+        assert_eq!(load(&moved).unwrap().as_deref(), Some("baseline"));
+        assert_eq!(load_crdt(&moved).unwrap(), Some(vec![7, 8, 9]));
+    }
+
+    #[test]
+    fn legacy_path_hashed_state_is_migrated() {
+        let (dir, doc) = setup_session_project();
+        let hash = doc_hash(&doc).unwrap();
+        let snap_dir = dir.path().join(SNAP_DIR);
+        let crdt_dir = dir.path().join(CRDT_DIR);
+        let pending_dir = dir.path().join(PENDING_DIR);
+        fs::create_dir_all(&snap_dir).unwrap();
+        fs::create_dir_all(&crdt_dir).unwrap();
+        fs::create_dir_all(&pending_dir).unwrap();
+        fs::write(snap_dir.join(format!("{}.md", hash)), "legacy snapshot").unwrap();
+        fs::write(crdt_dir.join(format!("{}.yrs", hash)), [1, 2]).unwrap();
+        fs::write(pending_dir.join(format!("{}.md", hash)), "legacy pending").unwrap();
+
+        assert_eq!(load(&doc).unwrap().as_deref(), Some("legacy snapshot"));
+        assert_eq!(load_crdt(&doc).unwrap(), Some(vec![1, 2]));
+        let pending = pending_path_for(&doc).unwrap();
+        assert_eq!(fs::read_to_string(&pending).unwrap(), "legacy pending");
+        assert!(snap_dir.join(format!("{}.md", hash)).exists(), "reads don't migrate");
+
+        migrate(&doc).unwrap();
+        assert_eq!(load(&doc).unwrap().as_deref(), Some("legacy snapshot"));
+        assert_eq!(load_crdt(&doc).unwrap(), Some(vec![1, 2]));
+        assert_eq!(fs::read_to_string(pending_path_for(&doc).unwrap()).unwrap(), "legacy pending");
+
+        // Legacy files were moved, not copied
+        assert!(!snap_dir.join(format!("{}.md", hash)).exists());
+        assert!(!crdt_dir.join(format!("{}.yrs", hash)).exists());
+        assert!(!pending_dir.join(format!("{}.md", hash)).exists());
+    }
+
+    #[test]
+    fn legacy_lock_files_are_migrated() {
+        let (dir, doc) = setup_session_project();
+        let hash = doc_hash(&doc).unwrap();
+        let key = doc_key(&doc).unwrap();
+        let snap_dir = dir.path().join(SNAP_DIR);
+        let lock_dir = dir.path().join(LOCK_DIR);
+        fs::create_dir_all(&snap_dir).unwrap();
+        fs::create_dir_all(&lock_dir).unwrap();
+        fs::write(snap_dir.join(format!("{}.md", hash)), "legacy snapshot").unwrap();
+        fs::write(snap_dir.join(format!("{}.md.lock", hash)), "").unwrap();
+        fs::write(lock_dir.join(format!("{}.lock", hash)), "").unwrap();
+
+        assert_eq!(lock_path_for(&doc).unwrap(), lock_dir.join(format!("{}.lock", hash)));
+        migrate(&doc).unwrap();
+        assert_eq!(lock_path_for(&doc).unwrap(), lock_dir.join(format!("{}.lock", key)));
+        assert_eq!(load(&doc).unwrap().as_deref(), Some("legacy snapshot"));
+
+        assert!(lock_dir.join(format!("{}.lock", key)).exists());
+        assert!(!lock_dir.join(format!("{}.lock", hash)).exists());
+        assert!(snap_dir.join(format!("{}.md.lock", key)).exists());
+        assert!(!snap_dir.join(format!("{}.md.lock", hash)).exists());
+    }
+
+    #[test]
+    fn migration_keeps_existing_session_state() {
+        let (dir, doc) = setup_session_project();
+        save(&doc, "current").unwrap();
+        let hash = doc_hash(&doc).unwrap();
+        let legacy = dir.path().join(SNAP_DIR).join(format!("{}.md", hash));
+        fs::write(&legacy, "stale legacy").unwrap();
+
+        migrate(&doc).unwrap();
+        assert_eq!(load(&doc).unwrap().as_deref(), Some("current"));
+        assert!(legacy.exists());
+    }
+
+    #[test]
+    fn doc_key_follows_session_edits() {
+        let (_dir, doc) = setup_session_project();
+        let before = doc_key(&doc).unwrap();
+        // Same size, so an (mtime, size) cache would miss the change
+        fs::write(&doc, "---\nagent_doc_session: 99999999-2222-3333-4444-555555555555\n---\n# Doc\n").unwrap();
+        assert_ne!(doc_key(&doc).unwrap(), before);
+        fs::write(&doc, "---\nagent_doc_session: other-session\n---\n# Doc\n").unwrap();
+        assert_eq!(doc_key(&doc).unwrap(), session_key("other-session"));
+        assert_ne!(doc_key(&doc).unwrap(), before);
+    }
 }
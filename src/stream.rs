@@ -17,12 +17,13 @@ use std::sync::{Arc, Mutex};
 use std::time::Duration;
 
 use crate::agent::streaming::{StreamChunk, StreamingAgent};
-use crate::{agent, config::Config, crdt, diff, frontmatter, git, recover, snapshot, template};
+use crate::hooks::{self, Event};
+use crate::{agent, config::{self, Config}, crdt, diff, dynamic, frontmatter, git, recover, schema, snapshot, template};
 
 /// Run the stream command: stream agent output to document in real-time.
 pub fn run(
     file: &Path,
-    interval_ms: u64,
+    interval_ms: Option<u64>,
     agent_name: Option<&str>,
     model: Option<&str>,
     no_git: bool,
@@ -35,6 +36,7 @@ pub fn run(
     // Validate mode — requires CRDT write strategy
     let raw_content = std::fs::read_to_string(file)?;
     let (fm, _body) = frontmatter::parse(&raw_content)?;
+    schema::warn(file, &raw_content);
     let resolved = fm.resolve_mode();
     if !resolved.is_crdt() {
         anyhow::bail!(
@@ -44,9 +46,10 @@ pub fn run(
         );
     }
 
-    // Read stream config from frontmatter (overrides CLI args where set)
+    // Interval: CLI flag, then frontmatter, project and global config
+    let layers = config::Layers::for_document(config, file)?;
+    let interval = interval_ms.unwrap_or_else(|| layers.stream_interval_ms());
     let stream_config = fm.stream_config.clone().unwrap_or_default();
-    let interval = stream_config.interval.unwrap_or(interval_ms);
     let target = stream_config.target.as_deref().unwrap_or("exchange");
     let thinking_enabled = stream_config.thinking.unwrap_or(false);
     let thinking_target = stream_config.thinking_target.clone();
@@ -72,6 +75,14 @@ pub fn run(
         }
     };
 
+    // Refresh `run=` components so the agent sees current command output
+    let (the_diff, raw_content) = if dynamic::refresh(file)? {
+        let diff = diff::compute(file)?.unwrap_or(the_diff);
+        (diff, std::fs::read_to_string(file)?)
+    } else {
+        (the_diff, raw_content)
+    };
+
     // Ensure session UUID
     let (content_original, _session_id) = frontmatter::ensure_session(&raw_content)?;
     if content_original != raw_content {
@@ -80,17 +91,20 @@ pub fn run(
     let (fm, _body) = frontmatter::parse(&content_original)?;
 
     // Resolve agent
-    let agent_name = agent_name
-        .or(fm.agent.as_deref())
-        .or(config.default_agent.as_deref())
-        .unwrap_or("claude");
+    let agent_name = agent_name.map_or_else(|| layers.agent(), str::to_string);
+    let agent_name = agent_name.as_str();
     let agent_config = config.agents.get(agent_name);
 
     // Resolve streaming agent
     let streaming_agent = resolve_streaming(agent_name, agent_config)?;
 
     // Build prompt
-    let prompt = build_prompt(&fm, &the_diff, &content_original);
+    let prompt = match layers.prompt(fm.resume.is_some(), file, &the_diff, &content_original)? {
+        Some(prompt) => prompt,
+        None => build_prompt(&fm, &the_diff, &content_original),
+    };
+    hooks::fire(file, Event::OnPromptDetected, serde_json::json!({ "diff": the_diff }))?;
+    let prompt = hooks::pre_submit(file, &prompt, &the_diff, agent_name)?;
 
     // Pre-commit user changes
     if !no_git
@@ -103,7 +117,8 @@ pub fn run(
 
     // Send to streaming agent
     let fork = fm.resume.is_none();
-    let model = model.or(fm.model.as_deref());
+    let model = model.map(str::to_string).or_else(|| layers.model());
+    let model = model.as_deref();
     let chunks = streaming_agent.send_streaming(&prompt, fm.resume.as_deref(), fork, model)?;
 
     // Build thinking config
@@ -117,6 +132,11 @@ pub fn run(
 
     // Run the write-back loop
     let result = stream_loop(file, chunks, interval, target, &content_original, thinking_cfg.as_ref())?;
+    hooks::fire(file, Event::PostResponse, serde_json::json!({
+        "agent": agent_name,
+        "response": result.text,
+        "session_id": result.session_id,
+    }))?;
 
     // Update resume ID if we got a session_id
     if let Some(ref sid) = result.session_id {
@@ -134,7 +154,13 @@ pub fn run(
     }
 
     eprintln!("[stream] Stream complete for {}", file.display());
-    Ok(())
+    hooks::fire(file, Event::PostWrite, serde_json::json!({ "source": "stream" }))?;
+    hooks::fire(file, Event::OnStreamComplete, serde_json::json!({
+        "source": "stream",
+        "status": "complete",
+        "target": target,
+        "chunks": result.chunks,
+    }))
 }
 
 /// Configuration for chain-of-thought streaming.
@@ -146,6 +172,9 @@ struct ThinkingConfig {
 /// Result of a completed stream.
 struct StreamResult {
     session_id: Option<String>,
+    /// The complete response text
+    text: String,
+    chunks: usize,
 }
 
 /// The core write-back loop: accumulates chunks, periodically merges into document.
@@ -311,13 +340,12 @@ fn stream_loop(
                 .unwrap_or_else(|_| std::fs::read_to_string(file).unwrap_or_default())
         };
         snapshot::save(file, &content_ours)?;
-        let doc = crdt::CrdtDoc::from_text(&content_ours);
-        snapshot::save_crdt(file, &doc.encode_state())?;
+        snapshot::save_crdt_text(file, &content_ours)?;
 
         recover::clear_pending(file)?;
     }
 
-    Ok(StreamResult { session_id })
+    Ok(StreamResult { session_id, text: final_text, chunks: chunk_count })
 }
 
 /// Flush accumulated text to the document via template patch.
@@ -330,6 +358,9 @@ fn stream_loop(
 /// because the stream buffer is cumulative — each flush contains the full text
 /// so far, not just the delta.
 ///
+/// With `agent_doc_crdt_layout: components`, the patch is applied to the last
+/// flushed CRDT state and merged into the file component by component.
+///
 /// When a JetBrains/VS Code plugin is active (`.agent-doc/patches/` directory
 /// exists), attempts IPC first to avoid "externally modified" dialogs. Falls
 /// back to direct write on IPC timeout.
@@ -373,10 +404,27 @@ pub(crate) fn flush_to_document(
     let content_current = std::fs::read_to_string(file)
         .with_context(|| format!("failed to read {}", file.display()))?;
 
-    // Apply patches with replace override for stream target
-    let content_patched = template::apply_patches_with_overrides(
-        &content_current, &patches, &unmatched, file, &mode_overrides,
-    ).context("failed to apply template patches")?;
+    let content_patched = if frontmatter::crdt_layout(&content_current) == frontmatter::CrdtLayout::Components {
+        // Component layout: patch the last flushed state, then merge per component
+        // with the file so user edits made while streaming survive — including
+        // edits inside the target component.
+        let base_state = snapshot::load_crdt(file)?;
+        let base = match base_state.as_deref() {
+            Some(state) => crdt::CrdtDoc::decode_state(state)?.to_text(),
+            None => content_current.clone(),
+        };
+        let ours = template::apply_patches_with_overrides(
+            &base, &patches, &unmatched, file, &mode_overrides,
+        ).context("failed to apply template patches")?;
+        let merged = crdt::merge_components(base_state.as_deref(), &ours, &content_current)?;
+        snapshot::save_crdt_text(file, &merged)?;
+        merged
+    } else {
+        // Apply patches with replace override for stream target
+        template::apply_patches_with_overrides(
+            &content_current, &patches, &unmatched, file, &mode_overrides,
+        ).context("failed to apply template patches")?
+    };
 
     // Write atomically
     crate::write::atomic_write_pub(file, &content_patched)?;
@@ -460,6 +508,30 @@ mod tests {
         assert!(!result.contains("Old content"), "old content should be replaced: {}", result);
     }
 
+    #[test]
+    fn flush_component_layout_keeps_user_edits() {
+        let dir = tempfile::TempDir::new().unwrap();
+        std::fs::create_dir_all(dir.path().join(".agent-doc")).unwrap();
+
+        let doc = dir.path().join("test.md");
+        let content = "---\nagent_doc_crdt_layout: components\n---\n\n<!-- agent:notes -->\nDraft.\n<!-- /agent:notes -->\n\n<!-- agent:output -->\n<!-- /agent:output -->\n";
+        std::fs::write(&doc, content).unwrap();
+        snapshot::save_crdt_text(&doc, content).unwrap();
+
+        flush_to_document(&doc, "Part one", "output", content).unwrap();
+
+        // User edits another component while the agent is streaming
+        let edited = std::fs::read_to_string(&doc).unwrap().replace("Draft.", "Draft, revised.");
+        std::fs::write(&doc, &edited).unwrap();
+
+        flush_to_document(&doc, "Part one, part two", "output", content).unwrap();
+
+        let result = std::fs::read_to_string(&doc).unwrap();
+        assert!(result.contains("Draft, revised."), "user edit lost: {}", result);
+        assert_eq!(result.matches("Part one").count(), 1, "stream text duplicated: {}", result);
+        assert!(result.contains("Part one, part two"));
+    }
+
     #[test]
     fn flush_replaces_exchange_in_stream_mode() {
         let dir = tempfile::TempDir::new().unwrap();
@@ -708,7 +780,7 @@ mod tests {
         std::fs::write(&doc, "---\nagent_doc_format: template\nagent_doc_write: merge\n---\n\nBody\n").unwrap();
 
         let config = Config::default();
-        let err = run(&doc, 2000, None, None, true, &config).unwrap_err();
+        let err = run(&doc, Some(2000), None, None, true, &config).unwrap_err();
         assert!(err.to_string().contains("expected crdt"), "error: {}", err);
     }
 
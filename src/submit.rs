@@ -3,7 +3,9 @@ use fs2::FileExt;
 use std::fs::OpenOptions;
 use std::path::Path;
 
-use crate::{agent, config::Config, diff, frontmatter, git, merge, snapshot};
+use crate::conflicts::RecordedMerge;
+use crate::hooks::{self, Event};
+use crate::{agent, config::{self, Config}, diff, dynamic, frontmatter, generate, git, merge, recover, schema, snapshot};
 
 pub fn run(
     file: &Path,
@@ -32,6 +34,13 @@ pub fn run(
         }
     };
 
+    // Refresh `run=` components so the agent sees current command output
+    let the_diff = if !dry_run && dynamic::refresh(file)? {
+        diff::compute(file)?.unwrap_or(the_diff)
+    } else {
+        the_diff
+    };
+
     // Ensure the document has a session UUID (for tmux routing)
     let raw_content = std::fs::read_to_string(file)?;
     let (content_original, _session_id) = frontmatter::ensure_session(&raw_content)?;
@@ -39,17 +48,20 @@ pub fn run(
         std::fs::write(file, &content_original)?;
     }
     let (fm, _body) = frontmatter::parse(&content_original)?;
+    schema::warn(file, &content_original);
 
-    // Resolve agent
-    let agent_name = agent_name
-        .or(fm.agent.as_deref())
-        .or(config.default_agent.as_deref())
-        .unwrap_or("claude");
+    // Resolve agent: CLI flag, then frontmatter, project and global config
+    let layers = config::Layers::for_document(config, file)?;
+    let agent_name = agent_name.map_or_else(|| layers.agent(), str::to_string);
+    let agent_name = agent_name.as_str();
     let agent_config = config.agents.get(agent_name);
     let backend = agent::resolve(agent_name, agent_config)?;
 
-    // Build prompt
-    let prompt = if fm.resume.is_some() {
+    // Build prompt (a configured template replaces the built-in one)
+    let resume = fm.resume.is_some();
+    let prompt = if let Some(prompt) = layers.prompt(resume, file, &the_diff, &content_original)? {
+        prompt
+    } else if resume {
         format!(
             "The user edited the session document. Here is the diff since the last submit:\n\n\
              <diff>\n{}\n</diff>\n\n\
@@ -78,6 +90,9 @@ pub fn run(
         return Ok(());
     }
 
+    hooks::fire(file, Event::OnPromptDetected, serde_json::json!({ "diff": the_diff }))?;
+    let prompt = hooks::pre_submit(file, &prompt, &the_diff, agent_name)?;
+
     // Create branch if requested
     if branch && !no_git {
         git::create_branch(file)?;
@@ -93,18 +108,28 @@ pub fn run(
 
     // Send to agent — use `resume` for agent conversation tracking
     let fork = fm.resume.is_none();
-    let model = model.or(fm.model.as_deref());
+    let model = model.map(str::to_string).or_else(|| layers.model());
+    let model = model.as_deref();
     let response = backend.send(&prompt, fm.resume.as_deref(), fork, model)?;
+    // Keep the response if a post-response hook aborts (or the write fails)
+    recover::save_pending(file, &response.text)?;
+    hooks::fire(file, Event::PostResponse, serde_json::json!({
+        "agent": agent_name,
+        "response": response.text,
+        "session_id": response.session_id,
+    }))
+    .with_context(|| format!("response saved; run `agent-doc recover {}` to write it", file.display()))?;
 
     // Build our version: original + resume_id update + response appended
     let mut content_ours = content_original.clone();
     if let Some(ref sid) = response.session_id {
         content_ours = frontmatter::set_resume_id(&content_ours, sid)?;
     }
-    let response_text = crate::write::strip_assistant_heading(&response.text);
+    let response_text = dynamic::strip_run_attrs(&crate::write::strip_assistant_heading(&response.text));
     content_ours.push_str("\n## Assistant\n\n");
     content_ours.push_str(&response_text);
     content_ours.push_str("\n\n## User\n\n");
+    let content_ours = generate::regenerate(&content_ours, file);
 
     // Acquire advisory lock on the document for agent-doc-vs-agent-doc
     // coordination (e.g., watch daemon vs. manual `agent-doc run`).
@@ -114,32 +139,34 @@ pub fn run(
     // Re-read file to check for user edits during submit
     let content_current = std::fs::read_to_string(file)?;
 
-    let final_content = if content_current == content_original {
+    let merged = if content_current == content_original {
         // No edits during submit — use our version directly
-        content_ours.clone()
+        RecordedMerge::clean(content_ours.clone())
     } else {
         eprintln!("File was modified during submit. Merging changes...");
-        merge::merge_contents(&content_original, &content_ours, &content_current)?
+        merge::merge_document(file, &content_original, &content_ours, &content_current)?
     };
 
     // Try IPC first — if an IDE plugin is active, it applies the change via
     // Document API (no "externally modified" dialog, cursor preserved).
-    let ipc_ok = crate::write::try_ipc_full_content(file, &final_content)?;
+    let ipc_ok = crate::write::try_ipc_full_content(file, &merged.text)?;
 
     if !ipc_ok {
         // IPC not available or timed out — fall back to direct disk write
-        atomic_write(file, &final_content)?;
+        atomic_write(file, &merged.text)?;
 
-        // Save snapshot as content_ours (baseline + response), not final_content.
-        // If the user edited concurrently, final_content includes their edits.
+        // Save snapshot as content_ours (baseline + response), not the merged text.
+        // If the user edited concurrently, the merge includes their edits.
         // Saving content_ours ensures the next diff detects those concurrent edits.
         snapshot::save(file, &content_ours)?;
     }
 
     drop(doc_lock); // explicit release after both doc and snapshot are written
+    recover::clear_pending(file)?;
+    merged.fire_hooks(file);
 
     eprintln!("Response appended to {}", file.display());
-    Ok(())
+    hooks::fire(file, Event::PostWrite, serde_json::json!({ "source": "submit" }))
 }
 
 /// Acquire an advisory flock on a document file for agent-doc-vs-agent-doc
@@ -157,6 +184,7 @@ fn acquire_doc_lock(path: &Path) -> Result<std::fs::File> {
         .with_context(|| format!("failed to open doc lock {}", lock_path.display()))?;
     file.lock_exclusive()
         .with_context(|| format!("failed to acquire doc lock on {}", lock_path.display()))?;
+    crate::snapshot::migrate(path)?;
     Ok(file)
 }
 
@@ -372,39 +400,16 @@ mod tests {
 
     #[test]
     fn merge_clean_no_conflicts() {
-        // merge_contents spawns `git merge-file` which inherits CWD.
-        // Other tests may invalidate CWD via TempDir drops, so we
-        // perform the merge manually using temp files + Command with
-        // an explicit current_dir to avoid CWD pollution.
-        let dir = TempDir::new().unwrap();
-        let base_path = dir.path().join("base");
-        let ours_path = dir.path().join("ours");
-        let theirs_path = dir.path().join("theirs");
-
         let base = "line 1\nline 2\nline 3\n";
         let ours = "line 1\nline 2\nline 3\n\n## Assistant\n\nResponse here.\n";
         let theirs = "line 1\nline 2\nline 3\n";
 
-        std::fs::write(&base_path, base).unwrap();
-        std::fs::write(&ours_path, ours).unwrap();
-        std::fs::write(&theirs_path, theirs).unwrap();
-
-        let output = std::process::Command::new("git")
-            .current_dir(dir.path())
-            .args([
-                "merge-file", "-p", "--diff3",
-                "-L", "agent-response",
-                "-L", "original",
-                "-L", "your-edits",
-            ])
-            .arg(&ours_path)
-            .arg(&base_path)
-            .arg(&theirs_path)
-            .output()
-            .unwrap();
-
-        let merged = String::from_utf8(output.stdout).unwrap();
-        assert!(output.status.success(), "merge should be clean");
+        let result = crate::merge::merge3(base, ours, theirs);
+        assert!(
+            !result.hunks.iter().any(|h| matches!(h, crate::merge::MergeHunk::Conflict(_))),
+            "merge should be clean"
+        );
+        let merged = result.to_text();
         assert!(merged.contains("Response here."));
     }
 }
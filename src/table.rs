@@ -0,0 +1,307 @@
+//! Keyed markdown tables for `mode = "table"` components.
+//!
+//! A table-mode patch contains table rows. Each row is matched to an existing
+//! row by its key column (the component's `key`, default: the first column)
+//! and replaces it, or is appended as a new row. A row whose key is struck
+//! through (`| ~~T-3~~ |`) deletes that row.
+//!
+//! If the patch starts with a header row and separator, its cells are matched
+//! to the table's columns by name, so a patch may update a subset of columns.
+//! Otherwise cells are positional.
+//!
+//! Untouched rows are kept byte-for-byte; changed and new rows are padded to
+//! the header's column widths and alignment.
+
+use anyhow::{bail, Context, Result};
+
+/// Column alignment from the separator row.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Align {
+    Left,
+    Center,
+    Right,
+}
+
+/// Split a `| a | b |` line into trimmed cells. `\|` is kept as part of a cell.
+fn split_row(line: &str) -> Option<Vec<String>> {
+    let trimmed = line.trim();
+    let inner = trimmed.strip_prefix('|')?;
+    let mut cells = Vec::new();
+    let mut cell = String::new();
+    let mut chars = inner.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' if chars.peek() == Some(&'|') => {
+                cell.push_str("\\|");
+                chars.next();
+            }
+            '|' => cells.push(std::mem::take(&mut cell).trim().to_string()),
+            _ => cell.push(c),
+        }
+    }
+    if !cell.trim().is_empty() {
+        cells.push(cell.trim().to_string());
+    }
+    Some(cells)
+}
+
+/// Widths (in chars) of the segments between pipes, used to pad rendered rows.
+fn segment_widths(line: &str) -> Vec<usize> {
+    let inner = line.trim().trim_start_matches('|');
+    let inner = inner.strip_suffix('|').unwrap_or(inner);
+    inner.split('|').map(|seg| seg.chars().count()).collect()
+}
+
+fn is_separator(cells: &[String]) -> bool {
+    !cells.is_empty()
+        && cells.iter().all(|c| {
+            let dashes = c.trim_start_matches(':').trim_end_matches(':');
+            !dashes.is_empty() && dashes.chars().all(|ch| ch == '-')
+        })
+}
+
+fn alignment(cell: &str) -> Align {
+    match (cell.starts_with(':'), cell.ends_with(':')) {
+        (true, true) => Align::Center,
+        (false, true) => Align::Right,
+        _ => Align::Left,
+    }
+}
+
+/// The key of a row and whether the row deletes it (`~~key~~`).
+fn parse_key(cell: &str) -> (&str, bool) {
+    match cell.strip_prefix("~~").and_then(|k| k.strip_suffix("~~")) {
+        Some(key) => (key.trim(), true),
+        None => (cell, false),
+    }
+}
+
+/// A table found in component content, as line indices.
+struct Table {
+    header: usize,
+    /// One past the last row.
+    end: usize,
+}
+
+fn find_table(lines: &[&str]) -> Option<Table> {
+    let header = (0..lines.len().saturating_sub(1)).find(|&i| {
+        split_row(lines[i]).is_some() && split_row(lines[i + 1]).is_some_and(|cells| is_separator(&cells))
+    })?;
+    let end = (header + 2..lines.len())
+        .find(|&i| split_row(lines[i]).is_none())
+        .unwrap_or(lines.len());
+    Some(Table { header, end })
+}
+
+/// Upsert or delete the rows in `patch` into the first table in `existing`.
+///
+/// If `existing` has no table, the patch must start with a header row and
+/// separator, which become the new table (appended to the content).
+pub fn upsert(existing: &str, patch: &str, key: Option<&str>) -> Result<String> {
+    let patch_lines: Vec<&str> = patch.lines().filter(|l| split_row(l).is_some()).collect();
+    let patch_header = (patch_lines.len() >= 2)
+        .then(|| (split_row(patch_lines[0]).unwrap_or_default(), split_row(patch_lines[1]).unwrap_or_default()))
+        .filter(|(_, sep)| is_separator(sep))
+        .map(|(header, _)| header);
+
+    let mut lines: Vec<String> = existing.lines().map(str::to_string).collect();
+    let table = match find_table(&lines.iter().map(String::as_str).collect::<Vec<_>>()) {
+        Some(table) => table,
+        None => {
+            if patch_header.is_none() {
+                bail!("component has no table, and the patch has no header row to create one");
+            }
+            let header = lines.len();
+            lines.push(patch_lines[0].trim().to_string());
+            lines.push(patch_lines[1].trim().to_string());
+            Table { header, end: header + 2 }
+        }
+    };
+
+    let columns = split_row(&lines[table.header]).unwrap_or_default();
+    let separator = split_row(&lines[table.header + 1]).unwrap_or_default();
+    let aligns: Vec<Align> = (0..columns.len())
+        .map(|i| separator.get(i).map_or(Align::Left, |c| alignment(c)))
+        .collect();
+    let widths = segment_widths(&lines[table.header]);
+    let find_column = |name: &str| columns.iter().position(|c| c.eq_ignore_ascii_case(name.trim()));
+    let key_col = match key {
+        Some(name) => find_column(name).with_context(|| format!("key column '{}' not in table header", name))?,
+        None => 0,
+    };
+
+    // Map patch cells to table columns
+    let (mapping, patch_key, rows) = match &patch_header {
+        Some(header) => {
+            let mapping = header
+                .iter()
+                .map(|name| find_column(name).with_context(|| format!("unknown column '{}'", name)))
+                .collect::<Result<Vec<usize>>>()?;
+            let patch_key = mapping
+                .iter()
+                .position(|&c| c == key_col)
+                .with_context(|| format!("patch header has no '{}' column", columns[key_col]))?;
+            (mapping, patch_key, &patch_lines[2..])
+        }
+        None => ((0..columns.len()).collect(), key_col, &patch_lines[..]),
+    };
+
+    let mut body: Vec<Option<String>> = lines[table.header + 2..table.end].iter().cloned().map(Some).collect();
+    let mut appended: Vec<String> = Vec::new();
+    for row in rows {
+        let cells = split_row(row).unwrap_or_default();
+        if cells.len() > mapping.len() {
+            bail!("row has {} cells, expected at most {}: {}", cells.len(), mapping.len(), row.trim());
+        }
+        let (row_key, delete) = parse_key(cells.get(patch_key).map_or("", String::as_str));
+        if row_key.is_empty() {
+            bail!("row has no '{}' key: {}", columns[key_col], row.trim());
+        }
+        let key_of = |line: &str| split_row(line).and_then(|c| c.get(key_col).cloned()).unwrap_or_default();
+        let existing_row = body.iter().position(|l| l.as_deref().is_some_and(|l| key_of(l) == row_key));
+        let appended_row = appended.iter().position(|l| key_of(l) == row_key);
+
+        if delete {
+            match (existing_row, appended_row) {
+                (Some(i), _) => body[i] = None,
+                (None, Some(i)) => {
+                    appended.remove(i);
+                }
+                (None, None) => bail!("cannot delete '{}': no row with that key", row_key),
+            }
+            continue;
+        }
+
+        let current = existing_row
+            .and_then(|i| body[i].as_deref())
+            .or(appended_row.map(|i| appended[i].as_str()))
+            .and_then(split_row);
+        let mut new_cells = current.unwrap_or_default();
+        new_cells.resize(columns.len(), String::new());
+        if patch_header.is_none() {
+            // Positional rows replace the whole row
+            new_cells.iter_mut().for_each(String::clear);
+        }
+        for (i, cell) in cells.iter().enumerate() {
+            new_cells[mapping[i]] = cell.clone();
+        }
+        new_cells[key_col] = row_key.to_string();
+        let rendered = render_row(&new_cells, &widths, &aligns);
+        match (existing_row, appended_row) {
+            (Some(i), _) => body[i] = Some(rendered),
+            (None, Some(i)) => appended[i] = rendered,
+            (None, None) => appended.push(rendered),
+        }
+    }
+
+    let mut out: Vec<String> = lines[..table.header + 2].to_vec();
+    out.extend(body.into_iter().flatten());
+    out.extend(appended);
+    out.extend(lines[table.end..].iter().cloned());
+    let mut result = out.join("\n");
+    if !result.is_empty() {
+        result.push('\n');
+    }
+    Ok(result)
+}
+
+/// Render cells padded to the header's segment widths (minus one space each side).
+fn render_row(cells: &[String], widths: &[usize], aligns: &[Align]) -> String {
+    let mut line = String::from("|");
+    for (i, cell) in cells.iter().enumerate() {
+        let width = widths.get(i).map_or(0, |w| w.saturating_sub(2));
+        let pad = width.saturating_sub(cell.chars().count());
+        let (left, right) = match aligns.get(i).copied().unwrap_or(Align::Left) {
+            Align::Left => (0, pad),
+            Align::Right => (pad, 0),
+            Align::Center => (pad / 2, pad - pad / 2),
+        };
+        line.push(' ');
+        line.push_str(&" ".repeat(left));
+        line.push_str(cell);
+        line.push_str(&" ".repeat(right));
+        line.push_str(" |");
+    }
+    line
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TASKS: &str = "\
+Open tasks:
+
+| ID  | Task        | Status |
+|-----|-------------|:------:|
+| T-1 | Write specs | done   |
+|T-2|hand edited|todo|
+
+Notes below.
+";
+
+    #[test]
+    fn updates_row_and_preserves_others() {
+        let result = upsert(TASKS, "| T-1 | Write specs | review |\n", None).unwrap();
+        assert!(result.contains("| T-1 | Write specs | review |\n"));
+        assert!(result.contains("|T-2|hand edited|todo|\n"), "untouched row kept byte-for-byte");
+        assert!(result.starts_with("Open tasks:\n\n"));
+        assert!(result.ends_with("\nNotes below.\n"));
+    }
+
+    #[test]
+    fn appends_new_rows_with_alignment() {
+        let result = upsert(TASKS, "| T-3 | Ship | todo |\n", None).unwrap();
+        assert!(result.contains("|T-2|hand edited|todo|\n| T-3 | Ship        |  todo  |\n\nNotes"));
+    }
+
+    #[test]
+    fn deletes_struck_through_key() {
+        let result = upsert(TASKS, "| ~~T-2~~ |\n", None).unwrap();
+        assert!(!result.contains("T-2"));
+        assert!(result.contains("| T-1 | Write specs | done   |\n\nNotes"));
+
+        let err = upsert(TASKS, "| ~~T-9~~ |\n", None).unwrap_err();
+        assert!(err.to_string().contains("no row with that key"));
+    }
+
+    #[test]
+    fn header_patch_updates_subset_of_columns() {
+        let patch = "| status | id |\n|---|---|\n| done | T-2 |\n";
+        let result = upsert(TASKS, patch, Some("ID")).unwrap();
+        assert!(result.contains("| T-2 | hand edited |  done  |\n"));
+    }
+
+    #[test]
+    fn custom_key_column() {
+        let result = upsert(TASKS, "| T-9 | Write specs | todo |\n", Some("Task")).unwrap();
+        assert!(result.contains("| T-9 | Write specs |  todo  |\n"));
+        assert!(!result.contains("T-1"));
+
+        let err = upsert(TASKS, "| a |\n", Some("Owner")).unwrap_err();
+        assert!(err.to_string().contains("key column 'Owner' not in table header"));
+    }
+
+    #[test]
+    fn creates_table_from_patch_header() {
+        let patch = "| Key | Decision |\n|-----|----------|\n| D-1 | Use yrs |\n";
+        let result = upsert("Decisions so far:\n", patch, None).unwrap();
+        assert_eq!(result, "Decisions so far:\n| Key | Decision |\n|-----|----------|\n| D-1 | Use yrs  |\n");
+
+        let err = upsert("no table\n", "| D-1 | x |\n", None).unwrap_err();
+        assert!(err.to_string().contains("no table"));
+    }
+
+    #[test]
+    fn rejects_unknown_columns_and_missing_keys() {
+        let err = upsert(TASKS, "| Owner |\n|---|\n| me |\n", None).unwrap_err();
+        assert!(err.to_string().contains("unknown column 'Owner'"));
+        let err = upsert(TASKS, "|  | x | y |\n", None).unwrap_err();
+        assert!(err.to_string().contains("row has no 'ID' key"));
+    }
+
+    #[test]
+    fn escaped_pipes_stay_in_cell() {
+        assert_eq!(split_row("| a \\| b | c |").unwrap(), vec!["a \\| b", "c"]);
+    }
+}
@@ -3,17 +3,30 @@
 //! Parses `<!-- patch:name -->...<!-- /patch:name -->` blocks from agent responses
 //! and applies them to the corresponding `<!-- agent:name -->` components in the document.
 
-use anyhow::{Context, Result};
+use anyhow::{bail, Context, Result};
 use serde::Serialize;
+use std::collections::BTreeMap;
 use std::path::Path;
 
-use crate::component;
+use crate::patch_ops::{self, PatchOp, Placement};
+use crate::{component, dynamic, generate, patch};
 
 /// A parsed patch directive from an agent response.
 #[derive(Debug, Clone)]
 pub struct PatchBlock {
     pub name: String,
     pub content: String,
+    /// Operation from the opening marker's attributes (`op=insert-after anchor="..."`).
+    pub op: PatchOp,
+    /// Where to create the component if it doesn't exist (`after=status`).
+    pub placement: Option<Placement>,
+}
+
+/// A patch refused because it would change a user-owned component.
+#[derive(Debug, Clone)]
+pub struct RejectedPatch {
+    pub name: String,
+    pub reason: String,
 }
 
 /// Template info output for plugins.
@@ -24,29 +37,63 @@ pub struct TemplateInfo {
 }
 
 /// Per-component info for plugin rendering.
+///
+/// Components are listed in document order; nested components carry their
+/// `path` and `parent` path so plugins can rebuild the hierarchy.
 #[derive(Debug, Serialize)]
 pub struct ComponentInfo {
     pub name: String,
+    /// `/`-separated path from the top-level ancestor (equals `name` at top level).
+    pub path: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub parent: Option<String>,
+    pub depth: usize,
     pub mode: String,
     pub content: String,
     pub line: usize,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub max_entries: Option<usize>,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub timestamp: bool,
+    #[serde(skip_serializing_if = "patch::Owner::is_shared")]
+    pub owner: patch::Owner,
+    /// Inline attributes from the opening marker.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub attrs: BTreeMap<String, String>,
 }
 
-/// Check if a document is in template mode (deprecated — use `fm.resolve_mode().is_template()`).
-#[cfg(test)]
-pub fn is_template_mode(mode: Option<&str>) -> bool {
-    matches!(mode, Some("template"))
+/// A patch marker whose attributes failed to parse.
+#[derive(Debug, Clone)]
+pub struct InvalidPatch {
+    /// Byte offset of the opening marker in the response.
+    pub offset: usize,
+    pub error: String,
 }
 
 /// Parse `<!-- patch:name -->...<!-- /patch:name -->` blocks from an agent response.
 ///
+/// The opening marker may carry an operation (`<!-- patch:name op=diff -->`) and a
+/// placement for creating the component (`after=status`, see `patch_ops`); the
+/// closing marker uses the bare name. A block with invalid attributes is logged
+/// and its content treated as unmatched, so it is not lost. Shell command
+/// attributes are stripped from component markers in the content
+/// (see [`dynamic::strip_run_attrs`]).
+///
 /// Content outside patch blocks is collected as "unmatched" and returned separately.
 pub fn parse_patches(response: &str) -> Result<(Vec<PatchBlock>, String)> {
+    let (patches, unmatched, invalid) = parse_patches_checked(response);
+    for bad in &invalid {
+        eprintln!("[template] {}; treating its content as unmatched", bad.error);
+    }
+    Ok((patches, unmatched))
+}
+
+/// Like [`parse_patches`], but returns the invalid markers instead of logging them.
+pub fn parse_patches_checked(response: &str) -> (Vec<PatchBlock>, String, Vec<InvalidPatch>) {
     let bytes = response.as_bytes();
     let len = bytes.len();
     let mut patches = Vec::new();
+    let mut invalid = Vec::new();
     let mut unmatched = String::new();
     let mut pos = 0;
     let mut last_end = 0;
@@ -71,12 +118,21 @@ pub fn parse_patches(response: &str) -> Result<(Vec<PatchBlock>, String)> {
         let inner = &response[marker_start + 4..close - 3];
         let trimmed = inner.trim();
 
-        if let Some(name) = trimmed.strip_prefix("patch:") {
-            let name = name.trim();
+        if let Some(rest) = trimmed.strip_prefix("patch:") {
+            let (name, attr_text) = component::split_name(rest.trim());
             if name.is_empty() || name.starts_with('/') {
                 pos = close;
                 continue;
             }
+            let parsed = component::parse_attrs(attr_text)
+                .and_then(|attrs| Ok((PatchOp::from_attrs(&attrs)?, Placement::from_attrs(&attrs)?)))
+                .with_context(|| format!("invalid patch marker for '{}'", name));
+            if let Err(e) = &parsed {
+                invalid.push(InvalidPatch {
+                    offset: marker_start,
+                    error: format!("{:#}", e),
+                });
+            }
 
             // Consume trailing newline after opening marker
             let mut content_start = close;
@@ -98,10 +154,23 @@ pub fn parse_patches(response: &str) -> Result<(Vec<PatchBlock>, String)> {
             let close_marker = format!("<!-- /patch:{} -->", name);
             if let Some(close_pos) = response[content_start..].find(&close_marker) {
                 let content = &response[content_start..content_start + close_pos];
-                patches.push(PatchBlock {
-                    name: name.to_string(),
-                    content: content.to_string(),
-                });
+                match parsed {
+                    Ok((op, placement)) => patches.push(PatchBlock {
+                        name: name.to_string(),
+                        content: dynamic::strip_run_attrs(content),
+                        op,
+                        placement,
+                    }),
+                    Err(_) => {
+                        let content = content.trim();
+                        if !content.is_empty() {
+                            if !unmatched.is_empty() {
+                                unmatched.push('\n');
+                            }
+                            unmatched.push_str(content);
+                        }
+                    }
+                }
 
                 let mut end = content_start + close_pos + close_marker.len();
                 if end < len && bytes[end] == b'\n' {
@@ -127,14 +196,15 @@ pub fn parse_patches(response: &str) -> Result<(Vec<PatchBlock>, String)> {
         }
     }
 
-    Ok((patches, unmatched))
+    (patches, dynamic::strip_run_attrs(&unmatched), invalid)
 }
 
 /// Apply patch blocks to a document's components.
 ///
 /// For each patch block, finds the matching `<!-- agent:name -->` component
-/// and replaces its content. Uses patch.rs mode logic (replace/append/prepend)
-/// based on `.agent-doc/components.toml` config.
+/// and replaces its content. Uses patch.rs mode logic (replace/append/prepend,
+/// timestamp, max_entries) from inline marker attributes and
+/// `.agent-doc/components.toml` config.
 ///
 /// Returns the modified document. Unmatched content (outside patch blocks)
 /// is appended to `<!-- agent:output -->` if it exists, or creates one at the end.
@@ -153,22 +223,36 @@ pub fn apply_patches_with_overrides(
 ) -> Result<String> {
     let mut result = doc.to_string();
 
-    // Apply patches in reverse order (by position) to preserve byte offsets
     let components = component::parse(&result)
         .context("failed to parse components")?;
 
     // Load component configs
-    let configs = load_component_configs(file);
+    let configs = patch::load_configs(file).unwrap_or_default();
 
-    // Build a list of (component_index, patch) pairs, sorted by component position descending.
+    let (patches, rejected) = partition_owned(&result, &components, patches, &configs)?;
+    for r in &rejected {
+        eprintln!("[template] patch '{}' rejected: {}", r.name, r.reason);
+    }
+
+    // Create components for placement patches (`<!-- patch:risks after=plan -->`)
+    result = create_placed_components(&result, &patches, &configs)?;
+    let components = component::parse(&result)
+        .context("failed to re-parse components after creating components")?;
+
+    // Build a list of (nesting depth, patch) pairs. Patch names are component
+    // names or `/`-separated paths (`plan/step-1`, see `component::find_by_path`).
     // Patches targeting missing components are collected as overflow and routed to
     // exchange/output (same as unmatched content) — this avoids silent failures when
     // the agent uses a wrong component name.
     let mut ops: Vec<(usize, &PatchBlock)> = Vec::new();
     let mut overflow = String::new();
-    for patch in patches {
-        if let Some(idx) = components.iter().position(|c| c.name == patch.name) {
-            ops.push((idx, patch));
+    for patch in &patches {
+        if let Some(comp) = component::find_by_path(&components, &patch.name) {
+            let depth = components
+                .iter()
+                .filter(|c| c.open_start < comp.open_start && c.close_start > comp.open_start)
+                .count();
+            ops.push((depth, patch));
         } else {
             let available: Vec<&str> = components.iter().map(|c| c.name.as_str()).collect();
             eprintln!(
@@ -183,17 +267,39 @@ pub fn apply_patches_with_overrides(
         }
     }
 
-    // Sort by position descending so replacements don't shift earlier offsets
-    ops.sort_by(|a, b| b.0.cmp(&a.0));
+    // Apply outer components before nested ones, so a patch to a child lands in
+    // its parent's new content. Components are re-parsed after each patch since
+    // replacing a parent shifts (or removes) its children.
+    ops.sort_by_key(|(depth, _)| *depth);
 
-    for (idx, patch) in &ops {
-        let comp = &components[*idx];
-        // Mode overrides take precedence over config and defaults
-        let mode = mode_overrides.get(&patch.name)
-            .map(|s| s.as_str())
-            .or_else(|| configs.get(&patch.name).map(|s| s.as_str()))
-            .unwrap_or_else(|| default_mode(&patch.name));
-        let new_content = apply_mode(mode, comp.content(&result), &patch.content);
+    for (_, patch) in &ops {
+        let components = component::parse(&result)
+            .context("failed to re-parse components after patching")?;
+        let Some(comp) = component::find_by_path(&components, &patch.name) else {
+            eprintln!(
+                "[template] patch target '{}' was removed by an enclosing patch, routing to exchange/output",
+                patch.name
+            );
+            if !overflow.is_empty() {
+                overflow.push('\n');
+            }
+            overflow.push_str(&patch.content);
+            continue;
+        };
+        let new_content = match resolve_patch(&result, comp, patch, &configs, mode_overrides) {
+            Ok(content) => content,
+            Err(e) => {
+                eprintln!(
+                    "[template] patch '{}' ({}) failed: {:#}, routing to exchange/output",
+                    patch.name, patch.op, e
+                );
+                if !overflow.is_empty() {
+                    overflow.push('\n');
+                }
+                overflow.push_str(&patch.content);
+                continue;
+            }
+        };
         result = comp.replace_content(&result, &new_content);
     }
 
@@ -236,9 +342,288 @@ pub fn apply_patches_with_overrides(
         }
     }
 
+    Ok(generate::regenerate(&result, file))
+}
+
+/// Patches that agents may not apply because of component ownership
+/// (see [`patch::Owner`]), checked against `doc`.
+///
+/// Writers list these in their summary; [`apply_patches_with_overrides`]
+/// skips them.
+pub fn rejected_patches(doc: &str, patches: &[PatchBlock], file: &Path) -> Result<Vec<RejectedPatch>> {
+    let components = component::parse(doc).context("failed to parse components")?;
+    let configs = patch::load_configs(file).unwrap_or_default();
+    Ok(partition_owned(doc, &components, patches, &configs)?.1)
+}
+
+/// Split patches into those agents may apply and those that would change a
+/// user-owned component: the target itself, an enclosing component, one
+/// nested inside it, or (for a component to be created) the component its
+/// placement falls in. Other patches with unknown targets are kept (they are
+/// created or routed to exchange/output later).
+fn partition_owned(
+    doc: &str,
+    components: &[component::Component],
+    patches: &[PatchBlock],
+    configs: &std::collections::HashMap<String, patch::ComponentConfig>,
+) -> Result<(Vec<PatchBlock>, Vec<RejectedPatch>)> {
+    let parents = component::parents(components);
+    let mut paths: Vec<String> = Vec::with_capacity(components.len());
+    let mut user_owned = Vec::with_capacity(components.len());
+    for (i, comp) in components.iter().enumerate() {
+        let path = match parents[i] {
+            Some(p) => format!("{}/{}", paths[p], comp.name),
+            None => comp.name.clone(),
+        };
+        user_owned.push(patch::config_for(configs, &path, comp)?.owner == patch::Owner::User);
+        paths.push(path);
+    }
+
+    let mut accepted = Vec::new();
+    let mut rejected = Vec::new();
+    for patch in patches {
+        let target = component::find_by_path(components, &patch.name)
+            .and_then(|t| components.iter().position(|c| c.open_start == t.open_start));
+        let Some(t) = target else {
+            let inside = patch
+                .placement
+                .as_ref()
+                .and_then(|placement| placement_offset(doc, components, placement).ok())
+                .and_then(|offset| {
+                    components
+                        .iter()
+                        .enumerate()
+                        .find(|(i, c)| user_owned[*i] && c.open_end <= offset && offset <= c.close_start)
+                });
+            match inside {
+                Some((_, c)) => rejected.push(RejectedPatch {
+                    name: patch.name.clone(),
+                    reason: format!("it would be created inside user-owned '{}'", c.name),
+                }),
+                None => accepted.push(patch.clone()),
+            }
+            continue;
+        };
+        match owner_conflict(components, &parents, &user_owned, t) {
+            Some(reason) => rejected.push(RejectedPatch { name: patch.name.clone(), reason }),
+            None => accepted.push(patch.clone()),
+        }
+    }
+    Ok((accepted, rejected))
+}
+
+/// Why patching `components[t]` would change a user-owned component, if it would.
+fn owner_conflict(
+    components: &[component::Component],
+    parents: &[Option<usize>],
+    user_owned: &[bool],
+    t: usize,
+) -> Option<String> {
+    if user_owned[t] {
+        return Some(format!("'{}' is user-owned", components[t].name));
+    }
+    let mut ancestor = parents[t];
+    while let Some(a) = ancestor {
+        if user_owned[a] {
+            return Some(format!("it is inside user-owned '{}'", components[a].name));
+        }
+        ancestor = parents[a];
+    }
+    let comp = &components[t];
+    components
+        .iter()
+        .enumerate()
+        .find(|(i, c)| user_owned[*i] && c.open_start > comp.open_start && c.open_start < comp.close_start)
+        .map(|(_, c)| format!("it contains user-owned '{}'", c.name))
+}
+
+/// Insert empty components for patches whose target doesn't exist but that
+/// carry a placement, so they are then applied like any other patch.
+///
+/// Only names allowed by `agent_doc_creatable` in the frontmatter or
+/// `creatable = true` in `components.toml` are created; other patches are left
+/// to be routed to exchange/output.
+fn create_placed_components(
+    doc: &str,
+    patches: &[PatchBlock],
+    configs: &std::collections::HashMap<String, patch::ComponentConfig>,
+) -> Result<String> {
+    let allowed = crate::frontmatter::creatable_components(doc);
+    let mut result = doc.to_string();
+    for patch in patches {
+        let Some(placement) = &patch.placement else {
+            continue;
+        };
+        let components = component::parse(&result).context("failed to parse components")?;
+        if component::find_by_path(&components, &patch.name).is_some() {
+            continue;
+        }
+        let name = patch.name.as_str();
+        if !component::is_valid_name(name) {
+            eprintln!(
+                "[template] cannot create component '{}': names must match [a-zA-Z0-9][a-zA-Z0-9-]*",
+                name
+            );
+            continue;
+        }
+        let creatable = configs.get(name).is_some_and(|c| c.creatable)
+            || allowed.iter().any(|pattern| match pattern.strip_suffix('*') {
+                Some(prefix) => name.starts_with(prefix),
+                None => pattern == name,
+            });
+        if !creatable {
+            eprintln!(
+                "[template] patch '{}' ({}) would create a component, but '{}' is not in agent_doc_creatable",
+                name, placement, name
+            );
+            continue;
+        }
+        let mut offset = match placement_offset(&result, &components, placement) {
+            Ok(offset) => offset,
+            Err(e) => {
+                eprintln!("[template] cannot create component '{}' ({}): {:#}", name, placement, e);
+                continue;
+            }
+        };
+        // At the end of a heading section, keep the blank line before the next heading.
+        let next_is_heading = result[offset..].starts_with('#');
+        if next_is_heading && result[..offset].ends_with("\n\n") {
+            offset -= 1;
+        }
+        let mut block = String::new();
+        if offset > 0 && !result[..offset].ends_with('\n') {
+            block.push('\n');
+        }
+        block.push_str(&format!("<!-- agent:{} -->\n<!-- /agent:{} -->\n", name, name));
+        result.insert_str(offset, &block);
+        eprintln!("[template] created component '{}' ({})", name, placement);
+    }
     Ok(result)
 }
 
+/// Byte offset where a component with this placement is inserted.
+fn placement_offset(doc: &str, components: &[component::Component], placement: &Placement) -> Result<usize> {
+    match placement {
+        Placement::After(path) => component::find_by_path(components, path)
+            .map(|c| c.close_end)
+            .with_context(|| format!("component '{}' not found", path)),
+        Placement::Before(path) => component::find_by_path(components, path)
+            .map(|c| c.open_start)
+            .with_context(|| format!("component '{}' not found", path)),
+        Placement::Under(heading) => section_end(doc, components, heading),
+    }
+}
+
+/// Markdown heading level and title of a line (`## Risks` is `(2, "Risks")`).
+fn heading(line: &str) -> Option<(usize, &str)> {
+    let level = line.chars().take_while(|&c| c == '#').count();
+    let title = &line[level..];
+    (level > 0 && (title.is_empty() || title.starts_with([' ', '\t']))).then_some((level, title.trim()))
+}
+
+/// End of the section under `wanted` (`"## Risks"`, or just `"Risks"` for any
+/// level): the next heading of the same or a higher level, the end of the
+/// component the heading is in, or the end of the document. The offset is
+/// moved out of any component that starts inside the section.
+fn section_end(doc: &str, components: &[component::Component], wanted: &str) -> Result<usize> {
+    let wanted_level = heading(wanted.trim()).map(|(level, _)| level);
+    let wanted_title = heading(wanted.trim()).map_or(wanted.trim(), |(_, title)| title);
+
+    // Skip the frontmatter, where `#` starts a YAML comment
+    let body = crate::frontmatter::parse(doc).map_or(doc, |(_, body)| body);
+    let mut offset = doc.len() - body.len();
+    let mut found: Option<(usize, usize)> = None;
+    let mut end = doc.len();
+    for line in body.split_inclusive('\n') {
+        if let Some((level, title)) = heading(line) {
+            match found {
+                None if title == wanted_title && wanted_level.is_none_or(|l| l == level) => {
+                    found = Some((offset, level));
+                }
+                Some((_, found_level)) if level <= found_level => {
+                    end = offset;
+                    break;
+                }
+                _ => {}
+            }
+        }
+        offset += line.len();
+    }
+    let Some((start, _)) = found else {
+        bail!("heading {:?} not found", wanted);
+    };
+
+    let contains = |c: &component::Component, at: usize| c.open_end <= at && at <= c.close_start;
+    if let Some(enclosing) = components.iter().rev().find(|c| contains(c, start)) {
+        end = end.min(enclosing.close_start);
+    }
+    if let Some(inner) = components.iter().find(|c| c.open_start < end && end < c.close_end && !contains(c, start)) {
+        end = inner.open_start;
+    }
+    Ok(end)
+}
+
+/// Compute a component's new content for a patch.
+///
+/// Operations other than `PatchOp::Mode` edit the existing content directly.
+/// Otherwise mode overrides take precedence over marker attributes, config and defaults.
+fn resolve_patch(
+    doc: &str,
+    comp: &component::Component,
+    patch: &PatchBlock,
+    configs: &std::collections::HashMap<String, patch::ComponentConfig>,
+    mode_overrides: &std::collections::HashMap<String, String>,
+) -> Result<String> {
+    let existing = comp.content(doc);
+    if patch.op != PatchOp::Mode {
+        return patch_ops::apply(&patch.op, existing, &patch.content);
+    }
+    let config = patch::config_for(configs, &patch.name, comp)?;
+    Ok(match mode_overrides.get(&patch.name).or_else(|| mode_overrides.get(&comp.name)) {
+        Some(mode) => apply_mode(mode, existing, &patch.content, config.key.as_deref())?,
+        None => patch::apply_config(&config, default_mode(&comp.name), existing, &patch.content)?,
+    })
+}
+
+/// Full new content of the component targeted by an operation patch or a
+/// table- or checklist-mode component (other patches return `None`; their content is sent as-is).
+///
+/// Used by IPC, where the plugin only knows replace/append/prepend.
+pub fn resolved_content(doc: &str, patch: &PatchBlock, file: &Path) -> Result<Option<String>> {
+    let components = component::parse(doc).context("failed to parse components")?;
+    if patch.op == PatchOp::Mode {
+        let Some(comp) = component::find_by_path(&components, &patch.name) else {
+            return Ok(None);
+        };
+        let configs = patch::load_configs(file).unwrap_or_default();
+        let config = patch::config_for(&configs, &patch.name, comp)?;
+        let Some(mode) = config.mode.clone().filter(|m| m == "table" || m == "checklist") else {
+            return Ok(None);
+        };
+        return patch::apply_config(&config, &mode, comp.content(doc), &patch.content)
+            .with_context(|| format!("patch '{}' ({})", patch.name, mode))
+            .map(Some);
+    }
+    let comp = component::find_by_path(&components, &patch.name)
+        .with_context(|| format!("patch target '{}' not found", patch.name))?;
+    patch_ops::apply(&patch.op, comp.content(doc), &patch.content)
+        .with_context(|| format!("patch '{}' ({})", patch.name, patch.op))
+        .map(Some)
+}
+
+/// Full new content of the component a patch targets, with its operation or
+/// mode applied as a direct write would (`None` if the component doesn't exist).
+pub fn component_content(doc: &str, patch: &PatchBlock, file: &Path) -> Result<Option<String>> {
+    let components = component::parse(doc).context("failed to parse components")?;
+    let Some(comp) = component::find_by_path(&components, &patch.name) else {
+        return Ok(None);
+    };
+    let configs = patch::load_configs(file).unwrap_or_default();
+    resolve_patch(doc, comp, patch, &configs, &std::collections::HashMap::new())
+        .with_context(|| format!("patch '{}' ({})", patch.name, patch.op))
+        .map(Some)
+}
+
 /// Get template info for a document (for plugin rendering).
 pub fn template_info(file: &Path) -> Result<TemplateInfo> {
     let doc = std::fs::read_to_string(file)
@@ -247,27 +632,13 @@ pub fn template_info(file: &Path) -> Result<TemplateInfo> {
     let (fm, _body) = crate::frontmatter::parse(&doc)?;
     let template_mode = fm.resolve_mode().is_template();
 
-    let components = component::parse(&doc)
+    let tree = component::parse_tree(&doc)
         .with_context(|| format!("failed to parse components in {}", file.display()))?;
 
-    let configs = load_component_configs(file);
+    let configs = patch::load_configs(file).unwrap_or_default();
 
-    let component_infos: Vec<ComponentInfo> = components
-        .iter()
-        .map(|comp| {
-            let content = comp.content(&doc).to_string();
-            let mode = configs.get(&comp.name).cloned().unwrap_or_else(|| default_mode(&comp.name).to_string());
-            // Compute line number from byte offset
-            let line = doc[..comp.open_start].matches('\n').count() + 1;
-            ComponentInfo {
-                name: comp.name.clone(),
-                mode,
-                content,
-                line,
-                max_entries: None, // TODO: read from components.toml
-            }
-        })
-        .collect();
+    let mut component_infos = Vec::new();
+    collect_component_infos(&doc, &tree, None, 0, &configs, &mut component_infos)?;
 
     Ok(TemplateInfo {
         template_mode,
@@ -275,25 +646,36 @@ pub fn template_info(file: &Path) -> Result<TemplateInfo> {
     })
 }
 
-/// Load component mode configs from `.agent-doc/components.toml`.
-/// Returns a map of component_name → mode string.
-fn load_component_configs(file: &Path) -> std::collections::HashMap<String, String> {
-    let mut result = std::collections::HashMap::new();
-    let root = find_project_root(file);
-    if let Some(root) = root {
-        let config_path = root.join(".agent-doc/components.toml");
-        if config_path.exists()
-            && let Ok(content) = std::fs::read_to_string(&config_path)
-            && let Ok(table) = content.parse::<toml::Table>()
-        {
-            for (name, value) in &table {
-                if let Some(mode) = value.get("mode").and_then(|v| v.as_str()) {
-                    result.insert(name.clone(), mode.to_string());
-                }
-            }
-        }
+/// Flatten a component tree into `out` in document order.
+fn collect_component_infos(
+    doc: &str,
+    nodes: &[component::ComponentNode],
+    parent: Option<&str>,
+    depth: usize,
+    configs: &std::collections::HashMap<String, patch::ComponentConfig>,
+    out: &mut Vec<ComponentInfo>,
+) -> Result<()> {
+    for node in nodes {
+        let comp = &node.component;
+        let config = patch::config_for(configs, &node.path, comp)?;
+        // Compute line number from byte offset
+        let line = doc[..comp.open_start].matches('\n').count() + 1;
+        out.push(ComponentInfo {
+            name: comp.name.clone(),
+            path: node.path.clone(),
+            parent: parent.map(str::to_string),
+            depth,
+            mode: config.mode.unwrap_or_else(|| default_mode(&comp.name).to_string()),
+            content: comp.content(doc).to_string(),
+            line,
+            max_entries: (config.max_entries > 0).then_some(config.max_entries),
+            timestamp: config.timestamp,
+            owner: config.owner,
+            attrs: comp.attrs.clone(),
+        });
+        collect_component_infos(doc, &node.children, Some(&node.path), depth + 1, configs, out)?;
     }
-    result
+    Ok(())
 }
 
 /// Default mode for a component by name.
@@ -302,24 +684,15 @@ fn default_mode(name: &str) -> &'static str {
     if name == "exchange" { "append" } else { "replace" }
 }
 
-/// Apply mode logic (replace/append/prepend).
-fn apply_mode(mode: &str, existing: &str, new_content: &str) -> String {
-    match mode {
+/// Apply mode logic (replace/append/prepend/checklist/table, keyed on `key` or the first column).
+fn apply_mode(mode: &str, existing: &str, new_content: &str, key: Option<&str>) -> Result<String> {
+    Ok(match mode {
         "append" => format!("{}{}", existing, new_content),
         "prepend" => format!("{}{}", new_content, existing),
+        "table" => crate::table::upsert(existing, new_content, key)?,
+        "checklist" => crate::checklist::apply(existing, new_content),
         _ => new_content.to_string(), // "replace" default
-    }
-}
-
-fn find_project_root(file: &Path) -> Option<std::path::PathBuf> {
-    let canonical = file.canonicalize().ok()?;
-    let mut dir = canonical.parent()?;
-    loop {
-        if dir.join(".agent-doc").is_dir() {
-            return Some(dir.to_path_buf());
-        }
-        dir = dir.parent()?;
-    }
+    })
 }
 
 fn find_comment_end(bytes: &[u8], start: usize) -> Option<usize> {
@@ -385,6 +758,51 @@ All green.
         assert!(unmatched.contains("Trailing text."));
     }
 
+    #[test]
+    fn parse_patch_with_op() {
+        let response = "<!-- patch:plan op=insert-after anchor=\"## Next\" -->\n- step\n<!-- /patch:plan -->\n";
+        let (patches, unmatched) = parse_patches(response).unwrap();
+        assert_eq!(patches.len(), 1);
+        assert_eq!(patches[0].name, "plan");
+        assert_eq!(patches[0].op, PatchOp::InsertAfter { anchor: "## Next".to_string() });
+        assert_eq!(patches[0].content, "- step\n");
+        assert!(unmatched.is_empty());
+    }
+
+    #[test]
+    fn parse_patch_with_unknown_op_is_unmatched() {
+        let response = "Intro\n<!-- patch:plan op=shuffle -->\nx\n<!-- /patch:plan -->\n<!-- patch:status -->\nok\n<!-- /patch:status -->\n";
+        let (patches, unmatched, invalid) = parse_patches_checked(response);
+        assert_eq!(patches.len(), 1);
+        assert_eq!(patches[0].name, "status");
+        assert_eq!(unmatched, "Intro\nx");
+        assert_eq!(invalid.len(), 1);
+        assert_eq!(invalid[0].offset, 6);
+        assert!(invalid[0].error.contains("'plan'"));
+        assert!(invalid[0].error.contains("unknown op 'shuffle'"));
+
+        let (patches, unmatched) = parse_patches(response).unwrap();
+        assert_eq!(patches.len(), 1);
+        assert_eq!(unmatched, "Intro\nx");
+    }
+
+    #[test]
+    fn parse_strips_run_attrs_from_patch_content() {
+        let response = "<!-- patch:plan -->\n<!-- agent:x run=\"rm -rf ~\" -->\n<!-- /agent:x -->\n<!-- /patch:plan -->\n<!-- agent:y run=\"ls\" -->\n<!-- /agent:y -->\n";
+        let (patches, unmatched) = parse_patches(response).unwrap();
+        assert_eq!(patches[0].content, "<!-- agent:x -->\n<!-- /agent:x -->\n");
+        assert_eq!(unmatched, "<!-- agent:y -->\n<!-- /agent:y -->");
+    }
+
+    #[test]
+    fn parse_replace_without_pattern_is_invalid() {
+        let response = "<!-- patch:plan op=replace -->\nx\n<!-- /patch:plan -->\n";
+        let (patches, unmatched, invalid) = parse_patches_checked(response);
+        assert!(patches.is_empty());
+        assert_eq!(unmatched, "x");
+        assert!(invalid[0].error.contains("needs pattern"));
+    }
+
     #[test]
     fn parse_empty_response() {
         let (patches, unmatched) = parse_patches("").unwrap();
@@ -410,6 +828,8 @@ All green.
         let patches = vec![PatchBlock {
             name: "status".to_string(),
             content: "new\n".to_string(),
+            op: PatchOp::Mode,
+            placement: None,
         }];
         let result = apply_patches(doc, &patches, "", &doc_path).unwrap();
         assert!(result.contains("new\n"));
@@ -454,6 +874,8 @@ All green.
         let patches = vec![PatchBlock {
             name: "nonexistent".to_string(),
             content: "overflow data\n".to_string(),
+            op: PatchOp::Mode,
+            placement: None,
         }];
         let result = apply_patches(doc, &patches, "", &doc_path).unwrap();
         // Missing component content should be routed to exchange
@@ -471,6 +893,8 @@ All green.
         let patches = vec![PatchBlock {
             name: "nonexistent".to_string(),
             content: "overflow data\n".to_string(),
+            op: PatchOp::Mode,
+            placement: None,
         }];
         let result = apply_patches(doc, &patches, "", &doc_path).unwrap();
         // Should auto-create exchange component
@@ -479,10 +903,314 @@ All green.
     }
 
     #[test]
-    fn is_template_mode_detection() {
-        assert!(is_template_mode(Some("template")));
-        assert!(!is_template_mode(Some("append")));
-        assert!(!is_template_mode(None));
+    fn apply_patches_nested_path() {
+        let dir = setup_project();
+        let doc_path = dir.path().join("test.md");
+        let doc = "<!-- agent:plan -->\nIntro\n<!-- agent:plan-step-1 -->\ntodo\n<!-- /agent:plan-step-1 -->\n<!-- /agent:plan -->\n";
+        std::fs::write(&doc_path, doc).unwrap();
+
+        let patches = vec![PatchBlock {
+            name: "plan/step-1".to_string(),
+            content: "done\n".to_string(),
+            op: PatchOp::Mode,
+            placement: None,
+        }];
+        let result = apply_patches(doc, &patches, "", &doc_path).unwrap();
+        assert_eq!(
+            result,
+            "<!-- agent:plan -->\nIntro\n<!-- agent:plan-step-1 -->\ndone\n<!-- /agent:plan-step-1 -->\n<!-- /agent:plan -->\n"
+        );
+    }
+
+    #[test]
+    fn apply_patches_parent_then_child() {
+        let dir = setup_project();
+        let doc_path = dir.path().join("test.md");
+        let doc = "<!-- agent:plan -->\n<!-- agent:step -->\nold\n<!-- /agent:step -->\n<!-- /agent:plan -->\n";
+        std::fs::write(&doc_path, doc).unwrap();
+
+        // Child listed first: still applied after the parent's replacement.
+        let patches = vec![
+            PatchBlock { name: "plan/step".to_string(), content: "child\n".to_string(), op: PatchOp::Mode, placement: None },
+            PatchBlock {
+                name: "plan".to_string(),
+                content: "New intro\n<!-- agent:step -->\n<!-- /agent:step -->\n".to_string(),
+                op: PatchOp::Mode,
+                placement: None,
+            },
+        ];
+        let result = apply_patches(doc, &patches, "", &doc_path).unwrap();
+        assert_eq!(
+            result,
+            "<!-- agent:plan -->\nNew intro\n<!-- agent:step -->\nchild\n<!-- /agent:step -->\n<!-- /agent:plan -->\n"
+        );
+    }
+
+    #[test]
+    fn apply_patches_child_removed_by_parent_routes_to_exchange() {
+        let dir = setup_project();
+        let doc_path = dir.path().join("test.md");
+        let doc = "<!-- agent:plan -->\n<!-- agent:step -->\nold\n<!-- /agent:step -->\n<!-- /agent:plan -->\n";
+        std::fs::write(&doc_path, doc).unwrap();
+
+        let patches = vec![
+            PatchBlock { name: "plan".to_string(), content: "flat\n".to_string(), op: PatchOp::Mode, placement: None },
+            PatchBlock { name: "plan/step".to_string(), content: "orphaned\n".to_string(), op: PatchOp::Mode, placement: None },
+        ];
+        let result = apply_patches(doc, &patches, "", &doc_path).unwrap();
+        assert!(result.starts_with("<!-- agent:plan -->\nflat\n<!-- /agent:plan -->\n"));
+        assert!(result.contains("<!-- agent:exchange -->\norphaned"));
+    }
+
+    #[test]
+    fn template_info_shows_hierarchy() {
+        let dir = setup_project();
+        let doc_path = dir.path().join("test.md");
+        let doc = "<!-- agent:plan -->\n<!-- agent:plan-step-1 -->\none\n<!-- /agent:plan-step-1 -->\n<!-- /agent:plan -->\n<!-- agent:status -->\nok\n<!-- /agent:status -->\n";
+        std::fs::write(&doc_path, doc).unwrap();
+
+        let info = template_info(&doc_path).unwrap();
+        let rows: Vec<(&str, Option<&str>, usize)> = info
+            .components
+            .iter()
+            .map(|c| (c.path.as_str(), c.parent.as_deref(), c.depth))
+            .collect();
+        assert_eq!(
+            rows,
+            vec![
+                ("plan", None, 0),
+                ("plan/plan-step-1", Some("plan"), 1),
+                ("status", None, 0),
+            ]
+        );
+    }
+
+    #[test]
+    fn apply_patches_honors_inline_attributes() {
+        let dir = setup_project();
+        std::fs::write(dir.path().join(".agent-doc/components.toml"), "[log]\nmode = \"replace\"\n").unwrap();
+        let doc_path = dir.path().join("test.md");
+        let doc = "<!-- agent:log mode=append max_entries=2 -->\na\nb\n<!-- /agent:log -->\n";
+        std::fs::write(&doc_path, doc).unwrap();
+
+        let patches = vec![PatchBlock { name: "log".to_string(), content: "c\n".to_string(), op: PatchOp::Mode, placement: None }];
+        let result = apply_patches(doc, &patches, "", &doc_path).unwrap();
+        assert_eq!(result, "<!-- agent:log mode=append max_entries=2 -->\nb\nc\n<!-- /agent:log -->\n");
+    }
+
+    #[test]
+    fn apply_patches_with_ops() {
+        let dir = setup_project();
+        let doc_path = dir.path().join("test.md");
+        let doc = "<!-- agent:plan -->\n## Done\n- a\n## Next\n- b\n<!-- /agent:plan -->\n";
+        std::fs::write(&doc_path, doc).unwrap();
+
+        let response = "\
+<!-- patch:plan op=delete anchor=\"- a\" -->
+<!-- /patch:plan -->
+<!-- patch:plan op=insert-after anchor=\"## Next\" -->
+- c
+<!-- /patch:plan -->
+";
+        let (patches, unmatched) = parse_patches(response).unwrap();
+        let result = apply_patches(doc, &patches, &unmatched, &doc_path).unwrap();
+        assert_eq!(result, "<!-- agent:plan -->\n## Done\n## Next\n- c\n- b\n<!-- /agent:plan -->\n");
+    }
+
+    #[test]
+    fn apply_patches_failed_op_routes_to_exchange() {
+        let dir = setup_project();
+        let doc_path = dir.path().join("test.md");
+        let doc = "<!-- agent:plan -->\n- a\n<!-- /agent:plan -->\n";
+        std::fs::write(&doc_path, doc).unwrap();
+
+        let patches = vec![PatchBlock {
+            name: "plan".to_string(),
+            content: "- lost\n".to_string(),
+            op: PatchOp::InsertBefore { anchor: "## Missing".to_string() },
+            placement: None,
+        }];
+        let result = apply_patches(doc, &patches, "", &doc_path).unwrap();
+        assert!(result.starts_with("<!-- agent:plan -->\n- a\n<!-- /agent:plan -->\n"));
+        assert!(result.contains("<!-- agent:exchange -->\n- lost"));
+    }
+
+    #[test]
+    fn apply_patches_table_mode() {
+        let dir = setup_project();
+        let doc_path = dir.path().join("test.md");
+        let doc = "<!-- agent:tasks mode=table key=id -->\n| id | task | status |\n|----|------|--------|\n| 1  | spec | todo   |\n| 2  | impl | todo   |\n<!-- /agent:tasks -->\n";
+        std::fs::write(&doc_path, doc).unwrap();
+
+        let response = "<!-- patch:tasks -->\n| 1 | spec | done |\n| ~~2~~ |\n| 3 | docs | todo |\n<!-- /patch:tasks -->\n";
+        let (patches, unmatched) = parse_patches(response).unwrap();
+        let result = apply_patches(doc, &patches, &unmatched, &doc_path).unwrap();
+        assert_eq!(
+            result,
+            "<!-- agent:tasks mode=table key=id -->\n| id | task | status |\n|----|------|--------|\n| 1  | spec | done   |\n| 3  | docs | todo   |\n<!-- /agent:tasks -->\n"
+        );
+    }
+
+    #[test]
+    fn table_override_uses_configured_key() {
+        let dir = setup_project();
+        let doc_path = dir.path().join("test.md");
+        let doc = "<!-- agent:tasks key=id -->\n| task | id |\n|------|----|\n| spec | 1  |\n<!-- /agent:tasks -->\n";
+        std::fs::write(&doc_path, doc).unwrap();
+
+        let response = "<!-- patch:tasks -->\n| done | 1 |\n<!-- /patch:tasks -->\n";
+        let (patches, unmatched) = parse_patches(response).unwrap();
+        let overrides = std::collections::HashMap::from([("tasks".to_string(), "table".to_string())]);
+        let result = apply_patches_with_overrides(doc, &patches, &unmatched, &doc_path, &overrides).unwrap();
+        assert_eq!(
+            result,
+            "<!-- agent:tasks key=id -->\n| task | id |\n|------|----|\n| done | 1  |\n<!-- /agent:tasks -->\n"
+        );
+    }
+
+    #[test]
+    fn apply_patches_checklist_mode() {
+        let dir = setup_project();
+        let doc_path = dir.path().join("test.md");
+        let doc = "<!-- agent:todo mode=checklist -->\n- [x] spec\n- [ ] impl\n<!-- /agent:todo -->\n";
+        std::fs::write(&doc_path, doc).unwrap();
+
+        let response = "<!-- patch:todo -->\n- [ ] spec\n- [ ] docs\n<!-- /patch:todo -->\n";
+        let (patches, unmatched) = parse_patches(response).unwrap();
+        let result = apply_patches(doc, &patches, &unmatched, &doc_path).unwrap();
+        assert_eq!(
+            result,
+            "<!-- agent:todo mode=checklist -->\n- [x] spec\n- [ ] ~~impl~~\n- [ ] docs\n<!-- /agent:todo -->\n"
+        );
+        assert_eq!(
+            resolved_content(doc, &patches[0], &doc_path).unwrap().as_deref(),
+            Some("- [x] spec\n- [ ] ~~impl~~\n- [ ] docs\n")
+        );
+    }
+
+    #[test]
+    fn resolved_content_for_ipc() {
+        let doc = "<!-- agent:plan -->\n- a\n<!-- /agent:plan -->\n";
+        let mode = PatchBlock { name: "plan".to_string(), content: "x\n".to_string(), op: PatchOp::Mode, placement: None };
+        let dir = setup_project();
+        let doc_path = dir.path().join("test.md");
+        assert_eq!(resolved_content(doc, &mode, &doc_path).unwrap(), None);
+
+        let op = PatchBlock {
+            name: "plan".to_string(),
+            content: "- b\n".to_string(),
+            op: PatchOp::InsertAfter { anchor: "- a".to_string() },
+            placement: None,
+        };
+        assert_eq!(resolved_content(doc, &op, &doc_path).unwrap().as_deref(), Some("- a\n- b\n"));
+    }
+
+    #[test]
+    fn apply_patches_rejects_user_owned() {
+        let dir = setup_project();
+        std::fs::write(dir.path().join(".agent-doc/components.toml"), "[requirements]\nowner = \"user\"\n").unwrap();
+        let doc_path = dir.path().join("test.md");
+        let doc = "\
+<!-- agent:requirements -->
+Must be fast.
+<!-- /agent:requirements -->
+<!-- agent:plan -->
+<!-- agent:spec owner=user -->
+Hand-written.
+<!-- /agent:spec -->
+<!-- /agent:plan -->
+<!-- agent:status -->
+old
+<!-- /agent:status -->
+";
+        std::fs::write(&doc_path, doc).unwrap();
+
+        let patches: Vec<PatchBlock> = ["requirements", "plan", "spec", "status"]
+            .iter()
+            .map(|name| PatchBlock { name: name.to_string(), content: "agent\n".to_string(), op: PatchOp::Mode, placement: None })
+            .collect();
+        let rejected = rejected_patches(doc, &patches, &doc_path).unwrap();
+        let reasons: Vec<(&str, &str)> = rejected.iter().map(|r| (r.name.as_str(), r.reason.as_str())).collect();
+        assert_eq!(
+            reasons,
+            vec![
+                ("requirements", "'requirements' is user-owned"),
+                ("plan", "it contains user-owned 'spec'"),
+                ("spec", "'spec' is user-owned"),
+            ]
+        );
+
+        let result = apply_patches(doc, &patches, "", &doc_path).unwrap();
+        assert_eq!(result, doc.replace("<!-- agent:status -->\nold\n", "<!-- agent:status -->\nagent\n"));
+    }
+
+    #[test]
+    fn apply_patches_creates_component_after_anchor() {
+        let dir = setup_project();
+        let doc_path = dir.path().join("test.md");
+        let doc = "---\nagent_doc_creatable: [risks, step-*]\n---\n<!-- agent:plan -->\nplan\n<!-- /agent:plan -->\n<!-- agent:exchange -->\n<!-- /agent:exchange -->\n";
+        std::fs::write(&doc_path, doc).unwrap();
+
+        let response = "<!-- patch:risks after=plan -->\n- scope creep\n<!-- /patch:risks -->\n";
+        let (patches, unmatched) = parse_patches(response).unwrap();
+        assert_eq!(patches[0].placement, Some(Placement::After("plan".to_string())));
+        let result = apply_patches(doc, &patches, &unmatched, &doc_path).unwrap();
+        assert!(result.contains(
+            "<!-- /agent:plan -->\n<!-- agent:risks -->\n- scope creep\n<!-- /agent:risks -->\n<!-- agent:exchange -->"
+        ));
+    }
+
+    #[test]
+    fn apply_patches_creates_component_under_heading() {
+        let dir = setup_project();
+        let doc_path = dir.path().join("test.md");
+        let doc = "---\nagent_doc_creatable: [step-*]\n---\n# Plan\n\n## Steps\n\nIntro.\n\n## Notes\n\nnone\n";
+        std::fs::write(&doc_path, doc).unwrap();
+
+        let response = "<!-- patch:step-1 under=\"## Steps\" -->\nfirst\n<!-- /patch:step-1 -->\n";
+        let (patches, unmatched) = parse_patches(response).unwrap();
+        let result = apply_patches(doc, &patches, &unmatched, &doc_path).unwrap();
+        assert!(result.ends_with(
+            "## Steps\n\nIntro.\n<!-- agent:step-1 -->\nfirst\n<!-- /agent:step-1 -->\n\n## Notes\n\nnone\n"
+        ));
+    }
+
+    #[test]
+    fn apply_patches_creation_needs_allow_list() {
+        let dir = setup_project();
+        std::fs::write(dir.path().join(".agent-doc/components.toml"), "[summary]\ncreatable = true\n").unwrap();
+        let doc_path = dir.path().join("test.md");
+        let doc = "<!-- agent:status -->\nok\n<!-- /agent:status -->\n";
+        std::fs::write(&doc_path, doc).unwrap();
+
+        let response = "\
+<!-- patch:summary before=status -->
+short
+<!-- /patch:summary -->
+<!-- patch:secrets after=status -->
+leak
+<!-- /patch:secrets -->
+";
+        let (patches, unmatched) = parse_patches(response).unwrap();
+        let result = apply_patches(doc, &patches, &unmatched, &doc_path).unwrap();
+        assert!(result.starts_with("<!-- agent:summary -->\nshort\n<!-- /agent:summary -->\n<!-- agent:status -->"));
+        assert!(!result.contains("<!-- agent:secrets -->"));
+        assert!(result.contains("<!-- agent:exchange -->\nleak"));
+    }
+
+    #[test]
+    fn template_info_reports_attributes() {
+        let dir = setup_project();
+        let doc_path = dir.path().join("test.md");
+        let doc = "<!-- agent:log mode=prepend max_entries=20 timestamp -->\n<!-- /agent:log -->\n";
+        std::fs::write(&doc_path, doc).unwrap();
+
+        let info = template_info(&doc_path).unwrap();
+        let log = &info.components[0];
+        assert_eq!(log.mode, "prepend");
+        assert_eq!(log.max_entries, Some(20));
+        assert!(log.timestamp);
+        assert_eq!(log.attrs.get("mode").map(String::as_str), Some("prepend"));
     }
 
     #[test]
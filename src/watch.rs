@@ -7,7 +7,9 @@ use std::time::{Duration, Instant};
 
 use notify::{EventKind, RecursiveMode, Watcher};
 
-use crate::{config::Config, frontmatter, sessions, stream, submit};
+use crate::hooks::{self, Event};
+use crate::config::{Config, Layers};
+use crate::{frontmatter, sessions, stream, submit};
 
 const PID_FILE: &str = ".agent-doc/watch.pid";
 
@@ -16,7 +18,8 @@ const IDLE_TIMEOUT_SECS: u64 = 60;
 
 /// Configuration for the watch daemon.
 pub struct WatchConfig {
-    pub debounce_ms: u64,
+    /// `None` uses `watch_debounce_ms` from the watched project's config.
+    pub debounce_ms: Option<u64>,
     pub max_cycles: u32,
 }
 
@@ -73,7 +76,7 @@ fn hash_content(path: &Path) -> Option<u64> {
 }
 
 /// Check if a PID is alive via /proc.
-fn pid_alive(pid: u32) -> bool {
+pub(crate) fn pid_alive(pid: u32) -> bool {
     Path::new(&format!("/proc/{}", pid)).exists()
 }
 
@@ -157,13 +160,16 @@ pub fn ensure_running() -> Result<bool> {
 pub fn start(config: &Config, watch_config: WatchConfig) -> Result<()> {
     // Resolve project root and cd there (critical for finding .agent-doc/)
     let cwd = std::env::current_dir().unwrap_or_default();
-    if let Some(root) = find_project_root(&cwd)
-        && root != cwd
-    {
+    let root = find_project_root(&cwd).unwrap_or_else(|| cwd.clone());
+    if root != cwd {
         std::env::set_current_dir(&root)
             .with_context(|| format!("failed to cd to project root {}", root.display()))?;
         eprintln!("Resolved project root: {}", root.display());
     }
+    let debounce_ms = match watch_config.debounce_ms {
+        Some(ms) => ms,
+        None => Layers::for_path(config, &root)?.watch_debounce_ms(),
+    };
 
     // Check if already running
     if let Some(pid) = read_pid() {
@@ -186,7 +192,7 @@ pub fn start(config: &Config, watch_config: WatchConfig) -> Result<()> {
         });
     }
 
-    let result = run_event_loop(config, &watch_config, &running);
+    let result = run_event_loop(config, &watch_config, Duration::from_millis(debounce_ms), &running);
 
     remove_pid();
     eprintln!("Watch daemon stopped.");
@@ -212,9 +218,9 @@ fn signal_wait() {
 fn run_event_loop(
     config: &Config,
     watch_config: &WatchConfig,
+    debounce: Duration,
     running: &std::sync::atomic::AtomicBool,
 ) -> Result<()> {
-    let debounce = Duration::from_millis(watch_config.debounce_ms);
     let idle_timeout = Duration::from_secs(IDLE_TIMEOUT_SECS);
     let (tx, rx) = mpsc::channel();
 
@@ -359,7 +365,8 @@ fn run_event_loop(
                 }
             }
 
-            // Prune dead stream entries (pane no longer alive)
+            // Prune dead stream entries (pane no longer alive). A finished agent
+            // and a killed pane look the same here, so the hook gets "dead".
             let dead_streams: Vec<PathBuf> = stream_states
                 .iter()
                 .filter(|(_, ss)| !tmux.pane_alive(&ss.pane))
@@ -367,7 +374,15 @@ fn run_event_loop(
                 .collect();
             for path in dead_streams {
                 eprintln!("Stream pane dead for {} — removing", path.display());
-                stream_states.remove(&path);
+                if let Some(ss) = stream_states.remove(&path)
+                    && let Err(e) = hooks::fire(&path, Event::OnStreamComplete, serde_json::json!({
+                        "source": "watch",
+                        "status": "dead",
+                        "target": ss.target,
+                    }))
+                {
+                    eprintln!("[watch-stream] {}", e);
+                }
             }
 
             last_rescan = Instant::now();
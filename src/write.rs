@@ -11,7 +11,8 @@ use std::fs::OpenOptions;
 use std::io::Read;
 use std::path::Path;
 
-use crate::{merge, recover, snapshot, template};
+use crate::{generate, merge, recover, snapshot, template};
+use crate::conflicts::RecordedMerge;
 use crate::snapshot::find_project_root;
 
 /// Run the write command: append assistant response to document.
@@ -34,7 +35,7 @@ pub fn run(file: &Path, baseline: Option<&str>) -> Result<()> {
     }
 
     // Strip leading "## Assistant" heading if present — the write command adds its own
-    let response = strip_assistant_heading(&response);
+    let response = crate::dynamic::strip_run_attrs(&strip_assistant_heading(&response));
 
     // Read document state before lock (for baseline)
     let content_at_start = std::fs::read_to_string(file)
@@ -57,6 +58,7 @@ pub fn run(file: &Path, baseline: Option<&str>) -> Result<()> {
         content_ours.push('\n');
     }
     content_ours.push_str("\n## User\n\n");
+    let content_ours = generate::regenerate(&content_ours, file);
 
     // Acquire advisory lock
     let doc_lock = acquire_doc_lock(file)?;
@@ -65,22 +67,23 @@ pub fn run(file: &Path, baseline: Option<&str>) -> Result<()> {
     let content_current = std::fs::read_to_string(file)
         .with_context(|| format!("failed to re-read {}", file.display()))?;
 
-    let final_content = if content_current == base {
+    let merged = if content_current == base {
         // No edits — use our version directly
-        content_ours.clone()
+        RecordedMerge::clean(content_ours.clone())
     } else {
         eprintln!("[write] File was modified during response generation. Merging...");
-        merge::merge_contents(base, &content_ours, &content_current)?
+        merge::merge_document(file, base, &content_ours, &content_current)?
     };
 
-    atomic_write(file, &final_content)?;
+    atomic_write(file, &merged.text)?;
 
-    // Save snapshot as content_ours (baseline + response), NOT final_content.
-    // If the user edited during response generation, final_content includes their
-    // edits via merge. Saving content_ours ensures the next diff detects those edits.
+    // Save snapshot as content_ours (baseline + response), NOT the merged text.
+    // If the user edited during response generation, the merge includes their
+    // edits. Saving content_ours ensures the next diff detects those edits.
     snapshot::save(file, &content_ours)?;
 
     drop(doc_lock);
+    merged.fire_hooks(file);
 
     // Clear pending response after successful write
     recover::clear_pending(file)?;
@@ -135,34 +138,37 @@ pub fn run_template(file: &Path, baseline: Option<&str>) -> Result<()> {
     let content_current = std::fs::read_to_string(file)
         .with_context(|| format!("failed to re-read {}", file.display()))?;
 
-    let final_content = if content_current == base {
-        content_ours.clone()
+    let merged = if content_current == base {
+        RecordedMerge::clean(content_ours.clone())
     } else {
         eprintln!("[write] File was modified during response generation. Merging...");
-        merge::merge_contents(base, &content_ours, &content_current)?
+        merge::merge_document(file, base, &content_ours, &content_current)?
     };
 
-    atomic_write(file, &final_content)?;
+    atomic_write(file, &merged.text)?;
 
-    // Save snapshot as content_ours (baseline + response), not final_content
+    // Save snapshot as content_ours (baseline + response), not the merged text
     snapshot::save(file, &content_ours)?;
 
     drop(doc_lock);
+    merged.fire_hooks(file);
 
     // Clear pending response after successful write
     recover::clear_pending(file)?;
 
+    let rejected = template::rejected_patches(base, &patches, file)?;
     eprintln!(
         "[write] Template patches applied to {} ({} components patched)",
         file.display(),
-        patches.len()
+        patches.len() - rejected.len()
     );
+    log_rejected(&rejected);
     Ok(())
 }
 
 /// Run the stream write command: template patches with CRDT merge (conflict-free).
 ///
-/// Like `run_template`, but uses CRDT merge instead of git merge-file.
+/// Like `run_template`, but uses CRDT merge instead of the three-way line merge.
 /// `baseline` is the document content at the time the response was generated.
 pub fn run_stream(file: &Path, baseline: Option<&str>) -> Result<()> {
     if !file.exists() {
@@ -175,15 +181,20 @@ pub fn run_stream(file: &Path, baseline: Option<&str>) -> Result<()> {
         .read_to_string(&mut response)
         .context("failed to read response from stdin")?;
 
+    write_stream(file, baseline, &response)
+}
+
+/// [`run_stream`] with the response already read.
+fn write_stream(file: &Path, baseline: Option<&str>, response: &str) -> Result<()> {
     if response.trim().is_empty() {
         anyhow::bail!("empty response — nothing to write");
     }
 
     // Save response to pending store (survives context compaction)
-    recover::save_pending(file, &response)?;
+    recover::save_pending(file, response)?;
 
     // Parse patch blocks from response
-    let (patches, unmatched) = template::parse_patches(&response)
+    let (patches, unmatched) = template::parse_patches(response)
         .context("failed to parse patch blocks from response")?;
 
     if patches.is_empty() && unmatched.trim().is_empty() {
@@ -213,38 +224,38 @@ pub fn run_stream(file: &Path, baseline: Option<&str>) -> Result<()> {
     let content_current = std::fs::read_to_string(file)
         .with_context(|| format!("failed to re-read {}", file.display()))?;
 
-    let (final_content, crdt_state) = if content_current == base {
-        // No edits — build CRDT state from result
-        let doc = crate::crdt::CrdtDoc::from_text(&content_ours);
-        (content_ours.clone(), doc.encode_state())
+    let merged = if content_current == base {
+        RecordedMerge::clean(content_ours.clone())
     } else {
         eprintln!("[write] File was modified during response generation. CRDT merging...");
-        eprintln!("[write] CRDT merge successful — no conflicts possible.");
         let crdt_state = snapshot::load_crdt(file)?;
-        merge::merge_contents_crdt(crdt_state.as_deref(), &content_ours, &content_current)?
+        merge::merge_document_crdt(file, crdt_state.as_deref(), &content_ours, &content_current)?
     };
 
-    atomic_write(file, &final_content)?;
+    atomic_write(file, &merged.text)?;
 
-    // Save snapshot as content_ours (baseline + response), not final_content.
-    // If the user edited concurrently, final_content includes their edits via CRDT merge.
+    // Save snapshot as content_ours (baseline + response), not the merged text.
+    // If the user edited concurrently, the CRDT merge includes their edits.
     // Saving content_ours ensures the next diff detects those concurrent edits.
     snapshot::save(file, &content_ours)?;
-    // Save the merged CRDT state — NOT a fresh state from content_ours.
+    // Record the merged content in the CRDT state — NOT content_ours.
     // Using content_ours would lose user edits from the merge, causing
     // the next merge cycle to re-insert them as duplicates.
-    snapshot::save_crdt(file, &crdt_state)?;
+    snapshot::save_crdt_text(file, &merged.text)?;
 
     drop(doc_lock);
+    merged.fire_hooks(file);
 
     // Clear pending response after successful write
     recover::clear_pending(file)?;
 
+    let rejected = template::rejected_patches(base, &patches, file)?;
     eprintln!(
         "[write] Stream patches applied to {} ({} components patched, CRDT)",
         file.display(),
-        patches.len()
+        patches.len() - rejected.len()
     );
+    log_rejected(&rejected);
     Ok(())
 }
 
@@ -280,6 +291,17 @@ pub fn run_ipc(file: &Path, baseline: Option<&str>) -> Result<()> {
         anyhow::bail!("no patch blocks or content found in response");
     }
 
+    // Editor replicas connected to `agent-doc serve` take the update directly
+    let frontmatter_patch = patches
+        .iter()
+        .find(|p| p.name == "frontmatter")
+        .map(|p| p.content.trim().to_string());
+    if try_sync_patches(file, &patches, &unmatched, frontmatter_patch.as_deref())? {
+        recover::clear_pending(file)?;
+        eprintln!("[write] Update delivered over sync socket — snapshot updated");
+        return Ok(());
+    }
+
     // Build IPC patch file
     let canonical = file.canonicalize()?;
     let hash = snapshot::doc_hash(file)?;
@@ -289,71 +311,29 @@ pub fn run_ipc(file: &Path, baseline: Option<&str>) -> Result<()> {
     std::fs::create_dir_all(&patches_dir)?;
     let patch_file = patches_dir.join(format!("{}.json", hash));
 
-    // Separate frontmatter patch from component patches
-    let mut frontmatter_yaml: Option<String> = None;
-    let ipc_patches: Vec<serde_json::Value> = patches
-        .iter()
-        .filter_map(|p| {
-            if p.name == "frontmatter" {
-                frontmatter_yaml = Some(p.content.trim().to_string());
-                None
-            } else {
-                Some(serde_json::json!({
-                    "component": p.name,
-                    "content": p.content,
-                }))
-            }
-        })
-        .collect();
-
-    let mut ipc_payload = serde_json::json!({
-        "file": canonical.to_string_lossy(),
-        "patches": ipc_patches,
-        "unmatched": unmatched.trim(),
-        "baseline": baseline.unwrap_or(""),
-    });
-
-    if let Some(ref yaml) = frontmatter_yaml {
-        ipc_payload["frontmatter"] = serde_json::Value::String(yaml.clone());
-    }
-
-    // Atomic write of patch file
-    atomic_write(
-        &patch_file,
-        &serde_json::to_string_pretty(&ipc_payload)?,
-    )?;
+    let doc = std::fs::read_to_string(file)
+        .with_context(|| format!("failed to read {}", file.display()))?;
 
-    eprintln!(
-        "[write] IPC patch written to {} ({} components)",
-        patch_file.display(),
-        patches.len()
-    );
+    if let Some((ipc_patches, rejected)) = ipc_component_patches(file, &doc, &patches)? {
+        let count = ipc_patches.len();
+        let mut ipc_payload = serde_json::json!({
+            "file": canonical.to_string_lossy(),
+            "patches": ipc_patches,
+            "unmatched": unmatched.trim(),
+            "baseline": baseline.unwrap_or(""),
+        });
 
-    // Poll for ACK (plugin deletes file after applying)
-    let timeout = std::time::Duration::from_secs(2);
-    let poll_interval = std::time::Duration::from_millis(100);
-    let start = std::time::Instant::now();
+        if let Some(ref yaml) = frontmatter_patch {
+            ipc_payload["frontmatter"] = serde_json::Value::String(yaml.clone());
+        }
 
-    while start.elapsed() < timeout {
-        if !patch_file.exists() {
-            // Plugin consumed the patch — update snapshot from current file
-            let content = std::fs::read_to_string(file)
-                .with_context(|| format!("failed to read {} after IPC", file.display()))?;
-            snapshot::save(file, &content)?;
-            let crdt_doc = crate::crdt::CrdtDoc::from_text(&content);
-            snapshot::save_crdt(file, &crdt_doc.encode_state())?;
+        if write_ipc_and_poll(&patch_file, &ipc_payload, file, count)? {
             recover::clear_pending(file)?;
-            eprintln!("[write] IPC patch consumed by plugin — snapshot updated");
+            log_rejected(&rejected);
             return Ok(());
         }
-        std::thread::sleep(poll_interval);
     }
 
-    // Timeout — fall back to direct stream write
-    eprintln!("[write] IPC timeout ({}s) — falling back to direct write", timeout.as_secs());
-    // Clean up the unconsumed patch file
-    let _ = std::fs::remove_file(&patch_file);
-
     // Fall back to stream write logic
     let content_at_start = std::fs::read_to_string(file)
         .with_context(|| format!("failed to read {}", file.display()))?;
@@ -362,31 +342,33 @@ pub fn run_ipc(file: &Path, baseline: Option<&str>) -> Result<()> {
         .context("failed to apply template patches")?;
 
     // Apply frontmatter patch if present
-    if let Some(ref yaml) = frontmatter_yaml {
+    if let Some(ref yaml) = frontmatter_patch {
         content_ours = crate::frontmatter::merge_fields(&content_ours, yaml)
             .context("failed to apply frontmatter patch")?;
     }
     let doc_lock = acquire_doc_lock(file)?;
     let content_current = std::fs::read_to_string(file)
         .with_context(|| format!("failed to re-read {}", file.display()))?;
-    let (final_content, crdt_state) = if content_current == base {
-        let doc = crate::crdt::CrdtDoc::from_text(&content_ours);
-        (content_ours.clone(), doc.encode_state())
+    let merged = if content_current == base {
+        RecordedMerge::clean(content_ours.clone())
     } else {
         eprintln!("[write] File was modified during response generation. CRDT merging...");
         let crdt_state = snapshot::load_crdt(file)?;
-        merge::merge_contents_crdt(crdt_state.as_deref(), &content_ours, &content_current)?
+        merge::merge_document_crdt(file, crdt_state.as_deref(), &content_ours, &content_current)?
     };
-    atomic_write(file, &final_content)?;
+    atomic_write(file, &merged.text)?;
     snapshot::save(file, &content_ours)?;
-    snapshot::save_crdt(file, &crdt_state)?;
+    snapshot::save_crdt_text(file, &merged.text)?;
     drop(doc_lock);
+    merged.fire_hooks(file);
     recover::clear_pending(file)?;
+    let rejected = template::rejected_patches(base, &patches, file)?;
     eprintln!(
         "[write] Stream patches applied to {} ({} components patched, CRDT fallback)",
         file.display(),
-        patches.len()
+        patches.len() - rejected.len()
     );
+    log_rejected(&rejected);
     Ok(())
 }
 
@@ -408,19 +390,19 @@ pub fn apply_stream_from_string(file: &Path, response: &str) -> Result<()> {
     let content_current = std::fs::read_to_string(file)
         .with_context(|| format!("failed to re-read {}", file.display()))?;
 
-    let (final_content, crdt_state) = if content_current == content {
-        let doc = crate::crdt::CrdtDoc::from_text(&content_ours);
-        (content_ours.clone(), doc.encode_state())
+    let merged = if content_current == content {
+        RecordedMerge::clean(content_ours.clone())
     } else {
         let crdt_state = snapshot::load_crdt(file)?;
-        merge::merge_contents_crdt(crdt_state.as_deref(), &content_ours, &content_current)?
+        merge::merge_document_crdt(file, crdt_state.as_deref(), &content_ours, &content_current)?
     };
 
-    atomic_write(file, &final_content)?;
-    // Save snapshot as content_ours, not final_content
+    atomic_write(file, &merged.text)?;
+    // Save snapshot as content_ours, not the merged text
     snapshot::save(file, &content_ours)?;
-    snapshot::save_crdt(file, &crdt_state)?;
+    snapshot::save_crdt_text(file, &merged.text)?;
     drop(doc_lock);
+    merged.fire_hooks(file);
     eprintln!("[write] Stream patches applied to {}", file.display());
     Ok(())
 }
@@ -442,22 +424,24 @@ pub fn apply_append_from_string(file: &Path, response: &str) -> Result<()> {
         content_ours.push('\n');
     }
     content_ours.push_str("\n## User\n\n");
+    let content_ours = generate::regenerate(&content_ours, file);
 
     let doc_lock = acquire_doc_lock(file)?;
 
     let content_current = std::fs::read_to_string(file)
         .with_context(|| format!("failed to re-read {}", file.display()))?;
 
-    let final_content = if content_current == content {
-        content_ours.clone()
+    let merged = if content_current == content {
+        RecordedMerge::clean(content_ours.clone())
     } else {
-        merge::merge_contents(&content, &content_ours, &content_current)?
+        merge::merge_document(file, &content, &content_ours, &content_current)?
     };
 
-    atomic_write(file, &final_content)?;
-    // Save snapshot as content_ours, not final_content
+    atomic_write(file, &merged.text)?;
+    // Save snapshot as content_ours, not the merged text
     snapshot::save(file, &content_ours)?;
     drop(doc_lock);
+    merged.fire_hooks(file);
     eprintln!("[write] Response appended to {}", file.display());
     Ok(())
 }
@@ -479,23 +463,25 @@ pub fn apply_template_from_string(file: &Path, response: &str) -> Result<()> {
     let content_current = std::fs::read_to_string(file)
         .with_context(|| format!("failed to re-read {}", file.display()))?;
 
-    let final_content = if content_current == content {
-        content_ours.clone()
+    let merged = if content_current == content {
+        RecordedMerge::clean(content_ours.clone())
     } else {
-        merge::merge_contents(&content, &content_ours, &content_current)?
+        merge::merge_document(file, &content, &content_ours, &content_current)?
     };
 
-    atomic_write(file, &final_content)?;
-    // Save snapshot as content_ours, not final_content
+    atomic_write(file, &merged.text)?;
+    // Save snapshot as content_ours, not the merged text
     snapshot::save(file, &content_ours)?;
     drop(doc_lock);
+    merged.fire_hooks(file);
     eprintln!("[write] Template patches applied to {}", file.display());
     Ok(())
 }
 
 /// Attempt to write via IPC (file-based patch for IDE plugin consumption).
 ///
-/// Writes a JSON patch file to `.agent-doc/patches/` and polls for the plugin
+/// If editor replicas are connected to `agent-doc serve`, the patches are
+/// sent as a CRDT update over the sync socket instead. Otherwise writes a JSON patch file to `.agent-doc/patches/` and polls for the plugin
 /// to consume it (delete the file as ACK). Returns `Ok(true)` if the plugin
 /// consumed the patch, `Ok(false)` if it timed out (caller should fall back
 /// to direct write).
@@ -509,6 +495,10 @@ pub fn try_ipc(
     frontmatter_yaml: Option<&str>,
     baseline: Option<&str>,
 ) -> Result<bool> {
+    if try_sync_patches(file, patches, unmatched, frontmatter_yaml)? {
+        return Ok(true);
+    }
+
     let canonical = file.canonicalize()?;
     let hash = snapshot::doc_hash(file)?;
     let project_root = find_project_root(&canonical)
@@ -522,17 +512,11 @@ pub fn try_ipc(
 
     let patch_file = patches_dir.join(format!("{}.json", hash));
 
-    // Separate frontmatter patch from component patches
-    let ipc_patches: Vec<serde_json::Value> = patches
-        .iter()
-        .filter(|p| p.name != "frontmatter")
-        .map(|p| {
-            serde_json::json!({
-                "component": p.name,
-                "content": p.content,
-            })
-        })
-        .collect();
+    let doc = std::fs::read_to_string(file).unwrap_or_default();
+    let Some((ipc_patches, rejected)) = ipc_component_patches(file, &doc, patches)? else {
+        return Ok(false);
+    };
+    let count = ipc_patches.len();
 
     let mut ipc_payload = serde_json::json!({
         "file": canonical.to_string_lossy(),
@@ -545,7 +529,105 @@ pub fn try_ipc(
         ipc_payload["frontmatter"] = serde_json::Value::String(yaml.to_string());
     }
 
-    write_ipc_and_poll(&patch_file, &ipc_payload, file, patches.len())
+    if !write_ipc_and_poll(&patch_file, &ipc_payload, file, count)? {
+        return Ok(false);
+    }
+    log_rejected(&rejected);
+    Ok(true)
+}
+
+/// Component patches for an IPC payload, checked against `doc`.
+///
+/// Plugins only replace component content by marker name, so nested paths
+/// (`plan/step-1`) are resolved to marker names and operation patches
+/// (insert-after, diff, ...) to the component's full new content. Patches
+/// rejected by component ownership are left out and returned for the write
+/// summary. Returns `None` if an operation can't be applied or a patch would
+/// create a component; the caller falls back to the direct write, which
+/// handles both.
+fn ipc_component_patches(
+    file: &Path,
+    doc: &str,
+    patches: &[crate::template::PatchBlock],
+) -> Result<Option<(Vec<serde_json::Value>, Vec<template::RejectedPatch>)>> {
+    let components = crate::component::parse(doc).unwrap_or_default();
+    let rejected = template::rejected_patches(doc, patches, file).unwrap_or_default();
+
+    let mut ipc_patches = Vec::new();
+    let mut new_contents: Vec<(&str, String)> = Vec::new();
+    for p in patches {
+        if p.name == "frontmatter" || rejected.iter().any(|r| r.name == p.name) {
+            continue;
+        }
+        let target = crate::component::find_by_path(&components, &p.name);
+        if target.is_none() && p.placement.is_some() {
+            eprintln!("[write] IPC skipped: patch '{}' creates a component", p.name);
+            return Ok(None);
+        }
+        let name = target.map_or(p.name.as_str(), |c| c.name.as_str());
+        let content = match template::resolved_content(doc, p, file) {
+            Ok(Some(resolved)) => {
+                new_contents.push((&p.name, resolved.clone()));
+                resolved
+            }
+            Ok(None) => {
+                // Sent as-is for the plugin's mode; the local result is only for derived components
+                if let Ok(Some(full)) = template::component_content(doc, p, file) {
+                    new_contents.push((&p.name, full));
+                }
+                p.content.clone()
+            }
+            Err(e) => {
+                eprintln!("[write] IPC skipped: {:#}", e);
+                return Ok(None);
+            }
+        };
+        ipc_patches.push(serde_json::json!({
+            "component": name,
+            "content": content,
+        }));
+    }
+
+    // Derived components (toc, budget, ...) follow the patched document
+    let mut patched = doc.to_string();
+    for (path, content) in &new_contents {
+        let Ok(patched_components) = crate::component::parse(&patched) else {
+            break;
+        };
+        if let Some(comp) = crate::component::find_by_path(&patched_components, path) {
+            patched = comp.replace_content(&patched, content);
+        }
+    }
+    let patched = generate::regenerate(&patched, file);
+    if let Ok(patched_components) = crate::component::parse(&patched) {
+        for comp in &patched_components {
+            if generate::kind(comp).is_none()
+                || ipc_patches.iter().any(|p| p["component"] == comp.name.as_str())
+            {
+                continue;
+            }
+            let before = components.iter().find(|c| c.name == comp.name).map(|c| c.content(doc));
+            let content = comp.content(&patched);
+            if before.is_some_and(|b| b != content) {
+                ipc_patches.push(serde_json::json!({
+                    "component": comp.name,
+                    "content": content,
+                }));
+            }
+        }
+    }
+    Ok(Some((ipc_patches, rejected)))
+}
+
+/// List patches rejected by component ownership in a write summary.
+fn log_rejected(rejected: &[template::RejectedPatch]) {
+    if rejected.is_empty() {
+        return;
+    }
+    eprintln!("[write] {} patch(es) rejected (user-owned components):", rejected.len());
+    for r in rejected {
+        eprintln!("[write]   {}: {}", r.name, r.reason);
+    }
 }
 
 /// Attempt to write full document content via IPC.
@@ -559,6 +641,11 @@ pub fn try_ipc_full_content(
     file: &Path,
     content: &str,
 ) -> Result<bool> {
+    if let Some(written) = sync_write(file, |_| Ok(content.to_string()))? {
+        snapshot::save(file, &written)?;
+        return Ok(true);
+    }
+
     let canonical = file.canonicalize()?;
     let hash = snapshot::doc_hash(file)?;
     let project_root = find_project_root(&canonical)
@@ -583,6 +670,77 @@ pub fn try_ipc_full_content(
     write_ipc_and_poll(&patch_file, &ipc_payload, file, 0)
 }
 
+/// Send component patches to editor replicas over the sync socket.
+///
+/// Patches are applied to the live replica text in replace mode (the same
+/// semantics the plugins use for JSON patches), except for table and
+/// checklist components, which keep their merge rules. Returns `Ok(false)` if no sync server or
+/// editor replica is available.
+///
+/// The document file is not written here: like a consumed IPC patch, the
+/// update lands in the editor buffers and the editor saves the file. The
+/// snapshot is saved as the replica text, which is what the file holds once
+/// the editor saves; until then `diff` sees the file as behind the snapshot.
+fn try_sync_patches(
+    file: &Path,
+    patches: &[crate::template::PatchBlock],
+    unmatched: &str,
+    frontmatter_yaml: Option<&str>,
+) -> Result<bool> {
+    let patches: Vec<_> = patches.iter().filter(|p| p.name != "frontmatter").cloned().collect();
+    let written = sync_write(file, |current| {
+        let mode_overrides = sync_mode_overrides(file, current, &patches)?;
+        let mut updated = template::apply_patches_with_overrides(
+            current, &patches, unmatched, file, &mode_overrides,
+        )?;
+        if let Some(yaml) = frontmatter_yaml {
+            updated = crate::frontmatter::merge_fields(&updated, yaml)?;
+        }
+        Ok(updated)
+    })?;
+    match written {
+        Some(content) => {
+            snapshot::save(file, &content)?;
+            Ok(true)
+        }
+        None => Ok(false),
+    }
+}
+
+/// Replace-mode overrides for a sync write, leaving out components whose
+/// configured mode merges rows rather than replacing content.
+fn sync_mode_overrides(
+    file: &Path,
+    doc: &str,
+    patches: &[crate::template::PatchBlock],
+) -> Result<std::collections::HashMap<String, String>> {
+    let components = crate::component::parse(doc).context("failed to parse components")?;
+    let configs = crate::patch::load_configs(file).unwrap_or_default();
+    let mut overrides = std::collections::HashMap::new();
+    for patch in patches {
+        if let Some(comp) = crate::component::find_by_path(&components, &patch.name) {
+            let config = crate::patch::config_for(&configs, &patch.name, comp)?;
+            if matches!(config.mode.as_deref(), Some("table" | "checklist")) {
+                continue;
+            }
+        }
+        overrides.insert(patch.name.clone(), "replace".to_string());
+    }
+    Ok(overrides)
+}
+
+/// Push an edit to editor replicas connected to `agent-doc serve`.
+#[cfg(unix)]
+fn sync_write(file: &Path, edit: impl FnOnce(&str) -> Result<String>) -> Result<Option<String>> {
+    crate::ysync::try_write(file, edit)
+}
+
+/// The sync socket is Unix-only; other platforms always fall back to IPC.
+#[cfg(not(unix))]
+fn sync_write(_file: &Path, _edit: impl FnOnce(&str) -> Result<String>) -> Result<Option<String>> {
+    Ok(None)
+}
+
 /// Write an IPC patch file and poll for plugin ACK (file deletion).
 ///
 /// Returns `Ok(true)` if consumed, `Ok(false)` on timeout.
@@ -605,7 +763,7 @@ fn write_ipc_and_poll(
     );
 
     // Poll for ACK (plugin deletes file after applying)
-    let timeout = std::time::Duration::from_secs(2);
+    let timeout = crate::config::ipc_timeout(doc_file);
     let poll_interval = std::time::Duration::from_millis(100);
     let start = std::time::Instant::now();
 
@@ -615,8 +773,7 @@ fn write_ipc_and_poll(
             let content = std::fs::read_to_string(doc_file)
                 .with_context(|| format!("failed to read {} after IPC", doc_file.display()))?;
             snapshot::save(doc_file, &content)?;
-            let crdt_doc = crate::crdt::CrdtDoc::from_text(&content);
-            snapshot::save_crdt(doc_file, &crdt_doc.encode_state())?;
+            snapshot::save_crdt_text(doc_file, &content)?;
             eprintln!("[write] IPC patch consumed by plugin — snapshot updated");
             return Ok(true);
         }
@@ -624,7 +781,7 @@ fn write_ipc_and_poll(
     }
 
     // Timeout — clean up unconsumed patch file
-    eprintln!("[write] IPC timeout ({}s) — falling back to direct write", timeout.as_secs());
+    eprintln!("[write] IPC timeout ({}ms) — falling back to direct write", timeout.as_millis());
     let _ = std::fs::remove_file(patch_file);
     Ok(false)
 }
@@ -661,7 +818,7 @@ pub fn strip_assistant_heading(response: &str) -> String {
     result
 }
 
-fn acquire_doc_lock(path: &Path) -> Result<std::fs::File> {
+pub(crate) fn acquire_doc_lock(path: &Path) -> Result<std::fs::File> {
     let lock_path = crate::snapshot::lock_path_for(path)?;
     if let Some(parent) = lock_path.parent() {
         std::fs::create_dir_all(parent)?;
@@ -674,6 +831,7 @@ fn acquire_doc_lock(path: &Path) -> Result<std::fs::File> {
         .with_context(|| format!("failed to open doc lock {}", lock_path.display()))?;
     file.lock_exclusive()
         .with_context(|| format!("failed to acquire doc lock on {}", lock_path.display()))?;
+    crate::snapshot::migrate(path)?;
     Ok(file)
 }
 
@@ -763,7 +921,9 @@ mod tests {
         // "theirs" = user added a follow-up to the User block
         let theirs = "---\nsession: test\n---\n\n## User\n\nOriginal question\nAnd a follow-up!\n";
 
-        let merged = merge::merge_contents(base, &ours, theirs).unwrap();
+        let dir = TempDir::new().unwrap();
+        let doc = dir.path().join("test.md");
+        let merged = merge::merge_document(&doc, base, &ours, theirs).unwrap().text;
 
         // Both the response and the user's follow-up should be in the merge
         assert!(merged.contains("My response"), "response missing from merge");
@@ -791,7 +951,7 @@ mod tests {
         let final_content = if content_current == base {
             ours.clone()
         } else {
-            merge::merge_contents(base, &ours, &content_current).unwrap()
+            merge::merge_document(&doc, base, &ours, &content_current).unwrap().text
         };
 
         drop(doc_lock);
@@ -869,7 +1029,7 @@ mod tests {
         fs::write(&doc, &user_edited).unwrap();
 
         // Merge: content_ours + user edits
-        let merged = merge::merge_contents(base, &content_ours, &user_edited).unwrap();
+        let merged = merge::merge_document(&doc, base, &content_ours, &user_edited).unwrap().text;
 
         // Write merged content (includes both response and user edit)
         atomic_write(&doc, &merged).unwrap();
@@ -924,6 +1084,8 @@ mod tests {
         let patch = crate::template::PatchBlock {
             name: "exchange".to_string(),
             content: "new content".to_string(),
+            op: crate::patch_ops::PatchOp::Mode,
+            placement: None,
         };
 
         // This will timeout after 2s — patch file is written but never consumed
@@ -954,6 +1116,8 @@ mod tests {
         let patch = crate::template::PatchBlock {
             name: "exchange".to_string(),
             content: "new content".to_string(),
+            op: crate::patch_ops::PatchOp::Mode,
+            placement: None,
         };
 
         // Spawn "plugin" thread that watches for and deletes patch files
@@ -977,6 +1141,92 @@ mod tests {
         assert!(result, "should return true when plugin consumes patch");
     }
 
+    #[test]
+    fn ipc_patches_include_changed_derived_components() {
+        let dir = TempDir::new().unwrap();
+        let doc = dir.path().join("test.md");
+        let content = "<!-- agent:index generate=exchange-index -->\n<!-- /agent:index -->\n\
+                       <!-- agent:exchange -->\n### Re: one\n<!-- /agent:exchange -->\n\
+                       <!-- agent:plan -->\n- a\n<!-- /agent:plan -->\n";
+        fs::write(&doc, content).unwrap();
+
+        let response = "<!-- patch:exchange -->\n### Re: two\n<!-- /patch:exchange -->\n\
+                        <!-- patch:plan op=insert-after anchor=\"- a\" -->\n- b\n<!-- /patch:plan -->\n";
+        let (patches, _) = template::parse_patches(response).unwrap();
+        let (ipc_patches, rejected) = ipc_component_patches(&doc, content, &patches).unwrap().unwrap();
+        assert!(rejected.is_empty());
+        assert_eq!(ipc_patches.len(), 3);
+        assert_eq!(ipc_patches[0]["content"], "### Re: two\n", "mode patches are sent as-is");
+        assert_eq!(ipc_patches[1]["content"], "- a\n- b\n");
+        assert_eq!(ipc_patches[2]["component"], "index");
+        assert_eq!(ipc_patches[2]["content"], "- [Re: one](#re-one)\n- [Re: two](#re-two)\n");
+    }
+
+    #[test]
+    fn ipc_patches_return_rejected_separately() {
+        let dir = TempDir::new().unwrap();
+        let doc = dir.path().join("test.md");
+        let content = "<!-- agent:notes owner=user -->\nmine\n<!-- /agent:notes -->\n<!-- agent:exchange -->\n<!-- /agent:exchange -->\n";
+        fs::write(&doc, content).unwrap();
+
+        let response = "<!-- patch:notes -->\noverwrite\n<!-- /patch:notes -->\n<!-- patch:exchange -->\nhi\n<!-- /patch:exchange -->\n";
+        let (patches, _) = template::parse_patches(response).unwrap();
+        let (ipc_patches, rejected) = ipc_component_patches(&doc, content, &patches).unwrap().unwrap();
+        assert_eq!(ipc_patches.len(), 1);
+        assert_eq!(ipc_patches[0]["component"], "exchange");
+        assert_eq!(rejected.len(), 1);
+        assert_eq!(rejected[0].name, "notes");
+    }
+
+    #[test]
+    fn sync_overrides_skip_table_and_checklist_components() {
+        let dir = TempDir::new().unwrap();
+        let doc = dir.path().join("test.md");
+        let content = "<!-- agent:tasks mode=table key=id -->\n| id | task |\n|----|------|\n<!-- /agent:tasks -->\n<!-- agent:todo mode=checklist -->\n- [ ] a\n<!-- /agent:todo -->\n<!-- agent:exchange -->\n<!-- /agent:exchange -->\n";
+        fs::write(&doc, content).unwrap();
+
+        let patch = |name: &str| crate::template::PatchBlock {
+            name: name.to_string(),
+            content: "x\n".to_string(),
+            op: crate::patch_ops::PatchOp::Mode,
+            placement: None,
+        };
+        let overrides = sync_mode_overrides(&doc, content, &[patch("tasks"), patch("todo"), patch("exchange")]).unwrap();
+        assert!(!overrides.contains_key("tasks"));
+        assert!(!overrides.contains_key("todo"));
+        assert_eq!(overrides.get("exchange").map(String::as_str), Some("replace"));
+    }
+
+    #[test]
+    fn stream_writes_grow_crdt_log() {
+        let dir = TempDir::new().unwrap();
+        fs::create_dir_all(dir.path().join(".agent-doc")).unwrap();
+        let doc = dir.path().join("test.md");
+        let content = "---\nagent_doc_mode: stream\n---\n\n<!-- agent:exchange -->\nQ?\n<!-- /agent:exchange -->\n";
+        fs::write(&doc, content).unwrap();
+        let log_len = || snapshot::crdt_log(&doc).unwrap().len();
+
+        write_stream(&doc, None, "<!-- patch:exchange -->\nQ?\nA1.\n<!-- /patch:exchange -->\n").unwrap();
+        assert!(snapshot::crdt_path_for(&doc).unwrap().exists());
+        assert_eq!(log_len(), 0, "first write starts a checkpoint");
+
+        // The user edits while the next response is generated: the CRDT merge path.
+        let baseline = fs::read_to_string(&doc).unwrap();
+        fs::write(&doc, baseline.replace("Q?", "Q? (edited)")).unwrap();
+        write_stream(&doc, Some(&baseline), "<!-- patch:exchange -->\nQ?\nA1.\nA2.\n<!-- /patch:exchange -->\n").unwrap();
+        assert_eq!(log_len(), 1);
+
+        let baseline = fs::read_to_string(&doc).unwrap();
+        write_stream(&doc, None, &format!("<!-- patch:exchange -->\n{}A3.\n<!-- /patch:exchange -->\n", "Q? (edited)\nA1.\nA2.\n")).unwrap();
+        assert_eq!(log_len(), 2);
+
+        let result = fs::read_to_string(&doc).unwrap();
+        assert_ne!(result, baseline);
+        assert!(result.contains("Q? (edited)\nA1.\nA2.\nA3.\n"), "{}", result);
+        let state = snapshot::load_crdt(&doc).unwrap().unwrap();
+        assert_eq!(crate::crdt::CrdtDoc::decode_state(&state).unwrap().to_text(), result);
+    }
+
     #[test]
     fn try_ipc_full_content_returns_false_when_no_patches_dir() {
         let dir = TempDir::new().unwrap();
@@ -0,0 +1,552 @@
+//! `agent-doc serve` — Local Yjs sync socket for editor plugins.
+//!
+//! Usage: agent-doc serve <file.md>
+//!
+//! Listens on `.agent-doc/sync/<key>.sock` and speaks the y-sync protocol
+//! (sync step 1/2 and incremental updates, plus awareness relay) over the
+//! document's `CrdtDoc`. Editor plugins hold a live replica of the document;
+//! agent writes reach them as CRDT updates instead of JSON patch files, so
+//! agent output and user edits converge without file polling.
+//!
+//! Messages use the standard y-protocols encoding. A Unix stream has no
+//! message boundaries, so each message is prefixed with its byte length as
+//! a lib0 varuint.
+
+use anyhow::{Context, Result};
+use std::io::{Read, Write};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::time::Duration;
+
+use crate::crdt::CrdtDoc;
+use crate::snapshot;
+
+const SYNC_DIR: &str = ".agent-doc/sync";
+
+/// Longest socket path accepted by `sun_path` on Linux and macOS.
+const MAX_SOCKET_PATH: usize = 103;
+
+/// Largest frame accepted from a peer.
+const MAX_FRAME: u64 = 64 * 1024 * 1024;
+
+/// How long a writer waits for the server during the handshake.
+const CLIENT_TIMEOUT: Duration = Duration::from_secs(2);
+
+const MSG_SYNC: u64 = 0;
+const MSG_AWARENESS: u64 = 1;
+const MSG_QUERY_AWARENESS: u64 = 3;
+
+const SYNC_STEP1: u64 = 0;
+const SYNC_STEP2: u64 = 1;
+const SYNC_UPDATE: u64 = 2;
+
+/// A y-sync protocol message.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Message {
+    /// Sync step 1: the sender's encoded state vector.
+    SyncStep1(Vec<u8>),
+    /// Sync step 2: the update the receiver of step 1 was missing.
+    SyncStep2(Vec<u8>),
+    /// Incremental v1 update.
+    Update(Vec<u8>),
+    /// Opaque awareness update (cursors, user info), relayed as-is.
+    Awareness(Vec<u8>),
+    /// Request the awareness state of all other peers.
+    QueryAwareness,
+    /// Any other message type (e.g. auth); ignored.
+    Other(u64),
+}
+
+impl Message {
+    /// Encode with the y-protocols wire format.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut buf = Vec::new();
+        match self {
+            Message::SyncStep1(sv) => {
+                write_var(&mut buf, MSG_SYNC);
+                write_var(&mut buf, SYNC_STEP1);
+                write_buf(&mut buf, sv);
+            }
+            Message::SyncStep2(update) => {
+                write_var(&mut buf, MSG_SYNC);
+                write_var(&mut buf, SYNC_STEP2);
+                write_buf(&mut buf, update);
+            }
+            Message::Update(update) => {
+                write_var(&mut buf, MSG_SYNC);
+                write_var(&mut buf, SYNC_UPDATE);
+                write_buf(&mut buf, update);
+            }
+            Message::Awareness(payload) => {
+                write_var(&mut buf, MSG_AWARENESS);
+                write_buf(&mut buf, payload);
+            }
+            Message::QueryAwareness => write_var(&mut buf, MSG_QUERY_AWARENESS),
+            Message::Other(kind) => write_var(&mut buf, *kind),
+        }
+        buf
+    }
+
+    /// Decode a message in the y-protocols wire format.
+    pub fn decode(bytes: &[u8]) -> Result<Self> {
+        let mut cursor = bytes;
+        let kind = read_var(&mut cursor)?;
+        Ok(match kind {
+            MSG_SYNC => match read_var(&mut cursor)? {
+                SYNC_STEP1 => Message::SyncStep1(read_buf(&mut cursor)?),
+                SYNC_STEP2 => Message::SyncStep2(read_buf(&mut cursor)?),
+                SYNC_UPDATE => Message::Update(read_buf(&mut cursor)?),
+                other => anyhow::bail!("unknown sync message type {}", other),
+            },
+            MSG_AWARENESS => Message::Awareness(read_buf(&mut cursor)?),
+            MSG_QUERY_AWARENESS => Message::QueryAwareness,
+            other => Message::Other(other),
+        })
+    }
+}
+
+/// Write a length-prefixed message frame.
+pub fn write_frame(w: &mut impl Write, msg: &Message) -> Result<()> {
+    let mut frame = Vec::new();
+    write_buf(&mut frame, &msg.encode());
+    w.write_all(&frame)?;
+    w.flush()?;
+    Ok(())
+}
+
+/// Read a length-prefixed message frame. Returns `None` on a clean EOF.
+pub fn read_frame(r: &mut impl Read) -> Result<Option<Message>> {
+    let mut first = [0u8; 1];
+    if r.read(&mut first)? == 0 {
+        return Ok(None);
+    }
+    let len = read_var(&mut first.as_slice().chain(&mut *r))?;
+    if len > MAX_FRAME {
+        anyhow::bail!("frame too large ({} bytes)", len);
+    }
+    let mut body = vec![0u8; len as usize];
+    r.read_exact(&mut body).context("truncated frame")?;
+    Message::decode(&body).map(Some)
+}
+
+/// lib0 varuint: 7 bits per byte, least significant group first.
+fn write_var(buf: &mut Vec<u8>, mut n: u64) {
+    while n >= 0x80 {
+        buf.push((n as u8 & 0x7f) | 0x80);
+        n >>= 7;
+    }
+    buf.push(n as u8);
+}
+
+fn write_buf(buf: &mut Vec<u8>, bytes: &[u8]) {
+    write_var(buf, bytes.len() as u64);
+    buf.extend_from_slice(bytes);
+}
+
+fn read_var(r: &mut impl Read) -> Result<u64> {
+    let mut n = 0u64;
+    let mut shift = 0;
+    loop {
+        let mut byte = [0u8; 1];
+        r.read_exact(&mut byte).context("truncated varuint")?;
+        n |= ((byte[0] & 0x7f) as u64) << shift;
+        if byte[0] & 0x80 == 0 {
+            return Ok(n);
+        }
+        shift += 7;
+        if shift > 63 {
+            anyhow::bail!("varuint overflow");
+        }
+    }
+}
+
+fn read_buf(r: &mut &[u8]) -> Result<Vec<u8>> {
+    let len = read_var(r)? as usize;
+    if len > r.len() {
+        anyhow::bail!("buffer length {} exceeds message", len);
+    }
+    let (head, tail) = r.split_at(len);
+    *r = tail;
+    Ok(head.to_vec())
+}
+
+/// Compute the sync socket path for a document.
+/// Returns `<project_root>/.agent-doc/sync/<key>.sock`, using a shortened key
+/// so the path fits in `sun_path`.
+pub fn socket_path_for(doc: &Path) -> Result<PathBuf> {
+    let canonical = doc.canonicalize()?;
+    let root = snapshot::find_project_root(&canonical)
+        .unwrap_or_else(|| canonical.parent().unwrap_or(Path::new(".")).to_path_buf());
+    let key = snapshot::doc_key(doc)?;
+    Ok(root.join(SYNC_DIR).join(format!("{}.sock", &key[..16])))
+}
+
+/// A connected peer.
+struct Peer {
+    id: u64,
+    /// Write half, shared so messages go out without holding the hub lock.
+    writer: Arc<Mutex<UnixStream>>,
+    /// Last awareness update the peer sent (editor replicas announce themselves).
+    awareness: Option<Vec<u8>>,
+}
+
+/// Shared server state: the authoritative replica and its peers.
+struct Hub {
+    file: PathBuf,
+    doc: CrdtDoc,
+    peers: Vec<Peer>,
+    next_id: u64,
+}
+
+/// Lock the hub. A peer thread that panicked mid-message leaves the replica
+/// no worse than a dropped update, so poisoning is ignored.
+fn lock(hub: &Mutex<Hub>) -> MutexGuard<'_, Hub> {
+    hub.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Write one message to a peer, serialized with other writers to it.
+fn send(writer: &Mutex<UnixStream>, msg: &Message) -> Result<()> {
+    let mut stream = writer.lock().unwrap_or_else(PoisonError::into_inner);
+    write_frame(&mut *stream, msg)
+}
+
+/// Send a message to every peer except `from`, dropping peers that fail.
+fn broadcast(hub: &Mutex<Hub>, from: u64, msg: &Message) {
+    let writers: Vec<(u64, Arc<Mutex<UnixStream>>)> = lock(hub)
+        .peers
+        .iter()
+        .filter(|p| p.id != from)
+        .map(|p| (p.id, Arc::clone(&p.writer)))
+        .collect();
+    let failed: Vec<u64> = writers
+        .into_iter()
+        .filter(|(_, writer)| send(writer, msg).is_err())
+        .map(|(id, _)| id)
+        .collect();
+    if !failed.is_empty() {
+        lock(hub).peers.retain(|p| !failed.contains(&p.id));
+    }
+}
+
+fn reply(hub: &Mutex<Hub>, to: u64, msg: &Message) -> Result<()> {
+    let writer = lock(hub).peers.iter().find(|p| p.id == to).map(|p| Arc::clone(&p.writer));
+    if let Some(writer) = writer {
+        send(&writer, msg)?;
+    }
+    Ok(())
+}
+
+/// Run the sync server for a document until interrupted.
+pub fn run(file: &Path) -> Result<()> {
+    if !file.exists() {
+        anyhow::bail!("file not found: {}", file.display());
+    }
+    let socket = socket_path_for(file)?;
+    let listener = bind(&socket)?;
+    let hub = Arc::new(Mutex::new(Hub {
+        file: file.to_path_buf(),
+        doc: load_replica(file)?,
+        peers: Vec::new(),
+        next_id: 0,
+    }));
+    eprintln!("[serve] Listening on {} for {}", socket.display(), file.display());
+    serve(listener, hub)
+}
+
+/// Bind the socket, replacing a stale one left by a dead server.
+fn bind(socket: &Path) -> Result<UnixListener> {
+    if socket.as_os_str().len() > MAX_SOCKET_PATH {
+        anyhow::bail!(
+            "socket path too long ({} bytes): {}",
+            socket.as_os_str().len(),
+            socket.display()
+        );
+    }
+    if let Some(parent) = socket.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    if socket.exists() {
+        if UnixStream::connect(socket).is_ok() {
+            anyhow::bail!("a sync server is already running on {}", socket.display());
+        }
+        std::fs::remove_file(socket)
+            .with_context(|| format!("failed to remove stale socket {}", socket.display()))?;
+    }
+    UnixListener::bind(socket).with_context(|| format!("failed to bind {}", socket.display()))
+}
+
+/// Load the stored CRDT replica, bringing it up to date with the file on disk.
+///
+/// The catch-up edit is persisted first, so the stored state matches the hub
+/// and later peer updates can be logged on their own.
+fn load_replica(file: &Path) -> Result<CrdtDoc> {
+    let content = std::fs::read_to_string(file)
+        .with_context(|| format!("failed to read {}", file.display()))?;
+    // Edited on disk while no server was running — the file wins.
+    snapshot::save_crdt_text(file, &content)?;
+    let state = snapshot::load_crdt(file)?
+        .with_context(|| format!("no CRDT state for {}", file.display()))?;
+    CrdtDoc::decode_state(&state)
+}
+
+fn serve(listener: UnixListener, hub: Arc<Mutex<Hub>>) -> Result<()> {
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(s) => s,
+            Err(e) => {
+                eprintln!("[serve] accept failed: {}", e);
+                continue;
+            }
+        };
+        let hub = Arc::clone(&hub);
+        std::thread::spawn(move || {
+            if let Err(e) = handle_peer(stream, &hub) {
+                eprintln!("[serve] peer error: {:#}", e);
+            }
+        });
+    }
+    Ok(())
+}
+
+/// Serve one peer: send our step 1, then process its messages until EOF.
+fn handle_peer(stream: UnixStream, hub: &Mutex<Hub>) -> Result<()> {
+    let mut reader = stream.try_clone()?;
+    let writer = Arc::new(Mutex::new(stream));
+    let (id, sv) = {
+        let mut hub = lock(hub);
+        let id = hub.next_id;
+        hub.next_id += 1;
+        hub.peers.push(Peer { id, writer: Arc::clone(&writer), awareness: None });
+        (id, hub.doc.encode_state_vector())
+    };
+    send(&writer, &Message::SyncStep1(sv))?;
+
+    let result = (|| -> Result<()> {
+        while let Some(msg) = read_frame(&mut reader)? {
+            handle_message(hub, id, msg)?;
+        }
+        Ok(())
+    })();
+
+    lock(hub).peers.retain(|p| p.id != id);
+    result
+}
+
+/// Handle one peer message. The hub lock covers only the replica and peer
+/// list; persisting and sending happen after it is released.
+fn handle_message(hub: &Mutex<Hub>, id: u64, msg: Message) -> Result<()> {
+    match msg {
+        Message::SyncStep1(sv) => {
+            let update = lock(hub).doc.encode_diff_v1(&sv)?;
+            reply(hub, id, &Message::SyncStep2(update))?;
+        }
+        Message::SyncStep2(update) | Message::Update(update) => {
+            let file = {
+                let hub = lock(hub);
+                hub.doc.apply_update(&update)?;
+                hub.file.clone()
+            };
+            snapshot::save_crdt_update(&file, &update)?;
+            broadcast(hub, id, &Message::Update(update));
+        }
+        Message::Awareness(payload) => {
+            if let Some(peer) = lock(hub).peers.iter_mut().find(|p| p.id == id) {
+                peer.awareness = Some(payload.clone());
+            }
+            broadcast(hub, id, &Message::Awareness(payload));
+        }
+        Message::QueryAwareness => {
+            let states: Vec<Vec<u8>> = lock(hub)
+                .peers
+                .iter()
+                .filter(|p| p.id != id)
+                .filter_map(|p| p.awareness.clone())
+                .collect();
+            for state in states {
+                reply(hub, id, &Message::Awareness(state))?;
+            }
+        }
+        Message::Other(_) => {}
+    }
+    Ok(())
+}
+
+/// Push an edit to connected editor replicas through the sync socket.
+///
+/// Fetches the live document from the server, applies `edit` to its text and
+/// sends the difference as an update. Returns the new text, or `None` if no
+/// server is listening or no editor replica has announced itself via
+/// awareness — the caller then falls back to patch files or a direct write.
+pub fn try_write(file: &Path, edit: impl FnOnce(&str) -> Result<String>) -> Result<Option<String>> {
+    let socket = socket_path_for(file)?;
+    if !socket.exists() {
+        return Ok(None);
+    }
+    let Ok(mut stream) = UnixStream::connect(&socket) else {
+        return Ok(None);
+    };
+    stream.set_read_timeout(Some(CLIENT_TIMEOUT))?;
+
+    // Query awareness first: the server answers in order, so every replica's
+    // awareness arrives before the step 2 reply.
+    write_frame(&mut stream, &Message::QueryAwareness)?;
+    write_frame(&mut stream, &Message::SyncStep1(CrdtDoc::from_text("").encode_state_vector()))?;
+
+    let mut replicas = 0;
+    let mut pending = Vec::new();
+    let state = loop {
+        match read_frame(&mut stream).context("sync handshake failed")? {
+            Some(Message::SyncStep2(state)) => break state,
+            Some(Message::Awareness(_)) => replicas += 1,
+            Some(Message::Update(update)) => pending.push(update),
+            Some(_) => {}
+            None => anyhow::bail!("sync server closed the connection during handshake"),
+        }
+    };
+    if replicas == 0 {
+        return Ok(None);
+    }
+
+    let doc = CrdtDoc::decode_state(&state)?;
+    for update in &pending {
+        doc.apply_update(update)?;
+    }
+    let updated = edit(&doc.to_text())?;
+    let sv = doc.state_vector();
+    doc.set_text(&updated);
+    write_frame(&mut stream, &Message::Update(doc.encode_diff(&sv)))?;
+    eprintln!("[serve] Sent update to {} editor replica(s)", replicas);
+    Ok(Some(updated))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+
+    fn start_server(dir: &TempDir, content: &str) -> (PathBuf, PathBuf) {
+        fs::create_dir_all(dir.path().join(".agent-doc")).unwrap();
+        let doc = dir.path().join("doc.md");
+        fs::write(&doc, content).unwrap();
+        let socket = socket_path_for(&doc).unwrap();
+        let listener = bind(&socket).unwrap();
+        let hub = Arc::new(Mutex::new(Hub {
+            file: doc.clone(),
+            doc: load_replica(&doc).unwrap(),
+            peers: Vec::new(),
+            next_id: 0,
+        }));
+        std::thread::spawn(move || serve(listener, hub));
+        (doc, socket)
+    }
+
+    /// Connect as an editor replica: handshake and announce via awareness.
+    fn connect_replica(socket: &Path) -> (UnixStream, CrdtDoc) {
+        let mut stream = UnixStream::connect(socket).unwrap();
+        stream.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+        let replica = CrdtDoc::from_text("");
+        write_frame(&mut stream, &Message::SyncStep1(replica.encode_state_vector())).unwrap();
+        write_frame(&mut stream, &Message::Awareness(vec![1, 2, 3])).unwrap();
+        loop {
+            match read_frame(&mut stream).unwrap().unwrap() {
+                Message::SyncStep2(update) => {
+                    replica.apply_update(&update).unwrap();
+                    break;
+                }
+                _ => continue,
+            }
+        }
+        (stream, replica)
+    }
+
+    #[test]
+    fn varuint_roundtrip() {
+        for n in [0u64, 1, 127, 128, 300, 16384, u32::MAX as u64, u64::MAX] {
+            let mut buf = Vec::new();
+            write_var(&mut buf, n);
+            assert_eq!(read_var(&mut buf.as_slice()).unwrap(), n);
+        }
+    }
+
+    #[test]
+    fn message_encoding_matches_y_protocols() {
+        assert_eq!(Message::SyncStep1(vec![0]).encode(), vec![0, 0, 1, 0]);
+        assert_eq!(Message::Update(vec![9, 9]).encode(), vec![0, 2, 2, 9, 9]);
+        assert_eq!(Message::QueryAwareness.encode(), vec![3]);
+        for msg in [
+            Message::SyncStep1(vec![1, 2]),
+            Message::SyncStep2(vec![3]),
+            Message::Update(vec![]),
+            Message::Awareness(vec![4, 5]),
+            Message::QueryAwareness,
+        ] {
+            assert_eq!(Message::decode(&msg.encode()).unwrap(), msg);
+        }
+    }
+
+    #[test]
+    fn frames_roundtrip_over_stream() {
+        let mut buf = Vec::new();
+        write_frame(&mut buf, &Message::Update(vec![7; 300])).unwrap();
+        write_frame(&mut buf, &Message::QueryAwareness).unwrap();
+        let mut reader = buf.as_slice();
+        assert_eq!(read_frame(&mut reader).unwrap(), Some(Message::Update(vec![7; 300])));
+        assert_eq!(read_frame(&mut reader).unwrap(), Some(Message::QueryAwareness));
+        assert_eq!(read_frame(&mut reader).unwrap(), None);
+    }
+
+    #[test]
+    fn replica_receives_initial_state() {
+        let dir = TempDir::new().unwrap();
+        let (_doc, socket) = start_server(&dir, "# Doc\n\nHello.\n");
+        let (_stream, replica) = connect_replica(&socket);
+        assert_eq!(replica.to_text(), "# Doc\n\nHello.\n");
+    }
+
+    #[test]
+    fn try_write_without_server_returns_none() {
+        let dir = TempDir::new().unwrap();
+        fs::create_dir_all(dir.path().join(".agent-doc")).unwrap();
+        let doc = dir.path().join("doc.md");
+        fs::write(&doc, "text\n").unwrap();
+        assert!(try_write(&doc, |t| Ok(t.to_string())).unwrap().is_none());
+    }
+
+    #[test]
+    fn try_write_without_replicas_returns_none() {
+        let dir = TempDir::new().unwrap();
+        let (doc, _socket) = start_server(&dir, "text\n");
+        assert!(try_write(&doc, |t| Ok(format!("{}more\n", t))).unwrap().is_none());
+    }
+
+    #[test]
+    fn agent_write_converges_with_user_edit() {
+        let dir = TempDir::new().unwrap();
+        let (doc, socket) = start_server(&dir, "# Doc\n\nQuestion?\n");
+        let (mut stream, replica) = connect_replica(&socket);
+
+        // User types in the editor; the replica sends its update to the server.
+        let sv = replica.state_vector();
+        replica.set_text("# Doc\n\nQuestion? More detail.\n");
+        write_frame(&mut stream, &Message::Update(replica.encode_diff(&sv))).unwrap();
+        // Round-trip a step 1 so the server has processed the update.
+        write_frame(&mut stream, &Message::SyncStep1(replica.encode_state_vector())).unwrap();
+        while !matches!(read_frame(&mut stream).unwrap(), Some(Message::SyncStep2(_))) {}
+
+        let written = try_write(&doc, |text| Ok(format!("{}\nAnswer.\n", text))).unwrap();
+        assert_eq!(written.as_deref(), Some("# Doc\n\nQuestion? More detail.\n\nAnswer.\n"));
+
+        let update = loop {
+            if let Some(Message::Update(update)) = read_frame(&mut stream).unwrap() {
+                break update;
+            }
+        };
+        replica.apply_update(&update).unwrap();
+        assert_eq!(replica.to_text(), "# Doc\n\nQuestion? More detail.\n\nAnswer.\n");
+
+        let stored = CrdtDoc::decode_state(&snapshot::load_crdt(&doc).unwrap().unwrap()).unwrap();
+        assert_eq!(stored.to_text(), replica.to_text());
+    }
+}